@@ -0,0 +1,16 @@
+import Route from '@ember/routing/route';
+
+import ajax from '../utils/ajax';
+
+export default class UnsubscribeRoute extends Route {
+  async model(params) {
+    try {
+      let { msg } = await ajax(`/api/v1/unsubscribe/${params.token}`, { method: 'POST', body: '{}' });
+      return { ok: true, msg };
+    } catch (error) {
+      let json = await error.json?.();
+      let errorText = json?.errors?.[0]?.detail;
+      return { ok: false, errorText };
+    }
+  }
+}
@@ -2,8 +2,12 @@ import { htmlSafe } from '@ember/template';
 
 const DESCRIPTIONS = {
   'change-owners': 'Invite new crate owners or remove existing ones',
+  'manage-follows': 'Follow and unfollow crates',
+  'manage-invitations': 'Accept and decline crate ownership invitations',
+  'manage-tokens': 'Create, rotate and revoke API tokens with at most the permissions of this token',
   'publish-new': 'Publish new crates',
   'publish-update': 'Publish new versions of existing crates',
+  'read-account': 'Read the account details and the list of owned crates',
   yank: 'Yank and unyank crate versions',
 };
 
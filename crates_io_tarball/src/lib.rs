@@ -25,6 +25,9 @@ mod vcs_info;
 pub struct TarballInfo {
     pub manifest: Manifest,
     pub vcs_info: Option<CargoVcsInfo>,
+    /// Whether the package has a build script, either configured in the manifest or
+    /// auto-detected from a `build.rs` file in the package root.
+    pub has_build_script: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -67,6 +70,7 @@ pub fn process_tarball<R: Read>(
 
     let mut vcs_info = None;
     let mut manifests = BTreeMap::new();
+    let mut has_build_rs = false;
 
     for entry in archive.entries()? {
         let mut entry = entry.map_err(TarballError::Malformed)?;
@@ -112,6 +116,8 @@ pub fn process_tarball<R: Read>(
                 validate_manifest(&manifest)?;
 
                 manifests.insert(owned_entry_path, manifest);
+            } else if entry_file == "build.rs" {
+                has_build_rs = true;
             }
         }
     }
@@ -136,7 +142,19 @@ pub fn process_tarball<R: Read>(
         return Err(TarballError::IncorrectlyCasedManifest(file.into()));
     }
 
-    Ok(TarballInfo { manifest, vcs_info })
+    // This matches the auto-detection of build scripts in cargo, unless the `build` field of the
+    // manifest explicitly configures or disables one.
+    let has_build_script = match manifest.package.as_ref().and_then(|p| p.build.as_ref()) {
+        Some(StringOrBool::Bool(build)) => *build,
+        Some(StringOrBool::String(_)) => true,
+        None => has_build_rs,
+    };
+
+    Ok(TarballInfo {
+        manifest,
+        vcs_info,
+        has_build_script,
+    })
 }
 
 #[cfg(test)]
@@ -287,6 +305,45 @@ repository = "https://github.com/foo/bar"
         assert_matches!(package.readme, Some(MaybeInherited::Local(StringOrBool::Bool(b))) if !b);
     }
 
+    #[test]
+    fn process_tarball_test_build_script() {
+        let manifest = b"[package]\nname = \"foo\"\nversion = \"0.0.1\"\n";
+        let limit = 512 * 1024 * 1024;
+
+        let tarball = TarballBuilder::new()
+            .add_file("foo-0.0.1/Cargo.toml", manifest)
+            .build();
+        let tarball_info = assert_ok!(process_tarball("foo-0.0.1", &*tarball, limit));
+        assert!(!tarball_info.has_build_script);
+
+        let tarball = TarballBuilder::new()
+            .add_file("foo-0.0.1/Cargo.toml", manifest)
+            .add_file("foo-0.0.1/build.rs", b"fn main() {}")
+            .build();
+        let tarball_info = assert_ok!(process_tarball("foo-0.0.1", &*tarball, limit));
+        assert!(tarball_info.has_build_script);
+
+        let tarball = TarballBuilder::new()
+            .add_file(
+                "foo-0.0.1/Cargo.toml",
+                b"[package]\nname = \"foo\"\nversion = \"0.0.1\"\nbuild = false\n",
+            )
+            .add_file("foo-0.0.1/build.rs", b"fn main() {}")
+            .build();
+        let tarball_info = assert_ok!(process_tarball("foo-0.0.1", &*tarball, limit));
+        assert!(!tarball_info.has_build_script);
+
+        let tarball = TarballBuilder::new()
+            .add_file(
+                "foo-0.0.1/Cargo.toml",
+                b"[package]\nname = \"foo\"\nversion = \"0.0.1\"\nbuild = \"src/build.rs\"\n",
+            )
+            .add_file("foo-0.0.1/src/build.rs", b"fn main() {}")
+            .build();
+        let tarball_info = assert_ok!(process_tarball("foo-0.0.1", &*tarball, limit));
+        assert!(tarball_info.has_build_script);
+    }
+
     #[test]
     fn process_tarball_test_lowercase_manifest() {
         let tarball = TarballBuilder::new()
@@ -0,0 +1 @@
+drop table crate_downloads_by_client;
@@ -0,0 +1,19 @@
+create table crate_downloads_by_client
+(
+    crate_id      integer not null
+        constraint fk_crate_downloads_by_client_crate_id
+            references crates
+            on delete cascade,
+    date          date    not null default current_date,
+    client        varchar not null,
+    cargo_version varchar not null default '',
+    ci            boolean not null default false,
+    downloads     integer not null default 0,
+    constraint crate_downloads_by_client_pk
+        primary key (crate_id, date, client, cargo_version, ci)
+);
+
+comment on table crate_downloads_by_client is 'Daily per-crate download counts, broken down by the client that requested the download.';
+comment on column crate_downloads_by_client.client is 'The kind of client that requested the download: `cargo`, `browser` or `other`.';
+comment on column crate_downloads_by_client.cargo_version is 'The `major.minor` version of cargo, or an empty string for non-cargo clients and unparsable versions.';
+comment on column crate_downloads_by_client.ci is 'Whether the user agent identified the download as originating from a CI system.';
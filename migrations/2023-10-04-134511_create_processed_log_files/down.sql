@@ -0,0 +1 @@
+drop table processed_log_files;
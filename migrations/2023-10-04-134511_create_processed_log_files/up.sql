@@ -0,0 +1,12 @@
+create table processed_log_files
+(
+    path         varchar   not null
+        constraint processed_log_files_pk
+            primary key,
+    processed_at timestamp not null default now(),
+    downloads    integer   not null
+);
+
+comment on table processed_log_files is 'CDN access log files that have already been counted by the `process_cdn_logs` background job.';
+comment on column processed_log_files.path is 'Path of the log file in the storage bucket.';
+comment on column processed_log_files.downloads is 'Number of downloads that were counted from this log file.';
@@ -0,0 +1,15 @@
+create table version_downloads_monthly
+(
+    version_id integer not null
+        constraint fk_version_downloads_monthly_version_id
+            references versions
+            on delete cascade,
+    month      date    not null,
+    downloads  integer not null,
+    constraint version_downloads_monthly_pk
+        primary key (version_id, month)
+);
+
+comment on table version_downloads_monthly is 'Monthly download counts of the `version_downloads` rows that were archived by the `archive_version_downloads` background job.';
+comment on column version_downloads_monthly.month is 'First day of the month.';
+comment on column version_downloads_monthly.downloads is 'Sum of the archived daily downloads of the version in this month.';
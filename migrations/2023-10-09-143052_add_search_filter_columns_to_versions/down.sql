@@ -0,0 +1,5 @@
+alter table versions
+    drop column has_build_script,
+    drop column has_proc_macro,
+    drop column license_ids,
+    drop column license_alternatives;
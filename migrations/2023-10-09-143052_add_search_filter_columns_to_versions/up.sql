@@ -0,0 +1,10 @@
+alter table versions
+    add column has_build_script     boolean,
+    add column has_proc_macro       boolean,
+    add column license_ids          text[],
+    add column license_alternatives text[];
+
+comment on column versions.has_build_script is 'Whether the version has a build script. `NULL` for versions that were published before this was recorded.';
+comment on column versions.has_proc_macro is 'Whether the library target of the version is a procedural macro. `NULL` for versions that were published before this was recorded.';
+comment on column versions.license_ids is 'The SPDX licenses mentioned in the `license` expression. `NULL` for versions without a `license`, or whose licenses were not parsed yet.';
+comment on column versions.license_alternatives is 'The sets of SPDX licenses that each satisfy the `license` expression on their own, as space separated license names. `NULL` for versions without a `license`, or whose licenses were not parsed yet.';
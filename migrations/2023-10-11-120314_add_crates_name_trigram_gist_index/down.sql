@@ -0,0 +1 @@
+drop index index_crates_name_trgm_gist;
@@ -0,0 +1,4 @@
+-- The existing GIN index on the crate names only supports filtering by trigram similarity. This
+-- index additionally supports ordering by trigram distance (`<->`), which is used for the
+-- "did you mean" suggestions of the search.
+create index index_crates_name_trgm_gist on crates using gist (canon_crate_name(name) gist_trgm_ops);
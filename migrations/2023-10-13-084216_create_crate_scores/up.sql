@@ -0,0 +1,12 @@
+create table crate_scores
+(
+    crate_id   integer          not null
+        constraint crate_scores_pk primary key
+        constraint crate_scores_crates_id_fk references crates on delete cascade,
+    score      double precision not null,
+    updated_at timestamp        not null default now()
+);
+
+comment on table crate_scores is 'Popularity scores of the crates, recomputed periodically by the `update_crate_scores` background job.';
+comment on column crate_scores.score is 'Combination of the recent downloads, reverse dependencies, owners and time since the last release of the crate. Higher is more popular.';
+comment on column crate_scores.updated_at is 'When the score was last recomputed.';
@@ -0,0 +1,12 @@
+create table trending_crates
+(
+    crate_id   integer          not null
+        constraint trending_crates_pk primary key
+        constraint trending_crates_crates_id_fk references crates on delete cascade,
+    growth     double precision not null,
+    updated_at timestamp        not null default now()
+);
+
+comment on table trending_crates is 'Crates with the highest week-over-week growth of their downloads, recomputed periodically by the `update_trending_crates` background job.';
+comment on column trending_crates.growth is 'Growth of the downloads in the last week compared to the week before, relative to the downloads of the week before. Higher is trending more.';
+comment on column trending_crates.updated_at is 'When the growth was last recomputed.';
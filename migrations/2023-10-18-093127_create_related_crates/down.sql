@@ -0,0 +1 @@
+drop table related_crates;
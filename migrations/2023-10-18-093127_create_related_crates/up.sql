@@ -0,0 +1,18 @@
+create table related_crates
+(
+    crate_id         integer          not null
+        constraint related_crates_crates_id_fk references crates on delete cascade,
+    related_crate_id integer          not null
+        constraint related_crates_related_crate_id_fk references crates on delete cascade,
+    kind             smallint         not null,
+    score            double precision not null,
+    updated_at       timestamp        not null default now(),
+    constraint related_crates_pk primary key (crate_id, kind, related_crate_id)
+);
+
+comment on table related_crates is 'Crates that are related to each other, recomputed periodically by the `update_related_crates` background job.';
+comment on column related_crates.crate_id is 'The crate for which the related crate is recommended.';
+comment on column related_crates.related_crate_id is 'The recommended crate.';
+comment on column related_crates.kind is '0 for crates that are frequently depended on together with the crate, 1 for crates that are commonly used as alternatives to it.';
+comment on column related_crates.score is 'How strongly the crates are related. Only comparable between rows of the same kind.';
+comment on column related_crates.updated_at is 'When the relation was last recomputed.';
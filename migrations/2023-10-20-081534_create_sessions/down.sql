@@ -0,0 +1,3 @@
+alter table users drop column sessions_revoked_at;
+
+drop table sessions;
@@ -0,0 +1,29 @@
+create table sessions
+(
+    id           serial
+        constraint sessions_pk primary key,
+    user_id      integer   not null
+        constraint sessions_users_id_fk references users on delete cascade,
+    token        bytea     not null
+        constraint sessions_token_uindex unique,
+    created_at   timestamp not null default now(),
+    last_seen_at timestamp not null default now(),
+    ip_address   inet,
+    user_agent   varchar   not null default ''
+);
+
+create index sessions_user_id_index on sessions (user_id);
+
+comment on table sessions is 'Login sessions of the users. The session cookie contains the plaintext token of the session, and deleting the row logs the session out.';
+comment on column sessions.id is 'Identifier of the session, used for listing and revoking sessions. Can not be used to authenticate.';
+comment on column sessions.user_id is 'The user that is logged in with this session.';
+comment on column sessions.token is 'SHA256 hash of the token in the session cookie.';
+comment on column sessions.created_at is 'When the user logged in. Sessions expire 90 days after this.';
+comment on column sessions.last_seen_at is 'When the session was last used to authenticate a request. Only updated every few minutes.';
+comment on column sessions.ip_address is 'The IP address of the client that logged in, if known.';
+comment on column sessions.user_agent is 'The user agent of the client that logged in.';
+
+alter table users
+    add column sessions_revoked_at timestamp;
+
+comment on column users.sessions_revoked_at is 'When the user logged out of all sessions for the last time, or NULL if they never did. Legacy `user_id` cookies are no longer accepted once this is set.';
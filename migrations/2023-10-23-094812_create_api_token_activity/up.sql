@@ -0,0 +1,23 @@
+create table api_token_activity
+(
+    id           bigserial
+        constraint api_token_activity_pk primary key,
+    api_token_id integer   not null
+        constraint api_token_activity_api_tokens_id_fk references api_tokens on delete cascade,
+    created_at   timestamp not null,
+    endpoint     varchar   not null,
+    crate_name   varchar,
+    ip_address   inet,
+    user_agent   varchar   not null default ''
+);
+
+create index api_token_activity_api_token_id_created_at_index on api_token_activity (api_token_id, created_at desc);
+
+comment on table api_token_activity is 'The most recent uses of each API token. Older entries are removed when new ones are added, so that the log stays bounded.';
+comment on column api_token_activity.id is 'Unique identifier of the log entry.';
+comment on column api_token_activity.api_token_id is 'The API token that was used to authenticate the request.';
+comment on column api_token_activity.created_at is 'When the request was authenticated. The entries are written in batches, so this can be slightly older than the row itself.';
+comment on column api_token_activity.endpoint is 'HTTP method and route of the request, e.g. `PUT /api/v1/crates/new`.';
+comment on column api_token_activity.crate_name is 'Name of the crate the request was authorized for, if any.';
+comment on column api_token_activity.ip_address is 'The IP address of the client, if known.';
+comment on column api_token_activity.user_agent is 'The user agent of the client.';
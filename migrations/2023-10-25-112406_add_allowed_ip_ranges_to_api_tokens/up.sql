@@ -0,0 +1,4 @@
+alter table api_tokens
+    add column allowed_ip_ranges cidr[];
+
+comment on column api_tokens.allowed_ip_ranges is 'NULL or an array of IP address ranges the token can be used from.';
@@ -0,0 +1,3 @@
+drop table notification_opt_outs;
+drop table api_token_ip_addresses;
+alter table api_tokens drop column expiry_notification_at;
@@ -0,0 +1,32 @@
+alter table api_tokens
+    add column expiry_notification_at timestamp;
+
+comment on column api_tokens.expiry_notification_at is 'When the last reminder about the upcoming expiry of the token was sent, or NULL if no reminder was sent yet.';
+
+create table api_token_ip_addresses
+(
+    api_token_id  integer   not null
+        constraint api_token_ip_addresses_api_tokens_id_fk references api_tokens on delete cascade,
+    ip_address    inet      not null,
+    first_seen_at timestamp not null default now(),
+    constraint api_token_ip_addresses_pk primary key (api_token_id, ip_address)
+);
+
+comment on table api_token_ip_addresses is 'The IP addresses each API token was used from, so that the owner can be notified when a token is used from a new IP address.';
+comment on column api_token_ip_addresses.api_token_id is 'The API token that was used.';
+comment on column api_token_ip_addresses.ip_address is 'The IP address the token was used from.';
+comment on column api_token_ip_addresses.first_seen_at is 'When the token was used from this IP address for the first time.';
+
+create table notification_opt_outs
+(
+    user_id           integer   not null
+        constraint notification_opt_outs_users_id_fk references users on delete cascade,
+    notification_type integer   not null,
+    created_at        timestamp not null default now(),
+    constraint notification_opt_outs_pk primary key (user_id, notification_type)
+);
+
+comment on table notification_opt_outs is 'The types of notification emails that users opted out of. Users receive all notifications that are not listed here.';
+comment on column notification_opt_outs.user_id is 'The user that opted out.';
+comment on column notification_opt_outs.notification_type is 'The type of notification, see the `NotificationType` enum for the possible values.';
+comment on column notification_opt_outs.created_at is 'When the user opted out.';
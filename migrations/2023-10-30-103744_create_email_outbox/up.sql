@@ -0,0 +1,29 @@
+create table email_outbox
+(
+    id         bigserial primary key,
+    created_at timestamp not null default now(),
+    recipient  varchar   not null,
+    subject    varchar   not null,
+    body_text  text      not null,
+    body_html  text      not null,
+    status     integer   not null default 0,
+    attempts   integer   not null default 0,
+    last_error text,
+    message_id varchar,
+    sent_at    timestamp
+);
+
+comment on table email_outbox is 'Emails that were queued for delivery by the `send_email` background job, kept for debugging delivery problems.';
+comment on column email_outbox.id is 'Unique identifier of the email, referenced by the background job.';
+comment on column email_outbox.created_at is 'When the email was queued.';
+comment on column email_outbox.recipient is 'The email address the email is sent to.';
+comment on column email_outbox.subject is 'The subject of the email.';
+comment on column email_outbox.body_text is 'The rendered plaintext part of the email, or an empty string once the email was sent or failed permanently.';
+comment on column email_outbox.body_html is 'The rendered HTML part of the email, or an empty string once the email was sent or failed permanently.';
+comment on column email_outbox.status is 'Whether the email is pending (0), was sent (1) or failed permanently (2).';
+comment on column email_outbox.attempts is 'How often the delivery of the email was attempted.';
+comment on column email_outbox.last_error is 'The error of the last failed delivery attempt, or NULL if no attempt failed yet.';
+comment on column email_outbox.message_id is 'The `Message-ID` header of the sent email, which our support staff needs to find misdelivered emails.';
+comment on column email_outbox.sent_at is 'When the email was sent, or NULL if it was not sent yet.';
+
+create index email_outbox_recipient_index on email_outbox (recipient);
@@ -0,0 +1 @@
+alter table email_outbox drop column unsubscribe_token;
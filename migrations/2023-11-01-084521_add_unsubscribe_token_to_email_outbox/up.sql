@@ -0,0 +1,4 @@
+alter table email_outbox
+    add column unsubscribe_token varchar;
+
+comment on column email_outbox.unsubscribe_token is 'The signed token of the one-click unsubscribe link in the email, used for the `List-Unsubscribe` header, or NULL if the email has no unsubscribe link.';
@@ -0,0 +1,11 @@
+drop index emails_user_id_email_index;
+drop index emails_user_id_primary_index;
+drop index emails_user_id_index;
+
+delete from emails where not is_primary;
+
+alter table emails
+    add constraint emails_user_id_key unique (user_id);
+
+alter table emails
+    drop column is_primary;
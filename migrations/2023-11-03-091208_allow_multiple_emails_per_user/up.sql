@@ -0,0 +1,14 @@
+alter table emails
+    add column is_primary boolean not null default false;
+
+comment on column emails.is_primary is 'Whether notification emails are sent to this address. Every user with email addresses has exactly one primary address.';
+
+-- Until now users could only have a single email address, which becomes their primary address.
+update emails set is_primary = true;
+
+alter table emails
+    drop constraint emails_user_id_key;
+
+create index emails_user_id_index on emails (user_id);
+create unique index emails_user_id_primary_index on emails (user_id) where is_primary;
+create unique index emails_user_id_email_index on emails (user_id, lower(email));
@@ -0,0 +1,27 @@
+create table notification_opt_ins
+(
+    user_id           integer   not null
+        constraint notification_opt_ins_users_id_fk references users on delete cascade,
+    notification_type integer   not null,
+    created_at        timestamp not null default now(),
+    constraint notification_opt_ins_pk primary key (user_id, notification_type)
+);
+
+comment on table notification_opt_ins is 'The types of notification emails that users opted in to. Only applies to notification types that are disabled by default, like the weekly digest.';
+comment on column notification_opt_ins.user_id is 'The user that opted in.';
+comment on column notification_opt_ins.notification_type is 'The type of notification, see the `NotificationType` enum for the possible values.';
+comment on column notification_opt_ins.created_at is 'When the user opted in.';
+
+create table weekly_digests
+(
+    user_id    integer   not null
+        constraint weekly_digests_users_id_fk references users on delete cascade,
+    week       date      not null,
+    created_at timestamp not null default now(),
+    constraint weekly_digests_pk primary key (user_id, week)
+);
+
+comment on table weekly_digests is 'The weekly digests that were sent to users, so that every user receives at most one digest per week, even if the job sending them runs more than once.';
+comment on column weekly_digests.user_id is 'The user that the digest was sent to.';
+comment on column weekly_digests.week is 'The Monday of the week the digest was sent in. The digest covers the seven days before.';
+comment on column weekly_digests.created_at is 'When the digest was queued for delivery.';
@@ -13,6 +13,9 @@ use secrecy::{ExposeSecret, SecretString};
 )]
 pub enum Command {
     UpdateDownloads,
+    UpdateCrateScores,
+    UpdateRelatedCrates,
+    UpdateTrendingCrates,
     DumpDb {
         #[arg(env = "READ_ONLY_REPLICA_URL")]
         database_url: SecretString,
@@ -20,6 +23,17 @@ pub enum Command {
         target_name: String,
     },
     DailyDbMaintenance,
+    DeleteExpiredSessions,
+    ArchiveVersionDownloads {
+        /// Only months that are completely older than this number of days are archived.
+        #[arg(long = "horizon-days", default_value_t = 365)]
+        horizon_days: u32,
+    },
+    BackfillVersionLicenses,
+    BackfillVersionTarballInfo,
+    ProcessCdnLogs,
+    SendTokenExpiryNotifications,
+    SendWeeklyDigests,
     SquashIndex,
     NormalizeIndex {
         #[arg(long = "dry-run")]
@@ -46,11 +60,27 @@ pub fn run(command: Command) -> Result<()> {
                 Ok(Job::update_downloads().enqueue(conn)?)
             }
         }
+        Command::UpdateCrateScores => Ok(Job::update_crate_scores().enqueue(conn)?),
+        Command::UpdateRelatedCrates => Ok(Job::update_related_crates().enqueue(conn)?),
+        Command::UpdateTrendingCrates => Ok(Job::update_trending_crates().enqueue(conn)?),
         Command::DumpDb {
             database_url,
             target_name,
         } => Ok(Job::dump_db(database_url.expose_secret().to_string(), target_name).enqueue(conn)?),
         Command::DailyDbMaintenance => Ok(Job::daily_db_maintenance().enqueue(conn)?),
+        Command::DeleteExpiredSessions => Ok(Job::delete_expired_sessions().enqueue(conn)?),
+        Command::ArchiveVersionDownloads { horizon_days } => {
+            Ok(Job::archive_version_downloads(horizon_days).enqueue(conn)?)
+        }
+        Command::BackfillVersionLicenses => Ok(Job::backfill_version_licenses().enqueue(conn)?),
+        Command::BackfillVersionTarballInfo => {
+            Ok(Job::backfill_version_tarball_info(0).enqueue(conn)?)
+        }
+        Command::ProcessCdnLogs => Ok(Job::process_cdn_logs().enqueue(conn)?),
+        Command::SendTokenExpiryNotifications => {
+            Ok(Job::send_token_expiry_notifications().enqueue(conn)?)
+        }
+        Command::SendWeeklyDigests => Ok(Job::send_weekly_digests().enqueue(conn)?),
         Command::SquashIndex => Ok(Job::squash_index().enqueue(conn)?),
         Command::NormalizeIndex { dry_run } => Ok(Job::normalize_index(dry_run).enqueue(conn)?),
     }
@@ -1,6 +1,7 @@
 //! Application-wide components in a struct accessible from each request
 
 use crate::config;
+use crate::controllers::krate::suggest::Suggestions;
 use crate::db::{ConnectionConfig, DieselPool, DieselPooledConn, PoolError};
 use std::ops::Deref;
 use std::sync::atomic::AtomicUsize;
@@ -12,6 +13,7 @@ use crate::github::{GitHubClient, RealGitHubClient};
 use crate::metrics::{InstanceMetrics, ServiceMetrics};
 use crate::rate_limiter::RateLimiter;
 use crate::storage::Storage;
+use crate::token_activity::TokenActivityLog;
 use axum::extract::{FromRef, FromRequestParts, State};
 use diesel::r2d2;
 use moka::future::{Cache, CacheBuilder};
@@ -35,7 +37,7 @@ pub struct App {
     pub github_oauth: BasicClient,
 
     /// The server configuration
-    pub config: config::Server,
+    pub config: Arc<config::Server>,
 
     /// Cache the `version_id` of a `canonical_crate_name:semver` pair
     ///
@@ -43,9 +45,18 @@ pub struct App {
     /// `version_id` is only cached under the canonical spelling of the crate name.
     pub(crate) version_id_cacher: Cache<(String, String), i32>,
 
+    /// Cache the search suggestions for a normalized prefix
+    ///
+    /// The suggestions are requested on every keystroke in the search field, so they are only
+    /// loaded from the database once per prefix and refreshed after the configured time to live.
+    pub(crate) suggestions_cacher: Cache<String, Suggestions>,
+
     /// Count downloads and periodically persist them in the database
     pub downloads_counter: DownloadsCounter,
 
+    /// Record API token uses and periodically persist them in the database
+    pub token_activity: TokenActivityLog,
+
     /// Backend used to send emails
     pub emails: Emails,
 
@@ -163,6 +174,10 @@ impl App {
             .time_to_live(config.version_id_cache_ttl)
             .build();
 
+        let suggestions_cacher = CacheBuilder::new(config.suggestions_cache_size)
+            .time_to_live(config.suggestions_cache_ttl)
+            .build();
+
         let fastboot_client = match config.use_fastboot.as_deref() {
             Some("staging-experimental") => Some(reqwest::Client::new()),
             _ => None,
@@ -174,7 +189,10 @@ impl App {
             github,
             github_oauth,
             version_id_cacher,
-            downloads_counter: DownloadsCounter::new(),
+            suggestions_cacher,
+            downloads_counter: DownloadsCounter::new()
+                .with_snapshot_path(config.downloads_snapshot_path.clone()),
+            token_activity: TokenActivityLog::new(),
             emails: Emails::from_environment(&config),
             storage: Arc::new(Storage::from_config(&config.storage)),
             service_metrics: ServiceMetrics::new().expect("could not initialize service metrics"),
@@ -183,7 +201,7 @@ impl App {
             fastboot_client,
             balance_capacity: Default::default(),
             rate_limiter: RateLimiter::new(config.rate_limiter.clone()),
-            config,
+            config: Arc::new(config),
         }
     }
 
@@ -1,19 +1,26 @@
+use crate::app::AppState;
 use crate::controllers;
-use crate::controllers::util::RequestPartsExt;
+use crate::controllers::util::{real_ip, RequestPartsExt};
 use crate::middleware::log_request::RequestLogExt;
 use crate::middleware::session::RequestSession;
-use crate::models::token::{CrateScope, EndpointScope};
-use crate::models::{ApiToken, User};
+use crate::models::token::{CrateScope, EndpointScope, NewApiTokenActivity};
+use crate::models::{ApiToken, Session, User};
 use crate::util::errors::{
-    account_locked, forbidden, internal, AppError, AppResult, InsecurelyGeneratedTokenRevoked,
+    account_locked, forbidden, internal, ip_address_not_allowed, AppError, AppResult,
+    InsecurelyGeneratedTokenRevoked,
 };
+use axum::extract::MatchedPath;
 use chrono::Utc;
-use diesel::PgConnection;
+use diesel::{Connection, PgConnection};
 use http::header;
+use ipnetwork::IpNetwork;
+use secrecy::ExposeSecret;
+use std::net::IpAddr;
 
 #[derive(Debug, Clone)]
 pub struct AuthCheck {
     allow_token: bool,
+    allow_legacy_token: bool,
     endpoint_scope: Option<EndpointScope>,
     crate_name: Option<String>,
 }
@@ -25,6 +32,7 @@ impl AuthCheck {
     pub fn default() -> Self {
         Self {
             allow_token: true,
+            allow_legacy_token: true,
             endpoint_scope: None,
             crate_name: None,
         }
@@ -34,6 +42,7 @@ impl AuthCheck {
     pub fn only_cookie() -> Self {
         Self {
             allow_token: false,
+            allow_legacy_token: false,
             endpoint_scope: None,
             crate_name: None,
         }
@@ -42,6 +51,21 @@ impl AuthCheck {
     pub fn with_endpoint_scope(&self, endpoint_scope: EndpointScope) -> Self {
         Self {
             allow_token: self.allow_token,
+            allow_legacy_token: self.allow_legacy_token,
+            endpoint_scope: Some(endpoint_scope),
+            crate_name: self.crate_name.clone(),
+        }
+    }
+
+    /// Allows API tokens with the given endpoint scope in addition to cookies.
+    ///
+    /// Unlike `AuthCheck::default().with_endpoint_scope()` this does not allow legacy tokens
+    /// without endpoint scopes, for endpoints that were only available via cookie before.
+    #[must_use]
+    pub fn or_scoped_token(&self, endpoint_scope: EndpointScope) -> Self {
+        Self {
+            allow_token: true,
+            allow_legacy_token: false,
             endpoint_scope: Some(endpoint_scope),
             crate_name: self.crate_name.clone(),
         }
@@ -50,6 +74,7 @@ impl AuthCheck {
     pub fn for_crate(&self, crate_name: &str) -> Self {
         Self {
             allow_token: self.allow_token,
+            allow_legacy_token: self.allow_legacy_token,
             endpoint_scope: self.endpoint_scope,
             crate_name: Some(crate_name.to_string()),
         }
@@ -70,6 +95,11 @@ impl AuthCheck {
                 return Err(internal(error_message).chain(forbidden()));
             }
 
+            if token.endpoint_scopes.is_none() && !self.allow_legacy_token {
+                let error_message = "Legacy API tokens were explicitly disallowed for this API";
+                return Err(internal(error_message).chain(forbidden()));
+            }
+
             if !self.endpoint_scope_matches(token.endpoint_scopes.as_ref()) {
                 let error_message = "Endpoint scope mismatch";
                 return Err(internal(error_message).chain(forbidden()));
@@ -79,11 +109,46 @@ impl AuthCheck {
                 let error_message = "Crate scope mismatch";
                 return Err(internal(error_message).chain(forbidden()));
             }
+
+            let ip = real_ip(request);
+            if !ip_address_matches(token.allowed_ip_ranges.as_ref(), ip) {
+                return Err(ip_address_not_allowed(ip));
+            }
+
+            self.record_token_activity(request, token);
         }
 
         Ok(auth)
     }
 
+    /// Adds the request to the activity log of the API token. The log is persisted in batches, see
+    /// `crate::token_activity` for details.
+    fn record_token_activity<T: RequestPartsExt>(&self, request: &T, token: &ApiToken) {
+        let Some(app) = request.extensions().get::<AppState>() else {
+            return;
+        };
+
+        let path = match request.extensions().get::<MatchedPath>() {
+            Some(matched_path) => matched_path.as_str(),
+            None => request.uri().path(),
+        };
+
+        let user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        app.token_activity.record(NewApiTokenActivity {
+            api_token_id: token.id,
+            created_at: Utc::now().naive_utc(),
+            endpoint: format!("{} {path}", request.method()),
+            crate_name: self.crate_name.clone(),
+            ip_address: real_ip(request).map(IpNetwork::from),
+            user_agent: user_agent.to_string(),
+        });
+    }
+
     fn endpoint_scope_matches(&self, token_scopes: Option<&Vec<EndpointScope>>) -> bool {
         match (&token_scopes, &self.endpoint_scope) {
             // The token is a legacy token.
@@ -116,6 +181,20 @@ impl AuthCheck {
     }
 }
 
+fn ip_address_matches(allowed_ip_ranges: Option<&Vec<IpNetwork>>, ip: Option<IpAddr>) -> bool {
+    match (allowed_ip_ranges, ip) {
+        // The token can be used from everywhere.
+        (None, _) => true,
+
+        // The token is restricted, but the IP address of the request is unknown.
+        (Some(_), None) => false,
+
+        (Some(allowed_ip_ranges), Some(ip)) => {
+            allowed_ip_ranges.iter().any(|range| range.contains(ip))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Authentication {
     Cookie(CookieAuthentication),
@@ -125,6 +204,8 @@ pub enum Authentication {
 #[derive(Debug)]
 pub struct CookieAuthentication {
     user: User,
+    /// `None` for legacy cookies that could not be migrated to a server-side session yet.
+    session: Option<Session>,
 }
 
 #[derive(Debug)]
@@ -142,6 +223,13 @@ impl Authentication {
         self.api_token().map(|token| token.id)
     }
 
+    pub fn session_id(&self) -> Option<i32> {
+        match self {
+            Authentication::Cookie(cookie) => cookie.session.as_ref().map(|session| session.id),
+            _ => None,
+        }
+    }
+
     pub fn api_token(&self) -> Option<&ApiToken> {
         match self {
             Authentication::Token(token) => Some(&token.token),
@@ -161,6 +249,47 @@ impl Authentication {
 fn authenticate_via_cookie<T: RequestPartsExt>(
     req: &T,
     conn: &mut PgConnection,
+) -> AppResult<Option<CookieAuthentication>> {
+    let Some(token) = req.session().get("session_id") else {
+        return authenticate_via_legacy_cookie(req, conn);
+    };
+
+    let session = match Session::find_by_token(conn, &token) {
+        Ok(session) => session,
+        Err(diesel::result::Error::NotFound) => {
+            // The session expired or was revoked, so the cookie can be removed
+            req.session().remove("session_id");
+            let error_message = "session from cookie not found in database";
+            return Err(internal(error_message).chain(forbidden()));
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let user = User::find(conn, session.user_id)
+        .map_err(|err| err.chain(internal("user_id from session not found in database")))?;
+
+    ensure_not_locked(&user)?;
+
+    req.request_log().add("uid", user.id);
+    req.request_log().add("sessionid", session.id);
+
+    let session = Some(session);
+    Ok(Some(CookieAuthentication { user, session }))
+}
+
+/// Authenticates cookies from before sessions were stored server-side, which only contain the
+/// `user_id`. The cookie is migrated to a new server-side session, so that existing logins keep
+/// working and can be revoked like any other session.
+///
+/// Legacy cookies are rejected once the user logged out of all sessions, since they can't be
+/// revoked individually.
+///
+/// The session is created on the primary database, since `conn` might be a connection to the
+/// read-only replica. If the primary database is unavailable or in read only mode, the request is
+/// authenticated without migrating the cookie, and the migration is retried on the next request.
+fn authenticate_via_legacy_cookie<T: RequestPartsExt>(
+    req: &T,
+    conn: &mut PgConnection,
 ) -> AppResult<Option<CookieAuthentication>> {
     let user_id_from_session = req
         .session()
@@ -174,11 +303,60 @@ fn authenticate_via_cookie<T: RequestPartsExt>(
     let user = User::find(conn, id)
         .map_err(|err| err.chain(internal("user_id from cookie not found in database")))?;
 
+    if user.sessions_revoked_at.is_some() {
+        req.session().remove("user_id");
+        let error_message = "legacy cookie was revoked by logging out of all sessions";
+        return Err(internal(error_message).chain(forbidden()));
+    }
+
     ensure_not_locked(&user)?;
 
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let insert_session = |conn: &mut PgConnection| match conn
+        .transaction(|conn| Session::insert(conn, id, real_ip(req), user_agent))
+    {
+        Ok(created) => Ok(Some(created)),
+        Err(error) if is_read_only_error(&error) => Ok(None),
+        Err(error) => Err(error),
+    };
+
+    // Without a read-only replica, `conn` is already a connection to the primary database.
+    let app_with_replica = req
+        .extensions()
+        .get::<AppState>()
+        .filter(|app| app.read_only_replica_database.is_some());
+
+    let created = if let Some(app) = app_with_replica {
+        match app.db_write() {
+            Ok(mut conn) => insert_session(&mut conn)?,
+            Err(error) => {
+                warn!("Failed to migrate legacy cookie of user {id}: {error}");
+                None
+            }
+        }
+    } else {
+        insert_session(conn)?
+    };
+
     req.request_log().add("uid", id);
 
-    Ok(Some(CookieAuthentication { user }))
+    let session = created.map(|created| {
+        req.session().remove("user_id");
+        req.session().insert(
+            "session_id".to_string(),
+            created.plaintext.expose_secret().to_string(),
+        );
+
+        req.request_log().add("sessionid", created.model.id);
+        created.model
+    });
+
+    Ok(Some(CookieAuthentication { user, session }))
 }
 
 #[instrument(skip_all)]
@@ -234,6 +412,14 @@ fn authenticate<T: RequestPartsExt>(req: &T, conn: &mut PgConnection) -> AppResu
     return Err(internal("no cookie session or auth header found").chain(forbidden()));
 }
 
+fn is_read_only_error(error: &diesel::result::Error) -> bool {
+    matches!(
+        error,
+        diesel::result::Error::DatabaseError(_, info)
+            if info.message().ends_with("read-only transaction")
+    )
+}
+
 fn ensure_not_locked(user: &User) -> AppResult<()> {
     if let Some(reason) = &user.account_lock_reason {
         let still_locked = if let Some(until) = user.account_lock_until {
@@ -347,4 +533,38 @@ mod tests {
         assert!(!auth_check.crate_scope_matches(Some(&vec![cs("anyhow")])));
         assert!(!auth_check.crate_scope_matches(Some(&vec![cs("actix-*")])));
     }
+
+    #[test]
+    fn account_endpoint() {
+        let auth_check = AuthCheck::only_cookie().or_scoped_token(EndpointScope::ReadAccount);
+
+        assert!(auth_check.allow_token);
+        assert!(!auth_check.allow_legacy_token);
+
+        assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadAccount])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ManageTokens])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishNew])));
+
+        assert!(auth_check.crate_scope_matches(Some(&vec![])));
+        assert!(!auth_check.crate_scope_matches(Some(&vec![cs("tokio-*")])));
+    }
+
+    #[test]
+    fn allowed_ip_ranges() {
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+        let ranges = vec![
+            "192.0.2.0/24".parse::<IpNetwork>().unwrap(),
+            "2001:db8::/32".parse::<IpNetwork>().unwrap(),
+        ];
+
+        assert!(ip_address_matches(None, ip("198.51.100.1")));
+        assert!(ip_address_matches(None, None));
+
+        assert!(ip_address_matches(Some(&ranges), ip("192.0.2.1")));
+        assert!(ip_address_matches(Some(&ranges), ip("2001:db8::1")));
+        assert!(!ip_address_matches(Some(&ranges), ip("198.51.100.1")));
+        assert!(!ip_address_matches(Some(&ranges), ip("2001:db9::1")));
+        assert!(!ip_address_matches(Some(&ranges), None));
+        assert!(!ip_address_matches(Some(&vec![]), ip("192.0.2.1")));
+    }
 }
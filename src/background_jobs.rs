@@ -5,10 +5,13 @@ use diesel::sql_types::{Int2, Jsonb, Text};
 use paste::paste;
 use reqwest::blocking::Client;
 use std::fmt::Display;
+use std::net::IpAddr;
 use std::panic::AssertUnwindSafe;
 use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
+use crate::config;
 use crate::db::ConnectionPool;
+use crate::email::Emails;
 use crate::storage::Storage;
 use crate::swirl::errors::EnqueueError;
 use crate::swirl::PerformError;
@@ -77,14 +80,26 @@ macro_rules! job_variant_from_value {
 
 jobs! {
     pub enum Job {
+        ArchiveVersionDownloads(ArchiveVersionDownloadsJob),
+        BackfillVersionLicenses,
+        BackfillVersionTarballInfo(BackfillVersionTarballInfoJob),
         DailyDbMaintenance,
+        DeleteExpiredSessions,
         DumpDb(DumpDbJob),
         NormalizeIndex(NormalizeIndexJob),
+        ProcessCdnLogs,
         RenderAndUploadReadme(RenderAndUploadReadmeJob),
+        SendEmail(SendEmailJob),
+        SendTokenExpiryNotifications,
+        SendVersionActionNotifications(SendVersionActionNotificationsJob),
+        SendWeeklyDigests,
         SquashIndex,
         SyncToGitIndex(SyncToIndexJob),
         SyncToSparseIndex(SyncToIndexJob),
+        UpdateCrateScores,
         UpdateDownloads,
+        UpdateRelatedCrates,
+        UpdateTrendingCrates,
     }
 }
 
@@ -166,10 +181,26 @@ impl Job {
         Ok(())
     }
 
+    pub fn archive_version_downloads(horizon_days: u32) -> Self {
+        Self::ArchiveVersionDownloads(ArchiveVersionDownloadsJob { horizon_days })
+    }
+
+    pub fn backfill_version_licenses() -> Self {
+        Self::BackfillVersionLicenses
+    }
+
+    pub fn backfill_version_tarball_info(after_version_id: i32) -> Self {
+        Self::BackfillVersionTarballInfo(BackfillVersionTarballInfoJob { after_version_id })
+    }
+
     pub fn daily_db_maintenance() -> Self {
         Self::DailyDbMaintenance
     }
 
+    pub fn delete_expired_sessions() -> Self {
+        Self::DeleteExpiredSessions
+    }
+
     pub fn dump_db(database_url: String, target_name: String) -> Self {
         Self::DumpDb(DumpDbJob {
             database_url,
@@ -181,6 +212,10 @@ impl Job {
         Self::NormalizeIndex(NormalizeIndexJob { dry_run })
     }
 
+    pub fn process_cdn_logs() -> Self {
+        Self::ProcessCdnLogs
+    }
+
     pub fn render_and_upload_readme(
         version_id: i32,
         text: String,
@@ -197,6 +232,28 @@ impl Job {
         })
     }
 
+    pub fn send_email(email_id: i64) -> Self {
+        Self::SendEmail(SendEmailJob { email_id })
+    }
+
+    pub fn send_token_expiry_notifications() -> Self {
+        Self::SendTokenExpiryNotifications
+    }
+
+    pub fn send_version_action_notifications(
+        version_owner_action_id: i32,
+        ip_address: Option<IpAddr>,
+    ) -> Self {
+        Self::SendVersionActionNotifications(SendVersionActionNotificationsJob {
+            version_owner_action_id,
+            ip_address,
+        })
+    }
+
+    pub fn send_weekly_digests() -> Self {
+        Self::SendWeeklyDigests
+    }
+
     pub fn squash_index() -> Self {
         Self::SquashIndex
     }
@@ -213,10 +270,22 @@ impl Job {
         })
     }
 
+    pub fn update_crate_scores() -> Self {
+        Self::UpdateCrateScores
+    }
+
     pub fn update_downloads() -> Self {
         Self::UpdateDownloads
     }
 
+    pub fn update_related_crates() -> Self {
+        Self::UpdateRelatedCrates
+    }
+
+    pub fn update_trending_crates() -> Self {
+        Self::UpdateTrendingCrates
+    }
+
     pub fn enqueue(&self, conn: &mut PgConnection) -> Result<(), EnqueueError> {
         self.enqueue_with_priority(conn, PRIORITY_DEFAULT)
     }
@@ -240,6 +309,29 @@ impl Job {
         Ok(())
     }
 
+    /// Enqueues the job as if it had already failed `failed_attempts` times, so that the
+    /// background worker waits with its usual exponential backoff before running it.
+    #[instrument(name = "swirl.enqueue", skip(self, conn), fields(message = self.as_type_str()))]
+    pub fn enqueue_retry(
+        &self,
+        conn: &mut PgConnection,
+        failed_attempts: i32,
+    ) -> Result<(), EnqueueError> {
+        use crate::schema::background_jobs::dsl::*;
+
+        let job_data = self.to_value()?;
+        diesel::insert_into(background_jobs)
+            .values((
+                job_type.eq(self.as_type_str()),
+                data.eq(job_data),
+                priority.eq(PRIORITY_DEFAULT),
+                retries.eq(failed_attempts),
+                last_retry.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
     pub(super) fn perform(
         self,
         env: &Option<Environment>,
@@ -250,12 +342,34 @@ impl Job {
             .as_ref()
             .expect("Application should configure a background runner environment");
         match self {
+            Job::ArchiveVersionDownloads(args) => {
+                worker::perform_archive_version_downloads(conn, env, args.horizon_days)
+            }
+            Job::BackfillVersionLicenses => worker::perform_backfill_version_licenses(conn),
+            Job::BackfillVersionTarballInfo(args) => {
+                worker::perform_backfill_version_tarball_info(conn, env, args.after_version_id)
+            }
             Job::DailyDbMaintenance => {
                 worker::perform_daily_db_maintenance(&mut *fresh_connection(pool)?)
             }
+            Job::DeleteExpiredSessions => worker::perform_delete_expired_sessions(conn),
             Job::DumpDb(args) => worker::perform_dump_db(env, args.database_url, args.target_name),
+            Job::SendEmail(args) => worker::perform_send_email(conn, env, args.email_id),
+            Job::SendTokenExpiryNotifications => {
+                worker::perform_send_token_expiry_notifications(conn, env)
+            }
+            Job::SendVersionActionNotifications(args) => {
+                worker::perform_send_version_action_notifications(
+                    conn,
+                    env,
+                    args.version_owner_action_id,
+                    args.ip_address,
+                )
+            }
+            Job::SendWeeklyDigests => worker::perform_send_weekly_digests(conn, env),
             Job::SquashIndex => worker::perform_index_squash(env),
             Job::NormalizeIndex(args) => worker::perform_normalize_index(env, args),
+            Job::ProcessCdnLogs => worker::perform_process_cdn_logs(conn, env),
             Job::RenderAndUploadReadme(args) => worker::perform_render_and_upload_readme(
                 conn,
                 env,
@@ -267,7 +381,10 @@ impl Job {
             ),
             Job::SyncToGitIndex(args) => worker::sync_to_git_index(env, conn, &args.krate),
             Job::SyncToSparseIndex(args) => worker::sync_to_sparse_index(env, conn, &args.krate),
+            Job::UpdateCrateScores => worker::perform_update_crate_scores(conn),
             Job::UpdateDownloads => worker::perform_update_downloads(&mut *fresh_connection(pool)?),
+            Job::UpdateRelatedCrates => worker::perform_update_related_crates(conn),
+            Job::UpdateTrendingCrates => worker::perform_update_trending_crates(conn),
         }
     }
 }
@@ -287,6 +404,16 @@ fn fresh_connection(
     Ok(pool.get()?)
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveVersionDownloadsJob {
+    pub(super) horizon_days: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BackfillVersionTarballInfoJob {
+    pub(super) after_version_id: i32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct DumpDbJob {
     pub(super) database_url: String,
@@ -319,6 +446,19 @@ pub struct NormalizeIndexJob {
     pub dry_run: bool,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct SendEmailJob {
+    /// The ID of the email in the `email_outbox` table.
+    pub(super) email_id: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SendVersionActionNotificationsJob {
+    pub(super) version_owner_action_id: i32,
+    /// The IP address of the client that performed the action, if known.
+    pub(super) ip_address: Option<IpAddr>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RenderAndUploadReadmeJob {
     pub(super) version_id: i32,
@@ -329,43 +469,53 @@ pub struct RenderAndUploadReadmeJob {
 }
 
 pub struct Environment {
+    config: Arc<config::Server>,
     index: Arc<Mutex<Repository>>,
     http_client: AssertUnwindSafe<Client>,
     cloudfront: Option<CloudFront>,
     fastly: Option<Fastly>,
     pub storage: AssertUnwindSafe<Arc<Storage>>,
+    emails: AssertUnwindSafe<Emails>,
 }
 
 impl Environment {
     pub fn new(
+        config: Arc<config::Server>,
         index: Repository,
         http_client: Client,
         cloudfront: Option<CloudFront>,
         fastly: Option<Fastly>,
         storage: Arc<Storage>,
+        emails: Emails,
     ) -> Self {
         Self::new_shared(
+            config,
             Arc::new(Mutex::new(index)),
             http_client,
             cloudfront,
             fastly,
             storage,
+            emails,
         )
     }
 
     pub fn new_shared(
+        config: Arc<config::Server>,
         index: Arc<Mutex<Repository>>,
         http_client: Client,
         cloudfront: Option<CloudFront>,
         fastly: Option<Fastly>,
         storage: Arc<Storage>,
+        emails: Emails,
     ) -> Self {
         Self {
+            config,
             index,
             http_client: AssertUnwindSafe(http_client),
             cloudfront,
             fastly,
             storage: AssertUnwindSafe(storage),
+            emails: AssertUnwindSafe(emails),
         }
     }
 
@@ -376,6 +526,10 @@ impl Environment {
         Ok(repo)
     }
 
+    pub(crate) fn config(&self) -> &config::Server {
+        &self.config
+    }
+
     /// Returns a client for making HTTP requests to upload crate files.
     pub(crate) fn http_client(&self) -> &Client {
         &self.http_client
@@ -388,4 +542,8 @@ impl Environment {
     pub(crate) fn fastly(&self) -> Option<&Fastly> {
         self.fastly.as_ref()
     }
+
+    pub(crate) fn emails(&self) -> &Emails {
+        &self.emails
+    }
 }
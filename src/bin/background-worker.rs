@@ -16,6 +16,7 @@
 extern crate tracing;
 
 use crates_io::config;
+use crates_io::email::Emails;
 use crates_io::storage::Storage;
 use crates_io::worker::cloudfront::CloudFront;
 use crates_io::{background_jobs::*, db, ssh};
@@ -82,7 +83,17 @@ fn main() {
         .build()
         .expect("Couldn't build client");
 
-    let environment = Environment::new_shared(repository, client, cloudfront, fastly, storage);
+    let emails = Emails::from_environment(&config);
+
+    let environment = Environment::new_shared(
+        Arc::new(config),
+        repository,
+        client,
+        cloudfront,
+        fastly,
+        storage,
+        emails,
+    );
 
     let environment = Arc::new(Some(environment));
 
@@ -30,9 +30,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let client = Client::new();
     let app = Arc::new(App::new(config, Some(client)));
 
+    // Restore the download counts that were not persisted before the previous process exited.
+    // This has to happen before any traffic is served, to avoid overwriting the snapshot.
+    match app.downloads_counter.replay_snapshot() {
+        Ok(replayed) => info!(replayed, "Replayed downloads_counter snapshot"),
+        Err(err) => error!(?err, "downloads_counter snapshot replay error"),
+    }
+
     // Start the background thread periodically persisting download counts to the database.
     downloads_counter_thread(app.clone());
 
+    // Start the background thread periodically persisting the API token activity log.
+    token_activity_thread(app.clone());
+
     // Start the background thread periodically logging instance metrics.
     log_instance_metrics_thread(app.clone());
 
@@ -100,14 +110,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Block the main thread until the server has shutdown
-    rt.block_on(server)?;
+    let result = rt.block_on(server);
 
+    // The remaining download counts are persisted even if the server exited with an error.
     info!("Persisting remaining downloads counters");
     match app.downloads_counter.persist_all_shards(&app) {
         Ok(stats) => stats.log(),
         Err(err) => error!(?err, "downloads_counter error"),
     }
 
+    info!("Persisting remaining token activity");
+    if let Err(err) = app.token_activity.persist(&app) {
+        error!(?err, "token_activity error");
+    }
+
+    result?;
+
     info!("Server has gracefully shutdown!");
     Ok(())
 }
@@ -127,6 +145,18 @@ fn downloads_counter_thread(app: Arc<App>) {
     });
 }
 
+fn token_activity_thread(app: Arc<App>) {
+    let interval = Duration::from_millis(app.config.downloads_persist_interval_ms as u64);
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+
+        if let Err(err) = app.token_activity.persist(&app) {
+            error!(?err, "token_activity error");
+        }
+    });
+}
+
 fn log_instance_metrics_thread(app: Arc<App>) {
     // Only run the thread if the configuration is provided
     let interval = if let Some(secs) = app.config.instance_metrics_log_every_seconds {
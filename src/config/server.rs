@@ -12,10 +12,14 @@ use crate::storage::StorageConfig;
 use http::HeaderValue;
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 const DEFAULT_VERSION_ID_CACHE_SIZE: u64 = 10_000;
 const DEFAULT_VERSION_ID_CACHE_TTL: u64 = 5 * 60; // 5 minutes
+const DEFAULT_SUGGESTIONS_CACHE_SIZE: u64 = 10_000;
+const DEFAULT_SUGGESTIONS_CACHE_TTL: u64 = 10 * 60; // 10 minutes
+const DEFAULT_TOKEN_ROTATION_GRACE_PERIOD: u64 = 24 * 60 * 60; // 1 day
 
 pub struct Server {
     pub base: Base,
@@ -40,14 +44,21 @@ pub struct Server {
     pub domain_name: String,
     pub allowed_origins: AllowedOrigins,
     pub downloads_persist_interval_ms: usize,
+    pub downloads_snapshot_path: Option<PathBuf>,
     pub ownership_invitations_expiration_days: u64,
     pub metrics_authorization_token: Option<String>,
     pub use_test_database_pool: bool,
     pub instance_metrics_log_every_seconds: Option<u64>,
     pub force_unconditional_redirects: bool,
+    /// Are version downloads counted from the CDN access logs instead of the download endpoint?
+    pub cdn_log_downloads: bool,
     pub blocked_routes: HashSet<String>,
     pub version_id_cache_size: u64,
     pub version_id_cache_ttl: Duration,
+    pub suggestions_cache_size: u64,
+    pub suggestions_cache_ttl: Duration,
+    /// How long the old secret of a rotated API token keeps working.
+    pub token_rotation_grace_period: Duration,
     pub cdn_user_agent: String,
     pub balance_capacity: BalanceCapacityConfig,
 
@@ -77,6 +88,9 @@ impl Default for Server {
     /// - `BLOCKED_TRAFFIC`: A list of headers and environment variables to use for blocking
     ///   traffic. See the `block_traffic` module for more documentation.
     /// - `DOWNLOADS_PERSIST_INTERVAL_MS`: how frequent to persist download counts (in ms).
+    /// - `DOWNLOADS_SNAPSHOT_PATH`: file to periodically write the download counts that are not
+    ///   persisted yet to, so that they can be restored after a crash. If not set, no snapshot is
+    ///   written.
     /// - `METRICS_AUTHORIZATION_TOKEN`: authorization token needed to query metrics. If missing,
     ///   querying metrics will be completely disabled.
     /// - `WEB_MAX_ALLOWED_PAGE_OFFSET`: Page offsets larger than this value are rejected. Defaults
@@ -92,6 +106,9 @@ impl Default for Server {
     ///   If the environment variable is not present instance metrics are not logged.
     /// - `FORCE_UNCONDITIONAL_REDIRECTS`: Whether to force unconditional redirects in the download
     ///   endpoint even with a healthy database pool.
+    /// - `CDN_LOG_DOWNLOADS`: Whether version downloads are counted by the `process_cdn_logs`
+    ///   background job instead of the download endpoint. The job does nothing unless this is
+    ///   set, since every download would be counted twice otherwise.
     /// - `BLOCKED_ROUTES`: A comma separated list of HTTP route patterns that are manually blocked
     ///   by an operator (e.g. `/crates/:crate_id/:version/download`).
     ///
@@ -187,11 +204,13 @@ impl Default for Server {
                         .expect("invalid DOWNLOADS_PERSIST_INTERVAL_MS")
                 })
                 .unwrap_or(60_000), // 1 minute
+            downloads_snapshot_path: env_optional("DOWNLOADS_SNAPSHOT_PATH"),
             ownership_invitations_expiration_days: 30,
             metrics_authorization_token: dotenvy::var("METRICS_AUTHORIZATION_TOKEN").ok(),
             use_test_database_pool: false,
             instance_metrics_log_every_seconds: env_optional("INSTANCE_METRICS_LOG_EVERY_SECONDS"),
             force_unconditional_redirects: dotenvy::var("FORCE_UNCONDITIONAL_REDIRECTS").is_ok(),
+            cdn_log_downloads: dotenvy::var("CDN_LOG_DOWNLOADS").is_ok(),
             blocked_routes: env_optional("BLOCKED_ROUTES")
                 .map(|routes: String| routes.split(',').map(|s| s.into()).collect())
                 .unwrap_or_else(HashSet::new),
@@ -200,6 +219,15 @@ impl Default for Server {
             version_id_cache_ttl: Duration::from_secs(
                 env_optional("VERSION_ID_CACHE_TTL").unwrap_or(DEFAULT_VERSION_ID_CACHE_TTL),
             ),
+            suggestions_cache_size: env_optional("SUGGESTIONS_CACHE_SIZE")
+                .unwrap_or(DEFAULT_SUGGESTIONS_CACHE_SIZE),
+            suggestions_cache_ttl: Duration::from_secs(
+                env_optional("SUGGESTIONS_CACHE_TTL").unwrap_or(DEFAULT_SUGGESTIONS_CACHE_TTL),
+            ),
+            token_rotation_grace_period: Duration::from_secs(
+                env_optional("TOKEN_ROTATION_GRACE_PERIOD")
+                    .unwrap_or(DEFAULT_TOKEN_ROTATION_GRACE_PERIOD),
+            ),
             cdn_user_agent: dotenvy::var("WEB_CDN_USER_AGENT")
                 .unwrap_or_else(|_| "Amazon CloudFront".into()),
             balance_capacity: BalanceCapacityConfig::from_environment(),
@@ -64,6 +64,7 @@ mod prelude {
 pub mod helpers;
 pub mod util;
 
+pub mod audit;
 pub mod category;
 mod conduit_axum;
 pub mod crate_owner_invitation;
@@ -0,0 +1,274 @@
+//! Endpoint for auditing the crates.io packages of a `Cargo.lock` file
+//!
+//! This replaces one `GET /crates/:crate_id` request per package for CI jobs that check their
+//! dependencies for yanked versions and available updates. Like for the batch lookup, the rate
+//! limit of this endpoint counts the number of audited packages instead of the requests.
+
+use crate::auth::AuthCheck;
+use crate::controllers::frontend_prelude::*;
+use crate::models::TopVersions;
+use crate::rate_limiter::LimitedAction;
+use crate::schema::{crates, versions};
+use crate::sql::canon_crate_name;
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+/// The `source` values of packages from the crates.io index in `Cargo.lock` files.
+const CRATES_IO_SOURCES: &[&str] = &[
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// The maximum number of crates.io packages in an audited `Cargo.lock` file.
+const MAX_PACKAGES: usize = 5000;
+
+/// Every started group of this many packages takes one token from the `AuditLockfile` rate limit.
+const PACKAGES_PER_RATE_LIMIT_TOKEN: usize = 100;
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockfilePackage>,
+}
+
+#[derive(Deserialize)]
+struct LockfilePackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PackageAudit {
+    name: String,
+    version: String,
+    status: PackageStatus,
+    license: Option<String>,
+    /// The highest version that is semver compatible with and newer than the locked version.
+    newer_compatible_version: Option<String>,
+    /// The highest version that is newer than the locked version, but not semver compatible.
+    newer_incompatible_version: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PackageStatus {
+    Available,
+    Yanked,
+    /// The crate exists, but the locked version does not (anymore).
+    MissingVersion,
+    /// The crate does not exist (anymore).
+    MissingCrate,
+}
+
+impl PackageAudit {
+    fn new(package: LockfilePackage, status: PackageStatus) -> Self {
+        Self {
+            name: package.name,
+            version: package.version,
+            status,
+            license: None,
+            newer_compatible_version: None,
+            newer_incompatible_version: None,
+        }
+    }
+}
+
+/// Handles the `POST /audit/lockfile` route.
+///
+/// The request body is the content of a `Cargo.lock` file. Packages that are not from the
+/// crates.io index, like path or git dependencies, are skipped.
+pub async fn lockfile(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let lockfile = std::str::from_utf8(req.body())
+            .map_err(|_| bad_request("the lockfile must be valid UTF-8"))?;
+        let lockfile: Lockfile = toml::from_str(lockfile)
+            .map_err(|e| bad_request(&format!("invalid lockfile: {}", e.message())))?;
+
+        let packages = lockfile
+            .package
+            .into_iter()
+            .filter(|package| {
+                let source = package.source.as_deref().unwrap_or_default();
+                CRATES_IO_SOURCES.contains(&source)
+            })
+            .collect::<Vec<_>>();
+
+        if packages.len() > MAX_PACKAGES {
+            return Err(bad_request(&format!(
+                "the lockfile must not contain more than {MAX_PACKAGES} crates.io packages"
+            )));
+        }
+
+        let conn = &mut *app.db_write()?;
+        let auth = AuthCheck::default().check(&req, conn)?;
+
+        let cost =
+            (packages.len() + PACKAGES_PER_RATE_LIMIT_TOKEN - 1) / PACKAGES_PER_RATE_LIMIT_TOKEN;
+        app.rate_limiter.check_rate_limit_with_cost(
+            auth.user_id(),
+            LimitedAction::AuditLockfile,
+            cost.max(1) as u32,
+            conn,
+        )?;
+
+        let canonical_name = |name: &str| name.to_lowercase().replace('-', "_");
+        let names = packages
+            .iter()
+            .map(|package| canonical_name(&package.name))
+            .collect::<Vec<_>>();
+
+        let crate_ids: HashMap<String, i32> = crates::table
+            .filter(canon_crate_name(crates::name).eq_any(&names))
+            .select((crates::name, crates::id))
+            .load::<(String, i32)>(conn)?
+            .into_iter()
+            .map(|(name, id)| (canonical_name(&name), id))
+            .collect();
+        let ids = crate_ids.values().collect::<Vec<_>>();
+
+        // The details are only loaded for the locked versions...
+        let nums = packages
+            .iter()
+            .map(|package| &package.version)
+            .collect::<Vec<_>>();
+        let locked: HashMap<(i32, String), (bool, Option<String>)> = versions::table
+            .filter(versions::crate_id.eq_any(&ids))
+            .filter(versions::num.eq_any(&nums))
+            .select((
+                versions::crate_id,
+                versions::num,
+                versions::yanked,
+                versions::license,
+            ))
+            .load::<(i32, String, bool, Option<String>)>(conn)?
+            .into_iter()
+            .map(|(crate_id, num, yanked, license)| ((crate_id, num), (yanked, license)))
+            .collect();
+
+        // ...while the other versions are only needed to find the newer ones.
+        let mut available: HashMap<i32, Vec<(NaiveDateTime, String)>> = HashMap::new();
+        let rows: Vec<(i32, NaiveDateTime, String)> = versions::table
+            .filter(versions::crate_id.eq_any(&ids))
+            .filter(versions::yanked.eq(false))
+            .select((versions::crate_id, versions::created_at, versions::num))
+            .load(conn)?;
+        for (crate_id, created_at, num) in rows {
+            available
+                .entry(crate_id)
+                .or_default()
+                .push((created_at, num));
+        }
+
+        let audits = packages
+            .into_iter()
+            .map(|package| {
+                let Some(&crate_id) = crate_ids.get(&canonical_name(&package.name)) else {
+                    return PackageAudit::new(package, PackageStatus::MissingCrate);
+                };
+                let key = (crate_id, package.version.clone());
+                let Some((yanked, license)) = locked.get(&key) else {
+                    return PackageAudit::new(package, PackageStatus::MissingVersion);
+                };
+
+                let status = match yanked {
+                    true => PackageStatus::Yanked,
+                    false => PackageStatus::Available,
+                };
+                let mut audit = PackageAudit::new(package, status);
+                audit.license = license.clone();
+
+                if let Ok(locked) = semver::Version::parse(&audit.version) {
+                    let available = available
+                        .get(&crate_id)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default();
+                    let (compatible, incompatible) = newer_versions(&locked, available);
+                    audit.newer_compatible_version = compatible.map(|v| v.to_string());
+                    audit.newer_incompatible_version = incompatible.map(|v| v.to_string());
+                }
+
+                audit
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "packages": audits })))
+    })
+    .await
+}
+
+/// Returns the highest semver compatible and the highest incompatible version of the
+/// `available` date/version pairs that are newer than `locked`, using the same rules as cargo
+/// for the default `^` requirements.
+///
+/// Pre-releases are only considered if the locked version is a pre-release itself.
+fn newer_versions(
+    locked: &semver::Version,
+    available: &[(NaiveDateTime, String)],
+) -> (Option<semver::Version>, Option<semver::Version>) {
+    let requirement = semver::Comparator {
+        op: semver::Op::Caret,
+        major: locked.major,
+        minor: Some(locked.minor),
+        patch: Some(locked.patch),
+        pre: locked.pre.clone(),
+    };
+
+    let (compatible, incompatible): (Vec<_>, Vec<_>) = available
+        .iter()
+        .filter_map(|(date, num)| Some((*date, num, semver::Version::parse(num).ok()?)))
+        .filter(|(_, _, version)| version > locked)
+        .partition(|(_, _, version)| requirement.matches(version));
+
+    let highest = |versions: Vec<(NaiveDateTime, &String, semver::Version)>| {
+        let top = TopVersions::from_date_version_pairs(
+            versions
+                .into_iter()
+                .map(|(date, num, _)| (date, num.clone())),
+        );
+        match locked.pre.is_empty() {
+            true => top.highest_stable,
+            false => top.highest,
+        }
+    };
+
+    (highest(compatible), highest(incompatible))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn newer(locked: &str, available: &[&str]) -> (Option<String>, Option<String>) {
+        let locked = semver::Version::parse(locked).unwrap();
+        let available = available
+            .iter()
+            .map(|v| (NaiveDateTime::default(), v.to_string()))
+            .collect::<Vec<_>>();
+        let (compatible, incompatible) = newer_versions(&locked, &available);
+        (
+            compatible.map(|v| v.to_string()),
+            incompatible.map(|v| v.to_string()),
+        )
+    }
+
+    #[test]
+    fn newer_compatible_and_incompatible_versions() {
+        let available = &["0.9.0", "1.0.0", "1.2.0", "1.3.0-rc.1", "2.0.0", "2.1.0"];
+        assert_eq!(
+            newer("1.0.0", available),
+            (Some("1.2.0".into()), Some("2.1.0".into()))
+        );
+        assert_eq!(newer("2.1.0", available), (None, None));
+        assert_eq!(newer("0.9.0", available), (None, Some("2.1.0".into())));
+
+        let available = &["0.1.0", "0.1.5", "0.2.0"];
+        assert_eq!(
+            newer("0.1.0", available),
+            (Some("0.1.5".into()), Some("0.2.0".into()))
+        );
+
+        let available = &["1.0.0-rc.1", "1.0.0-rc.2", "1.0.0"];
+        assert_eq!(newer("1.0.0-rc.1", available), (Some("1.0.0".into()), None));
+    }
+}
@@ -3,6 +3,7 @@ use super::frontend_prelude::*;
 use crate::auth::AuthCheck;
 use crate::auth::Authentication;
 use crate::controllers::helpers::pagination::{Page, PaginationOptions};
+use crate::models::token::EndpointScope;
 use crate::models::{Crate, CrateOwnerInvitation, Rights, User};
 use crate::schema::{crate_owner_invitations, crates, users};
 use crate::util::errors::{forbidden, internal};
@@ -19,7 +20,9 @@ use std::collections::{HashMap, HashSet};
 pub async fn list(app: AppState, req: Parts) -> AppResult<Json<Value>> {
     conduit_compat(move || {
         let conn = &mut app.db_read()?;
-        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let auth = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ManageInvitations)
+            .check(&req, conn)?;
         let user_id = auth.user_id();
 
         let PrivateListResponse {
@@ -267,7 +270,9 @@ pub async fn handle_invite(state: AppState, req: BytesRequest) -> AppResult<Json
 
         let conn = &mut state.db_write()?;
 
-        let auth = AuthCheck::default().check(&req, conn)?;
+        let auth = AuthCheck::default()
+            .with_endpoint_scope(EndpointScope::ManageInvitations)
+            .check(&req, conn)?;
         let user_id = auth.user_id();
 
         let config = &state.config;
@@ -1,6 +1,6 @@
 use crate::app::AppState;
 use crate::controllers::frontend_prelude::*;
-use crate::models::{ApiToken, User};
+use crate::models::{ApiToken, NotificationType, User};
 use crate::schema::api_tokens;
 use crate::util::token::HashedToken;
 use anyhow::{anyhow, Context};
@@ -207,13 +207,21 @@ fn send_notification_email(
     conn: &mut PgConnection,
 ) -> anyhow::Result<()> {
     let user = User::find(conn, token.user_id).context("Failed to find user")?;
-    let Some(email) = user.email(conn)? else {
-        return Err(anyhow!("No address found"));
+    let Some(email) = NotificationType::TokenExposed.recipient(conn, &user)? else {
+        return Ok(());
     };
 
     state
         .emails
-        .send_token_exposed_notification(&email, &alert.url, "GitHub", &alert.source, &token.name)
+        .send_token_exposed_notification(
+            conn,
+            user.id,
+            &email,
+            &alert.url,
+            "GitHub",
+            &alert.source,
+            &token.name,
+        )
         .map_err(|error| anyhow!("{error}"))?;
 
     Ok(())
@@ -1,6 +1,9 @@
+pub mod batch;
 pub mod downloads;
 pub mod follow;
 pub mod metadata;
 pub mod owners;
 pub mod publish;
+pub mod related;
 pub mod search;
+pub mod suggest;
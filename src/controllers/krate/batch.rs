@@ -0,0 +1,150 @@
+//! Endpoint for looking up the metadata of many crates at once
+//!
+//! Tools like dependency dashboards and lockfile auditors need the metadata of hundreds of
+//! crates, which would otherwise require one `GET /crates/:crate_id` request per crate. Instead of
+//! the number of requests, the rate limit of this endpoint counts the number of requested crates.
+
+use crate::auth::AuthCheck;
+use crate::controllers::frontend_prelude::*;
+use crate::models::{Crate, CrateVersions, TopVersions, Version};
+use crate::rate_limiter::LimitedAction;
+use crate::schema::{crates, recent_crate_downloads};
+use crate::sql::canon_crate_name;
+use crate::views::{EncodableCrate, EncodableVersion};
+use std::collections::HashMap;
+
+/// The maximum number of crates that can be looked up in a single request.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Every started group of this many crates takes one token from the `BatchLookup` rate limit.
+const CRATES_PER_RATE_LIMIT_TOKEN: usize = 10;
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    crates: Vec<BatchRequestItem>,
+}
+
+#[derive(Deserialize)]
+struct BatchRequestItem {
+    name: String,
+    version: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BatchResponseItem {
+    /// The name as given in the request.
+    name: String,
+    /// `None` if the crate does not exist.
+    #[serde(rename = "crate")]
+    krate: Option<EncodableCrate>,
+    /// `None` if no version was requested or the version does not exist. Yanked versions are
+    /// included.
+    version: Option<EncodableVersion>,
+}
+
+/// Handles the `POST /crates_batch` route.
+///
+/// The results are returned in the order of the request, including crates that don't exist.
+pub async fn batch(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let request: BatchRequest = serde_json::from_slice(req.body())
+            .map_err(|e| bad_request(&format!("invalid batch request: {e}")))?;
+
+        if request.crates.is_empty() {
+            return Err(bad_request("at least one crate has to be looked up"));
+        }
+
+        if request.crates.len() > MAX_BATCH_SIZE {
+            return Err(bad_request(&format!(
+                "at most {MAX_BATCH_SIZE} crates can be looked up in a single request"
+            )));
+        }
+
+        let conn = &mut *app.db_write()?;
+        let auth = AuthCheck::default().check(&req, conn)?;
+
+        let cost =
+            (request.crates.len() + CRATES_PER_RATE_LIMIT_TOKEN - 1) / CRATES_PER_RATE_LIMIT_TOKEN;
+        app.rate_limiter.check_rate_limit_with_cost(
+            auth.user_id(),
+            LimitedAction::BatchLookup,
+            cost as u32,
+            conn,
+        )?;
+
+        let canonical_name = |name: &str| name.to_lowercase().replace('-', "_");
+        let names = request
+            .crates
+            .iter()
+            .map(|item| canonical_name(&item.name))
+            .collect::<Vec<_>>();
+
+        let (krates, recent_downloads): (Vec<Crate>, Vec<Option<i64>>) = crates::table
+            .left_join(recent_crate_downloads::table)
+            .filter(canon_crate_name(crates::name).eq_any(&names))
+            .select((
+                Crate::as_select(),
+                recent_crate_downloads::downloads.nullable(),
+            ))
+            .load::<(Crate, Option<i64>)>(conn)?
+            .into_iter()
+            .unzip();
+
+        let versions: Vec<Version> = krates.all_versions().load(conn)?;
+        let crates_by_name = versions
+            .grouped_by(&krates)
+            .into_iter()
+            .zip(krates)
+            .zip(recent_downloads)
+            .map(|((versions, krate), recent_downloads)| {
+                (
+                    canonical_name(&krate.name),
+                    (krate, versions, recent_downloads),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        let results = request
+            .crates
+            .into_iter()
+            .map(|item| {
+                let Some((krate, versions, recent_downloads)) =
+                    crates_by_name.get(&canonical_name(&item.name))
+                else {
+                    return BatchResponseItem {
+                        name: item.name,
+                        krate: None,
+                        version: None,
+                    };
+                };
+
+                let version = item
+                    .version
+                    .and_then(|num| versions.iter().find(|version| version.num == num))
+                    .map(|version| {
+                        EncodableVersion::from(version.clone(), &krate.name, None, vec![])
+                    });
+
+                let top_versions = TopVersions::from_versions(
+                    versions.iter().filter(|v| !v.yanked).cloned().collect(),
+                );
+
+                BatchResponseItem {
+                    name: item.name,
+                    krate: Some(EncodableCrate::from_minimal(
+                        krate.clone(),
+                        Some(&top_versions),
+                        None,
+                        false,
+                        *recent_downloads,
+                        None,
+                    )),
+                    version,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "crates": results })))
+    })
+    .await
+}
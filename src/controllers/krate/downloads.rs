@@ -4,20 +4,58 @@
 //! download counts are located in `version::downloads`.
 
 use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
 
 use crate::controllers::frontend_prelude::*;
 
-use crate::models::{Crate, CrateVersions, Version, VersionDownload};
-use crate::schema::version_downloads;
+use crate::models::{
+    Crate, CrateDownloadByClient, CrateVersions, DownloadsInterval, Version, VersionDownload,
+};
+use crate::schema::{crate_downloads_by_client, version_downloads, versions};
 use crate::sql::to_char;
-use crate::views::EncodableVersionDownload;
+use crate::views::{EncodableCrateDownloadByClient, EncodableVersionDownload};
+use chrono::{Duration, NaiveDate, Utc};
+
+/// Query parameters that switch the endpoint to the download history mode.
+const HISTORY_PARAMS: &[&str] = &["start_date", "end_date", "interval", "group_by"];
 
 /// Handles the `GET /crates/:crate_id/downloads` route.
-pub async fn downloads(state: AppState, Path(crate_name): Path<String>) -> AppResult<Json<Value>> {
+///
+/// Passing `breakdown=cargo_version` additionally returns the daily downloads of the crate
+/// broken down by the client, cargo version and CI detection in `meta.downloads_by_client`.
+///
+/// Passing any of the `start_date`, `end_date`, `interval` or `group_by` query parameters, or
+/// requesting `text/csv`, returns the download history of the crate instead. See `history`.
+pub async fn downloads(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Response> {
     conduit_compat(move || {
         use diesel::dsl::*;
         use diesel::sql_types::BigInt;
 
+        let query = req.query();
+        let wants_csv = wants_csv(&req);
+        if wants_csv
+            || HISTORY_PARAMS
+                .iter()
+                .any(|param| query.contains_key(*param))
+        {
+            let conn = &mut *state.db_read()?;
+            let krate: Crate = Crate::by_name(&crate_name).first(conn)?;
+            return history(conn, &krate, &query, wants_csv);
+        }
+
+        let include_breakdown = match req.query().get("breakdown").map(String::as_str) {
+            None => false,
+            Some("cargo_version") => true,
+            Some(breakdown) => {
+                return Err(bad_request(&format!("invalid breakdown: {breakdown}")));
+            }
+        };
+
         let conn = &mut *state.db_read()?;
         let krate: Crate = Crate::by_name(&crate_name).first(conn)?;
 
@@ -51,12 +89,202 @@ pub async fn downloads(state: AppState, Path(crate_name): Path<String>) -> AppRe
             downloads: i64,
         }
 
+        if !include_breakdown {
+            return Ok(Json(json!({
+                "version_downloads": downloads,
+                "meta": {
+                    "extra_downloads": extra,
+                },
+            }))
+            .into_response());
+        }
+
+        let downloads_by_client = CrateDownloadByClient::belonging_to(&krate)
+            .filter(crate_downloads_by_client::date.gt(date(now - 90.days())))
+            .order((
+                crate_downloads_by_client::date.asc(),
+                crate_downloads_by_client::client.asc(),
+                crate_downloads_by_client::cargo_version.asc(),
+                crate_downloads_by_client::ci.asc(),
+            ))
+            .load(conn)?
+            .into_iter()
+            .map(CrateDownloadByClient::into)
+            .collect::<Vec<EncodableCrateDownloadByClient>>();
+
         Ok(Json(json!({
             "version_downloads": downloads,
             "meta": {
                 "extra_downloads": extra,
+                "downloads_by_client": downloads_by_client,
             },
-        })))
+        }))
+        .into_response())
     })
     .await
 }
+
+/// Returns the downloads of the crate between `start_date` and `end_date` (inclusive, defaulting
+/// to the last 90 days), summed up per `interval` (`day`, `week` or `month`) and version series
+/// (`group_by`: `version`, `minor` or `major`), either as JSON or as CSV.
+///
+/// The date range is limited to one year for daily, five years for weekly and ten years for
+/// monthly downloads.
+fn history(
+    conn: &mut PgConnection,
+    krate: &Crate,
+    query: &indexmap::IndexMap<String, String>,
+    wants_csv: bool,
+) -> AppResult<Response> {
+    let parse_date = |param: &str| {
+        query
+            .get(param)
+            .map(|date| {
+                NaiveDate::parse_from_str(date, "%F")
+                    .map_err(|_| bad_request(&format!("invalid {param}: {date}")))
+            })
+            .transpose()
+    };
+
+    let end_date = parse_date("end_date")?.unwrap_or_else(|| Utc::now().date_naive());
+    let start_date = parse_date("start_date")?.unwrap_or(end_date - Duration::days(89));
+    if start_date > end_date {
+        return Err(bad_request("start_date must not be after end_date"));
+    }
+
+    let interval = match query.get("interval").map(String::as_str) {
+        None | Some("day") => DownloadsInterval::Day,
+        Some("week") => DownloadsInterval::Week,
+        Some("month") => DownloadsInterval::Month,
+        Some(interval) => return Err(bad_request(&format!("invalid interval: {interval}"))),
+    };
+
+    let max_days = max_history_days(interval);
+    if (end_date - start_date).num_days() >= max_days {
+        let interval = interval.as_str();
+        let message =
+            format!("the date range must not exceed {max_days} days for interval={interval}");
+        return Err(bad_request(&message));
+    }
+
+    let group_by = match query.get("group_by").map(String::as_str) {
+        None | Some("version") => GroupBy::Version,
+        Some("minor") => GroupBy::Minor,
+        Some("major") => GroupBy::Major,
+        Some(group_by) => return Err(bad_request(&format!("invalid group_by: {group_by}"))),
+    };
+
+    let versions: HashMap<i32, String> = krate
+        .all_versions()
+        .select((versions::id, versions::num))
+        .load(conn)?
+        .into_iter()
+        .collect();
+
+    let mut downloads = BTreeMap::new();
+    for row in krate.download_history(conn, interval, start_date, end_date)? {
+        let Some(num) = versions.get(&row.version_id) else {
+            continue;
+        };
+        *downloads
+            .entry((row.date, group_by.series(num)))
+            .or_insert(0) += row.downloads;
+    }
+
+    if wants_csv {
+        let mut csv = String::from("date,series,downloads\n");
+        for ((date, (_, series)), downloads) in downloads {
+            // Neither dates nor version numbers can contain commas or quotes, so no escaping is
+            // necessary here.
+            let _ = writeln!(csv, "{date},{series},{downloads}");
+        }
+
+        let headers = [(header::CONTENT_TYPE, "text/csv; charset=utf-8")];
+        return Ok((headers, csv).into_response());
+    }
+
+    let downloads = downloads
+        .into_iter()
+        .map(|((date, (_, series)), downloads)| HistoryDownloads {
+            date: date.to_string(),
+            series,
+            downloads,
+        })
+        .collect::<Vec<_>>();
+
+    #[derive(Serialize)]
+    struct HistoryDownloads {
+        date: String,
+        series: String,
+        downloads: i64,
+    }
+
+    Ok(Json(json!({
+        "downloads": downloads,
+        "meta": {
+            "start_date": start_date.to_string(),
+            "end_date": end_date.to_string(),
+            "interval": interval,
+            "group_by": group_by.as_str(),
+        },
+    }))
+    .into_response())
+}
+
+/// The longest date range (in days, including both ends) the download history can be requested
+/// for, so that a single request can't scan and return the whole history of a large crate.
+fn max_history_days(interval: DownloadsInterval) -> i64 {
+    match interval {
+        DownloadsInterval::Day => 366,
+        DownloadsInterval::Week => 5 * 366,
+        DownloadsInterval::Month => 10 * 366,
+    }
+}
+
+fn wants_csv(req: &Parts) -> bool {
+    req.headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .any(|val| val.to_str().unwrap_or_default().contains("text/csv"))
+}
+
+/// The version series the download history is grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupBy {
+    Version,
+    Minor,
+    Major,
+}
+
+impl GroupBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Version => "version",
+            Self::Minor => "minor",
+            Self::Major => "major",
+        }
+    }
+
+    /// Returns the series of the version number, together with a key to sort the series by
+    /// semver precedence.
+    fn series(&self, num: &str) -> (Option<semver::Version>, String) {
+        let Ok(version) = semver::Version::parse(num) else {
+            return (None, num.to_string());
+        };
+
+        match self {
+            Self::Version => (Some(version), num.to_string()),
+            Self::Minor => {
+                let series = format!("{}.{}", version.major, version.minor);
+                (
+                    Some(semver::Version::new(version.major, version.minor, 0)),
+                    series,
+                )
+            }
+            Self::Major => {
+                let series = version.major.to_string();
+                (Some(semver::Version::new(version.major, 0, 0)), series)
+            }
+        }
+    }
+}
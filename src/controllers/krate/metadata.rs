@@ -10,11 +10,13 @@ use std::str::FromStr;
 use crate::controllers::frontend_prelude::*;
 use crate::controllers::helpers::pagination::PaginationOptions;
 
+use crate::models::krate::MAX_SUGGESTIONS;
 use crate::models::{
     Category, Crate, CrateCategory, CrateKeyword, CrateVersions, Keyword, RecentCrateDownloads,
     TopVersions, User, Version, VersionOwnerAction,
 };
 use crate::schema::*;
+use crate::util::errors::crate_not_found;
 use crate::views::{
     EncodableCategory, EncodableCrate, EncodableDependency, EncodableKeyword, EncodableVersion,
 };
@@ -54,6 +56,7 @@ pub async fn summary(state: AppState) -> AppResult<Json<Value>> {
                         None,
                         false,
                         recent_downloads,
+                        None,
                     ))
                 })
                 .collect()
@@ -103,6 +106,21 @@ pub async fn summary(state: AppState) -> AppResult<Json<Value>> {
             .limit(10)
             .load(conn)?;
 
+        // The growth is computed periodically by the `update_trending_crates` background job,
+        // since it needs to aggregate the `version_downloads` of the last two weeks.
+        let mut trending_query = crates
+            .inner_join(trending_crates::table)
+            .left_join(recent_crate_downloads::table)
+            .into_boxed();
+        if !config.excluded_crate_names.is_empty() {
+            trending_query = trending_query.filter(name.ne_all(&config.excluded_crate_names));
+        }
+        let trending = trending_query
+            .order((trending_crates::growth.desc(), name.asc()))
+            .select(selection)
+            .limit(10)
+            .load(conn)?;
+
         let popular_keywords = keywords::table
             .order(keywords::crates_cnt.desc())
             .limit(10)
@@ -123,6 +141,7 @@ pub async fn summary(state: AppState) -> AppResult<Json<Value>> {
             "most_downloaded": encode_crates(conn, most_downloaded)?,
             "most_recently_downloaded": encode_crates(conn, most_recently_downloaded)?,
             "just_updated": encode_crates(conn, just_updated)?,
+            "trending": encode_crates(conn, trending)?,
             "popular_keywords": popular_keywords,
             "popular_categories": popular_categories,
         })))
@@ -141,7 +160,11 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             .unwrap_or_default();
 
         let conn = &mut *app.db_read()?;
-        let krate: Crate = Crate::by_name(&name).first(conn)?;
+        let krate: Option<Crate> = Crate::by_name(&name).first(conn).optional()?;
+        let Some(krate) = krate else {
+            let suggestions = Crate::similar_names(conn, &name, MAX_SUGGESTIONS)?;
+            return Err(crate_not_found(&name, suggestions));
+        };
 
         let versions_publishers_and_audit_actions = if include.versions {
             let mut versions_and_publishers: Vec<(Version, Option<User>)> = krate
@@ -201,6 +224,12 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             None
         };
 
+        let score = crate_scores::table
+            .find(krate.id)
+            .select(crate_scores::score)
+            .get_result(conn)
+            .optional()?;
+
         let badges = if include.badges { Some(vec![]) } else { None };
 
         let top_versions = if include.versions {
@@ -218,6 +247,7 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             badges,
             false,
             recent_downloads,
+            score,
         );
         let encodable_versions = versions_publishers_and_audit_actions.map(|vpa| {
             vpa.into_iter()
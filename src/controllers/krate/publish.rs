@@ -12,6 +12,7 @@ use tokio::runtime::Handle;
 use url::Url;
 
 use crate::controllers::cargo_prelude::*;
+use crate::controllers::util::real_ip;
 use crate::models::{
     insert_version_owner_action, Category, Crate, Keyword, NewCrate, NewVersion, Rights,
     VersionAction,
@@ -245,6 +246,8 @@ pub async fn publish(app: AppState, req: BytesRequest) -> AppResult<Json<GoodCra
             let hex_cksum: String = Sha256::digest(&tarball_bytes).encode_hex();
 
             let rust_version = package.rust_version.map(|rv| rv.as_local().unwrap());
+            let lib = tarball_info.manifest.lib.as_ref();
+            let has_proc_macro = lib.is_some_and(|lib| lib.proc_macro);
 
             // Persist the new version of this crate
             let version = NewVersion::new(
@@ -259,16 +262,20 @@ pub async fn publish(app: AppState, req: BytesRequest) -> AppResult<Json<GoodCra
                 hex_cksum,
                 package.links,
                 rust_version,
+                tarball_info.has_build_script,
+                has_proc_macro,
             )?
             .save(conn, &verified_email_address)?;
 
-            insert_version_owner_action(
+            let owner_action = insert_version_owner_action(
                 conn,
                 version.id,
                 user.id,
                 api_token_id,
                 VersionAction::Publish,
             )?;
+            Job::send_version_action_notifications(owner_action.id, real_ip(&req))
+                .enqueue(conn)?;
 
             // Link this new version to all dependencies
             add_dependencies(conn, &metadata.deps, version.id)?;
@@ -320,7 +327,14 @@ pub async fn publish(app: AppState, req: BytesRequest) -> AppResult<Json<GoodCra
             };
 
             Ok(Json(GoodCrate {
-                krate: EncodableCrate::from_minimal(krate, Some(&top_versions), None, false, None),
+                krate: EncodableCrate::from_minimal(
+                    krate,
+                    Some(&top_versions),
+                    None,
+                    false,
+                    None,
+                    None,
+                ),
                 warnings,
             }))
         })
@@ -0,0 +1,65 @@
+//! Endpoint for the crates that are related to a crate
+//!
+//! The related crates are computed periodically by the `update_related_crates` background job,
+//! see `worker::update_related_crates` for how they are found.
+
+use crate::controllers::frontend_prelude::*;
+use crate::models::{Crate, RelatedCrateKind};
+use crate::schema::{crates, recent_crate_downloads, related_crates};
+
+#[derive(Debug, Default, Serialize)]
+struct RelatedCrates {
+    /// Crates that are frequently depended on together with the crate.
+    dependencies: Vec<RelatedCrate>,
+    /// Crates that are commonly used as alternatives to the crate.
+    alternatives: Vec<RelatedCrate>,
+}
+
+#[derive(Debug, Serialize, Queryable)]
+struct RelatedCrate {
+    #[serde(skip)]
+    kind: i16,
+    name: String,
+    description: Option<String>,
+    downloads: i32,
+    recent_downloads: Option<i64>,
+    /// How strongly the crates are related, only comparable within the same list.
+    score: f64,
+}
+
+/// Handles the `GET /crates/:crate_id/related` route.
+pub async fn related(app: AppState, Path(name): Path<String>) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_read()?;
+        let krate: Crate = Crate::by_name(&name).first(conn)?;
+
+        let rows: Vec<RelatedCrate> = related_crates::table
+            .inner_join(crates::table.on(crates::id.eq(related_crates::related_crate_id)))
+            .left_join(
+                recent_crate_downloads::table.on(recent_crate_downloads::crate_id.eq(crates::id)),
+            )
+            .filter(related_crates::crate_id.eq(krate.id))
+            .select((
+                related_crates::kind,
+                crates::name,
+                crates::description,
+                crates::downloads,
+                recent_crate_downloads::downloads.nullable(),
+                related_crates::score,
+            ))
+            .order((related_crates::score.desc(), crates::name.asc()))
+            .load(conn)?;
+
+        let mut related = RelatedCrates::default();
+        for row in rows {
+            if row.kind == RelatedCrateKind::Dependency as i16 {
+                related.dependencies.push(row);
+            } else if row.kind == RelatedCrateKind::Alternative as i16 {
+                related.alternatives.push(row);
+            }
+        }
+
+        Ok(Json(json!(related)))
+    })
+    .await
+}
@@ -1,8 +1,12 @@
 //! Endpoint for searching and discovery functionality
 
 use crate::auth::AuthCheck;
+use crate::models::token::EndpointScope;
+use chrono::{NaiveDate, NaiveTime};
 use diesel::dsl::*;
-use diesel::sql_types::Array;
+use diesel::helper_types::LeftJoinQuerySource;
+use diesel::pg::Pg;
+use diesel::sql_types::{Array, Bool, Integer, Nullable};
 use diesel_full_text_search::*;
 use indexmap::IndexMap;
 
@@ -14,8 +18,19 @@ use crate::util::errors::bad_request;
 use crate::views::EncodableCrate;
 
 use crate::controllers::helpers::pagination::{Page, Paginated, PaginationOptions};
-use crate::models::krate::ALL_COLUMNS;
-use crate::sql::{array_agg, canon_crate_name, lower};
+use crate::licenses::{license_ids, parse_license_expr};
+use crate::models::krate::{ALL_COLUMNS, MAX_SUGGESTIONS};
+use crate::sql::{
+    array_agg, canon_crate_name, escape_like, lower, similarity, TrigramDistance, TrigramSimilar,
+};
+
+use self::query::{RustVersionBound, SearchQuery};
+
+mod query;
+
+/// If the full text search finds fewer crates than this, crates with similar names are included
+/// in the results too, and names of similar crates are suggested.
+const SPARSE_RESULTS: i64 = 3;
 
 /// Handles the `GET /crates` route.
 /// Returns a list of crates. Called in a variety of scenarios in the
@@ -38,12 +53,309 @@ use crate::sql::{array_agg, canon_crate_name, lower};
 /// caused the break. In the future, we should look at splitting this
 /// function out to cover the different use cases, and create unit tests
 /// for them.
+///
+/// The `q` parameter supports qualifiers like `keyword:async`, exact phrases and excluded words,
+/// see the `query` module for the syntax.
 pub async fn search(app: AppState, req: Parts) -> AppResult<Json<Value>> {
     conduit_compat(move || {
-        use diesel::sql_types::{Bool, Text};
+        let params = req.query();
+        let include_facets = params.get("include_facets").is_some_and(|s| s == "yes");
+
+        let conn = &mut *app.db_read()?;
+
+        let mut filter_params = FilterParams::from_request(&req, conn)?;
+
+        // Computing the facets of the whole registry is too expensive for a public endpoint, so
+        // they are only available for searches that are narrowed down by a query or a filter.
+        let text = filter_params.q_string.as_deref().filter(|q| !q.is_empty());
+        if include_facets && text.is_none() && filter_params.is_plain_text() {
+            return Err(bad_request(
+                "include_facets requires a search query or at least one filter",
+            ));
+        }
+
+        // Typos like `reqwset` don't match anything in the full text search, so if there are only
+        // a few results, the search is repeated with crate names matched by trigram similarity.
+        // Queries with filters only narrow the results down, so they never use the fallback.
+        let (mut total, mut next_page, mut prev_page, mut data) =
+            load_crates(&req, &filter_params, conn)?;
+        if text.is_some() && filter_params.is_plain_text() && total < SPARSE_RESULTS {
+            filter_params.similar_names = true;
+            (total, next_page, prev_page, data) = load_crates(&req, &filter_params, conn)?;
+        }
+
+        let perfect_matches = data.iter().map(|&(_, b, _, _)| b).collect::<Vec<_>>();
+        let recent_downloads = data
+            .iter()
+            .map(|&(_, _, s, _)| s.unwrap_or(0))
+            .collect::<Vec<_>>();
+        let scores = data.iter().map(|&(_, _, _, s)| s).collect::<Vec<_>>();
+        let crates = data.into_iter().map(|(c, _, _, _)| c).collect::<Vec<_>>();
+
+        let versions: Vec<Version> = info_span!("db.query", message = "SELECT ... FROM versions")
+            .in_scope(|| crates.versions().load(conn))?;
+        let versions = versions
+            .grouped_by(&crates)
+            .into_iter()
+            .map(TopVersions::from_versions);
+
+        let crates = versions
+            .zip(crates)
+            .zip(perfect_matches)
+            .zip(recent_downloads)
+            .zip(scores)
+            .map(
+                |((((max_version, krate), perfect_match), recent_downloads), score)| {
+                    EncodableCrate::from_minimal(
+                        krate,
+                        Some(&max_version),
+                        Some(vec![]),
+                        perfect_match,
+                        Some(recent_downloads),
+                        score,
+                    )
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let mut json = json!({
+            "crates": crates,
+            "meta": {
+                "total": total,
+                "next_page": next_page,
+                "prev_page": prev_page,
+            },
+        });
+
+        if let Some(q_string) = filter_params.q_string.as_deref().filter(|q| !q.is_empty()) {
+            let suggestions = if filter_params.similar_names {
+                suggestions(conn, q_string)?
+            } else {
+                vec![]
+            };
+            json["suggestions"] = json!(suggestions);
+        }
+
+        if include_facets {
+            json["facets"] = serde_json::to_value(filter_params.facets(conn)?)?;
+        }
+
+        Ok(Json(json))
+    })
+    .await
+}
+
+/// Loads a page of the crates matching the filters.
+fn load_crates(
+    req: &Parts,
+    filter_params: &FilterParams,
+    conn: &mut PgConnection,
+) -> AppResult<CratesPage> {
+    use diesel::sql_types::Text;
+
+    let params = req.query();
+    let sort = params.get("sort").map(|s| &**s);
+    let mut supports_seek = filter_params.supports_seek();
+
+    let selection = (
+        ALL_COLUMNS,
+        false.into_sql::<Bool>(),
+        recent_crate_downloads::downloads.nullable(),
+        crate_scores::score.nullable(),
+    );
+    let mut query = filter_params.make_query().select(selection);
+
+    if let Some(q_string) = &filter_params.q_string {
+        if !q_string.is_empty() {
+            let sort = params.get("sort").map(|s| &**s).unwrap_or("relevance");
+
+            query = query.select((
+                ALL_COLUMNS,
+                Crate::with_name(q_string),
+                recent_crate_downloads::downloads.nullable(),
+                crate_scores::score.nullable(),
+            ));
+            query = query.order(Crate::with_name(q_string).desc());
 
+            if sort == "relevance" {
+                let q = sql::<TsQuery>("plainto_tsquery('english', ")
+                    .bind::<Text, _>(q_string)
+                    .sql(")");
+                let rank = ts_rank_cd(crates::textsearchable_index_col, q);
+                if filter_params.similar_names {
+                    let similarity =
+                        similarity(canon_crate_name(crates::name), canon_crate_name(q_string));
+                    query = query.then_order_by((rank + similarity).desc())
+                } else {
+                    query = query.then_order_by(rank.desc())
+                }
+            }
+        }
+    }
+
+    // Any sort other than 'relevance' (default) would ignore exact crate name matches
+    if sort == Some("downloads") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.order(crates::downloads.desc())
+    } else if sort == Some("recent-downloads") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.order(recent_crate_downloads::downloads.desc().nulls_last())
+    } else if sort == Some("recent-updates") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.order(crates::updated_at.desc());
+    } else if sort == Some("new") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.order(crates::created_at.desc());
+    } else if sort == Some("score") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.order((crate_scores::score.desc().nulls_last(), crates::name.asc()));
+    } else {
+        query = query.then_order_by(crates::name.asc())
+    }
+
+    let pagination: PaginationOptions = PaginationOptions::builder()
+        .limit_page_numbers()
+        .enable_seek(supports_seek)
+        .gather(req)?;
+
+    let (explicit_page, seek) = match pagination.page {
+        Page::Numeric(_) => (true, None),
+        Page::Seek(ref s) => (false, Some(s.decode::<i32>()?)),
+        Page::Unspecified => (false, None),
+    };
+
+    // To avoid breaking existing users, seek-based pagination is only used if an explicit page has
+    // not been provided. This way clients relying on meta.next_page will use the faster seek-based
+    // paginations, while client hardcoding pages handling will use the slower offset-based code.
+    if supports_seek && !explicit_page {
+        // Equivalent of:
+        // `WHERE name > (SELECT name FROM crates WHERE id = $1) LIMIT $2`
+        query = query.limit(pagination.per_page);
+        if let Some(seek) = seek {
+            let crate_name: String = crates::table
+                .find(seek)
+                .select(crates::name)
+                .get_result(conn)?;
+            query = query.filter(crates::name.gt(crate_name));
+        }
+
+        // This does a full index-only scan over the crates table to gather how many crates were
+        // published. Unfortunately on PostgreSQL counting the rows in a table requires scanning
+        // the table, and the `total` field is part of the stable registries API.
+        //
+        // If this becomes a problem in the future the crates count could be denormalized, at least
+        // for the filterless happy path.
+        let total: i64 = info_span!("db.query", message = "SELECT COUNT(*) FROM crates")
+            .in_scope(|| crates::table.count().get_result(conn))?;
+
+        let results: Vec<CrateRow> = info_span!("db.query", message = "SELECT ... FROM crates")
+            .in_scope(|| query.load(conn))?;
+
+        let next_page = if let Some(last) = results.last() {
+            let mut params = IndexMap::new();
+            params.insert(
+                "seek".into(),
+                crate::controllers::helpers::pagination::encode_seek(last.0.id)?,
+            );
+            Some(req.query_with_params(params))
+        } else {
+            None
+        };
+
+        Ok((total, next_page, None, results))
+    } else {
+        let query = query.pages_pagination(pagination);
+        let data: Paginated<CrateRow> =
+            info_span!("db.query", message = "SELECT ..., COUNT(*) FROM crates")
+                .in_scope(|| query.load(conn))?;
+        Ok((
+            data.total(),
+            data.next_page_params().map(|p| req.query_with_params(p)),
+            data.prev_page_params().map(|p| req.query_with_params(p)),
+            data.into_iter().collect::<Vec<_>>(),
+        ))
+    }
+}
+
+/// A crate from the search results, with whether its name matches the query exactly, its recent
+/// downloads and its score.
+type CrateRow = (Crate, bool, Option<i64>, Option<f64>);
+/// The total number of matching crates, the query strings of the next and previous pages, and the
+/// crates of the page.
+type CratesPage = (i64, Option<String>, Option<String>, Vec<CrateRow>);
+type QuerySource = LeftJoinQuerySource<
+    LeftJoinQuerySource<crates::table, recent_crate_downloads::table>,
+    crate_scores::table,
+>;
+type BoxedCrateIds<'a> = IntoBoxed<
+    'a,
+    Select<
+        LeftJoin<LeftJoin<crates::table, recent_crate_downloads::table>, crate_scores::table>,
+        crates::id,
+    >,
+    Pg,
+>;
+type BoxedCondition<'a> = Box<dyn BoxableExpression<QuerySource, Pg, SqlType = Bool> + 'a>;
+
+/// The highest version of the crate from the surrounding query that is not yanked, in the same
+/// order as for the reverse dependencies: the highest stable version, or the newest prerelease if
+/// there is none.
+///
+/// This is a macro, since the correlated subquery can't be boxed.
+macro_rules! highest_version {
+    () => {
+        versions::table
+            .filter(versions::crate_id.eq(crates::id))
+            .filter(versions::yanked.eq(false))
+            .order((
+                versions::semver_no_prerelease.desc().nulls_last(),
+                versions::id.desc(),
+            ))
+            .limit(1)
+    };
+}
+
+/// The filters of the `GET /crates` route, parsed from the query parameters.
+struct FilterParams {
+    /// The free text of the `q` parameter, without qualifiers, phrases and excluded words.
+    q_string: Option<String>,
+    /// The remaining parts of the `q` parameter.
+    query: SearchQuery,
+    /// Whether crates with names similar to the free text are included in the results.
+    similar_names: bool,
+    include_yanked: bool,
+    category: Option<String>,
+    all_keywords: Option<Vec<String>>,
+    keyword: Option<String>,
+    letter: Option<String>,
+    user_id: Option<i32>,
+    team_id: Option<i32>,
+    following_user_id: Option<i32>,
+    ids: Option<Vec<String>>,
+    /// The SPDX licenses of the `license` parameter, which must be enough to comply with the
+    /// license of the highest version.
+    licenses: Option<Vec<String>>,
+    max_rust_version: Option<RustVersionBound>,
+    updated_since: Option<NaiveDate>,
+    build_script: Option<bool>,
+    proc_macro: Option<bool>,
+}
+
+impl FilterParams {
+    fn from_request(req: &Parts, conn: &mut PgConnection) -> AppResult<Self> {
         let params = req.query();
-        let sort = params.get("sort").map(|s| &**s);
+        let get = |name: &str| params.get(name).cloned();
+
         let include_yanked = params
             .get("include_yanked")
             .map(|s| s == "yes")
@@ -52,56 +364,239 @@ pub async fn search(app: AppState, req: Parts) -> AppResult<Json<Value>> {
         // Remove 0x00 characters from the query string because Postgres can not
         // handle them and will return an error, which would cause us to throw
         // an Internal Server Error ourselves.
-        let q_string = params.get("q").map(|q| q.replace('\u{0}', ""));
+        let q = params.get("q").map(|q| q.replace('\u{0}', ""));
+        let query = q
+            .as_deref()
+            .map(query::parse)
+            .transpose()
+            .map_err(|err| bad_request(&err))?
+            .unwrap_or_default();
+        let q_string = q.map(|_| query.text.clone());
+
+        // The `all_keywords`, `keyword`, `letter`, `user_id`, `team_id`, `following` and `ids[]`
+        // filters are mutually exclusive, and only the first one of them is applied.
+        let mut all_keywords = None;
+        let mut keyword = None;
+        let mut letter = None;
+        let mut user_id = None;
+        let mut team_id = None;
+        let mut following_user_id = None;
+        let mut ids = None;
+
+        if let Some(kws) = params.get("all_keywords") {
+            let names = kws
+                .split_whitespace()
+                .map(|name| name.to_lowercase())
+                .collect();
+
+            all_keywords = Some(names);
+        } else if let Some(kw) = params.get("keyword") {
+            keyword = Some(kw.clone());
+        } else if let Some(l) = params.get("letter") {
+            let l = l
+                .chars()
+                .next()
+                .ok_or_else(|| bad_request("letter value must contain 1 character"))?;
+
+            letter = Some(l.to_lowercase().collect());
+        } else if let Some(id) = params.get("user_id").and_then(|s| s.parse::<i32>().ok()) {
+            user_id = Some(id);
+        } else if let Some(id) = params.get("team_id").and_then(|s| s.parse::<i32>().ok()) {
+            team_id = Some(id);
+        } else if params.get("following").is_some() {
+            let auth = AuthCheck::default()
+                .with_endpoint_scope(EndpointScope::ManageFollows)
+                .check(req, conn)?;
+            following_user_id = Some(auth.user_id());
+        } else if params.get("ids[]").is_some() {
+            let query_bytes = req.uri.query().unwrap_or("").as_bytes();
+            let names = url::form_urlencoded::parse(query_bytes)
+                .filter(|(key, _)| key == "ids[]")
+                .map(|(_, value)| value.to_string())
+                .collect();
+
+            ids = Some(names);
+        }
 
-        let selection = (
-            ALL_COLUMNS,
-            false.into_sql::<Bool>(),
-            recent_crate_downloads::downloads.nullable(),
-        );
+        let license = match (params.get("license"), &query.license) {
+            (Some(_), Some(_)) => {
+                let message = "`license:` can not be used in the search query together with the license parameter";
+                return Err(bad_request(message));
+            }
+            (license, query_license) => license.or(query_license.as_ref()),
+        };
+        let licenses = match license {
+            Some(license) => {
+                let expr = parse_license_expr(license)
+                    .map_err(|_| bad_request(&format!("invalid license expression: {license}")))?;
+                Some(license_ids(&expr).into_iter().map(String::from).collect())
+            }
+            None => None,
+        };
+
+        let max_rust_version = match (params.get("max_rust_version"), query.msrv.as_ref()) {
+            (Some(_), Some(_)) => {
+                let message = "`msrv:` can not be used in the search query together with the max_rust_version parameter";
+                return Err(bad_request(message));
+            }
+            (Some(value), None) => {
+                let version = parse_rust_version(value)
+                    .ok_or_else(|| bad_request(&format!("invalid max_rust_version: {value}")))?;
+                Some(RustVersionBound {
+                    version,
+                    inclusive: true,
+                })
+            }
+            (None, Some(bound)) => Some(RustVersionBound {
+                version: bound.version.clone(),
+                inclusive: bound.inclusive,
+            }),
+            (None, None) => None,
+        };
+
+        // A `rust-version` of `1.70` is equivalent to `1.70.0`, so the missing components are
+        // filled up with zeros to compare correctly against the `rust-version` of the crates.
+        let max_rust_version = max_rust_version.map(|mut bound| {
+            bound.version.resize(3, 0);
+            bound
+        });
+
+        let updated_since = params
+            .get("updated_since")
+            .map(|date| {
+                NaiveDate::parse_from_str(date, "%F")
+                    .map_err(|_| bad_request(&format!("invalid updated_since: {date}")))
+            })
+            .transpose()?;
+
+        let parse_flag = |name: &str| match params.get(name).map(String::as_str) {
+            None => Ok(None),
+            Some("yes") => Ok(Some(true)),
+            Some("no") => Ok(Some(false)),
+            Some(value) => Err(bad_request(&format!("invalid {name}: {value}"))),
+        };
+
+        Ok(Self {
+            q_string,
+            query,
+            similar_names: false,
+            include_yanked,
+            category: get("category"),
+            all_keywords,
+            keyword,
+            letter,
+            user_id,
+            team_id,
+            following_user_id,
+            ids,
+            licenses,
+            max_rust_version,
+            updated_since,
+            build_script: parse_flag("build_script")?,
+            proc_macro: parse_flag("proc_macro")?,
+        })
+    }
+
+    /// Seek-based pagination is only supported for the unfiltered list of all crates.
+    fn supports_seek(&self) -> bool {
+        // Searching with a query string always puts the exact match at the start of the results,
+        // so we can't support seek-based pagination with it.
+        //
+        // Calculating the total number of results with filters is not supported yet.
+        self.q_string.is_none()
+            && self.include_yanked
+            && self.category.is_none()
+            && self.all_keywords.is_none()
+            && self.keyword.is_none()
+            && self.letter.is_none()
+            && self.user_id.is_none()
+            && self.team_id.is_none()
+            && self.following_user_id.is_none()
+            && self.ids.is_none()
+            && self.licenses.is_none()
+            && self.max_rust_version.is_none()
+            && self.updated_since.is_none()
+            && self.build_script.is_none()
+            && self.proc_macro.is_none()
+    }
+
+    /// Whether the free text of the `q` parameter is the only filter of the search.
+    fn is_plain_text(&self) -> bool {
+        let query = &self.query;
+        self.include_yanked
+            && query.phrases.is_empty()
+            && query.excluded.is_empty()
+            && query.keywords.is_empty()
+            && query.categories.is_empty()
+            && query.owner.is_none()
+            && self.category.is_none()
+            && self.all_keywords.is_none()
+            && self.keyword.is_none()
+            && self.letter.is_none()
+            && self.user_id.is_none()
+            && self.team_id.is_none()
+            && self.following_user_id.is_none()
+            && self.ids.is_none()
+            && self.licenses.is_none()
+            && self.max_rust_version.is_none()
+            && self.updated_since.is_none()
+            && self.build_script.is_none()
+            && self.proc_macro.is_none()
+    }
+
+    /// Returns a query for all crates that match the filters, selecting only their IDs.
+    fn make_query(&self) -> BoxedCrateIds<'_> {
         let mut query = crates::table
             .left_join(recent_crate_downloads::table)
-            .select(selection)
+            .left_join(crate_scores::table)
+            .select(crates::id)
             .into_boxed();
 
-        let mut supports_seek = true;
+        for condition in self.conditions() {
+            query = query.filter(condition);
+        }
 
-        if let Some(q_string) = &q_string {
-            // Searching with a query string always puts the exact match at the start of the results,
-            // so we can't support seek-based pagination with it.
-            supports_seek = false;
+        query
+    }
 
-            if !q_string.is_empty() {
-                let sort = params.get("sort").map(|s| &**s).unwrap_or("relevance");
+    fn conditions(&self) -> Vec<BoxedCondition<'_>> {
+        use diesel::sql_types::Text;
 
-                let q = sql::<TsQuery>("plainto_tsquery('english', ")
-                    .bind::<Text, _>(q_string)
-                    .sql(")");
-                query = query.filter(
-                    q.clone()
-                        .matches(crates::textsearchable_index_col)
-                        .or(Crate::loosly_matches_name(q_string)),
-                );
-
-                query = query.select((
-                    ALL_COLUMNS,
-                    Crate::with_name(q_string),
-                    recent_crate_downloads::downloads.nullable(),
-                ));
-                query = query.order(Crate::with_name(q_string).desc());
-
-                if sort == "relevance" {
-                    let rank = ts_rank_cd(crates::textsearchable_index_col, q);
-                    query = query.then_order_by(rank.desc())
-                }
+        let mut conditions: Vec<BoxedCondition<'_>> = Vec::new();
+
+        if let Some(q_string) = self.q_string.as_deref().filter(|q| !q.is_empty()) {
+            let q = sql::<TsQuery>("plainto_tsquery('english', ")
+                .bind::<Text, _>(q_string)
+                .sql(")");
+            let mut condition: BoxedCondition<'_> = Box::new(
+                q.matches(crates::textsearchable_index_col)
+                    .or(Crate::loosly_matches_name(q_string)),
+            );
+            if self.similar_names {
+                condition = Box::new(condition.or(TrigramSimilar::new(
+                    canon_crate_name(crates::name),
+                    canon_crate_name(q_string),
+                )));
             }
+            conditions.push(condition);
         }
 
-        if let Some(cat) = params.get("category") {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
+        for phrase in &self.query.phrases {
+            let q = sql::<TsQuery>("phraseto_tsquery('english', ")
+                .bind::<Text, _>(phrase)
+                .sql(")");
+            conditions.push(Box::new(q.matches(crates::textsearchable_index_col)));
+        }
+
+        for excluded in &self.query.excluded {
+            let q = sql::<TsQuery>("phraseto_tsquery('english', ")
+                .bind::<Text, _>(excluded)
+                .sql(")");
+            conditions.push(Box::new(not(q.matches(crates::textsearchable_index_col))));
+        }
 
-            query = query.filter(
+        for cat in self.category.iter().chain(&self.query.categories) {
+            conditions.push(Box::new(
                 crates::id.eq_any(
                     crates_categories::table
                         .select(crates_categories::crate_id)
@@ -112,21 +607,52 @@ pub async fn search(app: AppState, req: Parts) -> AppResult<Json<Value>> {
                                 .or(categories::slug.like(format!("{cat}::%"))),
                         ),
                 ),
-            );
+            ));
         }
 
-        let conn = &mut *app.db_read()?;
+        for kw in &self.query.keywords {
+            conditions.push(Box::new(
+                crates::id.eq_any(
+                    crates_keywords::table
+                        .select(crates_keywords::crate_id)
+                        .inner_join(keywords::table)
+                        .filter(lower(keywords::keyword).eq(kw)),
+                ),
+            ));
+        }
 
-        if let Some(kws) = params.get("all_keywords") {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
+        if let Some(owner) = &self.query.owner {
+            // Matches users by their GitHub login, and teams by their name or by the organization
+            // they belong to, e.g. `rust-lang` matches `github:rust-lang:core`.
+            let owner = owner.to_lowercase();
+            let user_ids = users::table
+                .select(users::id)
+                .filter(lower(users::gh_login).eq(owner.clone()));
+            let team_ids = teams::table.select(teams::id).filter(
+                lower(teams::login)
+                    .eq(owner.clone())
+                    .or(lower(teams::login).like(format!("github:{}:%", escape_like(&owner)))),
+            );
 
-            let names: Vec<_> = kws
-                .split_whitespace()
-                .map(|name| name.to_lowercase())
-                .collect();
+            conditions.push(Box::new(
+                crates::id.eq_any(
+                    crate_owners::table
+                        .select(crate_owners::crate_id)
+                        .filter(crate_owners::deleted.eq(false))
+                        .filter(
+                            crate_owners::owner_kind
+                                .eq(OwnerKind::User as i32)
+                                .and(crate_owners::owner_id.eq_any(user_ids))
+                                .or(crate_owners::owner_kind
+                                    .eq(OwnerKind::Team as i32)
+                                    .and(crate_owners::owner_id.eq_any(team_ids))),
+                        ),
+                ),
+            ));
+        }
 
-            query = query.filter(
+        if let Some(names) = &self.all_keywords {
+            conditions.push(Box::new(
                 // FIXME: Just use `.contains` in Diesel 2.0
                 // https://github.com/diesel-rs/diesel/issues/2066
                 Contains::new(
@@ -136,224 +662,292 @@ pub async fn search(app: AppState, req: Parts) -> AppResult<Json<Value>> {
                         .select(array_agg(keywords::keyword))
                         .single_value(),
                     names.into_sql::<Array<Text>>(),
-                ),
-            );
-        } else if let Some(kw) = params.get("keyword") {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            query = query.filter(
+                )
+                .assume_not_null(),
+            ));
+        } else if let Some(kw) = &self.keyword {
+            conditions.push(Box::new(
                 crates::id.eq_any(
                     crates_keywords::table
                         .select(crates_keywords::crate_id)
                         .inner_join(keywords::table)
                         .filter(lower(keywords::keyword).eq(lower(kw))),
                 ),
-            );
-        } else if let Some(letter) = params.get("letter") {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            let pattern = format!(
-                "{}%",
-                letter
-                    .chars()
-                    .next()
-                    .ok_or_else(|| bad_request("letter value must contain 1 character"))?
-                    .to_lowercase()
-                    .collect::<String>()
-            );
-            query = query.filter(canon_crate_name(crates::name).like(pattern));
-        } else if let Some(user_id) = params.get("user_id").and_then(|s| s.parse::<i32>().ok()) {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            query = query.filter(
+            ));
+        } else if let Some(letter) = &self.letter {
+            let pattern = format!("{letter}%");
+            conditions.push(Box::new(canon_crate_name(crates::name).like(pattern)));
+        } else if let Some(user_id) = self.user_id {
+            conditions.push(Box::new(
                 crates::id.eq_any(
                     CrateOwner::by_owner_kind(OwnerKind::User)
                         .select(crate_owners::crate_id)
                         .filter(crate_owners::owner_id.eq(user_id)),
                 ),
-            );
-        } else if let Some(team_id) = params.get("team_id").and_then(|s| s.parse::<i32>().ok()) {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            query = query.filter(
+            ));
+        } else if let Some(team_id) = self.team_id {
+            conditions.push(Box::new(
                 crates::id.eq_any(
                     CrateOwner::by_owner_kind(OwnerKind::Team)
                         .select(crate_owners::crate_id)
                         .filter(crate_owners::owner_id.eq(team_id)),
                 ),
-            );
-        } else if params.get("following").is_some() {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            let user_id = AuthCheck::default().check(&req, conn)?.user_id();
-
-            query = query.filter(
+            ));
+        } else if let Some(user_id) = self.following_user_id {
+            conditions.push(Box::new(
                 crates::id.eq_any(
                     follows::table
                         .select(follows::crate_id)
                         .filter(follows::user_id.eq(user_id)),
                 ),
-            );
-        } else if params.get("ids[]").is_some() {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            let query_bytes = req.uri.query().unwrap_or("").as_bytes();
-            let ids: Vec<_> = url::form_urlencoded::parse(query_bytes)
-                .filter(|(key, _)| key == "ids[]")
-                .map(|(_, value)| value.to_string())
-                .collect();
-
-            query = query.filter(crates::name.eq_any(ids));
+            ));
+        } else if let Some(ids) = &self.ids {
+            conditions.push(Box::new(crates::name.eq_any(ids)));
         }
 
-        if !include_yanked {
-            // Calculating the total number of results with filters is not supported yet.
-            supports_seek = false;
-
-            query = query.filter(exists(
+        if !self.include_yanked {
+            conditions.push(Box::new(exists(
                 versions::table
                     .filter(versions::crate_id.eq(crates::id))
                     .filter(versions::yanked.eq(false)),
+            )));
+        }
+
+        if let Some(updated_since) = self.updated_since {
+            conditions.push(Box::new(
+                crates::updated_at.ge(updated_since.and_time(NaiveTime::MIN)),
             ));
         }
 
-        // Any sort other than 'relevance' (default) would ignore exact crate name matches
-        if sort == Some("downloads") {
-            // Custom sorting is not supported yet with seek.
-            supports_seek = false;
+        // The remaining filters apply to the highest version of the crate that is not yanked.
 
-            query = query.order(crates::downloads.desc())
-        } else if sort == Some("recent-downloads") {
-            // Custom sorting is not supported yet with seek.
-            supports_seek = false;
+        if let Some(licenses) = &self.licenses {
+            // Any of the alternatives of the license expression (see `license_alternatives`) has
+            // to consist of the requested licenses only.
+            let satisfied = sql::<Bool>(
+                "EXISTS (SELECT 1 FROM unnest(versions.license_alternatives) AS alternative \
+                WHERE string_to_array(alternative, ' ') <@ ",
+            )
+            .bind::<Array<Text>, _>(licenses)
+            .sql(")");
+
+            conditions.push(Box::new(
+                highest_version!()
+                    .select(satisfied)
+                    .single_value()
+                    .assume_not_null(),
+            ));
+        }
 
-            query = query.order(recent_crate_downloads::downloads.desc().nulls_last())
-        } else if sort == Some("recent-updates") {
-            // Custom sorting is not supported yet with seek.
-            supports_seek = false;
+        if let Some(bound) = &self.max_rust_version {
+            let rust_version = highest_version!()
+                .select(sql::<Nullable<Array<Integer>>>(RUST_VERSION_ARRAY))
+                .single_value();
 
-            query = query.order(crates::updated_at.desc());
-        } else if sort == Some("new") {
-            // Custom sorting is not supported yet with seek.
-            supports_seek = false;
+            if bound.inclusive {
+                conditions.push(Box::new(rust_version.le(&bound.version).assume_not_null()));
+            } else {
+                conditions.push(Box::new(rust_version.lt(&bound.version).assume_not_null()));
+            }
+        }
 
-            query = query.order(crates::created_at.desc());
-        } else {
-            query = query.then_order_by(crates::name.asc())
+        // Versions that were published before these flags were stored have `NULL` values until
+        // the `backfill_version_tarball_info` job has processed them, and they match neither
+        // `yes` nor `no`.
+        if let Some(build_script) = self.build_script {
+            conditions.push(Box::new(
+                highest_version!()
+                    .select(versions::has_build_script)
+                    .single_value()
+                    .eq(build_script)
+                    .assume_not_null(),
+            ));
         }
 
-        let pagination: PaginationOptions = PaginationOptions::builder()
-            .limit_page_numbers()
-            .enable_seek(supports_seek)
-            .gather(&req)?;
+        if let Some(proc_macro) = self.proc_macro {
+            conditions.push(Box::new(
+                highest_version!()
+                    .select(versions::has_proc_macro)
+                    .single_value()
+                    .eq(proc_macro)
+                    .assume_not_null(),
+            ));
+        }
 
-        let (explicit_page, seek) = match pagination.page {
-            Page::Numeric(_) => (true, None),
-            Page::Seek(ref s) => (false, Some(s.decode::<i32>()?)),
-            Page::Unspecified => (false, None),
+        conditions
+    }
+
+    /// Counts the crates matching the filters per license, category and minimum supported Rust
+    /// version of their highest version that is not yanked.
+    fn facets(&self, conn: &mut PgConnection) -> AppResult<Facets> {
+        use diesel::sql_types::Text;
+
+        let facet_versions = diesel::alias!(versions as facet_versions);
+        let highest_versions = || {
+            versions::table
+                .filter(versions::crate_id.eq_any(self.make_query()))
+                .filter(versions::yanked.eq(false))
+                .distinct_on(versions::crate_id)
+                .order((
+                    versions::crate_id,
+                    versions::semver_no_prerelease.desc().nulls_last(),
+                    versions::id.desc(),
+                ))
+                .select(versions::id)
         };
 
-        // To avoid breaking existing users, seek-based pagination is only used if an explicit page has
-        // not been provided. This way clients relying on meta.next_page will use the faster seek-based
-        // paginations, while client hardcoding pages handling will use the slower offset-based code.
-        let (total, next_page, prev_page, data, conn) = if supports_seek && !explicit_page {
-            // Equivalent of:
-            // `WHERE name > (SELECT name FROM crates WHERE id = $1) LIMIT $2`
-            query = query.limit(pagination.per_page);
-            if let Some(seek) = seek {
-                let crate_name: String = crates::table
-                    .find(seek)
-                    .select(crates::name)
-                    .get_result(conn)?;
-                query = query.filter(crates::name.gt(crate_name));
-            }
-
-            // This does a full index-only scan over the crates table to gather how many crates were
-            // published. Unfortunately on PostgreSQL counting the rows in a table requires scanning
-            // the table, and the `total` field is part of the stable registries API.
-            //
-            // If this becomes a problem in the future the crates count could be denormalized, at least
-            // for the filterless happy path.
-            let total: i64 = info_span!("db.query", message = "SELECT COUNT(*) FROM crates")
-                .in_scope(|| crates::table.count().get_result(conn))?;
-
-            let results: Vec<(Crate, bool, Option<i64>)> =
-                info_span!("db.query", message = "SELECT ... FROM crates")
-                    .in_scope(|| query.load(conn))?;
-
-            let next_page = if let Some(last) = results.last() {
-                let mut params = IndexMap::new();
-                params.insert(
-                    "seek".into(),
-                    crate::controllers::helpers::pagination::encode_seek(last.0.id)?,
-                );
-                Some(req.query_with_params(params))
-            } else {
-                None
-            };
-
-            (total, next_page, None, results, conn)
-        } else {
-            let query = query.pages_pagination(pagination);
-            let data: Paginated<(Crate, bool, Option<i64>)> =
-                info_span!("db.query", message = "SELECT ..., COUNT(*) FROM crates")
-                    .in_scope(|| query.load(conn))?;
-            (
-                data.total(),
-                data.next_page_params().map(|p| req.query_with_params(p)),
-                data.prev_page_params().map(|p| req.query_with_params(p)),
-                data.into_iter().collect::<Vec<_>>(),
-                conn,
-            )
-        };
+        let licenses: Vec<(String, i64)> =
+            info_span!("db.query", message = "SELECT ... FROM versions").in_scope(|| {
+                facet_versions
+                    .filter(
+                        facet_versions
+                            .field(versions::id)
+                            .eq_any(highest_versions()),
+                    )
+                    .select((
+                        sql::<Text>("unnest(facet_versions.license_ids)"),
+                        count_star(),
+                    ))
+                    .group_by(sql::<Text>("1"))
+                    .load(conn)
+            })?;
+
+        let rust_versions: Vec<(Option<String>, i64)> =
+            info_span!("db.query", message = "SELECT ... FROM versions").in_scope(|| {
+                facet_versions
+                    .filter(
+                        facet_versions
+                            .field(versions::id)
+                            .eq_any(highest_versions()),
+                    )
+                    .select((sql::<Nullable<Text>>(RUST_VERSION_BUCKET), count_star()))
+                    .group_by(sql::<Nullable<Text>>("1"))
+                    .load(conn)
+            })?;
+
+        let categories: Vec<(String, i64)> =
+            info_span!("db.query", message = "SELECT ... FROM crates_categories").in_scope(
+                || {
+                    crates_categories::table
+                        .inner_join(categories::table)
+                        .filter(crates_categories::crate_id.eq_any(self.make_query()))
+                        .group_by(categories::slug)
+                        .select((categories::slug, count_star()))
+                        .load(conn)
+                },
+            )?;
 
-        let perfect_matches = data.iter().map(|&(_, b, _)| b).collect::<Vec<_>>();
-        let recent_downloads = data
-            .iter()
-            .map(|&(_, _, s)| s.unwrap_or(0))
+        let mut licenses = licenses
+            .into_iter()
+            .map(|(license, count)| LicenseFacet { license, count })
             .collect::<Vec<_>>();
-        let crates = data.into_iter().map(|(c, _, _)| c).collect::<Vec<_>>();
+        licenses.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.license.cmp(&b.license))
+        });
 
-        let versions: Vec<Version> = info_span!("db.query", message = "SELECT ... FROM versions")
-            .in_scope(|| crates.versions().load(conn))?;
-        let versions = versions
-            .grouped_by(&crates)
+        let mut categories = categories
             .into_iter()
-            .map(TopVersions::from_versions);
+            .map(|(category, count)| CategoryFacet { category, count })
+            .collect::<Vec<_>>();
+        categories.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.category.cmp(&b.category))
+        });
 
-        let crates = versions
-            .zip(crates)
-            .zip(perfect_matches)
-            .zip(recent_downloads)
-            .map(
-                |(((max_version, krate), perfect_match), recent_downloads)| {
-                    EncodableCrate::from_minimal(
-                        krate,
-                        Some(&max_version),
-                        Some(vec![]),
-                        perfect_match,
-                        Some(recent_downloads),
-                    )
-                },
-            )
+        let mut rust_versions = rust_versions
+            .into_iter()
+            .map(|(rust_version, count)| RustVersionFacet {
+                rust_version,
+                count,
+            })
             .collect::<Vec<_>>();
+        rust_versions
+            .sort_by_key(|facet| facet.rust_version.as_deref().and_then(parse_rust_version));
+
+        Ok(Facets {
+            licenses,
+            categories,
+            rust_versions,
+        })
+    }
+}
 
-        Ok(Json(json!({
-            "crates": crates,
-            "meta": {
-                "total": total,
-                "next_page": next_page,
-                "prev_page": prev_page,
-            },
-        })))
-    })
-    .await
+#[derive(Serialize)]
+struct Facets {
+    licenses: Vec<LicenseFacet>,
+    categories: Vec<CategoryFacet>,
+    rust_versions: Vec<RustVersionFacet>,
+}
+
+#[derive(Serialize)]
+struct LicenseFacet {
+    license: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct CategoryFacet {
+    category: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct RustVersionFacet {
+    /// The `major.minor` version, or `None` for crates that don't specify a `rust-version`.
+    rust_version: Option<String>,
+    count: i64,
+}
+
+/// The `rust_version` of a version as an array of its three numeric components, which can be
+/// compared by PostgreSQL. Missing components are filled up with zeros, like for the bounds of
+/// the filter. The values have already been validated on publish, but the query must not fail
+/// if that ever changes, so components with more than 9 digits, which might not fit into an
+/// `int`, are treated like an invalid `rust_version`.
+const RUST_VERSION_ARRAY: &str =
+    "CASE WHEN versions.rust_version ~ '^[0-9]{1,9}(\\.[0-9]{1,9}){0,2}$' \
+    THEN (string_to_array(versions.rust_version, '.')::int[] || '{0,0}'::int[])[1:3] END";
+
+/// The `major.minor` version of the `rust_version` of a version, which the versions are grouped
+/// by in the facets.
+const RUST_VERSION_BUCKET: &str =
+    "CASE WHEN facet_versions.rust_version ~ '^[0-9]{1,9}(\\.[0-9]{1,9}){0,2}$' \
+    THEN split_part(facet_versions.rust_version, '.', 1)::int || '.' \
+    || coalesce(nullif(split_part(facet_versions.rust_version, '.', 2), ''), '0')::int END";
+
+/// Parses a `rust-version` like `1.70` or `1.70.1` into its numeric components.
+fn parse_rust_version(value: &str) -> Option<Vec<i32>> {
+    let parts = value
+        .split('.')
+        .map(|part| part.parse::<i32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    (1..=3).contains(&parts.len()).then_some(parts)
+}
+
+/// Returns the names of the crates that are most similar to the query, for "did you mean"
+/// suggestions.
+fn suggestions(conn: &mut PgConnection, q_string: &str) -> AppResult<Vec<String>> {
+    let suggestions =
+        info_span!("db.query", message = "SELECT name FROM crates").in_scope(|| {
+            crates::table
+                .select(crates::name)
+                .filter(TrigramSimilar::new(
+                    canon_crate_name(crates::name),
+                    canon_crate_name(q_string),
+                ))
+                .filter(not(Crate::with_name(q_string)))
+                .order(TrigramDistance::new(
+                    canon_crate_name(crates::name),
+                    canon_crate_name(q_string),
+                ))
+                .limit(MAX_SUGGESTIONS as i64)
+                .load(conn)
+        })?;
+
+    Ok(suggestions)
 }
 
 diesel::infix_operator!(Contains, "@>");
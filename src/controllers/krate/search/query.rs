@@ -0,0 +1,304 @@
+//! Parser for the query language of the `q` parameter of the `GET /crates` route.
+//!
+//! A query consists of whitespace separated terms:
+//!
+//! - `word`: free text, which is matched against the full text search index and the crate
+//!   names, like a plain query
+//! - `"exact phrase"`: the words must appear in this order
+//! - `!word` or `!"exact phrase"`: excludes crates matching the word or phrase. Unlike `-`,
+//!   `!` can't be part of a crate name, so words like `-sys` are still treated as free text.
+//! - `keyword:async`, `category:network-programming`: the crate must have this keyword or be in
+//!   this category (or one of its subcategories). Both can be used multiple times.
+//! - `license:MIT` or `license:"MIT OR Apache-2.0"`: the license of the crate must be satisfiable
+//!   with the given licenses
+//! - `owner:rust-lang`: the crate must be owned by this user, or a team of this organization
+//! - `msrv:1.60`, `msrv:<=1.60` or `msrv:<1.60`: the `rust-version` of the crate must be at most
+//!   (or below) the given version
+//!
+//! Words with a colon that don't start with one of these qualifiers, like `tokio::spawn`, are
+//! treated as free text.
+
+use super::parse_rust_version;
+use crate::licenses::parse_license_expr;
+use std::iter::Peekable;
+use std::str::Chars;
+
+const KEYWORD: &str = "keyword";
+const CATEGORY: &str = "category";
+const LICENSE: &str = "license";
+const OWNER: &str = "owner";
+const MSRV: &str = "msrv";
+
+const QUALIFIERS: &[&str] = &[KEYWORD, CATEGORY, LICENSE, OWNER, MSRV];
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// The free text words of the query, separated by a single space.
+    pub text: String,
+    pub phrases: Vec<String>,
+    /// Words and phrases that must not match.
+    pub excluded: Vec<String>,
+    /// Lowercased keywords that the crate must have.
+    pub keywords: Vec<String>,
+    /// Category slugs that the crate must be in.
+    pub categories: Vec<String>,
+    pub license: Option<String>,
+    pub owner: Option<String>,
+    pub msrv: Option<RustVersionBound>,
+}
+
+/// An upper bound for the `rust-version` of a crate.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RustVersionBound {
+    /// The numeric components of the version, see `parse_rust_version`.
+    pub version: Vec<i32>,
+    pub inclusive: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum QueryError {
+    #[error("unterminated quote in search query")]
+    UnterminatedQuote,
+    #[error("missing value for `{0}:` in search query")]
+    MissingValue(&'static str),
+    #[error("`{0}:` can only be used once in a search query")]
+    Duplicate(&'static str),
+    #[error("`{0}:` can not be excluded in a search query")]
+    Excluded(&'static str),
+    #[error("invalid `msrv:` value in search query: {0}")]
+    InvalidRustVersion(String),
+    #[error("invalid `license:` expression in search query: {0}")]
+    InvalidLicense(String),
+    #[error("invalid `owner:` value in search query: {0}")]
+    InvalidOwner(String),
+}
+
+pub fn parse(q: &str) -> Result<SearchQuery, QueryError> {
+    let mut query = SearchQuery::default();
+    let mut words = Vec::new();
+    let mut chars = q.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let excluded = chars.next_if_eq(&'!').is_some();
+
+        if chars.next_if_eq(&'"').is_some() {
+            let phrase = read_quoted(&mut chars)?;
+            if phrase.trim().is_empty() {
+                continue;
+            }
+
+            if excluded {
+                query.excluded.push(phrase);
+            } else {
+                query.phrases.push(phrase);
+            }
+            continue;
+        }
+
+        let mut word = String::new();
+        let mut value = None;
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            if c == '"' && word.ends_with(':') && qualifier(&word[..word.len() - 1]).is_some() {
+                value = Some(read_quoted(&mut chars)?);
+                break;
+            }
+            word.push(c);
+        }
+
+        let qualified = word.split_once(':').and_then(|(key, rest)| {
+            let key = qualifier(key)?;
+            Some((key, value.take().unwrap_or_else(|| rest.to_string())))
+        });
+
+        let Some((key, value)) = qualified else {
+            if excluded && !word.is_empty() {
+                query.excluded.push(word);
+            } else if excluded {
+                words.push("!".to_string());
+            } else {
+                words.push(word);
+            }
+            continue;
+        };
+
+        if excluded {
+            return Err(QueryError::Excluded(key));
+        }
+
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(QueryError::MissingValue(key));
+        }
+
+        match key {
+            KEYWORD => query.keywords.push(value.to_lowercase()),
+            CATEGORY => query.categories.push(value.to_string()),
+            LICENSE => {
+                if query.license.is_some() {
+                    return Err(QueryError::Duplicate(key));
+                }
+                if parse_license_expr(value).is_err() {
+                    return Err(QueryError::InvalidLicense(value.to_string()));
+                }
+                query.license = Some(value.to_string());
+            }
+            OWNER => {
+                if query.owner.is_some() {
+                    return Err(QueryError::Duplicate(key));
+                }
+                let is_valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+                if !value.chars().all(is_valid) {
+                    return Err(QueryError::InvalidOwner(value.to_string()));
+                }
+                query.owner = Some(value.to_string());
+            }
+            MSRV => {
+                if query.msrv.is_some() {
+                    return Err(QueryError::Duplicate(key));
+                }
+                query.msrv = Some(parse_msrv(value)?);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    query.text = words.join(" ");
+    Ok(query)
+}
+
+fn qualifier(key: &str) -> Option<&'static str> {
+    QUALIFIERS
+        .iter()
+        .copied()
+        .find(|q| q.eq_ignore_ascii_case(key))
+}
+
+/// Reads the rest of a quoted string, after the opening quote.
+fn read_quoted(chars: &mut Peekable<Chars<'_>>) -> Result<String, QueryError> {
+    let mut value = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(value);
+        }
+        value.push(c);
+    }
+    Err(QueryError::UnterminatedQuote)
+}
+
+fn parse_msrv(value: &str) -> Result<RustVersionBound, QueryError> {
+    let (version, inclusive) = if let Some(version) = value.strip_prefix("<=") {
+        (version, true)
+    } else if let Some(version) = value.strip_prefix('<') {
+        (version, false)
+    } else {
+        (value, true)
+    };
+
+    let version = parse_rust_version(version)
+        .ok_or_else(|| QueryError::InvalidRustVersion(value.to_string()))?;
+
+    Ok(RustVersionBound { version, inclusive })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text() {
+        let query = parse("  serde   json ").unwrap();
+        assert_eq!(query.text, "serde json");
+        assert_eq!(
+            query,
+            SearchQuery {
+                text: query.text.clone(),
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(parse("tokio::spawn").unwrap().text, "tokio::spawn");
+        assert_eq!(parse("foo:bar c++").unwrap().text, "foo:bar c++");
+        assert_eq!(parse("foo-bar - !").unwrap().text, "foo-bar - !");
+
+        // A leading `-` is part of the word, as crate names can contain it
+        let query = parse("-sys openssl").unwrap();
+        assert_eq!(query.text, "-sys openssl");
+        assert!(query.excluded.is_empty());
+    }
+
+    #[test]
+    fn phrases_and_exclusions() {
+        let query = parse(r#"http "web framework" !async !"proc macro" """#).unwrap();
+        assert_eq!(query.text, "http");
+        assert_eq!(query.phrases, vec!["web framework"]);
+        assert_eq!(query.excluded, vec!["async", "proc macro"]);
+
+        assert_eq!(parse(r#"foo "bar"#), Err(QueryError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn qualifiers() {
+        let query = parse(
+            r#"http keyword:Async Category:network-programming keyword:web license:"MIT OR Apache-2.0" owner:rust-lang msrv:<1.60"#,
+        )
+        .unwrap();
+
+        assert_eq!(query.text, "http");
+        assert_eq!(query.keywords, vec!["async", "web"]);
+        assert_eq!(query.categories, vec!["network-programming"]);
+        assert_eq!(query.license.as_deref(), Some("MIT OR Apache-2.0"));
+        assert_eq!(query.owner.as_deref(), Some("rust-lang"));
+        assert_eq!(
+            query.msrv,
+            Some(RustVersionBound {
+                version: vec![1, 60],
+                inclusive: false
+            })
+        );
+
+        let msrv = |q| parse(q).unwrap().msrv.unwrap();
+        assert!(msrv("msrv:1.60").inclusive);
+        assert!(msrv("msrv:<=1.60.1").inclusive);
+        assert_eq!(msrv("msrv:<=1.60.1").version, vec![1, 60, 1]);
+    }
+
+    #[test]
+    fn errors() {
+        let err = |q| parse(q).unwrap_err().to_string();
+
+        assert_eq!(
+            err("keyword:"),
+            "missing value for `keyword:` in search query"
+        );
+        assert_eq!(
+            err(r#"license:"""#),
+            "missing value for `license:` in search query"
+        );
+        assert_eq!(
+            err("owner:foo owner:bar"),
+            "`owner:` can only be used once in a search query"
+        );
+        assert_eq!(
+            err("!keyword:async"),
+            "`keyword:` can not be excluded in a search query"
+        );
+        assert_eq!(
+            err("msrv:>1.60"),
+            "invalid `msrv:` value in search query: >1.60"
+        );
+        assert_eq!(
+            err("license:apache"),
+            "invalid `license:` expression in search query: apache"
+        );
+        assert_eq!(
+            err("owner:foo%"),
+            "invalid `owner:` value in search query: foo%"
+        );
+        assert_eq!(err(r#"license:"MIT"#), "unterminated quote in search query");
+    }
+}
@@ -0,0 +1,177 @@
+//! Endpoint for the autocompletion of search queries
+//!
+//! This is called on every keystroke in the search field of the frontend, so unlike the
+//! `GET /crates` search it only runs a few small queries that can use the trigram indexes on the
+//! names, and the results are cached in memory for each normalized prefix, see
+//! `normalize_prefix`.
+
+use crate::controllers::frontend_prelude::*;
+use crate::schema::{categories, crates, keywords, recent_crate_downloads};
+use crate::sql::{canon_crate_name, escape_like, lower};
+use axum::extract::Query;
+use diesel::helper_types::LeftJoinQuerySource;
+use diesel::pg::Pg;
+use diesel::sql_types::Bool;
+
+/// The maximum number of crates, keywords and categories that are returned each.
+const MAX_PREFIX_SUGGESTIONS: i64 = 10;
+
+/// Longer prefixes are truncated, which is still more than the maximum length of a crate name.
+const MAX_PREFIX_LENGTH: usize = 64;
+
+/// Prefixes with fewer characters than this only return names that start with the prefix, since
+/// the trigram similarity of very short strings is meaningless.
+const MIN_SIMILAR_PREFIX_LENGTH: usize = 3;
+
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    q: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Suggestions {
+    crates: Vec<CrateSuggestion>,
+    keywords: Vec<String>,
+    categories: Vec<CategorySuggestion>,
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+struct CrateSuggestion {
+    name: String,
+    recent_downloads: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize, Queryable)]
+struct CategorySuggestion {
+    slug: String,
+    category: String,
+}
+
+/// Handles the `GET /suggest` route.
+///
+/// Returns the crates that start with or closely match the `q` prefix, ranked by their recent
+/// downloads, and the keywords and categories that start with it, ranked by their number of
+/// crates.
+pub async fn suggest(app: AppState, Query(query): Query<SuggestQuery>) -> AppResult<Json<Value>> {
+    let prefix = normalize_prefix(query.q.as_deref().unwrap_or_default());
+    if prefix.is_empty() {
+        return Ok(Json(json!(Suggestions::default())));
+    }
+
+    let cache_result =
+        info_span!("cache.read", %prefix).in_scope(|| app.suggestions_cacher.get(&prefix));
+
+    if let Some(suggestions) = cache_result {
+        app.instance_metrics.suggestions_cache_hits.inc();
+        return Ok(Json(json!(suggestions)));
+    }
+
+    app.instance_metrics.suggestions_cache_misses.inc();
+
+    conduit_compat(move || {
+        let conn = &mut *app.db_read()?;
+        let suggestions = load_suggestions(conn, &prefix)?;
+
+        info_span!("cache.write", %prefix).in_scope(|| {
+            app.suggestions_cacher
+                .blocking()
+                .insert(prefix, suggestions.clone())
+        });
+
+        Ok(Json(json!(suggestions)))
+    })
+    .await
+}
+
+fn load_suggestions(conn: &mut PgConnection, prefix: &str) -> QueryResult<Suggestions> {
+    diesel::infix_operator!(WordSimilar, " <% ");
+
+    let pattern = format!("{}%", escape_like(prefix));
+    let crate_pattern = format!("{}%", escape_like(&prefix.replace('-', "_")));
+
+    let starts_with = canon_crate_name(crates::name).like(crate_pattern);
+    let mut condition: Box<
+        dyn BoxableExpression<
+            LeftJoinQuerySource<crates::table, recent_crate_downloads::table>,
+            Pg,
+            SqlType = Bool,
+        >,
+    > = Box::new(starts_with.clone());
+    if prefix.chars().count() >= MIN_SIMILAR_PREFIX_LENGTH {
+        let similar = WordSimilar::new(canon_crate_name(prefix), canon_crate_name(crates::name));
+        condition = Box::new(condition.or(similar));
+    }
+
+    let crates = info_span!("db.query", message = "SELECT name FROM crates").in_scope(|| {
+        crates::table
+            .left_join(recent_crate_downloads::table)
+            .filter(condition)
+            .select((crates::name, recent_crate_downloads::downloads.nullable()))
+            .order((
+                starts_with.desc(),
+                recent_crate_downloads::downloads.desc().nulls_last(),
+                crates::name.asc(),
+            ))
+            .limit(MAX_PREFIX_SUGGESTIONS)
+            .load(conn)
+    })?;
+
+    let keywords =
+        info_span!("db.query", message = "SELECT keyword FROM keywords").in_scope(|| {
+            keywords::table
+                .filter(keywords::keyword.like(&pattern))
+                .select(keywords::keyword)
+                .order((keywords::crates_cnt.desc(), keywords::keyword.asc()))
+                .limit(MAX_PREFIX_SUGGESTIONS)
+                .load(conn)
+        })?;
+
+    let categories =
+        info_span!("db.query", message = "SELECT slug FROM categories").in_scope(|| {
+            categories::table
+                .filter(
+                    lower(categories::category)
+                        .like(&pattern)
+                        .or(categories::slug.like(&pattern)),
+                )
+                .select((categories::slug, categories::category))
+                .order((categories::crates_cnt.desc(), categories::slug.asc()))
+                .limit(MAX_PREFIX_SUGGESTIONS)
+                .load(conn)
+        })?;
+
+    Ok(Suggestions {
+        crates,
+        keywords,
+        categories,
+    })
+}
+
+/// Lowercases the prefix and removes all characters that can't appear in crate names, keywords
+/// or categories. This removes the `%` wildcard of `LIKE` patterns, but keeps `_`, so the prefix
+/// still has to be escaped for them.
+fn normalize_prefix(prefix: &str) -> String {
+    let is_allowed = |c: &char| c.is_alphanumeric() || matches!(c, '-' | '_' | '+' | ':' | ' ');
+
+    prefix
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(is_allowed)
+        .take(MAX_PREFIX_LENGTH)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_normalization() {
+        assert_eq!(normalize_prefix("  Serde "), "serde");
+        assert_eq!(normalize_prefix("100%_"), "100_");
+        assert_eq!(normalize_prefix("web prog"), "web prog");
+        assert_eq!(normalize_prefix("a\\b"), "ab");
+        assert_eq!(normalize_prefix(&"a".repeat(100)).len(), MAX_PREFIX_LENGTH);
+    }
+}
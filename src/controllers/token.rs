@@ -1,11 +1,14 @@
 use super::frontend_prelude::*;
 
-use crate::models::ApiToken;
-use crate::schema::api_tokens;
+use crate::app::App;
+use crate::models::token::ApiTokenActivity;
+use crate::models::{ApiToken, NotificationType, User};
+use crate::schema::{api_token_activity, api_tokens};
+use crate::util::errors::not_found;
 use crate::util::rfc3339;
-use crate::views::EncodableApiTokenWithToken;
+use crate::views::{EncodableApiTokenActivity, EncodableApiTokenWithToken};
 
-use crate::auth::AuthCheck;
+use crate::auth::{AuthCheck, Authentication};
 use crate::models::token::{CrateScope, EndpointScope};
 use axum::extract::Query;
 use axum::response::IntoResponse;
@@ -13,8 +16,12 @@ use chrono::NaiveDateTime;
 use diesel::data_types::PgInterval;
 use diesel::dsl::{now, sql, IntervalDsl};
 use diesel::sql_types::{Interval, Timestamp};
+use ipnetwork::IpNetwork;
 use serde_json as json;
 
+/// The maximum number of IP ranges a token can be restricted to.
+const MAX_ALLOWED_IP_RANGES: usize = 100;
+
 #[derive(Deserialize)]
 pub struct GetParams {
     expired_days: Option<i32>,
@@ -38,7 +45,9 @@ pub async fn list(
 ) -> AppResult<Json<Value>> {
     conduit_compat(move || {
         let conn = &mut *app.db_read_prefer_primary()?;
-        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let auth = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ManageTokens)
+            .check(&req, conn)?;
         let user = auth.user();
 
         let tokens: Vec<ApiToken> = ApiToken::belonging_to(user)
@@ -75,6 +84,7 @@ pub async fn new(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
             endpoint_scopes: Option<Vec<String>>,
             #[serde(default, with = "rfc3339::option")]
             expired_at: Option<NaiveDateTime>,
+            allowed_ip_ranges: Option<Vec<String>>,
         }
 
         /// The incoming serialization format for the `ApiToken` model.
@@ -93,8 +103,10 @@ pub async fn new(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
 
         let conn = &mut *app.db_write()?;
 
-        let auth = AuthCheck::default().check(&req, conn)?;
-        if auth.api_token_id().is_some() {
+        let auth = AuthCheck::default()
+            .with_endpoint_scope(EndpointScope::ManageTokens)
+            .check(&req, conn)?;
+        if is_legacy_token(&auth) {
             return Err(bad_request(
                 "cannot use an API token to create a new API token",
             ));
@@ -134,6 +146,21 @@ pub async fn new(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
             .transpose()
             .map_err(|_err| bad_request("invalid endpoint scope"))?;
 
+        let allowed_ip_ranges = new
+            .api_token
+            .allowed_ip_ranges
+            .map(|ranges| parse_ip_ranges(&ranges))
+            .transpose()?;
+
+        if let Some(api_token) = auth.api_token() {
+            ensure_not_broader(
+                api_token,
+                endpoint_scopes.as_deref(),
+                new.api_token.expired_at,
+                allowed_ip_ranges.as_deref(),
+            )?;
+        }
+
         let api_token = ApiToken::insert_with_scopes(
             conn,
             user.id,
@@ -141,7 +168,196 @@ pub async fn new(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
             crate_scopes,
             endpoint_scopes,
             new.api_token.expired_at,
+            allowed_ip_ranges,
         )?;
+
+        if let Err(error) = send_token_created_notification(&app, conn, user, name) {
+            warn!(user_id = %user.id, ?error, "Failed to send token created notification");
+        }
+
+        let api_token = EncodableApiTokenWithToken::from(api_token);
+
+        Ok(Json(json!({ "api_token": api_token })))
+    })
+    .await
+}
+
+/// Parses the allowed IP ranges of a new token, e.g. `192.0.2.0/24` or `2001:db8::1`.
+///
+/// Addresses without a prefix length allow only that single address. Host bits are removed, so
+/// `192.0.2.1/24` is stored as `192.0.2.0/24`.
+fn parse_ip_ranges(ranges: &[String]) -> AppResult<Vec<IpNetwork>> {
+    if ranges.is_empty() {
+        return Err(bad_request("allowed_ip_ranges must not be empty"));
+    }
+
+    if ranges.len() > MAX_ALLOWED_IP_RANGES {
+        return Err(bad_request(&format!(
+            "a token can have at most {MAX_ALLOWED_IP_RANGES} allowed IP ranges"
+        )));
+    }
+
+    ranges
+        .iter()
+        .map(|range| {
+            let range = range
+                .trim()
+                .parse::<IpNetwork>()
+                .map_err(|_| bad_request(&format!("invalid IP range: {range}")))?;
+
+            IpNetwork::new(range.network(), range.prefix())
+                .map_err(|_| bad_request(&format!("invalid IP range: {range}")))
+        })
+        .collect()
+}
+
+/// Legacy tokens without endpoint scopes can not manage tokens, since the permissions of new
+/// tokens could not be limited to their own.
+fn is_legacy_token(auth: &Authentication) -> bool {
+    auth.api_token()
+        .is_some_and(|api_token| api_token.endpoint_scopes.is_none())
+}
+
+/// Returns an error if a token with the given permissions could do anything that `api_token`
+/// can't, so that a token can't be used to create a token with more permissions than its own.
+///
+/// Crate scopes don't need to be compared, since tokens with crate scopes can't be used for
+/// endpoints that don't deal with a specific crate.
+fn ensure_not_broader(
+    api_token: &ApiToken,
+    endpoint_scopes: Option<&[EndpointScope]>,
+    expired_at: Option<NaiveDateTime>,
+    allowed_ip_ranges: Option<&[IpNetwork]>,
+) -> AppResult<()> {
+    let endpoint_scopes_allowed = match (api_token.endpoint_scopes.as_deref(), endpoint_scopes) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(own), Some(requested)) => requested.iter().all(|scope| own.contains(scope)),
+    };
+    if !endpoint_scopes_allowed {
+        return Err(broader_than_own("endpoint scopes"));
+    }
+
+    let expiry_allowed = match (api_token.expired_at, expired_at) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(own), Some(requested)) => requested <= own,
+    };
+    if !expiry_allowed {
+        return Err(broader_than_own("expiration date"));
+    }
+
+    let ip_ranges_allowed = match (api_token.allowed_ip_ranges.as_deref(), allowed_ip_ranges) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(own), Some(requested)) => requested.iter().all(|range| {
+            own.iter()
+                .any(|own| own.contains(range.network()) && own.prefix() <= range.prefix())
+        }),
+    };
+    if !ip_ranges_allowed {
+        return Err(broader_than_own("allowed IP ranges"));
+    }
+
+    Ok(())
+}
+
+fn broader_than_own(permission: &str) -> BoxedAppError {
+    bad_request(&format!(
+        "an API token can not create tokens with broader {permission} than its own"
+    ))
+}
+
+/// Notifies the user about a new token, so that they notice if someone else created it.
+fn send_token_created_notification(
+    app: &App,
+    conn: &mut PgConnection,
+    user: &User,
+    token_name: &str,
+) -> AppResult<()> {
+    let Some(email) = NotificationType::TokenCreated.recipient(conn, user)? else {
+        return Ok(());
+    };
+
+    app.emails
+        .send_token_created_notification(conn, user.id, &email, &user.gh_login, token_name)
+}
+
+/// Handles the `POST /me/tokens/:id/rotate` route.
+///
+/// Creates a new token with the same name, scopes and expiry, and returns its secret. The old
+/// secret keeps working for the configured grace period, so that it can be replaced wherever it
+/// is used without downtime.
+pub async fn rotate(app: AppState, Path(id): Path<i32>, req: Parts) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_write()?;
+
+        let auth = AuthCheck::default()
+            .with_endpoint_scope(EndpointScope::ManageTokens)
+            .check(&req, conn)?;
+        if is_legacy_token(&auth) {
+            return Err(bad_request(
+                "cannot use an API token to rotate an API token",
+            ));
+        }
+
+        let user = auth.user();
+        let grace_period = chrono::Duration::from_std(app.config.token_rotation_grace_period)
+            .map_err(|_| server_error("invalid token rotation grace period"))?;
+
+        let api_token = conn.transaction(|conn| {
+            let old_token: ApiToken = ApiToken::belonging_to(user)
+                .find(id)
+                .filter(api_tokens::revoked.eq(false))
+                .filter(
+                    api_tokens::expired_at
+                        .is_null()
+                        .or(api_tokens::expired_at.gt(now)),
+                )
+                .select(ApiToken::as_select())
+                .for_update()
+                .first(conn)
+                .optional()?
+                .ok_or_else(not_found)?;
+
+            // Otherwise a token could obtain the secret of a token with more permissions.
+            if let Some(api_token) = auth.api_token() {
+                ensure_not_broader(
+                    api_token,
+                    old_token.endpoint_scopes.as_deref(),
+                    old_token.expired_at,
+                    old_token.allowed_ip_ranges.as_deref(),
+                )?;
+            }
+
+            // Token expiry is checked against the database clock, so that's used here as well.
+            let grace_period_end =
+                diesel::select(now).get_result::<NaiveDateTime>(conn)? + grace_period;
+            let old_expired_at = match old_token.expired_at {
+                Some(expired_at) if expired_at < grace_period_end => expired_at,
+                _ => grace_period_end,
+            };
+
+            diesel::update(api_tokens::table.find(old_token.id))
+                .set(api_tokens::expired_at.eq(old_expired_at))
+                .execute(conn)?;
+
+            ApiToken::insert_with_scopes(
+                conn,
+                user.id,
+                &old_token.name,
+                old_token.crate_scopes,
+                old_token.endpoint_scopes,
+                old_token.expired_at,
+                old_token.allowed_ip_ranges,
+            )
+        })?;
+
+        let name = &api_token.model.name;
+        if let Err(error) = send_token_created_notification(&app, conn, user, name) {
+            warn!(user_id = %user.id, ?error, "Failed to send token created notification");
+        }
+
         let api_token = EncodableApiTokenWithToken::from(api_token);
 
         Ok(Json(json!({ "api_token": api_token })))
@@ -149,6 +365,43 @@ pub async fn new(app: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
     .await
 }
 
+/// Handles the `GET /me/tokens/:id/activity` route.
+///
+/// Returns the most recent uses of the token, newest first. Uses from the last minute might not
+/// be included yet, since the activity log is written in batches.
+pub async fn activity(app: AppState, Path(id): Path<i32>, req: Parts) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_read_prefer_primary()?;
+        let auth = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ManageTokens)
+            .check(&req, conn)?;
+        let user = auth.user();
+
+        let api_token: ApiToken = ApiToken::belonging_to(user)
+            .find(id)
+            .select(ApiToken::as_select())
+            .first(conn)
+            .optional()?
+            .ok_or_else(not_found)?;
+
+        let activity: Vec<ApiTokenActivity> = ApiTokenActivity::belonging_to(&api_token)
+            .select(ApiTokenActivity::as_select())
+            .order((
+                api_token_activity::created_at.desc(),
+                api_token_activity::id.desc(),
+            ))
+            .load(conn)?;
+
+        let activity = activity
+            .into_iter()
+            .map(EncodableApiTokenActivity::from)
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "activity": activity })))
+    })
+    .await
+}
+
 /// Handles the `DELETE /me/tokens/:id` route.
 pub async fn revoke(app: AppState, Path(id): Path<i32>, req: Parts) -> AppResult<Json<Value>> {
     conduit_compat(move || {
@@ -1,4 +1,5 @@
 use crate::auth::AuthCheck;
+use crate::models::token::EndpointScope;
 use std::collections::HashMap;
 
 use crate::controllers::frontend_prelude::*;
@@ -6,22 +7,35 @@ use crate::controllers::frontend_prelude::*;
 use crate::controllers::helpers::*;
 
 use crate::controllers::helpers::pagination::{Paginated, PaginationOptions};
+use crate::email::Subscription;
 use crate::models::{
-    CrateOwner, Email, Follow, NewEmail, OwnerKind, User, Version, VersionOwnerAction,
+    CrateOwner, Email, Follow, NewEmail, NotificationType, OwnerKind, User, Version,
+    VersionOwnerAction,
 };
 use crate::schema::{crate_owners, crates, emails, follows, users, versions};
-use crate::views::{EncodableMe, EncodablePrivateUser, EncodableVersion, OwnedCrate};
+use crate::sql::lower;
+use crate::util::errors::not_found;
+use crate::views::{
+    EncodableEmail, EncodableMe, EncodablePrivateUser, EncodableVersion, OwnedCrate,
+};
 
 /// Handles the `GET /me` route.
 pub async fn me(app: AppState, req: Parts) -> AppResult<Json<EncodableMe>> {
     conduit_compat(move || {
         let conn = &mut *app.db_read_prefer_primary()?;
-        let user_id = AuthCheck::only_cookie().check(&req, conn)?.user_id();
+        let user_id = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ReadAccount)
+            .check(&req, conn)?
+            .user_id();
 
         let (user, verified, email, verification_sent): (User, Option<bool>, Option<String>, bool) =
             users::table
                 .find(user_id)
-                .left_join(emails::table)
+                .left_join(
+                    emails::table.on(emails::user_id
+                        .eq(users::id)
+                        .and(emails::is_primary.eq(true))),
+                )
                 .select((
                     users::all_columns,
                     emails::verified.nullable(),
@@ -58,7 +72,9 @@ pub async fn me(app: AppState, req: Parts) -> AppResult<Json<EncodableMe>> {
 pub async fn updates(app: AppState, req: Parts) -> AppResult<Json<Value>> {
     conduit_compat(move || {
         let conn = &mut app.db_read_prefer_primary()?;
-        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let auth = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ReadAccount)
+            .check(&req, conn)?;
         let user = auth.user();
 
         let followed_crates = Follow::belonging_to(user).select(follows::crate_id);
@@ -97,14 +113,18 @@ pub async fn updates(app: AppState, req: Parts) -> AppResult<Json<Value>> {
 }
 
 /// Handles the `PUT /users/:user_id` route.
+///
+/// Replaces the primary email address of the user, or adds it if the user has no email
+/// addresses yet.
 pub async fn update_user(
     state: AppState,
     Path(param_user_id): Path<i32>,
     req: BytesRequest,
 ) -> AppResult<Response> {
     conduit_compat(move || {
-        use self::emails::user_id;
+        use diesel::dsl::sql;
         use diesel::insert_into;
+        use diesel::sql_types::Integer;
 
         let conn = &mut state.db_write()?;
 
@@ -139,14 +159,42 @@ pub async fn update_user(
         }
 
         conn.transaction::<_, BoxedAppError, _>(|conn| {
+            let secondary: Option<Email> = Email::belonging_to(user)
+                .filter(lower(emails::email).eq(user_email.to_lowercase()))
+                .filter(emails::is_primary.eq(false))
+                .first(conn)
+                .optional()?;
+
+            // If the address was already added as a secondary address, that row replaces the
+            // primary address, because every address can only be added once.
+            if let Some(email) = secondary {
+                diesel::delete(Email::belonging_to(user).filter(emails::is_primary.eq(true)))
+                    .execute(conn)?;
+                diesel::update(&email)
+                    .set(emails::is_primary.eq(true))
+                    .execute(conn)?;
+
+                if !email.verified {
+                    state.emails.send_user_confirm(
+                        conn,
+                        &email.email,
+                        &user.gh_login,
+                        &email.token,
+                    )?;
+                }
+
+                return Ok(());
+            }
+
             let new_email = NewEmail {
                 user_id: user.id,
                 email: user_email,
+                is_primary: true,
             };
 
             let token: String = insert_into(emails::table)
                 .values(&new_email)
-                .on_conflict(user_id)
+                .on_conflict(sql::<Integer>("(user_id) WHERE is_primary"))
                 .do_update()
                 .set(&new_email)
                 .returning(emails::token)
@@ -159,7 +207,7 @@ pub async fn update_user(
             // email. They'll then have to provide a valid email address.
             let _ = state
                 .emails
-                .send_user_confirm(user_email, &user.gh_login, &token);
+                .send_user_confirm(conn, user_email, &user.gh_login, &token);
 
             Ok(())
         })?;
@@ -170,6 +218,9 @@ pub async fn update_user(
 }
 
 /// Handles the `PUT /confirm/:email_token` route
+///
+/// Every email address has its own token, so this only confirms the address that the
+/// confirmation email was sent to.
 pub async fn confirm_user_email(state: AppState, Path(token): Path<String>) -> AppResult<Response> {
     conduit_compat(move || {
         use diesel::update;
@@ -189,7 +240,57 @@ pub async fn confirm_user_email(state: AppState, Path(token): Path<String>) -> A
     .await
 }
 
+/// Handles the `POST /unsubscribe/:token` route.
+///
+/// The token comes from the unsubscribe link of a notification email, so the request does not
+/// need to be authenticated. Mail clients also use this route for the one-click unsubscribe of
+/// RFC 8058, sending `List-Unsubscribe=One-Click` as the request body, which is ignored.
+pub async fn unsubscribe(state: AppState, Path(token): Path<String>) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let Some((user_id, subscription)) = state.emails.verify_unsubscribe_token(&token) else {
+            return Err(bad_request("invalid or expired unsubscribe link"));
+        };
+
+        let conn = &mut *state.db_write()?;
+
+        let msg = match subscription {
+            Subscription::Crate(crate_id) => {
+                diesel::update(
+                    crate_owners::table
+                        .filter(crate_owners::crate_id.eq(crate_id))
+                        .filter(crate_owners::owner_id.eq(user_id))
+                        .filter(crate_owners::owner_kind.eq(OwnerKind::User as i32)),
+                )
+                .set(crate_owners::email_notifications.eq(false))
+                .execute(conn)?;
+
+                let crate_name = crates::table
+                    .find(crate_id)
+                    .select(crates::name)
+                    .first::<String>(conn)
+                    .optional()?
+                    .unwrap_or_else(|| "this crate".to_string());
+
+                format!("You will no longer receive notifications about new, yanked and unyanked versions of {crate_name}.")
+            }
+            Subscription::Notification(notification_type) => {
+                notification_type.set_enabled_for(conn, user_id, false)?;
+
+                format!(
+                    "You will no longer receive {}.",
+                    notification_type.description()
+                )
+            }
+        };
+
+        Ok(Json(json!({ "ok": true, "msg": msg })))
+    })
+    .await
+}
+
 /// Handles `PUT /user/:user_id/resend` route
+///
+/// Resends the confirmation email for the primary email address of the user.
 pub async fn regenerate_token_and_send(
     state: AppState,
     Path(param_user_id): Path<i32>,
@@ -210,14 +311,186 @@ pub async fn regenerate_token_and_send(
         }
 
         conn.transaction(|conn| {
-            let email: Email = update(Email::belonging_to(user))
-                .set(emails::token.eq(sql("DEFAULT")))
+            let email: Email =
+                update(Email::belonging_to(user).filter(emails::is_primary.eq(true)))
+                    .set(emails::token.eq(sql("DEFAULT")))
+                    .get_result(conn)
+                    .map_err(|_| bad_request("Email could not be found"))?;
+
+            state
+                .emails
+                .send_user_confirm(conn, &email.email, &user.gh_login, &email.token)
+        })?;
+
+        ok_true()
+    })
+    .await
+}
+
+/// Handles the `GET /me/emails` route.
+pub async fn list_emails(app: AppState, req: Parts) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_read_prefer_primary()?;
+        let auth = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ReadAccount)
+            .check(&req, conn)?;
+
+        let emails = auth
+            .user()
+            .all_emails(conn)?
+            .into_iter()
+            .map(EncodableEmail::from)
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "emails": emails })))
+    })
+    .await
+}
+
+/// Handles the `POST /me/emails` route.
+///
+/// Adds an email address to the account of the user and sends a confirmation email to it. The
+/// first email address of a user becomes the primary address.
+pub async fn add_email(state: AppState, req: BytesRequest) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        use diesel::insert_into;
+
+        #[derive(Deserialize)]
+        struct NewEmailRequest {
+            email: String,
+        }
+
+        let new_email: NewEmailRequest =
+            serde_json::from_slice(req.body()).map_err(|_| bad_request("invalid json request"))?;
+
+        let email = new_email.email.trim();
+        if email.is_empty() {
+            return Err(bad_request("empty email rejected"));
+        }
+
+        let conn = &mut *state.db_write()?;
+        // API tokens can't be used here, so that a leaked token can't redirect the notifications
+        // about its own misuse.
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let user = auth.user();
+
+        let email = conn.transaction::<_, BoxedAppError, _>(|conn| {
+            let has_primary = diesel::select(diesel::dsl::exists(
+                Email::belonging_to(user).filter(emails::is_primary.eq(true)),
+            ))
+            .get_result::<bool>(conn)?;
+
+            let new_email = NewEmail {
+                user_id: user.id,
+                email,
+                is_primary: !has_primary,
+            };
+
+            let email: Email = insert_into(emails::table)
+                .values(&new_email)
+                .on_conflict_do_nothing()
                 .get_result(conn)
-                .map_err(|_| bad_request("Email could not be found"))?;
+                .optional()?
+                .ok_or_else(|| bad_request("this email address was already added"))?;
 
             state
                 .emails
-                .send_user_confirm(&email.email, &user.gh_login, &email.token)
+                .send_user_confirm(conn, &email.email, &user.gh_login, &email.token)?;
+
+            Ok(email)
+        })?;
+
+        Ok(Json(json!({ "email": EncodableEmail::from(email) })))
+    })
+    .await
+}
+
+/// Handles the `DELETE /me/emails/:email_id` route.
+///
+/// The primary email address can only be removed after another address became the primary one.
+pub async fn remove_email(
+    state: AppState,
+    Path(email_id): Path<i32>,
+    req: Parts,
+) -> AppResult<Response> {
+    conduit_compat(move || {
+        let conn = &mut *state.db_write()?;
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+
+        let email = find_email(conn, auth.user(), email_id)?;
+        if email.is_primary {
+            return Err(bad_request("the primary email address can't be removed"));
+        }
+
+        diesel::delete(&email).execute(conn)?;
+
+        ok_true()
+    })
+    .await
+}
+
+/// Handles the `PUT /me/emails/:email_id/primary` route.
+///
+/// Notification emails are sent to the new primary address from now on, so it has to be verified.
+pub async fn set_primary_email(
+    state: AppState,
+    Path(email_id): Path<i32>,
+    req: Parts,
+) -> AppResult<Response> {
+    conduit_compat(move || {
+        let conn = &mut *state.db_write()?;
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let user = auth.user();
+
+        let email = find_email(conn, user, email_id)?;
+        if !email.verified {
+            return Err(bad_request(
+                "only verified email addresses can become the primary address",
+            ));
+        }
+
+        conn.transaction(|conn| {
+            // The old primary address has to go first, as there can only be one at any time
+            diesel::update(Email::belonging_to(user).filter(emails::is_primary.eq(true)))
+                .set(emails::is_primary.eq(false))
+                .execute(conn)?;
+
+            diesel::update(&email)
+                .set(emails::is_primary.eq(true))
+                .execute(conn)
+        })?;
+
+        ok_true()
+    })
+    .await
+}
+
+/// Handles the `PUT /me/emails/:email_id/resend` route.
+pub async fn resend_email_confirmation(
+    state: AppState,
+    Path(email_id): Path<i32>,
+    req: Parts,
+) -> AppResult<Response> {
+    conduit_compat(move || {
+        use diesel::dsl::sql;
+
+        let conn = &mut *state.db_write()?;
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let user = auth.user();
+
+        let email = find_email(conn, user, email_id)?;
+        if email.verified {
+            return Err(bad_request("this email address is already verified"));
+        }
+
+        conn.transaction(|conn| {
+            let email: Email = diesel::update(&email)
+                .set(emails::token.eq(sql("DEFAULT")))
+                .get_result(conn)?;
+
+            state
+                .emails
+                .send_user_confirm(conn, &email.email, &user.gh_login, &email.token)
         })?;
 
         ok_true()
@@ -225,6 +498,14 @@ pub async fn regenerate_token_and_send(
     .await
 }
 
+fn find_email(conn: &mut PgConnection, user: &User, email_id: i32) -> AppResult<Email> {
+    Email::belonging_to(user)
+        .filter(emails::id.eq(email_id))
+        .first(conn)
+        .optional()?
+        .ok_or_else(not_found)
+}
+
 /// Handles `PUT /me/email_notifications` route
 pub async fn update_email_notifications(app: AppState, req: BytesRequest) -> AppResult<Response> {
     conduit_compat(move || {
@@ -279,3 +560,63 @@ pub async fn update_email_notifications(app: AppState, req: BytesRequest) -> App
     })
     .await
 }
+
+/// Handles the `GET /me/notification_settings` route.
+pub async fn notification_settings(app: AppState, req: Parts) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_read_prefer_primary()?;
+        let auth = AuthCheck::only_cookie()
+            .or_scoped_token(EndpointScope::ReadAccount)
+            .check(&req, conn)?;
+
+        let settings = encode_notification_settings(conn, auth.user_id())?;
+        Ok(Json(json!({ "notification_settings": settings })))
+    })
+    .await
+}
+
+/// Handles the `PUT /me/notification_settings` route.
+///
+/// Notification types that are missing in the request body keep their current setting.
+pub async fn update_notification_settings(
+    app: AppState,
+    req: BytesRequest,
+) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        #[derive(Deserialize)]
+        struct NotificationSettingsUpdate {
+            notification_settings: HashMap<NotificationType, bool>,
+        }
+
+        let update: NotificationSettingsUpdate =
+            serde_json::from_slice(req.body()).map_err(|_| bad_request("invalid json request"))?;
+
+        let conn = &mut *app.db_write()?;
+        // API tokens can't be used here, so that a leaked token can't disable the notifications
+        // about its own misuse.
+        let user_id = AuthCheck::only_cookie().check(&req, conn)?.user_id();
+
+        conn.transaction(|conn| {
+            for (notification_type, enabled) in update.notification_settings {
+                notification_type.set_enabled_for(conn, user_id, enabled)?;
+            }
+            QueryResult::Ok(())
+        })?;
+
+        let settings = encode_notification_settings(conn, user_id)?;
+        Ok(Json(json!({ "notification_settings": settings })))
+    })
+    .await
+}
+
+fn encode_notification_settings(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> AppResult<HashMap<NotificationType, bool>> {
+    let enabled = NotificationType::enabled_for(conn, user_id)?;
+
+    Ok(NotificationType::VARIANTS
+        .iter()
+        .map(|notification_type| (*notification_type, enabled.contains(notification_type)))
+        .collect())
+}
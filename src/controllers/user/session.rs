@@ -1,15 +1,19 @@
 use crate::controllers::frontend_prelude::*;
 
+use diesel::dsl::now;
 use oauth2::reqwest::http_client;
 use oauth2::{AuthorizationCode, Scope, TokenResponse};
+use secrecy::ExposeSecret;
 
+use crate::auth::AuthCheck;
+use crate::controllers::util::real_ip;
 use crate::email::Emails;
 use crate::github::GithubUser;
 use crate::middleware::session::SessionExtension;
-use crate::models::{NewUser, User};
-use crate::schema::users;
-use crate::util::errors::ReadOnlyMode;
-use crate::views::EncodableMe;
+use crate::models::{NewUser, Session, User};
+use crate::schema::{sessions, users};
+use crate::util::errors::{not_found, ReadOnlyMode};
+use crate::views::{EncodableMe, EncodableSession};
 
 /// Handles the `GET /api/private/session/begin` route.
 ///
@@ -101,11 +105,21 @@ pub async fn authorize(
 
         // Fetch the user info from GitHub using the access token we just got and create a user record
         let ghuser = app.github.current_user(token)?;
-        let user =
-            save_user_to_database(&ghuser, token.secret(), &app.emails, &mut *app.db_write()?)?;
+        let conn = &mut *app.db_write()?;
+        let user = save_user_to_database(&ghuser, token.secret(), &app.emails, conn)?;
 
-        // Log in by setting a cookie and the middleware authentication
-        session.insert("user_id".to_string(), user.id.to_string());
+        // Log in by creating a session and storing its token in the session cookie, which is
+        // checked by the middleware authentication
+        let user_agent = req
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let created = Session::insert(conn, user.id, real_ip(&req), user_agent)?;
+        session.insert(
+            "session_id".to_string(),
+            created.plaintext.expose_secret().to_string(),
+        );
 
         Ok(req)
     })
@@ -145,9 +159,97 @@ fn save_user_to_database(
 }
 
 /// Handles the `DELETE /api/private/session` route.
-pub async fn logout(session: SessionExtension) -> Json<bool> {
-    session.remove("user_id");
-    Json(true)
+pub async fn logout(app: AppState, session: SessionExtension) -> AppResult<Json<bool>> {
+    conduit_compat(move || {
+        if let Some(token) = session.remove("session_id") {
+            Session::delete_by_token(&mut *app.db_write()?, &token)?;
+        }
+
+        // Legacy cookies that were not migrated to a server-side session yet
+        session.remove("user_id");
+
+        Ok(Json(true))
+    })
+    .await
+}
+
+/// Handles the `GET /me/sessions` route.
+///
+/// Lists the sessions of the current user that did not expire yet, most recently used first.
+pub async fn list(app: AppState, req: Parts) -> AppResult<Json<Value>> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_read_prefer_primary()?;
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+        let current_session_id = auth.session_id();
+
+        let sessions: Vec<Session> = Session::active()
+            .filter(sessions::user_id.eq(auth.user_id()))
+            .select(Session::as_select())
+            .order((sessions::last_seen_at.desc(), sessions::id.desc()))
+            .load(conn)?;
+
+        let sessions = sessions
+            .into_iter()
+            .map(|session| EncodableSession::from(session, current_session_id))
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "sessions": sessions })))
+    })
+    .await
+}
+
+/// Handles the `DELETE /me/sessions/:id` route.
+///
+/// Logs out a single session of the current user, e.g. on a lost device.
+pub async fn revoke(app: AppState, Path(id): Path<i32>, req: Parts) -> AppResult<Response> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_write()?;
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+
+        let deleted = diesel::delete(
+            sessions::table
+                .filter(sessions::id.eq(id))
+                .filter(sessions::user_id.eq(auth.user_id())),
+        )
+        .execute(conn)?;
+
+        if deleted == 0 {
+            return Err(not_found());
+        }
+
+        ok_true()
+    })
+    .await
+}
+
+/// Handles the `DELETE /me/sessions` route.
+///
+/// Logs out all sessions of the current user, including the one of this request.
+pub async fn revoke_all(
+    app: AppState,
+    session: SessionExtension,
+    req: Parts,
+) -> AppResult<Response> {
+    conduit_compat(move || {
+        let conn = &mut *app.db_write()?;
+        let auth = AuthCheck::only_cookie().check(&req, conn)?;
+
+        conn.transaction(|conn| {
+            diesel::delete(sessions::table.filter(sessions::user_id.eq(auth.user_id())))
+                .execute(conn)?;
+
+            // Legacy cookies don't have a server-side session that could be deleted
+            diesel::update(users::table.find(auth.user_id()))
+                .set(users::sessions_revoked_at.eq(now))
+                .execute(conn)
+        })?;
+
+        session.remove("session_id");
+        session.remove("user_id");
+
+        ok_true()
+    })
+    .await
 }
 
 #[cfg(test)]
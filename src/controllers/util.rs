@@ -1,7 +1,10 @@
 use super::prelude::*;
+use crate::headers::XRealIp;
 use crate::util::errors::{forbidden, internal, AppError, AppResult};
+use axum::headers::HeaderMapExt;
 use http::request::Parts;
 use http::{Extensions, HeaderMap, HeaderValue, Method, Request, Uri, Version};
+use std::net::IpAddr;
 
 /// The Origin header (<https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Origin>)
 /// is sent with CORS requests and POST requests, and indicates where the request comes from.
@@ -25,6 +28,14 @@ pub fn verify_origin<T: RequestPartsExt>(req: &T) -> AppResult<()> {
     Ok(())
 }
 
+/// Returns the IP address of the client, as forwarded by the load balancer in the `X-Real-Ip`
+/// header.
+pub fn real_ip<T: RequestPartsExt>(req: &T) -> Option<IpAddr> {
+    req.headers()
+        .typed_get::<XRealIp>()
+        .and_then(|real_ip| real_ip.as_str().trim().parse().ok())
+}
+
 pub trait RequestPartsExt {
     fn method(&self) -> &Method;
     fn uri(&self) -> &Uri;
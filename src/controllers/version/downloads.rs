@@ -6,10 +6,10 @@ use super::version_and_crate;
 use crate::controllers::prelude::*;
 use crate::db::PoolError;
 use crate::middleware::log_request::RequestLogExt;
-use crate::models::{Crate, VersionDownload};
+use crate::models::{Crate, DownloadClient, MonthlyVersionDownload, VersionDownload};
 use crate::schema::*;
 use crate::views::EncodableVersionDownload;
-use chrono::{Duration, NaiveDate, Utc};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
 
 /// Handles the `GET /crates/:crate_id/:version/download` route.
 /// This returns a URL to the location where the crate is stored.
@@ -20,6 +20,9 @@ pub async fn download(
 ) -> AppResult<Response> {
     let wants_json = req.wants_json();
 
+    let user_agent = req.headers.get(header::USER_AGENT);
+    let client = DownloadClient::from_user_agent(user_agent.and_then(|ua| ua.to_str().ok()));
+
     let cache_key = (crate_name.to_string(), version.to_string());
 
     let cache_result =
@@ -28,9 +31,7 @@ pub async fn download(
     let (crate_name, version) = if let Some(version_id) = cache_result {
         app.instance_metrics.version_id_cache_hits.inc();
 
-        // The increment does not happen instantly, but it's deferred to be executed in a batch
-        // along with other downloads. See crate::downloads_counter for the implementation.
-        app.downloads_counter.increment(version_id);
+        count_download(&app, version_id, client);
 
         (crate_name, version)
     } else {
@@ -73,9 +74,7 @@ pub async fn download(
                         )
                     })?;
 
-                // The increment does not happen instantly, but it's deferred to be executed in a batch
-                // along with other downloads. See crate::downloads_counter for the implementation.
-                app.downloads_counter.increment(version_id);
+                count_download(&app, version_id, client);
 
                 if canonical_crate_name != crate_name {
                     app.instance_metrics
@@ -133,6 +132,20 @@ pub async fn download(
     }
 }
 
+/// Counts a download of the version through this endpoint.
+///
+/// The increment does not happen instantly, but it's deferred to be executed in a batch along
+/// with other downloads. See crate::downloads_counter for the implementation.
+///
+/// With `cdn_log_downloads` enabled the version downloads are counted from the CDN access logs
+/// instead, which also contain the requests redirected from here, so only the client is counted.
+fn count_download(app: &AppState, version_id: i32, client: DownloadClient) {
+    if !app.config.cdn_log_downloads {
+        app.downloads_counter.increment(version_id);
+    }
+    app.downloads_counter.increment_client(version_id, client);
+}
+
 /// Handles the `GET /crates/:crate_id/:version/downloads` route.
 pub async fn downloads(
     app: AppState,
@@ -154,7 +167,7 @@ pub async fn downloads(
             .unwrap_or_else(|| Utc::now().date_naive());
         let cutoff_start_date = cutoff_end_date - Duration::days(89);
 
-        let downloads = VersionDownload::belonging_to(&version)
+        let mut downloads = VersionDownload::belonging_to(&version)
             .filter(version_downloads::date.between(cutoff_start_date, cutoff_end_date))
             .order(version_downloads::date)
             .load(conn)?
@@ -162,6 +175,19 @@ pub async fn downloads(
             .map(VersionDownload::into)
             .collect::<Vec<EncodableVersionDownload>>();
 
+        // Downloads older than the archival horizon are only available per month, and are
+        // returned as a single entry on the first day of the month, with a `month` interval. The
+        // month the range starts in is included as a whole, like in the crate downloads.
+        let cutoff_start_month = cutoff_start_date.with_day(1).unwrap_or(cutoff_start_date);
+        let archived = MonthlyVersionDownload::belonging_to(&version)
+            .filter(version_downloads_monthly::month.between(cutoff_start_month, cutoff_end_date))
+            .load(conn)?;
+
+        if !archived.is_empty() {
+            downloads.extend(archived.into_iter().map(MonthlyVersionDownload::into));
+            downloads.sort_by(|a, b| a.date.cmp(&b.date));
+        }
+
         Ok(Json(json!({ "version_downloads": downloads })))
     })
     .await
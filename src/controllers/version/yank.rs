@@ -5,6 +5,7 @@ use crate::background_jobs::Job;
 
 use super::version_and_crate;
 use crate::controllers::cargo_prelude::*;
+use crate::controllers::util::real_ip;
 use crate::models::token::EndpointScope;
 use crate::models::Rights;
 use crate::models::{insert_version_owner_action, VersionAction};
@@ -87,7 +88,9 @@ fn modify_yank(
         VersionAction::Unyank
     };
 
-    insert_version_owner_action(conn, version.id, user.id, api_token_id, action)?;
+    let owner_action =
+        insert_version_owner_action(conn, version.id, user.id, api_token_id, action)?;
+    Job::send_version_action_notifications(owner_action.id, real_ip(req)).enqueue(conn)?;
 
     Job::enqueue_sync_to_index(&krate.name, conn)?;
 
@@ -1,8 +1,12 @@
+use crate::models::DownloadClient;
 use crate::App;
 use anyhow::Error;
 use dashmap::{DashMap, SharedValue};
 use diesel::{pg::upsert::excluded, prelude::*};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 
 /// crates.io receives a lot of download requests, and we can't execute a write query to the
@@ -18,38 +22,76 @@ use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 /// DashMaps: to avoid locking all the download requests at the same time each iteration only
 /// persists a single shard at the time.
 ///
+/// Alongside the per-version counts, the downloads are also counted per client (see
+/// `DownloadClient`) and aggregated into daily per-crate rows of the `crate_downloads_by_client`
+/// table, using the same batching.
+///
 /// The disadvantage of this approach is that download counts are stored in memory until they're
 /// persisted, so it's possible to lose some of them if the process exits ungracefully. While
 /// that's far from ideal, the advantage of batching database updates far outweights potentially
 /// losing some download counts.
+///
+/// To reduce the amount of lost downloads, a snapshot file path can be configured. Every persist
+/// cycle takes the shards it's about to persist out of memory, and then writes the pending counts
+/// of all the other shards to the snapshot once, which is then replayed by `replay_snapshot` when
+/// the process starts again. The snapshot is written *before* the database update, so a crash
+/// between the two can lose the downloads of the shards in that cycle (a single one for
+/// `persist_next_shard`), but will never count downloads twice. If the database update fails
+/// instead, the downloads of the shards are put back into memory and into the snapshot, to be
+/// retried later.
 #[derive(Debug)]
 pub struct DownloadsCounter {
     /// Inner storage for the download counts.
     inner: DashMap<i32, AtomicUsize>,
+    /// Inner storage for the download counts, broken down by the client that requested them.
+    clients: DashMap<(i32, DownloadClient), AtomicUsize>,
     /// Index of the next shard that should be persisted by `persist_next_shard`.
     shard_idx: AtomicUsize,
     /// Number of downloads that are not yet persisted on the database. This is just used as a
     /// metric included in log lines, and it's not guaranteed to be accurate.
     pending_count: AtomicI64,
+    /// Path of the file the pending download counts are periodically written to, if enabled.
+    snapshot_path: Option<PathBuf>,
+    /// Number of downloads that were restored from the snapshot on startup.
+    replayed_count: AtomicUsize,
+    /// Number of downloads that were skipped on startup, because their snapshot line was malformed.
+    skipped_count: AtomicUsize,
 }
 
 impl DownloadsCounter {
     pub(crate) fn new() -> Self {
         Self {
             inner: DashMap::new(),
+            clients: DashMap::new(),
             shard_idx: AtomicUsize::new(0),
             pending_count: AtomicI64::new(0),
+            snapshot_path: None,
+            replayed_count: AtomicUsize::new(0),
+            skipped_count: AtomicUsize::new(0),
         }
     }
 
+    pub(crate) fn with_snapshot_path(mut self, snapshot_path: Option<PathBuf>) -> Self {
+        self.snapshot_path = snapshot_path;
+        self
+    }
+
     pub(crate) fn increment(&self, version_id: i32) {
-        self.pending_count.fetch_add(1, Ordering::SeqCst);
+        self.add(version_id, 1);
+    }
 
+    fn add(&self, version_id: i32, count: usize) {
+        self.pending_count.fetch_add(count as i64, Ordering::SeqCst);
+        self.add_to_shard(version_id, count);
+    }
+
+    /// Adds the downloads to the inner storage without updating the pending count.
+    fn add_to_shard(&self, version_id: i32, count: usize) {
         if let Some(counter) = self.inner.get(&version_id) {
             // The version is already recorded in the DashMap, so we don't need to lock the whole
             // shard in write mode. The shard is instead locked in read mode, which allows an
             // unbounded number of readers as long as there are no write locks.
-            counter.value().fetch_add(1, Ordering::SeqCst);
+            counter.value().fetch_add(count, Ordering::SeqCst);
         } else {
             // The version is not in the DashMap, so we need to lock the whole shard in write mode
             // and insert the version into it. This has worse performance than the above case.
@@ -58,9 +100,29 @@ impl DownloadsCounter {
                 .and_modify(|counter| {
                     // Handle the version being inserted by another thread while we were waiting
                     // for the write lock on the shard.
-                    counter.fetch_add(1, Ordering::SeqCst);
+                    counter.fetch_add(count, Ordering::SeqCst);
+                })
+                .or_insert_with(|| AtomicUsize::new(count));
+        }
+    }
+
+    /// Records which client performed a download of the version. Unlike `increment` this does not
+    /// count towards the total downloads of the version, and it's not reflected in the pending
+    /// count.
+    pub(crate) fn increment_client(&self, version_id: i32, client: DownloadClient) {
+        self.add_client((version_id, client), 1);
+    }
+
+    fn add_client(&self, key: (i32, DownloadClient), count: usize) {
+        if let Some(counter) = self.clients.get(&key) {
+            counter.value().fetch_add(count, Ordering::SeqCst);
+        } else {
+            self.clients
+                .entry(key)
+                .and_modify(|counter| {
+                    counter.fetch_add(count, Ordering::SeqCst);
                 })
-                .or_insert_with(|| AtomicUsize::new(1));
+                .or_insert_with(|| AtomicUsize::new(count));
         }
     }
 
@@ -75,13 +137,51 @@ impl DownloadsCounter {
     }
 
     fn persist_all_shards_with_conn(&self, conn: &mut PgConnection) -> Result<PersistStats, Error> {
+        let shards = self
+            .inner
+            .shards()
+            .iter()
+            .map(|shard| std::mem::take(&mut *shard.write()))
+            .collect::<Vec<_>>();
+        let client_shards = self
+            .clients
+            .shards()
+            .iter()
+            .map(|shard| std::mem::take(&mut *shard.write()))
+            .collect::<Vec<_>>();
+
+        self.write_snapshot();
+
+        // All shards were taken out of memory already, so the remaining ones still have to be
+        // persisted (or restored) after a failure, before the first error is returned.
         let mut stats = PersistStats::default();
-        for shard in self.inner.shards() {
-            let shard = std::mem::take(&mut *shard.write());
-            stats = stats.merge(self.persist_shard(conn, shard.iter())?);
+        let mut first_error = None;
+        for shard in &shards {
+            let result =
+                conn.transaction(|conn| self.persist_shard(conn, shard.iter(), std::iter::empty()));
+            match self.restore_failed_shard(result, shard.iter(), std::iter::empty()) {
+                Ok(shard_stats) => stats = stats.merge(shard_stats),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        for shard in &client_shards {
+            let result =
+                conn.transaction(|conn| self.persist_shard(conn, std::iter::empty(), shard.iter()));
+            if let Err(error) = self.restore_failed_shard(result, std::iter::empty(), shard.iter())
+            {
+                first_error.get_or_insert(error);
+            }
         }
 
-        Ok(stats)
+        match first_error {
+            Some(error) => {
+                self.write_snapshot();
+                Err(error)
+            }
+            None => Ok(stats),
+        }
     }
 
     fn persist_next_shard_with_conn(&self, conn: &mut PgConnection) -> Result<PersistStats, Error> {
@@ -92,17 +192,38 @@ impl DownloadsCounter {
         let idx = self.shard_idx.fetch_add(1, Ordering::SeqCst) % shards.len();
         let shard = std::mem::take(&mut *shards[idx].write());
 
-        let mut stats = self.persist_shard(conn, shard.iter())?;
+        // The per-client counts use a separate DashMap, whose shards are persisted in the same
+        // ring order as the ones of the main storage.
+        let client_shards = self.clients.shards();
+        let client_shard = std::mem::take(&mut *client_shards[idx % client_shards.len()].write());
+
+        self.write_snapshot();
+
+        // Both tables are updated in a single transaction, so that a failure never persists only
+        // part of the shard, and the whole shard can be put back into memory.
+        let result =
+            conn.transaction(|conn| self.persist_shard(conn, shard.iter(), client_shard.iter()));
+        let result = self.restore_failed_shard(result, shard.iter(), client_shard.iter());
+        if result.is_err() {
+            self.write_snapshot();
+        }
+
+        let mut stats = result?;
         stats.shard = Some(idx);
         Ok(stats)
     }
 
-    fn persist_shard<'a, Iter: Iterator<Item = (&'a i32, &'a SharedValue<AtomicUsize>)>>(
+    fn persist_shard<'a, Iter, ClientsIter>(
         &self,
         conn: &mut PgConnection,
         shard: Iter,
-    ) -> Result<PersistStats, Error> {
-        use crate::schema::{version_downloads, versions};
+        clients_shard: ClientsIter,
+    ) -> Result<PersistStats, Error>
+    where
+        Iter: Iterator<Item = (&'a i32, &'a SharedValue<AtomicUsize>)>,
+        ClientsIter: Iterator<Item = (&'a (i32, DownloadClient), &'a SharedValue<AtomicUsize>)>,
+    {
+        use crate::schema::{crate_downloads_by_client, version_downloads, versions};
 
         let mut discarded_downloads = 0;
         let mut counted_downloads = 0;
@@ -112,7 +233,11 @@ impl DownloadsCounter {
             .map(|(id, atomic)| (*id, atomic.get().load(Ordering::SeqCst)))
             .collect::<Vec<_>>();
 
-        if !to_insert.is_empty() {
+        let clients_to_insert = clients_shard
+            .map(|((id, client), atomic)| (*id, *client, atomic.get().load(Ordering::SeqCst)))
+            .collect::<Vec<_>>();
+
+        if !to_insert.is_empty() || !clients_to_insert.is_empty() {
             // The rows we're about to insert need to be sorted to avoid deadlocks when multiple
             // instances of crates.io are running at the same time.
             //
@@ -142,9 +267,13 @@ impl DownloadsCounter {
             // the shard we were about to persist. To avoid that from happening this snippet does a
             // `SELECT` query on the version table before persisting to check whether every version
             // still exists in the database. Missing versions are removed from the following query.
-            let version_ids = to_insert.iter().map(|(id, _)| *id).collect::<Vec<_>>();
-            let existing_version_ids: HashSet<i32> = versions::table
-                .select(versions::id)
+            let version_ids = to_insert
+                .iter()
+                .map(|(id, _)| *id)
+                .chain(clients_to_insert.iter().map(|(id, _, _)| *id))
+                .collect::<Vec<_>>();
+            let crate_ids_by_version: HashMap<i32, i32> = versions::table
+                .select((versions::id, versions::crate_id))
                 // `FOR SHARE` prevents updates or deletions on the selected rows in the `versions`
                 // table until this transaction commits. That prevents a version from being deleted
                 // between this query and the next one.
@@ -159,7 +288,7 @@ impl DownloadsCounter {
 
             let mut values = Vec::new();
             for (id, count) in &to_insert {
-                if !existing_version_ids.contains(id) {
+                if !crate_ids_by_version.contains_key(id) {
                     discarded_downloads += *count;
                     continue;
                 }
@@ -180,6 +309,51 @@ impl DownloadsCounter {
                         .eq(version_downloads::downloads + excluded(version_downloads::downloads)),
                 )
                 .execute(conn)?;
+
+            // The per-client counts are aggregated per crate before being persisted. Using a
+            // `BTreeMap` also keeps the rows sorted, avoiding deadlocks as described above.
+            let mut client_counts = BTreeMap::new();
+            for (id, client, count) in &clients_to_insert {
+                let Some(crate_id) = crate_ids_by_version.get(id) else {
+                    continue;
+                };
+                let key = (
+                    *crate_id,
+                    client.client(),
+                    client.cargo_version(),
+                    client.ci(),
+                );
+                *client_counts.entry(key).or_insert(0) += *count;
+            }
+
+            let values = client_counts
+                .into_iter()
+                .map(|((crate_id, client, cargo_version, ci), count)| {
+                    (
+                        crate_downloads_by_client::crate_id.eq(crate_id),
+                        crate_downloads_by_client::client.eq(client),
+                        crate_downloads_by_client::cargo_version.eq(cargo_version),
+                        crate_downloads_by_client::ci.eq(ci),
+                        crate_downloads_by_client::downloads.eq(count as i32),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            diesel::insert_into(crate_downloads_by_client::table)
+                .values(&values)
+                .on_conflict((
+                    crate_downloads_by_client::crate_id,
+                    crate_downloads_by_client::date,
+                    crate_downloads_by_client::client,
+                    crate_downloads_by_client::cargo_version,
+                    crate_downloads_by_client::ci,
+                ))
+                .do_update()
+                .set(
+                    crate_downloads_by_client::downloads.eq(crate_downloads_by_client::downloads
+                        + excluded(crate_downloads_by_client::downloads)),
+                )
+                .execute(conn)?;
         }
 
         let old_pending = self.pending_count.fetch_sub(
@@ -195,6 +369,149 @@ impl DownloadsCounter {
         })
     }
 
+    /// Puts the downloads of a shard that failed to be persisted back into memory, so that they
+    /// are retried by the next persist. The caller has to write the snapshot again afterwards, so
+    /// that they are also replayed after a restart instead of being lost.
+    fn restore_failed_shard<'a, Iter, ClientsIter>(
+        &self,
+        result: Result<PersistStats, Error>,
+        shard: Iter,
+        clients_shard: ClientsIter,
+    ) -> Result<PersistStats, Error>
+    where
+        Iter: Iterator<Item = (&'a i32, &'a SharedValue<AtomicUsize>)>,
+        ClientsIter: Iterator<Item = (&'a (i32, DownloadClient), &'a SharedValue<AtomicUsize>)>,
+    {
+        if result.is_err() {
+            // The pending count is only decreased once a shard is persisted successfully, so it
+            // already includes these downloads.
+            for (version_id, atomic) in shard {
+                self.add_to_shard(*version_id, atomic.get().load(Ordering::SeqCst));
+            }
+            for (key, atomic) in clients_shard {
+                self.add_client(*key, atomic.get().load(Ordering::SeqCst));
+            }
+        }
+
+        result
+    }
+
+    /// Restores the pending download counts from the snapshot file, if there is one. This should
+    /// be called on startup before any download is counted.
+    ///
+    /// Returns the number of downloads that were restored.
+    pub fn replay_snapshot(&self) -> Result<usize, Error> {
+        let Some(path) = &self.snapshot_path else {
+            return Ok(0);
+        };
+
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut replayed = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+
+            // A malformed line only loses its own downloads, since the rest of the snapshot has
+            // to be replayed anyway before it's overwritten by the next one.
+            match self.replay_snapshot_line(&line) {
+                Some(count) => replayed += count,
+                None => {
+                    warn!(line, "Skipping malformed downloads_counter snapshot line");
+                    // The number of skipped downloads is only known if the count itself is valid.
+                    if let Some((_, count)) = line.split_once(' ') {
+                        if let Ok(count) = count.parse() {
+                            self.skipped_count.fetch_add(count, Ordering::SeqCst);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.replayed_count.fetch_add(replayed, Ordering::SeqCst);
+        Ok(replayed)
+    }
+
+    /// Adds the downloads of a single snapshot line, returning the number of downloads towards
+    /// the version totals, or `None` if the line is malformed.
+    ///
+    /// Lines are either `<version_id> <count>` for the per-version counts, or
+    /// `client <version_id> <count> <client>` for the per-client counts.
+    fn replay_snapshot_line(&self, line: &str) -> Option<usize> {
+        let mut parts = line.split(' ');
+        match parts.next()? {
+            "client" => {
+                let version_id = parts.next()?.parse().ok()?;
+                let count = parts.next()?.parse().ok()?;
+                let client = parse_snapshot_client(parts)?;
+                self.add_client((version_id, client), count);
+                Some(0)
+            }
+            version_id => {
+                let version_id = version_id.parse().ok()?;
+                let count = parts.next()?.parse().ok()?;
+                if parts.next().is_some() {
+                    return None;
+                }
+                self.add(version_id, count);
+                Some(count)
+            }
+        }
+    }
+
+    /// Writes the pending download counts to the snapshot file, if enabled.
+    ///
+    /// Errors are only logged, since failing to write a snapshot should not prevent the download
+    /// counts from being persisted. The outdated snapshot is removed in that case though, as it
+    /// might contain downloads that are about to be persisted.
+    fn write_snapshot(&self) {
+        let Some(path) = &self.snapshot_path else {
+            return;
+        };
+
+        if let Err(err) = self.write_snapshot_to(path) {
+            error!(?err, "downloads_counter snapshot error");
+            if let Err(err) = fs::remove_file(path) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    error!(?err, "downloads_counter snapshot removal error");
+                }
+            }
+        }
+    }
+
+    fn write_snapshot_to(&self, path: &Path) -> Result<(), Error> {
+        // Write to a temporary file first, so that a crash while writing never leaves a
+        // truncated snapshot behind.
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+
+        for shard in self.inner.shards() {
+            for (version_id, count) in shard.read().iter() {
+                let count = count.get().load(Ordering::SeqCst);
+                if count > 0 {
+                    writeln!(writer, "{version_id} {count}")?;
+                }
+            }
+        }
+
+        for shard in self.clients.shards() {
+            for ((version_id, client), count) in shard.read().iter() {
+                let count = count.get().load(Ordering::SeqCst);
+                if count > 0 {
+                    let client = format_snapshot_client(client);
+                    writeln!(writer, "client {version_id} {count} {client}")?;
+                }
+            }
+        }
+
+        writer.into_inner()?.sync_all()?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
     pub fn shards_count(&self) -> usize {
         self.inner.shards().len()
     }
@@ -202,6 +519,50 @@ impl DownloadsCounter {
     pub(crate) fn pending_count(&self) -> i64 {
         self.pending_count.load(Ordering::SeqCst)
     }
+
+    pub(crate) fn replayed_count(&self) -> usize {
+        self.replayed_count.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn skipped_count(&self) -> usize {
+        self.skipped_count.load(Ordering::SeqCst)
+    }
+}
+
+/// Formats a client as `<client> <cargo_version> <ci>` for the snapshot, using `-` for a missing
+/// cargo version.
+fn format_snapshot_client(client: &DownloadClient) -> String {
+    let cargo_version = client.cargo_version();
+    let cargo_version = if cargo_version.is_empty() {
+        "-"
+    } else {
+        &cargo_version
+    };
+    format!("{} {cargo_version} {}", client.client(), client.ci())
+}
+
+fn parse_snapshot_client<'a>(mut parts: impl Iterator<Item = &'a str>) -> Option<DownloadClient> {
+    let (client, cargo_version, ci) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+
+    match client {
+        "cargo" => {
+            let version = match cargo_version {
+                "-" => None,
+                version => {
+                    let (major, minor) = version.split_once('.')?;
+                    Some((major.parse().ok()?, minor.parse().ok()?))
+                }
+            };
+            let ci = ci.parse().ok()?;
+            Some(DownloadClient::Cargo { version, ci })
+        }
+        "browser" => Some(DownloadClient::Browser),
+        "other" => Some(DownloadClient::Other),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
@@ -291,6 +652,160 @@ mod tests {
         state.assert_downloads_count(conn, v3, 0);
     }
 
+    #[test]
+    fn test_increment_client_and_persist_all() {
+        let counter = DownloadsCounter::new();
+        let conn = &mut pg_connection();
+        let mut state = State::new(conn);
+
+        let v1 = state.new_version(conn);
+        let v2 = state.new_version(conn);
+
+        let cargo = DownloadClient::Cargo {
+            version: Some((1, 72)),
+            ci: false,
+        };
+        for _ in 0..3 {
+            counter.increment_client(v1, cargo);
+        }
+        counter.increment_client(v2, cargo);
+        counter.increment_client(v2, DownloadClient::Browser);
+
+        // Client downloads are not counted towards the version downloads.
+        assert_eq!(counter.pending_count.load(Ordering::SeqCst), 0);
+
+        counter
+            .persist_all_shards_with_conn(conn)
+            .expect("failed to persist all shards");
+
+        // Downloads of different versions of the same crate are aggregated.
+        state.assert_client_downloads_count(conn, "cargo", "1.72", 4);
+        state.assert_client_downloads_count(conn, "browser", "", 1);
+        state.assert_downloads_count(conn, v1, 0);
+    }
+
+    #[test]
+    fn test_snapshot_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.snapshot");
+
+        let counter = DownloadsCounter::new().with_snapshot_path(Some(path.clone()));
+        let conn = &mut pg_connection();
+        let mut state = State::new(conn);
+
+        let v1 = state.new_version(conn);
+        let v2 = state.new_version(conn);
+
+        // Nothing to replay without a snapshot file
+        assert_eq!(counter.replay_snapshot().unwrap(), 0);
+
+        for _ in 0..10 {
+            counter.increment(v1);
+        }
+        for _ in 0..5 {
+            counter.increment(v2);
+        }
+        counter.write_snapshot();
+
+        // Simulate a crash by replaying the snapshot in a new counter.
+        let restarted = DownloadsCounter::new().with_snapshot_path(Some(path.clone()));
+        assert_eq!(restarted.replay_snapshot().unwrap(), 15);
+        assert_eq!(restarted.replayed_count(), 15);
+        assert_eq!(restarted.pending_count(), 15);
+
+        let stats = restarted
+            .persist_all_shards_with_conn(conn)
+            .expect("failed to persist all shards");
+        assert_eq!(stats.counted_downloads, 15);
+        state.assert_downloads_count(conn, v1, 10);
+        state.assert_downloads_count(conn, v2, 5);
+
+        // The persisted downloads are not part of the snapshot anymore, so they are not replayed
+        // again after another crash.
+        let restarted = DownloadsCounter::new().with_snapshot_path(Some(path));
+        assert_eq!(restarted.replay_snapshot().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_snapshot_and_replay_clients() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.snapshot");
+
+        let counter = DownloadsCounter::new().with_snapshot_path(Some(path.clone()));
+        let conn = &mut pg_connection();
+        let mut state = State::new(conn);
+
+        let v1 = state.new_version(conn);
+        let cargo = DownloadClient::Cargo {
+            version: Some((1, 72)),
+            ci: true,
+        };
+        counter.increment_client(v1, cargo);
+        counter.increment_client(v1, cargo);
+        counter.increment_client(v1, DownloadClient::Browser);
+        counter.write_snapshot();
+
+        // Client downloads are replayed, but not counted towards the version downloads.
+        let restarted = DownloadsCounter::new().with_snapshot_path(Some(path));
+        assert_eq!(restarted.replay_snapshot().unwrap(), 0);
+        assert_eq!(restarted.pending_count(), 0);
+
+        restarted
+            .persist_all_shards_with_conn(conn)
+            .expect("failed to persist all shards");
+        state.assert_client_downloads_count(conn, "cargo", "1.72", 2);
+        state.assert_client_downloads_count(conn, "browser", "", 1);
+    }
+
+    #[test]
+    fn test_replay_skips_malformed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.snapshot");
+        fs::write(
+            &path,
+            "1 10\nfoo 3\n2 bar\nclient 1 2 unknown - false\n3 5\n",
+        )
+        .unwrap();
+
+        let counter = DownloadsCounter::new().with_snapshot_path(Some(path));
+        assert_eq!(counter.replay_snapshot().unwrap(), 15);
+        assert_eq!(counter.pending_count(), 15);
+        assert_eq!(counter.skipped_count(), 3);
+    }
+
+    #[test]
+    fn test_failed_persist_restores_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.snapshot");
+
+        let counter = DownloadsCounter::new().with_snapshot_path(Some(path.clone()));
+        let conn = &mut pg_connection();
+        let mut state = State::new(conn);
+
+        let v1 = state.new_version(conn);
+        for _ in 0..3 {
+            counter.increment(v1);
+        }
+        counter.increment_client(v1, DownloadClient::Browser);
+
+        // Abort the test transaction, so that persisting the downloads fails.
+        assert_err!(diesel::sql_query("SELECT invalid").execute(conn));
+        assert_err!(counter.persist_all_shards_with_conn(conn));
+
+        // The downloads are still pending in memory and in the snapshot.
+        assert_eq!(counter.skipped_count(), 0);
+        assert_eq!(counter.pending_count(), 3);
+        assert_eq!(counter.inner.get(&v1).unwrap().load(Ordering::SeqCst), 3);
+
+        let restarted = DownloadsCounter::new().with_snapshot_path(Some(path));
+        assert_eq!(restarted.replay_snapshot().unwrap(), 3);
+        let key = (v1, DownloadClient::Browser);
+        assert_eq!(
+            restarted.clients.get(&key).unwrap().load(Ordering::SeqCst),
+            1
+        );
+    }
+
     #[test]
     fn test_increment_and_persist_shard() {
         let counter = DownloadsCounter::new();
@@ -460,6 +975,8 @@ mod tests {
                 "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
                 None,
                 None,
+                false,
+                false,
             )
             .expect("failed to create version")
             .save(conn, "ghost@example.com")
@@ -480,5 +997,24 @@ mod tests {
                 .unwrap();
             assert_eq!(actual.unwrap_or(0), expected);
         }
+
+        fn assert_client_downloads_count(
+            &self,
+            conn: &mut PgConnection,
+            client_kind: &str,
+            version: &str,
+            expected: i32,
+        ) {
+            use crate::schema::crate_downloads_by_client::dsl::*;
+
+            let actual: i32 = crate_downloads_by_client
+                .select(downloads)
+                .filter(crate_id.eq(self.krate.id))
+                .filter(client.eq(client_kind))
+                .filter(cargo_version.eq(version))
+                .first(conn)
+                .unwrap();
+            assert_eq!(actual, expected);
+        }
     }
 }
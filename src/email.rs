@@ -1,20 +1,87 @@
+use std::net::IpAddr;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use crate::util::errors::{server_error, AppResult};
 
+use crate::background_jobs::Job;
 use crate::config;
+use crate::models::{NewOutboxEmail, NotificationType, OutboxEmail};
 use crate::Env;
-use lettre::message::header::ContentType;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{Connection, PgConnection};
+use lettre::address::AddressError;
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
 use lettre::transport::file::FileTransport;
 use lettre::transport::smtp::authentication::{Credentials, Mechanism};
 use lettre::transport::smtp::SmtpTransport;
 use lettre::{Message, Transport};
+use minijinja::{context, Environment, Value};
+use once_cell::sync::Lazy;
 use rand::distributions::{Alphanumeric, DistString};
+use ring::hmac;
+use ring::rand::SystemRandom;
 
-#[derive(Debug)]
+pub use self::unsubscribe::Subscription;
+
+mod unsubscribe;
+
+/// The templates of all emails. Each email consists of a `.txt` template for the plaintext part
+/// and a `.html` template for the HTML part, which usually extend the `base.txt` and `base.html`
+/// layouts.
+static TEMPLATES: Lazy<Environment<'static>> = Lazy::new(|| {
+    macro_rules! add_templates {
+        ($env:ident, $($name:literal),+ $(,)?) => {
+            $(
+                $env.add_template($name, include_str!(concat!("email/templates/", $name, ".j2")))
+                    .expect(concat!("Failed to load the ", $name, " email template"));
+            )+
+        };
+    }
+
+    let mut env = Environment::new();
+    add_templates!(
+        env,
+        "base.html",
+        "base.txt",
+        "owner_invite.html",
+        "owner_invite.txt",
+        "token_created.html",
+        "token_created.txt",
+        "token_expiry_reminder.html",
+        "token_expiry_reminder.txt",
+        "token_exposed.html",
+        "token_exposed.txt",
+        "token_new_ip_address.html",
+        "token_new_ip_address.txt",
+        "user_confirm.html",
+        "user_confirm.txt",
+        "version_action.html",
+        "version_action.txt",
+        "weekly_digest.html",
+        "weekly_digest.txt",
+    );
+    env
+});
+
+/// Renders the plaintext and HTML parts of an email.
+fn render(template: &str, context: Value) -> Result<(String, String), minijinja::Error> {
+    let body_text = TEMPLATES
+        .get_template(&format!("{template}.txt"))?
+        .render(&context)?;
+    let body_html = TEMPLATES
+        .get_template(&format!("{template}.html"))?
+        .render(&context)?;
+
+    Ok((body_text, body_html))
+}
+
+#[derive(Debug, Clone)]
 pub struct Emails {
     backend: EmailBackend,
+    /// The key used to sign the unsubscribe links in the emails.
+    unsubscribe_key: hmac::Key,
 }
 
 impl Emails {
@@ -40,60 +107,73 @@ impl Emails {
             panic!("only the smtp backend is allowed in production");
         }
 
-        Self { backend }
+        // The web application and the background worker have to use the same key, so it is
+        // derived from the session key that both of them are configured with.
+        let unsubscribe_key = hmac::Key::new(hmac::HMAC_SHA256, config.session_key.signing());
+
+        Self {
+            backend,
+            unsubscribe_key,
+        }
     }
 
     /// Create a new test backend that stores all the outgoing emails in memory, allowing for tests
     /// to later assert the mails were sent.
     pub fn new_in_memory() -> Self {
+        let unsubscribe_key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .expect("Failed to generate the unsubscribe key");
+
         Self {
             backend: EmailBackend::Memory {
-                mails: Mutex::new(Vec::new()),
+                mails: Arc::new(Mutex::new(Vec::new())),
             },
+            unsubscribe_key,
         }
     }
 
-    /// Attempts to send a confirmation email.
-    pub fn send_user_confirm(&self, email: &str, user_name: &str, token: &str) -> AppResult<()> {
+    /// Queues a confirmation email.
+    pub fn send_user_confirm(
+        &self,
+        conn: &mut PgConnection,
+        email: &str,
+        user_name: &str,
+        token: &str,
+    ) -> AppResult<()> {
         // Create a URL with token string as path to send to user
         // If user clicks on path, look email/user up in database,
         // make sure tokens match
 
         let subject = "Please confirm your email address";
-        let body = format!(
-            "Hello {}! Welcome to Crates.io. Please click the
-link below to verify your email address. Thank you!\n
-https://{}/confirm/{}",
-            user_name,
-            crate::config::domain_name(),
-            token
-        );
+        let context = context! { user_name, token };
 
-        self.send(email, subject, &body)
+        // The user asked for this email, so there is nothing to unsubscribe from.
+        self.enqueue(conn, email, None, subject, "user_confirm", context)
     }
 
-    /// Attempts to send an ownership invitation.
+    /// Queues an ownership invitation for the user with the ID `user_id`.
     pub fn send_owner_invite(
         &self,
+        conn: &mut PgConnection,
+        user_id: i32,
         email: &str,
         user_name: &str,
         crate_name: &str,
         token: &str,
     ) -> AppResult<()> {
         let subject = "Crate ownership invitation";
-        let body = format!(
-            "{user_name} has invited you to become an owner of the crate {crate_name}!\n
-Visit https://{domain}/accept-invite/{token} to accept this invitation,
-or go to https://{domain}/me/pending-invites to manage all of your crate ownership invitations.",
-            domain = crate::config::domain_name()
-        );
+        let context = context! { user_name, crate_name, token };
 
-        self.send(email, subject, &body)
+        let subscription = Subscription::Notification(NotificationType::OwnerInvitation);
+        let unsubscribe = Some((user_id, subscription));
+        self.enqueue(conn, email, unsubscribe, subject, "owner_invite", context)
     }
 
-    /// Attempts to send an API token exposure notification email
+    /// Queues an API token exposure notification email
+    #[allow(clippy::too_many_arguments)]
     pub fn send_token_exposed_notification(
         &self,
+        conn: &mut PgConnection,
+        user_id: i32,
         email: &str,
         url: &str,
         reporter: &str,
@@ -101,21 +181,204 @@ or go to https://{domain}/me/pending-invites to manage all of your crate ownersh
         token_name: &str,
     ) -> AppResult<()> {
         let subject = "Exposed API token found";
-        let mut body = format!(
-            "{reporter} has notified us that your crates.io API token {token_name}\n
-has been exposed publicly. We have revoked this token as a precaution.\n
-Please review your account at https://{domain} to confirm that no\n
-unexpected changes have been made to your settings or crates.\n
-\n
-Source type: {source}\n",
-            domain = crate::config::domain_name()
-        );
-        if url.is_empty() {
-            body.push_str("\nWe were not informed of the URL where the token was found.\n");
-        } else {
-            body.push_str(&format!("\nURL where the token was found: {url}\n"));
-        }
-        self.send(email, subject, &body)
+        let context = context! { url, reporter, source, token_name };
+
+        let subscription = Subscription::Notification(NotificationType::TokenExposed);
+        let unsubscribe = Some((user_id, subscription));
+        self.enqueue(conn, email, unsubscribe, subject, "token_exposed", context)
+    }
+
+    /// Queues a reminder that an API token expires soon.
+    pub fn send_token_expiry_reminder(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+        email: &str,
+        user_name: &str,
+        token_name: &str,
+        expired_at: NaiveDateTime,
+    ) -> AppResult<()> {
+        let subject = "Your API token expires soon";
+        let expired_at = expired_at.format("%Y-%m-%d %H:%M UTC").to_string();
+        let context = context! { user_name, token_name, expired_at };
+
+        let subscription = Subscription::Notification(NotificationType::TokenExpiry);
+        let unsubscribe = Some((user_id, subscription));
+        self.enqueue(
+            conn,
+            email,
+            unsubscribe,
+            subject,
+            "token_expiry_reminder",
+            context,
+        )
+    }
+
+    /// Queues a notification that an API token was created.
+    pub fn send_token_created_notification(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+        email: &str,
+        user_name: &str,
+        token_name: &str,
+    ) -> AppResult<()> {
+        let subject = "A new API token was created";
+        let context = context! { user_name, token_name };
+
+        let subscription = Subscription::Notification(NotificationType::TokenCreated);
+        let unsubscribe = Some((user_id, subscription));
+        self.enqueue(conn, email, unsubscribe, subject, "token_created", context)
+    }
+
+    /// Queues a notification that an API token was used from a new IP address.
+    pub fn send_token_new_ip_address_notification(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+        email: &str,
+        user_name: &str,
+        token_name: &str,
+        ip_address: IpAddr,
+    ) -> AppResult<()> {
+        let subject = "Your API token was used from a new IP address";
+        let context = context! { user_name, token_name, ip_address };
+
+        let subscription = Subscription::Notification(NotificationType::TokenNewIpAddress);
+        let unsubscribe = Some((user_id, subscription));
+        self.enqueue(
+            conn,
+            email,
+            unsubscribe,
+            subject,
+            "token_new_ip_address",
+            context,
+        )
+    }
+
+    /// Queues a notification that a version of a crate was published, yanked or unyanked to one
+    /// of the owners of the crate.
+    pub fn send_version_action_notification(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+        email: &str,
+        user_name: &str,
+        notification: &VersionActionNotification<'_>,
+    ) -> AppResult<()> {
+        let VersionActionNotification {
+            crate_id,
+            crate_name,
+            version,
+            action,
+            ..
+        } = notification;
+
+        let subject = format!("crates.io: {crate_name} v{version} was {action}");
+        let context = context! { user_name, ..Value::from_serializable(notification) };
+
+        let unsubscribe = Some((user_id, Subscription::Crate(*crate_id)));
+        self.enqueue(
+            conn,
+            email,
+            unsubscribe,
+            &subject,
+            "version_action",
+            context,
+        )
+    }
+
+    /// Queues the weekly digest email of a user.
+    pub fn send_weekly_digest(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i32,
+        email: &str,
+        user_name: &str,
+        digest: &WeeklyDigest,
+    ) -> AppResult<()> {
+        let subject = "crates.io: Your weekly digest";
+        let context = context! { user_name, ..Value::from_serializable(digest) };
+
+        let unsubscribe = Some((
+            user_id,
+            Subscription::Notification(NotificationType::WeeklyDigest),
+        ));
+        self.enqueue(conn, email, unsubscribe, subject, "weekly_digest", context)
+    }
+
+    /// Renders the plaintext and HTML parts of the email from the `{template}.txt.j2` and
+    /// `{template}.html.j2` templates, stores the email in the outbox and enqueues a background
+    /// job to send it.
+    ///
+    /// If `unsubscribe` contains the ID of the recipient and what the email is about, the email
+    /// gets a signed link that unsubscribes the recipient with a single click.
+    fn enqueue(
+        &self,
+        conn: &mut PgConnection,
+        recipient: &str,
+        unsubscribe: Option<(i32, Subscription)>,
+        subject: &str,
+        template: &str,
+        context: Value,
+    ) -> AppResult<()> {
+        // Invalid addresses can't be fixed by retrying, so they are rejected right away instead
+        // of failing in the background job.
+        recipient.parse::<Mailbox>()?;
+
+        let domain = crate::config::domain_name();
+        let unsubscribe_token = unsubscribe.map(|(user_id, subscription)| {
+            unsubscribe::sign(&self.unsubscribe_key, user_id, subscription)
+        });
+        let unsubscribe_url = unsubscribe_token
+            .as_ref()
+            .map(|token| format!("https://{domain}/unsubscribe/{token}"));
+
+        let context = context! { subject, domain, unsubscribe_url, ..context };
+        let (body_text, body_html) = render(template, context).map_err(|error| {
+            error!(?error, template, "Failed to render email template");
+            server_error("Failed to render the email")
+        })?;
+
+        // Some callers ignore the errors of this function within their own transaction, so a
+        // failed insert must only roll back a savepoint instead of aborting their transaction.
+        conn.transaction(|conn| {
+            let email_id = NewOutboxEmail {
+                recipient,
+                subject,
+                body_text: &body_text,
+                body_html: &body_html,
+                unsubscribe_token: unsubscribe_token.as_deref(),
+            }
+            .insert(conn)?;
+
+            Job::send_email(email_id).enqueue(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Sends an email from the outbox using the configured backend and returns its message ID.
+    pub fn deliver(&self, email: &OutboxEmail) -> Result<String, EmailError> {
+        // The URL for the RFC 8058 one-click unsubscribe, which mail clients `POST` to directly.
+        let list_unsubscribe = email.unsubscribe_token.as_ref().map(|token| {
+            let domain = crate::config::domain_name();
+            format!("https://{domain}/api/v1/unsubscribe/{token}")
+        });
+
+        self.send(
+            &email.recipient,
+            &email.subject,
+            &email.body_text,
+            &email.body_html,
+            list_unsubscribe,
+        )
+    }
+
+    /// Returns the user and the subscription of an unsubscribe token from an email, or `None` if
+    /// the token is invalid or expired.
+    pub fn verify_unsubscribe_token(&self, token: &str) -> Option<(i32, Subscription)> {
+        unsubscribe::verify(&self.unsubscribe_key, token)
     }
 
     /// This is supposed to be used only during tests, to retrieve the messages stored in the
@@ -128,7 +391,14 @@ Source type: {source}\n",
         }
     }
 
-    fn send(&self, recipient: &str, subject: &str, body: &str) -> AppResult<()> {
+    fn send(
+        &self,
+        recipient: &str,
+        subject: &str,
+        body_text: &str,
+        body_html: &str,
+        list_unsubscribe: Option<String>,
+    ) -> Result<String, EmailError> {
         // The message ID is normally generated by the SMTP server, but if we let it generate the
         // ID there will be no way for the crates.io application to know the ID of the message it
         // just sent, as it's not included in the SMTP response.
@@ -142,13 +412,22 @@ Source type: {source}\n",
             crate::config::domain_name(),
         );
 
-        let email = Message::builder()
+        let mut builder = Message::builder()
             .message_id(Some(message_id.clone()))
             .to(recipient.parse()?)
             .from(self.sender_address().parse()?)
-            .subject(subject)
-            .header(ContentType::TEXT_PLAIN)
-            .body(body.to_string())?;
+            .subject(subject);
+
+        if let Some(list_unsubscribe) = &list_unsubscribe {
+            builder = builder
+                .header(ListUnsubscribe(format!("<{list_unsubscribe}>")))
+                .header(ListUnsubscribePost);
+        }
+
+        let email = builder.multipart(MultiPart::alternative_plain_html(
+            body_text.to_string(),
+            body_html.to_string(),
+        ))?;
 
         match &self.backend {
             EmailBackend::Smtp {
@@ -166,7 +445,7 @@ Source type: {source}\n",
                     })
                     .map_err(|error| {
                         error!(?error, "Failed to send email");
-                        server_error("Failed to send the email")
+                        error
                     })?;
 
                 info!(?message_id, ?subject, "Email sent");
@@ -174,7 +453,7 @@ Source type: {source}\n",
             EmailBackend::FileSystem { path } => {
                 let id = FileTransport::new(path).send(&email).map_err(|error| {
                     error!(?error, "Failed to send email");
-                    server_error("Email file could not be generated")
+                    error
                 })?;
 
                 info!(
@@ -187,12 +466,14 @@ Source type: {source}\n",
                 mails.lock().unwrap().push(StoredEmail {
                     to: recipient.into(),
                     subject: subject.into(),
-                    body: body.into(),
+                    body: body_text.into(),
+                    body_html: body_html.into(),
+                    list_unsubscribe,
                 });
             }
         }
 
-        Ok(())
+        Ok(message_id)
     }
 
     fn sender_address(&self) -> &str {
@@ -204,6 +485,7 @@ Source type: {source}\n",
     }
 }
 
+#[derive(Clone)]
 enum EmailBackend {
     /// Backend used in production to send mails using SMTP.
     Smtp {
@@ -214,7 +496,7 @@ enum EmailBackend {
     /// Backend used locally during development, will store the emails in the provided directory.
     FileSystem { path: PathBuf },
     /// Backend used during tests, will keep messages in memory to allow tests to retrieve them.
-    Memory { mails: Mutex<Vec<StoredEmail>> },
+    Memory { mails: Arc<Mutex<Vec<StoredEmail>>> },
 }
 
 // Custom Debug implementation to avoid showing the SMTP password.
@@ -237,11 +519,138 @@ impl std::fmt::Debug for EmailBackend {
     }
 }
 
+/// The details of a `send_version_action_notification()` email.
+#[derive(Debug, Serialize)]
+pub struct VersionActionNotification<'a> {
+    pub crate_id: i32,
+    pub crate_name: &'a str,
+    pub version: &'a str,
+    /// The past tense of the action, e.g. `published`.
+    pub action: &'a str,
+    /// The login of the user that performed the action.
+    pub actor: &'a str,
+    /// The name of the API token that was used, or `None` for the website.
+    pub token_name: Option<&'a str>,
+    pub ip_address: Option<IpAddr>,
+}
+
+/// The contents of a `send_weekly_digest()` email. Empty sections are left out of the email.
+#[derive(Debug, Serialize)]
+pub struct WeeklyDigest {
+    /// The first day of the week that the digest covers.
+    pub start: NaiveDate,
+    /// The last day of the week that the digest covers.
+    pub end: NaiveDate,
+    /// The downloads of the crates of the user in this week and the week before.
+    pub downloads: Vec<DigestDownloads>,
+    /// Crates that depend on a crate of the user for the first time.
+    pub new_reverse_dependencies: Vec<DigestReverseDependency>,
+    /// The names of the crates that the user was invited to become an owner of.
+    pub pending_invitations: Vec<String>,
+    /// API tokens of the user that expire within a week.
+    pub expiring_tokens: Vec<DigestToken>,
+    /// New versions of the crates that the user follows.
+    pub followed_versions: Vec<DigestVersion>,
+}
+
+impl WeeklyDigest {
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty()
+            && self.new_reverse_dependencies.is_empty()
+            && self.pending_invitations.is_empty()
+            && self.expiring_tokens.is_empty()
+            && self.followed_versions.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DigestDownloads {
+    pub crate_name: String,
+    pub downloads: i64,
+    pub previous_downloads: i64,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct DigestReverseDependency {
+    /// The crate of the user.
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub crate_name: String,
+    /// The crate that depends on it.
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub dependent: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DigestToken {
+    pub name: String,
+    /// When the token expires, formatted for the email.
+    pub expired_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DigestVersion {
+    pub crate_name: String,
+    pub version: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct StoredEmail {
     pub to: String,
     pub subject: String,
+    /// The plaintext part of the email.
     pub body: String,
+    pub body_html: String,
+    /// The URL of the `List-Unsubscribe` header, if any.
+    pub list_unsubscribe: Option<String>,
+}
+
+/// The `List-Unsubscribe` header, see RFC 2369.
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// The `List-Unsubscribe-Post` header, which marks the `List-Unsubscribe` URL as supporting the
+/// one-click unsubscribe of RFC 8058.
+#[derive(Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmailError {
+    #[error(transparent)]
+    Address(#[from] AddressError),
+    #[error(transparent)]
+    Message(#[from] lettre::error::Error),
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error(transparent)]
+    FileSystem(#[from] lettre::transport::file::Error),
 }
 
 #[cfg(test)]
@@ -256,6 +665,8 @@ mod tests {
             "String.Format(\"{0}.{1}@live.com\", FirstName, LastName)",
             "test",
             "test",
+            "<p>test</p>",
+            None,
         ));
     }
 
@@ -263,6 +674,22 @@ mod tests {
     fn sending_to_valid_email_succeeds() {
         let emails = Emails::new_in_memory();
 
-        assert_ok!(emails.send("someone@example.com", "test", "test"));
+        assert_ok!(emails.send("someone@example.com", "test", "test", "<p>test</p>", None));
+    }
+
+    #[test]
+    fn rendering_escapes_html_only() {
+        let context = context! {
+            subject => "test",
+            domain => "crates.io",
+            user_name => "foo",
+            token_name => "<script>",
+        };
+        let (body_text, body_html) = render("token_created", context).unwrap();
+
+        assert!(body_text.starts_with("Hello foo!"));
+        assert!(body_text.contains("\"<script>\""));
+        assert!(body_html.contains("<strong>&lt;script&gt;</strong>"));
+        assert!(body_html.contains("https://crates.io/settings/tokens"));
     }
 }
@@ -0,0 +1,134 @@
+//! Signed tokens for the one-click unsubscribe links in notification emails.
+//!
+//! A token consists of the base64 encoded payload `{user_id}.{kind}.{id}.{expires_at}` and its
+//! HMAC-SHA256 signature. The tokens are not stored anywhere, so they stay valid until they
+//! expire, even if the user subscribes again in the meantime.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use ring::hmac;
+
+use crate::models::NotificationType;
+
+/// How long the unsubscribe links in an email keep working.
+const TOKEN_LIFETIME_DAYS: i64 = 60;
+
+/// Prepended to the signed data, so that the signatures can't be mixed up with other signatures
+/// created with the same key.
+const SIGNING_CONTEXT: &[u8] = b"crates.io unsubscribe token\0";
+
+/// What the recipient of an email can unsubscribe from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscription {
+    /// The notifications about published, yanked and unyanked versions of the crate with the
+    /// given ID, see the `email_notifications` column of `crate_owners`.
+    Crate(i32),
+    /// A type of notification emails, regardless of the crate.
+    Notification(NotificationType),
+}
+
+pub(super) fn sign(key: &hmac::Key, user_id: i32, subscription: Subscription) -> String {
+    let (kind, id) = match subscription {
+        Subscription::Crate(crate_id) => ("crate", crate_id),
+        Subscription::Notification(notification_type) => ("notification", notification_type as i32),
+    };
+    let expires_at = (Utc::now() + Duration::days(TOKEN_LIFETIME_DAYS)).timestamp();
+
+    let payload = format!("{user_id}.{kind}.{id}.{expires_at}");
+    let signature = hmac::sign(key, &signed_data(&payload));
+
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+/// Returns the user and the subscription of a token, or `None` if the token is invalid or
+/// expired.
+pub(super) fn verify(key: &hmac::Key, token: &str) -> Option<(i32, Subscription)> {
+    let (payload, signature) = token.split_once('.')?;
+    let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    hmac::verify(key, &signed_data(&payload), &signature).ok()?;
+
+    let mut parts = payload.split('.');
+    let user_id = parts.next()?.parse().ok()?;
+    let kind = parts.next()?;
+    let id: i32 = parts.next()?.parse().ok()?;
+    let expires_at: i64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || expires_at < Utc::now().timestamp() {
+        return None;
+    }
+
+    let subscription = match kind {
+        "crate" => Subscription::Crate(id),
+        "notification" => Subscription::Notification(
+            *NotificationType::VARIANTS
+                .iter()
+                .find(|notification_type| **notification_type as i32 == id)?,
+        ),
+        _ => return None,
+    };
+
+    Some((user_id, subscription))
+}
+
+fn signed_data(payload: &str) -> Vec<u8> {
+    [SIGNING_CONTEXT, payload.as_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+
+    fn key() -> hmac::Key {
+        hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).unwrap()
+    }
+
+    #[test]
+    fn valid_tokens_are_accepted() {
+        let key = key();
+
+        let subscription = Subscription::Crate(42);
+        let token = sign(&key, 1, subscription);
+        assert_eq!(verify(&key, &token), Some((1, subscription)));
+
+        let subscription = Subscription::Notification(NotificationType::TokenExpiry);
+        let token = sign(&key, 2, subscription);
+        assert_eq!(verify(&key, &token), Some((2, subscription)));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let key = key();
+        let token = sign(&key, 1, Subscription::Crate(42));
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let expires_at = (Utc::now() + Duration::days(1)).timestamp();
+        let payload = URL_SAFE_NO_PAD.encode(format!("2.crate.42.{expires_at}"));
+        assert_eq!(verify(&key, &format!("{payload}.{signature}")), None);
+
+        assert_eq!(verify(&self::key(), &token), None);
+        assert_eq!(verify(&key, "foo.bar"), None);
+        assert_eq!(verify(&key, ""), None);
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let key = key();
+
+        let expires_at = (Utc::now() - Duration::seconds(1)).timestamp();
+        let payload = format!("1.crate.42.{expires_at}");
+        let signature = hmac::sign(&key, &signed_data(&payload));
+        let token = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        );
+
+        assert_eq!(verify(&key, &token), None);
+    }
+}
@@ -1,4 +1,6 @@
+use spdx::expression::{ExprNode, Operator};
 use spdx::{Expression, ParseError};
+use std::collections::BTreeSet;
 
 const PARSE_MODE: spdx::ParseMode = spdx::ParseMode {
     allow_lower_case_operators: false,
@@ -11,9 +13,79 @@ pub fn parse_license_expr(s: &str) -> Result<Expression, ParseError> {
     Expression::parse_mode(s, PARSE_MODE)
 }
 
+/// Returns the names of all SPDX licenses that are mentioned in the expression.
+pub fn license_ids(expr: &Expression) -> Vec<&'static str> {
+    let mut ids = expr
+        .requirements()
+        .filter_map(|req| req.req.license.id())
+        .map(|id| id.name)
+        .collect::<Vec<_>>();
+
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Returns the alternative sets of SPDX licenses that each satisfy the license expression on
+/// their own, as space separated license names, e.g. `MIT OR (Apache-2.0 AND ISC)` returns
+/// `MIT` and `Apache-2.0 ISC`.
+///
+/// The expression can be complied with by only using a set of accepted licenses if any of the
+/// alternatives only contains accepted licenses, e.g. `MIT OR Apache-2.0` is satisfied by `MIT`,
+/// while `MIT AND Apache-2.0` is not. Storing the alternatives allows that check to be done in
+/// SQL. Requirements that are not SPDX licenses can never be accepted, so alternatives containing
+/// them are left out.
+pub fn license_alternatives(expr: &Expression) -> Vec<String> {
+    let mut stack: Vec<Vec<BTreeSet<&'static str>>> = Vec::new();
+    for node in expr.iter() {
+        let alternatives = match node {
+            ExprNode::Req(req) => match req.req.license.id() {
+                Some(id) => vec![BTreeSet::from([id.name])],
+                None => vec![],
+            },
+            ExprNode::Op(operator) => {
+                let rhs = stack.pop().unwrap_or_default();
+                let lhs = stack.pop().unwrap_or_default();
+                match operator {
+                    Operator::Or => lhs.into_iter().chain(rhs).collect(),
+                    Operator::And => lhs
+                        .iter()
+                        .flat_map(|lhs| rhs.iter().map(move |rhs| lhs | rhs))
+                        .collect(),
+                }
+            }
+        };
+        stack.push(alternatives);
+    }
+
+    let mut alternatives = stack
+        .pop()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|ids| ids.into_iter().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>();
+
+    alternatives.sort_unstable();
+    alternatives.dedup();
+    alternatives
+}
+
+/// Returns the values of the `license_ids` and `license_alternatives` columns of a version with
+/// the `license` expression. Non-standard licenses are stored without any SPDX licenses, so that
+/// they never match the license filter of the search.
+pub fn spdx_licenses(license: &str) -> (Vec<String>, Vec<String>) {
+    match parse_license_expr(license) {
+        Ok(expr) => {
+            let ids = license_ids(&expr).into_iter().map(String::from).collect();
+            (ids, license_alternatives(&expr))
+        }
+        Err(_) => (vec![], vec![]),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::parse_license_expr;
+    use super::{license_alternatives, license_ids, parse_license_expr};
 
     #[test]
     fn licenses() {
@@ -26,4 +98,64 @@ mod tests {
 
         assert_err!(parse_license_expr("apache 2.0"));
     }
+
+    #[test]
+    fn ids() {
+        let ids = |s| license_ids(&parse_license_expr(s).unwrap());
+        assert_eq!(ids("MIT"), vec!["MIT"]);
+        assert_eq!(ids("MIT/Apache-2.0"), vec!["Apache-2.0", "MIT"]);
+        assert_eq!(
+            ids("MIT OR (Apache-2.0 AND MIT)"),
+            vec!["Apache-2.0", "MIT"]
+        );
+    }
+
+    #[test]
+    fn alternatives() {
+        let alternatives = |s| license_alternatives(&parse_license_expr(s).unwrap());
+        assert_eq!(alternatives("MIT"), vec!["MIT"]);
+        assert_eq!(alternatives("MIT/Apache-2.0"), vec!["Apache-2.0", "MIT"]);
+        assert_eq!(alternatives("MIT AND Apache-2.0"), vec!["Apache-2.0 MIT"]);
+        assert_eq!(
+            alternatives("(MIT OR Apache-2.0) AND ISC"),
+            vec!["Apache-2.0 ISC", "ISC MIT"]
+        );
+        assert_eq!(
+            alternatives("MIT OR (Apache-2.0 AND MIT)"),
+            vec!["Apache-2.0 MIT", "MIT"]
+        );
+        assert_eq!(
+            alternatives("Apache-2.0 WITH LLVM-exception"),
+            vec!["Apache-2.0"]
+        );
+        assert_eq!(alternatives("MIT OR LicenseRef-Proprietary"), vec!["MIT"]);
+        assert_eq!(
+            alternatives("MIT AND LicenseRef-Proprietary"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn satisfied() {
+        let is_satisfied_by = |s, accepted: &[&str]| {
+            license_alternatives(&parse_license_expr(s).unwrap())
+                .iter()
+                .any(|alternative| alternative.split(' ').all(|id| accepted.contains(&id)))
+        };
+
+        assert!(is_satisfied_by("MIT", &["MIT"]));
+        assert!(is_satisfied_by("MIT OR Apache-2.0", &["MIT"]));
+        assert!(is_satisfied_by("MIT/Apache-2.0", &["Apache-2.0"]));
+        assert!(is_satisfied_by(
+            "MIT AND Apache-2.0",
+            &["Apache-2.0", "MIT"]
+        ));
+        assert!(is_satisfied_by(
+            "Apache-2.0 WITH LLVM-exception",
+            &["Apache-2.0"]
+        ));
+
+        assert!(!is_satisfied_by("MIT AND Apache-2.0", &["MIT"]));
+        assert!(!is_satisfied_by("GPL-3.0", &["MIT", "Apache-2.0"]));
+    }
 }
@@ -52,11 +52,25 @@ metrics! {
         pub downloads_select_query_execution_time: Histogram,
         /// Number of download requests that are not counted yet.
         downloads_not_counted_total: IntGauge,
+        /// Number of downloads that were restored from the snapshot when the instance started.
+        downloads_replayed_total: IntCounter,
+        /// Number of downloads that were skipped because their snapshot line was malformed.
+        downloads_snapshot_skipped_total: IntCounter,
+
+        /// Number of API token uses that are not written to the activity log yet.
+        token_activity_not_persisted: IntGauge,
+        /// Number of API token uses that were lost because they could not be persisted.
+        token_activity_lost_total: IntCounter,
 
         /// Number of version ID cache hits on the download endpoint.
         pub version_id_cache_hits: IntCounter,
         /// Number of version ID cache misses on the download endpoint.
         pub version_id_cache_misses: IntCounter,
+
+        /// Number of cache hits on the search suggestions endpoint.
+        pub suggestions_cache_hits: IntCounter,
+        /// Number of cache misses on the search suggestions endpoint.
+        pub suggestions_cache_misses: IntCounter,
     }
 
     // All instance metrics will be prefixed with this namespace.
@@ -73,6 +87,21 @@ impl InstanceMetrics {
 
         self.downloads_not_counted_total
             .set(app.downloads_counter.pending_count());
+        sync_counter(
+            &self.downloads_replayed_total,
+            app.downloads_counter.replayed_count(),
+        );
+        sync_counter(
+            &self.downloads_snapshot_skipped_total,
+            app.downloads_counter.skipped_count(),
+        );
+
+        self.token_activity_not_persisted
+            .set(app.token_activity.pending_count() as i64);
+        sync_counter(
+            &self.token_activity_lost_total,
+            app.token_activity.lost_count(),
+        );
 
         Ok(self.registry.gather())
     }
@@ -90,3 +119,8 @@ impl InstanceMetrics {
         Ok(())
     }
 }
+
+/// Advances the counter to the `total` that is tracked elsewhere, since counters can't be set.
+fn sync_counter(counter: &IntCounter, total: usize) {
+    counter.inc_by((total as u64).saturating_sub(counter.get()));
+}
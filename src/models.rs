@@ -2,13 +2,20 @@ pub use self::action::{insert_version_owner_action, VersionAction, VersionOwnerA
 pub use self::category::{Category, CrateCategory, NewCategory};
 pub use self::crate_owner_invitation::{CrateOwnerInvitation, NewCrateOwnerInvitationOutcome};
 pub use self::dependency::{Dependency, DependencyKind, ReverseDependency};
-pub use self::download::VersionDownload;
+pub use self::download::{
+    CrateDownloadByClient, DownloadClient, DownloadHistoryRow, DownloadsInterval,
+    MonthlyVersionDownload, VersionDownload,
+};
 pub use self::email::{Email, NewEmail};
 pub use self::follow::Follow;
 pub use self::keyword::{CrateKeyword, Keyword};
 pub use self::krate::{Crate, CrateVersions, NewCrate, RecentCrateDownloads};
+pub use self::notification::NotificationType;
+pub use self::outbox_email::{NewOutboxEmail, OutboxEmail, OutboxEmailStatus};
 pub use self::owner::{CrateOwner, Owner, OwnerKind};
+pub use self::related_crate::RelatedCrateKind;
 pub use self::rights::Rights;
+pub use self::session::{CreatedSession, Session};
 pub use self::team::{NewTeam, Team};
 pub use self::token::{ApiToken, CreatedApiToken};
 pub use self::user::{NewUser, User};
@@ -25,8 +32,12 @@ mod email;
 mod follow;
 mod keyword;
 pub mod krate;
+mod notification;
+mod outbox_email;
 mod owner;
+mod related_crate;
 mod rights;
+mod session;
 mod team;
 pub mod token;
 pub mod user;
@@ -1,6 +1,7 @@
-use crate::models::Version;
-use crate::schema::version_downloads;
+use crate::models::{Crate, Version};
+use crate::schema::{crate_downloads_by_client, version_downloads, version_downloads_monthly};
 use chrono::NaiveDate;
+use diesel::sql_types::{BigInt, Date, Integer};
 
 #[derive(Queryable, Identifiable, Associations, Debug, Clone, Copy)]
 #[diesel(primary_key(version_id, date), belongs_to(Version))]
@@ -11,3 +12,226 @@ pub struct VersionDownload {
     pub date: NaiveDate,
     pub processed: bool,
 }
+
+/// The downloads of a version in a month, rolled up from the archived `version_downloads` rows
+/// by the `archive_version_downloads` background job.
+#[derive(Queryable, Identifiable, Associations, Debug, Clone, Copy)]
+#[diesel(
+    table_name = version_downloads_monthly,
+    primary_key(version_id, month),
+    belongs_to(Version)
+)]
+pub struct MonthlyVersionDownload {
+    pub version_id: i32,
+    /// The first day of the month.
+    pub month: NaiveDate,
+    pub downloads: i32,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug, Clone)]
+#[diesel(
+    table_name = crate_downloads_by_client,
+    primary_key(crate_id, date, client, cargo_version, ci),
+    belongs_to(Crate)
+)]
+pub struct CrateDownloadByClient {
+    pub crate_id: i32,
+    pub date: NaiveDate,
+    pub client: String,
+    pub cargo_version: String,
+    pub ci: bool,
+    pub downloads: i32,
+}
+
+/// The size of the buckets the download history of a crate is summed up into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadsInterval {
+    Day,
+    Week,
+    Month,
+}
+
+impl DownloadsInterval {
+    /// The name of the interval as used by the PostgreSQL `date_trunc` function.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+}
+
+/// A row of the download history of a crate, see `Crate::download_history`.
+#[derive(QueryableByName, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadHistoryRow {
+    /// The first day of the bucket.
+    #[diesel(sql_type = Date)]
+    pub date: NaiveDate,
+    #[diesel(sql_type = Integer)]
+    pub version_id: i32,
+    #[diesel(sql_type = BigInt)]
+    pub downloads: i64,
+}
+
+/// Substrings of a lowercased `User-Agent` header that identify requests coming from CI systems.
+///
+/// cargo itself does not include any CI marker in its user agent, but CI providers and users
+/// frequently override it with the `CARGO_HTTP_USER_AGENT` environment variable.
+const CI_USER_AGENT_MARKERS: &[&str] = &[
+    "github-actions",
+    "gitlab-ci",
+    "travis-ci",
+    "circleci",
+    "buildkite",
+    "jenkins",
+    "azure-pipelines",
+    "teamcity",
+    "(ci)",
+];
+
+/// The client that requested a crate download, as classified from the `User-Agent` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DownloadClient {
+    /// A download by cargo. The version is the `(major, minor)` version of cargo, if it could be
+    /// parsed from the user agent.
+    Cargo {
+        version: Option<(u64, u64)>,
+        ci: bool,
+    },
+    /// A download started from a web browser, for example from the crate page on crates.io.
+    Browser,
+    /// Any other client, like `curl` or third-party tooling.
+    Other,
+}
+
+impl DownloadClient {
+    pub fn from_user_agent(user_agent: Option<&str>) -> Self {
+        let Some(user_agent) = user_agent.map(str::trim) else {
+            return Self::Other;
+        };
+
+        let cargo_version = user_agent
+            .strip_prefix("cargo ")
+            .or_else(|| user_agent.strip_prefix("cargo/"));
+
+        if let Some(rest) = cargo_version {
+            let version = rest
+                .split_whitespace()
+                .next()
+                .and_then(|version| semver::Version::parse(version).ok())
+                .map(|version| (version.major, version.minor));
+
+            let user_agent = user_agent.to_lowercase();
+            let ci = CI_USER_AGENT_MARKERS
+                .iter()
+                .any(|marker| user_agent.contains(marker));
+
+            Self::Cargo { version, ci }
+        } else if user_agent.starts_with("Mozilla/") {
+            Self::Browser
+        } else {
+            Self::Other
+        }
+    }
+
+    /// The value stored in the `client` column of the `crate_downloads_by_client` table.
+    pub fn client(&self) -> &'static str {
+        match self {
+            Self::Cargo { .. } => "cargo",
+            Self::Browser => "browser",
+            Self::Other => "other",
+        }
+    }
+
+    /// The value stored in the `cargo_version` column of the `crate_downloads_by_client` table.
+    pub fn cargo_version(&self) -> String {
+        match self {
+            Self::Cargo {
+                version: Some((major, minor)),
+                ..
+            } => format!("{major}.{minor}"),
+            _ => String::new(),
+        }
+    }
+
+    /// The value stored in the `ci` column of the `crate_downloads_by_client` table.
+    pub fn ci(&self) -> bool {
+        matches!(self, Self::Cargo { ci: true, .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DownloadClient;
+
+    fn classify(user_agent: &str) -> DownloadClient {
+        DownloadClient::from_user_agent(Some(user_agent))
+    }
+
+    #[test]
+    fn cargo_user_agents() {
+        assert_eq!(
+            classify("cargo 1.72.1 (103a7ff2e 2023-08-15)"),
+            DownloadClient::Cargo {
+                version: Some((1, 72)),
+                ci: false
+            }
+        );
+        assert_eq!(
+            classify("cargo/1.41.0 (626f0f40e 2019-12-03)"),
+            DownloadClient::Cargo {
+                version: Some((1, 41)),
+                ci: false
+            }
+        );
+        assert_eq!(
+            classify("cargo 1.75.0-nightly (8eb8acbb1 2023-10-17)"),
+            DownloadClient::Cargo {
+                version: Some((1, 75)),
+                ci: false
+            }
+        );
+        assert_eq!(
+            classify("cargo 1.72.1 (103a7ff2e 2023-08-15) github-actions"),
+            DownloadClient::Cargo {
+                version: Some((1, 72)),
+                ci: true
+            }
+        );
+        assert_eq!(
+            classify("cargo unknown"),
+            DownloadClient::Cargo {
+                version: None,
+                ci: false
+            }
+        );
+    }
+
+    #[test]
+    fn other_user_agents() {
+        assert_eq!(
+            classify("Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/118.0"),
+            DownloadClient::Browser
+        );
+        assert_eq!(classify("curl/8.1.2"), DownloadClient::Other);
+        assert_eq!(classify("cargo-binstall/1.4.0"), DownloadClient::Other);
+        assert_eq!(DownloadClient::from_user_agent(None), DownloadClient::Other);
+    }
+
+    #[test]
+    fn column_values() {
+        let cargo = DownloadClient::Cargo {
+            version: Some((1, 70)),
+            ci: true,
+        };
+        assert_eq!(cargo.client(), "cargo");
+        assert_eq!(cargo.cargo_version(), "1.70");
+        assert!(cargo.ci());
+
+        assert_eq!(DownloadClient::Browser.client(), "browser");
+        assert_eq!(DownloadClient::Browser.cargo_version(), "");
+        assert!(!DownloadClient::Browser.ci());
+    }
+}
@@ -12,6 +12,8 @@ pub struct Email {
     pub verified: bool,
     pub token: String,
     pub token_generated_at: Option<NaiveDateTime>,
+    /// Whether notification emails are sent to this address.
+    pub is_primary: bool,
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -19,4 +21,5 @@ pub struct Email {
 pub struct NewEmail<'a> {
     pub user_id: i32,
     pub email: &'a str,
+    pub is_primary: bool,
 }
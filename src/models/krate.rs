@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::associations::Identifiable;
 use diesel::pg::Pg;
 use diesel::prelude::*;
@@ -10,14 +10,15 @@ use crate::app::App;
 use crate::controllers::helpers::pagination::*;
 use crate::models::version::TopVersions;
 use crate::models::{
-    CrateOwner, CrateOwnerInvitation, Dependency, NewCrateOwnerInvitationOutcome, Owner, OwnerKind,
-    ReverseDependency, User, Version,
+    CrateOwner, CrateOwnerInvitation, Dependency, DownloadHistoryRow, DownloadsInterval,
+    NewCrateOwnerInvitationOutcome, NotificationType, Owner, OwnerKind, ReverseDependency, User,
+    Version,
 };
 use crate::util::errors::{cargo_err, AppResult};
 
 use crate::models::helpers::with_count::*;
 use crate::schema::*;
-use crate::sql::canon_crate_name;
+use crate::sql::{canon_crate_name, TrigramDistance, TrigramSimilar};
 
 #[derive(Debug, Queryable, Identifiable, Associations, Clone, Copy)]
 #[diesel(
@@ -76,6 +77,10 @@ pub const ALL_COLUMNS: AllColumns = (
 
 pub const MAX_NAME_LENGTH: usize = 64;
 
+/// The maximum number of similar crate names in the error for unknown crates, see
+/// `Crate::similar_names`, and in the "did you mean" suggestions of the search.
+pub const MAX_SUGGESTIONS: usize = 3;
+
 type CanonCrateName<T> = canon_crate_name::HelperType<T>;
 type All = diesel::dsl::Select<crates::table, diesel::dsl::AsSelect<Crate, diesel::pg::Pg>>;
 type WithName<'a> = diesel::dsl::Eq<CanonCrateName<crates::name>, CanonCrateName<&'a str>>;
@@ -166,6 +171,51 @@ impl Crate {
         canon_crate_name(crates::name).eq(canon_crate_name(name))
     }
 
+    /// Returns the names of up to `limit` existing crates that are close to `name`, e.g. because
+    /// of a typo or mixed up `-` and `_`, ordered by their edit distance.
+    pub fn similar_names(
+        conn: &mut PgConnection,
+        name: &str,
+        limit: usize,
+    ) -> QueryResult<Vec<String>> {
+        // The candidates are found by trigram similarity, which can use an index, and are then
+        // filtered by their edit distance.
+        const CANDIDATES: i64 = 20;
+
+        let canonical = |name: &str| name.to_lowercase().replace('-', "_");
+        let target = canonical(name);
+        let max_distance = (target.chars().count() / 3).clamp(1, 3);
+
+        let candidates: Vec<String> = crates::table
+            .select(crates::name)
+            .filter(TrigramSimilar::new(
+                canon_crate_name(crates::name),
+                canon_crate_name(name),
+            ))
+            .order(TrigramDistance::new(
+                canon_crate_name(crates::name),
+                canon_crate_name(name),
+            ))
+            .limit(CANDIDATES)
+            .load(conn)?;
+
+        let mut similar = candidates
+            .into_iter()
+            .map(|candidate| {
+                let distance = strsim::damerau_levenshtein(&target, &canonical(&candidate));
+                (distance, candidate)
+            })
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect::<Vec<_>>();
+        similar.sort();
+
+        Ok(similar
+            .into_iter()
+            .take(limit)
+            .map(|(_, name)| name)
+            .collect())
+    }
+
     pub fn by_name(name: &str) -> ByName<'_> {
         Crate::all().filter(Self::with_name(name))
     }
@@ -292,11 +342,30 @@ impl Crate {
                 let config = &app.config;
                 match CrateOwnerInvitation::create(user.id, req_user.id, self.id, conn, config)? {
                     NewCrateOwnerInvitationOutcome::InviteCreated { plaintext_token } => {
-                        if let Ok(Some(email)) = user.verified_email(conn) {
+                        // Failing to look up the opt-out is treated like a missing email
+                        // address. The savepoint keeps the error from aborting the transaction.
+                        let notification_type = NotificationType::OwnerInvitation;
+                        let enabled = conn
+                            .transaction(|conn| notification_type.is_enabled_for(conn, user.id));
+                        let email = match enabled {
+                            Ok(true) => user.verified_email(conn),
+                            Ok(false) => Ok(None),
+                            Err(error) => {
+                                warn!(
+                                    user_id = user.id,
+                                    ?error,
+                                    "Failed to look up notification opt-out"
+                                );
+                                Ok(None)
+                            }
+                        };
+                        if let Ok(Some(email)) = email {
                             // Swallow any error. Whether or not the email is sent, the invitation
                             // entry will be created in the database and the user will see the
                             // invitation when they visit https://crates.io/me/pending-invites/.
                             let _ = app.emails.send_owner_invite(
+                                conn,
+                                user.id,
                                 &email,
                                 &req_user.gh_login,
                                 &self.name,
@@ -376,6 +445,31 @@ impl Crate {
         Ok(rows.records_and_total())
     }
 
+    /// Returns the downloads of all versions of the crate between `start_date` and `end_date`
+    /// (inclusive), summed up per version and `interval`.
+    ///
+    /// Buckets are identified by their first day, so the first bucket may start before
+    /// `start_date` for weekly and monthly intervals. Archived downloads are only available per
+    /// month and are counted in the bucket of the first day of their month.
+    #[instrument(skip_all, fields(krate.name = %self.name))]
+    pub fn download_history(
+        &self,
+        conn: &mut PgConnection,
+        interval: DownloadsInterval,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> QueryResult<Vec<DownloadHistoryRow>> {
+        use diesel::sql_query;
+        use diesel::sql_types::{Date, Integer, Text};
+
+        sql_query(include_str!("krate_download_history.sql"))
+            .bind::<Integer, _>(self.id)
+            .bind::<Text, _>(interval.as_str())
+            .bind::<Date, _>(start_date)
+            .bind::<Date, _>(end_date)
+            .load(conn)
+    }
+
     /// Gather all the necessary data to write an index metadata file
     pub fn index_metadata(
         &self,
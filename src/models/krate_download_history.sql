@@ -0,0 +1,23 @@
+-- Sums up the daily downloads of all versions of a crate in the given date
+-- range, bucketed by the given interval (`day`, `week` or `month`).
+--
+-- Downloads that were archived by the `archive_version_downloads` job are only
+-- available per month, so they are attributed to the first day of the month.
+SELECT
+    date_trunc($2, downloads.date)::date AS date,
+    downloads.version_id,
+    SUM(downloads.downloads)::bigint AS downloads
+FROM (
+    SELECT version_id, date, downloads
+    FROM version_downloads
+    WHERE date BETWEEN $3 AND $4
+    UNION ALL
+    SELECT version_id, month, downloads
+    FROM version_downloads_monthly
+    WHERE month BETWEEN date_trunc('month', $3::date) AND $4
+) AS downloads
+INNER JOIN versions
+    ON versions.id = downloads.version_id
+WHERE versions.crate_id = $1
+GROUP BY 1, 2
+ORDER BY 1, 2
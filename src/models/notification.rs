@@ -0,0 +1,150 @@
+use diesel::dsl::exists;
+use diesel::prelude::*;
+
+use crate::models::User;
+use crate::schema::{notification_opt_ins, notification_opt_outs};
+use crate::sql::pg_enum;
+
+// The types of notification emails that users can opt out of:
+//
+// - `TokenExpiry`: reminders that an API token expires in 7 days or 1 day
+// - `TokenCreated`: an API token was created or rotated
+// - `TokenNewIpAddress`: an API token was used from an IP address it was never used from before
+// - `TokenExposed`: an API token was found in public and revoked by secret scanning
+// - `OwnerInvitation`: the user was invited to become an owner of a crate
+//
+// and the types that users have to opt in to:
+//
+// - `WeeklyDigest`: a weekly summary of the crates of the user, see `worker::weekly_digests`
+pg_enum! {
+    pub enum NotificationType {
+        TokenExpiry = 0,
+        TokenCreated = 1,
+        TokenNewIpAddress = 2,
+        TokenExposed = 3,
+        OwnerInvitation = 4,
+        WeeklyDigest = 5,
+    }
+}
+
+impl NotificationType {
+    /// Describes the notifications of this type, e.g. for "You will no longer receive {…}."
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::TokenExpiry => "reminders about expiring API tokens",
+            Self::TokenCreated => "notifications about new API tokens",
+            Self::TokenNewIpAddress => "notifications about API tokens used from new IP addresses",
+            Self::TokenExposed => "notifications about exposed API tokens",
+            Self::OwnerInvitation => "emails about crate ownership invitations",
+            Self::WeeklyDigest => "the weekly digest of your crates",
+        }
+    }
+
+    /// Returns whether users receive notifications of this type unless they opt out. Otherwise
+    /// they have to opt in to them.
+    pub fn is_enabled_by_default(self) -> bool {
+        !matches!(self, Self::WeeklyDigest)
+    }
+
+    /// Returns whether the user receives notifications of this type.
+    pub fn is_enabled_for(self, conn: &mut PgConnection, user_id: i32) -> QueryResult<bool> {
+        if !self.is_enabled_by_default() {
+            return diesel::select(exists(
+                notification_opt_ins::table
+                    .filter(notification_opt_ins::user_id.eq(user_id))
+                    .filter(notification_opt_ins::notification_type.eq(self)),
+            ))
+            .get_result(conn);
+        }
+
+        let opted_out = diesel::select(exists(
+            notification_opt_outs::table
+                .filter(notification_opt_outs::user_id.eq(user_id))
+                .filter(notification_opt_outs::notification_type.eq(self)),
+        ))
+        .get_result::<bool>(conn)?;
+
+        Ok(!opted_out)
+    }
+
+    /// Returns the email address that notifications of this type are sent to, or `None` if the
+    /// user does not receive them or has no verified email address.
+    pub fn recipient(self, conn: &mut PgConnection, user: &User) -> QueryResult<Option<String>> {
+        if !self.is_enabled_for(conn, user.id)? {
+            return Ok(None);
+        }
+
+        user.verified_email(conn)
+    }
+
+    /// Opts the user in to or out of notifications of this type.
+    pub fn set_enabled_for(
+        self,
+        conn: &mut PgConnection,
+        user_id: i32,
+        enabled: bool,
+    ) -> QueryResult<()> {
+        match (self.is_enabled_by_default(), enabled) {
+            (true, true) => {
+                diesel::delete(
+                    notification_opt_outs::table
+                        .filter(notification_opt_outs::user_id.eq(user_id))
+                        .filter(notification_opt_outs::notification_type.eq(self)),
+                )
+                .execute(conn)?;
+            }
+            (true, false) => {
+                diesel::insert_into(notification_opt_outs::table)
+                    .values((
+                        notification_opt_outs::user_id.eq(user_id),
+                        notification_opt_outs::notification_type.eq(self),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            (false, true) => {
+                diesel::insert_into(notification_opt_ins::table)
+                    .values((
+                        notification_opt_ins::user_id.eq(user_id),
+                        notification_opt_ins::notification_type.eq(self),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            (false, false) => {
+                diesel::delete(
+                    notification_opt_ins::table
+                        .filter(notification_opt_ins::user_id.eq(user_id))
+                        .filter(notification_opt_ins::notification_type.eq(self)),
+                )
+                .execute(conn)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the notification types that the user receives.
+    pub fn enabled_for(conn: &mut PgConnection, user_id: i32) -> QueryResult<Vec<Self>> {
+        let opted_out: Vec<Self> = notification_opt_outs::table
+            .filter(notification_opt_outs::user_id.eq(user_id))
+            .select(notification_opt_outs::notification_type)
+            .load(conn)?;
+
+        let opted_in: Vec<Self> = notification_opt_ins::table
+            .filter(notification_opt_ins::user_id.eq(user_id))
+            .select(notification_opt_ins::notification_type)
+            .load(conn)?;
+
+        Ok(Self::VARIANTS
+            .iter()
+            .copied()
+            .filter(
+                |notification_type| match notification_type.is_enabled_by_default() {
+                    true => !opted_out.contains(notification_type),
+                    false => opted_in.contains(notification_type),
+                },
+            )
+            .collect())
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::schema::email_outbox;
+use crate::sql::pg_enum;
+
+pg_enum! {
+    pub enum OutboxEmailStatus {
+        Pending = 0,
+        Sent = 1,
+        Failed = 2,
+    }
+}
+
+/// An email that was queued for delivery by the `send_email` background job.
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable)]
+#[diesel(table_name = email_outbox, check_for_backend(diesel::pg::Pg))]
+pub struct OutboxEmail {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub recipient: String,
+    pub subject: String,
+    pub body_text: String,
+    pub body_html: String,
+    pub status: OutboxEmailStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub message_id: Option<String>,
+    pub sent_at: Option<NaiveDateTime>,
+    pub unsubscribe_token: Option<String>,
+}
+
+impl OutboxEmail {
+    pub fn find(conn: &mut PgConnection, id: i64) -> QueryResult<Self> {
+        email_outbox::table
+            .find(id)
+            .select(OutboxEmail::as_select())
+            .first(conn)
+    }
+
+    /// Records a successful delivery attempt, and clears the contents of the email.
+    pub fn mark_sent(&self, conn: &mut PgConnection, message_id: &str) -> QueryResult<()> {
+        diesel::update(self)
+            .set((
+                email_outbox::status.eq(OutboxEmailStatus::Sent),
+                email_outbox::attempts.eq(self.attempts + 1),
+                email_outbox::message_id.eq(message_id),
+                email_outbox::sent_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+
+        self.clear_contents(conn)
+    }
+
+    /// Records a failed delivery attempt. The email stays pending unless `status` says otherwise,
+    /// in which case its contents are cleared.
+    pub fn mark_attempt_failed(
+        &self,
+        conn: &mut PgConnection,
+        status: OutboxEmailStatus,
+        error: &str,
+    ) -> QueryResult<()> {
+        diesel::update(self)
+            .set((
+                email_outbox::status.eq(status),
+                email_outbox::attempts.eq(self.attempts + 1),
+                email_outbox::last_error.eq(error),
+            ))
+            .execute(conn)?;
+
+        if status != OutboxEmailStatus::Pending {
+            self.clear_contents(conn)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the rendered bodies and the unsubscribe token, which can contain secrets and are
+    /// not needed anymore once the email is processed.
+    fn clear_contents(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        diesel::update(self)
+            .set((
+                email_outbox::body_text.eq(""),
+                email_outbox::body_html.eq(""),
+                email_outbox::unsubscribe_token.eq(None::<String>),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = email_outbox, check_for_backend(diesel::pg::Pg))]
+pub struct NewOutboxEmail<'a> {
+    pub recipient: &'a str,
+    pub subject: &'a str,
+    pub body_text: &'a str,
+    pub body_html: &'a str,
+    pub unsubscribe_token: Option<&'a str>,
+}
+
+impl NewOutboxEmail<'_> {
+    /// Inserts the email into the outbox and returns its ID.
+    pub fn insert(&self, conn: &mut PgConnection) -> QueryResult<i64> {
+        diesel::insert_into(email_outbox::table)
+            .values(self)
+            .returning(email_outbox::id)
+            .get_result(conn)
+    }
+}
@@ -0,0 +1,9 @@
+/// The kind of relation between the crates in the `related_crates` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i16)]
+pub enum RelatedCrateKind {
+    /// The crates are frequently depended on together.
+    Dependency = 0,
+    /// The crates are commonly used as alternatives to each other.
+    Alternative = 1,
+}
@@ -0,0 +1,113 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+
+use crate::models::User;
+use crate::schema::sessions;
+use crate::util::token::{HashedToken, PlainToken};
+
+/// Sessions expire this many days after the user logged in, like the session cookie.
+pub const SESSION_MAX_AGE_DAYS: i32 = 90;
+
+/// `last_seen_at` is only updated when it is older than this, so that not every authenticated
+/// request writes to the database.
+const LAST_SEEN_AT_RESOLUTION_MINUTES: i64 = 5;
+
+/// The model representing a row in the `sessions` database table.
+#[derive(Debug, Identifiable, Queryable, Selectable, Associations)]
+#[diesel(belongs_to(User))]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub ip_address: Option<IpNetwork>,
+    pub user_agent: String,
+}
+
+impl Session {
+    /// Creates a new session for a user that just logged in.
+    pub fn insert(
+        conn: &mut PgConnection,
+        user_id: i32,
+        ip_address: Option<IpAddr>,
+        user_agent: &str,
+    ) -> QueryResult<CreatedSession> {
+        let token = PlainToken::generate();
+
+        let model = diesel::insert_into(sessions::table)
+            .values((
+                sessions::user_id.eq(user_id),
+                sessions::token.eq(token.hashed()),
+                sessions::ip_address.eq(ip_address.map(IpNetwork::from)),
+                sessions::user_agent.eq(user_agent),
+            ))
+            .returning(Session::as_returning())
+            .get_result(conn)?;
+
+        Ok(CreatedSession {
+            model,
+            plaintext: token,
+        })
+    }
+
+    /// Finds the session with the plaintext token from a session cookie, unless the session
+    /// expired or was deleted.
+    pub fn find_by_token(conn: &mut PgConnection, token: &str) -> QueryResult<Session> {
+        let token = HashedToken::parse(token).ok_or(diesel::result::Error::NotFound)?;
+
+        let mut session: Session = Self::active()
+            .filter(sessions::token.eq(&token))
+            .select(Session::as_select())
+            .first(conn)?;
+
+        let resolution = Duration::minutes(LAST_SEEN_AT_RESOLUTION_MINUTES);
+        if session.last_seen_at < Utc::now().naive_utc() - resolution {
+            // If the database is in read only mode, we can't update last_seen_at.
+            // Try updating in a new transaction, if that fails, keep the old value.
+            let last_seen_at = conn.transaction(|conn| {
+                diesel::update(sessions::table.find(session.id))
+                    .set(sessions::last_seen_at.eq(now))
+                    .returning(sessions::last_seen_at)
+                    .get_result(conn)
+            });
+            if let Ok(last_seen_at) = last_seen_at {
+                session.last_seen_at = last_seen_at;
+            }
+        }
+
+        Ok(session)
+    }
+
+    /// Deletes the session with the plaintext token from a session cookie, which logs it out.
+    pub fn delete_by_token(conn: &mut PgConnection, token: &str) -> QueryResult<usize> {
+        let Some(token) = HashedToken::parse(token) else {
+            return Ok(0);
+        };
+
+        diesel::delete(sessions::table.filter(sessions::token.eq(&token))).execute(conn)
+    }
+
+    /// Deletes the sessions that expired, and returns how many were deleted.
+    pub fn delete_expired(conn: &mut PgConnection) -> QueryResult<usize> {
+        diesel::delete(
+            sessions::table.filter(sessions::created_at.le(now - SESSION_MAX_AGE_DAYS.days())),
+        )
+        .execute(conn)
+    }
+
+    /// The sessions that did not expire yet.
+    pub fn active() -> sessions::BoxedQuery<'static, diesel::pg::Pg> {
+        sessions::table
+            .filter(sessions::created_at.gt(now - SESSION_MAX_AGE_DAYS.days()))
+            .into_boxed()
+    }
+}
+
+#[derive(Debug)]
+pub struct CreatedSession {
+    pub model: Session,
+    pub plaintext: PlainToken,
+}
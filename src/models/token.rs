@@ -1,15 +1,24 @@
+mod activity;
 mod scopes;
 
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+use std::collections::HashSet;
+use std::net::IpAddr;
 
+pub use self::activity::{ApiTokenActivity, NewApiTokenActivity};
 pub use self::scopes::{CrateScope, EndpointScope};
 use crate::models::User;
-use crate::schema::api_tokens;
+use crate::schema::{api_token_ip_addresses, api_tokens};
 use crate::util::errors::{AppResult, InsecurelyGeneratedTokenRevoked};
 use crate::util::rfc3339;
 use crate::util::token::{HashedToken, PlainToken};
 
+/// PostgreSQL supports at most 65535 bind parameters per query, and each IP address uses two of
+/// them.
+const IP_ADDRESS_CHUNK_SIZE: usize = 10_000;
+
 /// The model representing a row in the `api_tokens` database table.
 #[derive(Debug, Identifiable, Queryable, Selectable, Associations, Serialize)]
 #[diesel(belongs_to(User))]
@@ -30,12 +39,14 @@ pub struct ApiToken {
     pub endpoint_scopes: Option<Vec<EndpointScope>>,
     #[serde(with = "rfc3339::option")]
     pub expired_at: Option<NaiveDateTime>,
+    /// `None` or a list of IP address ranges the token can be used from
+    pub allowed_ip_ranges: Option<Vec<IpNetwork>>,
 }
 
 impl ApiToken {
     /// Generates a new named API token for a user
     pub fn insert(conn: &mut PgConnection, user_id: i32, name: &str) -> AppResult<CreatedApiToken> {
-        Self::insert_with_scopes(conn, user_id, name, None, None, None)
+        Self::insert_with_scopes(conn, user_id, name, None, None, None, None)
     }
 
     pub fn insert_with_scopes(
@@ -45,6 +56,7 @@ impl ApiToken {
         crate_scopes: Option<Vec<CrateScope>>,
         endpoint_scopes: Option<Vec<EndpointScope>>,
         expired_at: Option<NaiveDateTime>,
+        allowed_ip_ranges: Option<Vec<IpNetwork>>,
     ) -> AppResult<CreatedApiToken> {
         let token = PlainToken::generate();
 
@@ -56,6 +68,7 @@ impl ApiToken {
                 api_tokens::crate_scopes.eq(crate_scopes),
                 api_tokens::endpoint_scopes.eq(endpoint_scopes),
                 api_tokens::expired_at.eq(expired_at),
+                api_tokens::allowed_ip_ranges.eq(allowed_ip_ranges),
             ))
             .returning(ApiToken::as_returning())
             .get_result(conn)?;
@@ -89,6 +102,59 @@ impl ApiToken {
         .or_else(|_| tokens.select(ApiToken::as_select()).first(conn))
         .map_err(Into::into)
     }
+
+    /// Remembers that the tokens were used from the given IP addresses, and returns the
+    /// token/IP address pairs whose address is new while the token was already used from a
+    /// different address before, in the order they were given.
+    ///
+    /// The first address a token is used from is not considered new, since the owner just
+    /// created the token and is expected to use it somewhere.
+    pub fn record_ip_addresses(
+        conn: &mut PgConnection,
+        ip_addresses: &[(i32, IpAddr)],
+    ) -> QueryResult<Vec<(i32, IpAddr)>> {
+        let token_ids = ip_addresses.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+        let mut used_token_ids: HashSet<i32> = api_token_ip_addresses::table
+            .select(api_token_ip_addresses::api_token_id)
+            .distinct()
+            .filter(api_token_ip_addresses::api_token_id.eq_any(&token_ids))
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect();
+
+        let mut inserted = HashSet::new();
+        for chunk in ip_addresses.chunks(IP_ADDRESS_CHUNK_SIZE) {
+            let values = chunk
+                .iter()
+                .map(|(api_token_id, ip_address)| {
+                    (
+                        api_token_ip_addresses::api_token_id.eq(*api_token_id),
+                        api_token_ip_addresses::ip_address.eq(IpNetwork::from(*ip_address)),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let rows: Vec<(i32, IpNetwork)> = diesel::insert_into(api_token_ip_addresses::table)
+                .values(values)
+                .on_conflict_do_nothing()
+                .returning((
+                    api_token_ip_addresses::api_token_id,
+                    api_token_ip_addresses::ip_address,
+                ))
+                .get_results(conn)?;
+
+            inserted.extend(rows.into_iter().map(|(id, ip)| (id, ip.ip())));
+        }
+
+        Ok(ip_addresses
+            .iter()
+            .filter(|pair| inserted.contains(*pair))
+            // `insert()` returns `false` if the token was already used from another address
+            .filter(|(api_token_id, _)| !used_token_ids.insert(*api_token_id))
+            .copied()
+            .collect())
+    }
 }
 
 #[derive(Debug)]
@@ -119,6 +185,7 @@ mod tests {
             crate_scopes: None,
             endpoint_scopes: None,
             expired_at: None,
+            allowed_ip_ranges: None,
         };
         let json = serde_json::to_string(&tok).unwrap();
         assert_some!(json
@@ -0,0 +1,33 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+
+use crate::models::ApiToken;
+use crate::schema::api_token_activity;
+
+/// The model representing a row in the `api_token_activity` database table.
+#[derive(Debug, Identifiable, Queryable, Selectable, Associations)]
+#[diesel(table_name = api_token_activity, belongs_to(ApiToken))]
+pub struct ApiTokenActivity {
+    pub id: i64,
+    pub api_token_id: i32,
+    pub created_at: NaiveDateTime,
+    pub endpoint: String,
+    pub crate_name: Option<String>,
+    pub ip_address: Option<IpNetwork>,
+    pub user_agent: String,
+}
+
+/// A use of an API token that is not persisted in the database yet.
+///
+/// See `crate::token_activity::TokenActivityLog` for how these are written to the database.
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = api_token_activity)]
+pub struct NewApiTokenActivity {
+    pub api_token_id: i32,
+    pub created_at: NaiveDateTime,
+    pub endpoint: String,
+    pub crate_name: Option<String>,
+    pub ip_address: Option<IpNetwork>,
+    pub user_agent: String,
+}
@@ -13,6 +13,10 @@ pub enum EndpointScope {
     PublishUpdate,
     Yank,
     ChangeOwners,
+    ReadAccount,
+    ManageFollows,
+    ManageInvitations,
+    ManageTokens,
 }
 
 impl From<&EndpointScope> for &[u8] {
@@ -22,6 +26,10 @@ impl From<&EndpointScope> for &[u8] {
             EndpointScope::PublishUpdate => b"publish-update",
             EndpointScope::Yank => b"yank",
             EndpointScope::ChangeOwners => b"change-owners",
+            EndpointScope::ReadAccount => b"read-account",
+            EndpointScope::ManageFollows => b"manage-follows",
+            EndpointScope::ManageInvitations => b"manage-invitations",
+            EndpointScope::ManageTokens => b"manage-tokens",
         }
     }
 }
@@ -42,6 +50,10 @@ impl TryFrom<&[u8]> for EndpointScope {
             b"publish-update" => Ok(EndpointScope::PublishUpdate),
             b"yank" => Ok(EndpointScope::Yank),
             b"change-owners" => Ok(EndpointScope::ChangeOwners),
+            b"read-account" => Ok(EndpointScope::ReadAccount),
+            b"manage-follows" => Ok(EndpointScope::ManageFollows),
+            b"manage-invitations" => Ok(EndpointScope::ManageInvitations),
+            b"manage-tokens" => Ok(EndpointScope::ManageTokens),
             _ => Err("Unrecognized enum variant".to_string()),
         }
     }
@@ -137,6 +149,10 @@ mod tests {
         assert(EndpointScope::PublishNew, "\"publish-new\"");
         assert(EndpointScope::PublishUpdate, "\"publish-update\"");
         assert(EndpointScope::Yank, "\"yank\"");
+        assert(EndpointScope::ReadAccount, "\"read-account\"");
+        assert(EndpointScope::ManageFollows, "\"manage-follows\"");
+        assert(EndpointScope::ManageInvitations, "\"manage-invitations\"");
+        assert(EndpointScope::ManageTokens, "\"manage-tokens\"");
     }
 
     #[test]
@@ -20,6 +20,7 @@ pub struct User {
     pub gh_id: i32,
     pub account_lock_reason: Option<String>,
     pub account_lock_until: Option<NaiveDateTime>,
+    pub sessions_revoked_at: Option<NaiveDateTime>,
 }
 
 /// Represents a new user record insertable to the `users` table
@@ -89,8 +90,10 @@ impl<'a> NewUser<'a> {
                 let new_email = NewEmail {
                     user_id: user.id,
                     email: user_email,
+                    is_primary: true,
                 };
 
+                // Users that already have a primary address keep it
                 let token: Option<String> = insert_into(emails::table)
                     .values(&new_email)
                     .on_conflict_do_nothing()
@@ -100,7 +103,7 @@ impl<'a> NewUser<'a> {
 
                 if let Some(token) = token {
                     // Swallows any error. Some users might insert an invalid email address here.
-                    let _ = emails.send_user_confirm(user_email, &user.gh_login, &token);
+                    let _ = emails.send_user_confirm(conn, user_email, &user.gh_login, &token);
                 }
             }
 
@@ -160,21 +163,30 @@ impl User {
         Ok(best)
     }
 
-    /// Queries the database for the verified emails
-    /// belonging to a given user
+    /// Queries the database for a verified email address belonging to
+    /// a given user, preferring the primary address
     pub fn verified_email(&self, conn: &mut PgConnection) -> QueryResult<Option<String>> {
         Email::belonging_to(self)
             .select(emails::email)
             .filter(emails::verified.eq(true))
+            .order((emails::is_primary.desc(), emails::id.asc()))
             .first(conn)
             .optional()
     }
 
-    /// Queries for the email belonging to a particular user
+    /// Queries for the primary email address of a particular user
     pub fn email(&self, conn: &mut PgConnection) -> QueryResult<Option<String>> {
         Email::belonging_to(self)
             .select(emails::email)
+            .filter(emails::is_primary.eq(true))
             .first(conn)
             .optional()
     }
+
+    /// Queries for all email addresses of a particular user, primary address first
+    pub fn all_emails(&self, conn: &mut PgConnection) -> QueryResult<Vec<Email>> {
+        Email::belonging_to(self)
+            .order((emails::is_primary.desc(), emails::id.asc()))
+            .load(conn)
+    }
 }
@@ -6,6 +6,7 @@ use diesel::prelude::*;
 use crate::util::errors::{cargo_err, AppResult};
 
 use crate::db::sql_types::semver::Triple;
+use crate::licenses::spdx_licenses;
 use crate::models::{Crate, Dependency, User};
 use crate::schema::*;
 use crate::sql::split_part;
@@ -29,6 +30,10 @@ pub struct Version {
     pub links: Option<String>,
     pub rust_version: Option<String>,
     pub semver_no_prerelease: Option<Triple>,
+    pub has_build_script: Option<bool>,
+    pub has_proc_macro: Option<bool>,
+    pub license_ids: Option<Vec<String>>,
+    pub license_alternatives: Option<Vec<String>>,
 }
 
 #[derive(Insertable, Debug)]
@@ -43,6 +48,10 @@ pub struct NewVersion {
     checksum: String,
     links: Option<String>,
     rust_version: Option<String>,
+    has_build_script: bool,
+    has_proc_macro: bool,
+    license_ids: Option<Vec<String>>,
+    license_alternatives: Option<Vec<String>>,
 }
 
 /// The highest version (semver order) and the most recently updated version.
@@ -144,9 +153,16 @@ impl NewVersion {
         checksum: String,
         links: Option<String>,
         rust_version: Option<String>,
+        has_build_script: bool,
+        has_proc_macro: bool,
     ) -> AppResult<Self> {
         let features = serde_json::to_value(features)?;
 
+        let (license_ids, license_alternatives) = match license.as_deref().map(spdx_licenses) {
+            Some((ids, alternatives)) => (Some(ids), Some(alternatives)),
+            None => (None, None),
+        };
+
         Ok(NewVersion {
             crate_id,
             num: num.to_string(),
@@ -157,6 +173,10 @@ impl NewVersion {
             checksum,
             links,
             rust_version,
+            has_build_script,
+            has_proc_macro,
+            license_ids,
+            license_alternatives,
         })
     }
 
@@ -14,6 +14,8 @@ pg_enum! {
         PublishNew = 0,
         PublishUpdate = 1,
         YankUnyank = 2,
+        BatchLookup = 3,
+        AuditLockfile = 4,
     }
 }
 
@@ -23,6 +25,8 @@ impl LimitedAction {
             LimitedAction::PublishNew => 10 * 60, // 10 minutes
             LimitedAction::PublishUpdate => 60,   // 1 minute
             LimitedAction::YankUnyank => 60,      // 1 minute
+            LimitedAction::BatchLookup => 1,      // 1 second
+            LimitedAction::AuditLockfile => 1,    // 1 second
         }
     }
 
@@ -31,6 +35,8 @@ impl LimitedAction {
             LimitedAction::PublishNew => 5,
             LimitedAction::PublishUpdate => 30,
             LimitedAction::YankUnyank => 100,
+            LimitedAction::BatchLookup => 100,
+            LimitedAction::AuditLockfile => 100,
         }
     }
 
@@ -39,6 +45,8 @@ impl LimitedAction {
             LimitedAction::PublishNew => "PUBLISH_NEW",
             LimitedAction::PublishUpdate => "PUBLISH_UPDATE",
             LimitedAction::YankUnyank => "YANK_UNYANK",
+            LimitedAction::BatchLookup => "BATCH_LOOKUP",
+            LimitedAction::AuditLockfile => "AUDIT_LOCKFILE",
         }
     }
 
@@ -53,6 +61,12 @@ impl LimitedAction {
             LimitedAction::YankUnyank => {
                 "You have yanked or unyanked too many versions in a short period of time"
             }
+            LimitedAction::BatchLookup => {
+                "You have looked up the metadata of too many crates in a short period of time"
+            }
+            LimitedAction::AuditLockfile => {
+                "You have audited too many lockfile packages in a short period of time"
+            }
         }
     }
 }
@@ -92,6 +106,41 @@ impl RateLimiter {
         }
     }
 
+    /// Like `check_rate_limit`, but the action counts as `cost` actions, e.g. for requests that
+    /// look up a variable number of crates.
+    ///
+    /// If the bucket doesn't have enough tokens, the request is rejected without taking any.
+    pub fn check_rate_limit_with_cost(
+        &self,
+        uploader: i32,
+        performed_action: LimitedAction,
+        cost: u32,
+        conn: &mut PgConnection,
+    ) -> AppResult<()> {
+        let now = Utc::now().naive_utc();
+        let cost = i32::try_from(cost).unwrap_or(i32::MAX);
+        if self.take_tokens(uploader, performed_action, cost, now, conn)? {
+            return Ok(());
+        }
+
+        let rate =
+            chrono::Duration::from_std(self.config_for_action(performed_action).rate).unwrap();
+        let last_refill = publish_limit_buckets::table
+            .find((uploader, performed_action))
+            .select(publish_limit_buckets::last_refill)
+            .first(conn)
+            .optional()?
+            .unwrap_or(now);
+
+        // Rejected requests don't store the refilled tokens, so the next refill can be more than
+        // one `rate` after `last_refill`.
+        let refills = (now - last_refill).num_milliseconds() / rate.num_milliseconds().max(1);
+        Err(Box::new(TooManyRequests {
+            action: performed_action,
+            retry_after: last_refill + rate * (refills as i32 + 1),
+        }))
+    }
+
     /// Refill a user's bucket as needed, take a token from it,
     /// and returns the result.
     ///
@@ -111,18 +160,7 @@ impl RateLimiter {
 
         let config = self.config_for_action(performed_action);
         let refill_rate = (config.rate.as_millis() as i64).milliseconds();
-
-        let burst: i32 = publish_rate_overrides::table
-            .find((uploader, performed_action))
-            .filter(
-                publish_rate_overrides::expires_at
-                    .is_null()
-                    .or(publish_rate_overrides::expires_at.gt(now)),
-            )
-            .select(publish_rate_overrides::burst)
-            .first(conn)
-            .optional()?
-            .unwrap_or(config.burst);
+        let burst = self.burst(uploader, performed_action, now, conn)?;
 
         // Interval division is poorly defined in general (what is 1 month / 30 days?)
         // However, for the intervals we're dealing with, it is always well
@@ -148,6 +186,85 @@ impl RateLimiter {
             .get_result(conn)
     }
 
+    /// Refill a user's bucket as needed and take `cost` tokens from it, as if `take_token` was
+    /// called `cost` times.
+    ///
+    /// Returns `false` without changing the bucket if it doesn't have enough tokens. The check
+    /// and the update happen in a single query, so concurrent requests can't both take the last
+    /// tokens of the bucket.
+    fn take_tokens(
+        &self,
+        uploader: i32,
+        performed_action: LimitedAction,
+        cost: i32,
+        now: NaiveDateTime,
+        conn: &mut PgConnection,
+    ) -> QueryResult<bool> {
+        use self::publish_limit_buckets::dsl::*;
+
+        let config = self.config_for_action(performed_action);
+        let refill_rate = (config.rate.as_millis() as i64).milliseconds();
+        let burst = self.burst(uploader, performed_action, now, conn)?;
+
+        // The bucket can never hold enough tokens.
+        if cost > burst {
+            return Ok(false);
+        }
+
+        let tokens_to_add = floor(
+            (date_part("epoch", now) - date_part("epoch", last_refill))
+                / interval_part("epoch", refill_rate),
+        );
+
+        // The number of tokens after refilling the bucket and taking the first token, see
+        // `take_token`. The remaining `cost - 1` tokens are taken if that leaves at least one.
+        let available = least(burst, greatest(0, tokens - 1) + tokens_to_add);
+
+        let upsert = diesel::insert_into(publish_limit_buckets)
+            .values((
+                user_id.eq(uploader),
+                action.eq(performed_action),
+                tokens.eq(burst - (cost - 1)),
+                last_refill.eq(now),
+            ))
+            .on_conflict((user_id, action))
+            .do_update()
+            .set((
+                tokens.eq(available - (cost - 1)),
+                last_refill.eq(last_refill + refill_rate.into_sql::<Interval>() * tokens_to_add),
+            ));
+
+        // Insert statements only implement `FilterDsl`, which adds the `WHERE` clause of the
+        // `ON CONFLICT DO UPDATE`, but not `QueryDsl`.
+        let updated = diesel::query_dsl::methods::FilterDsl::filter(upsert, available.ge(cost))
+            .execute(conn)?;
+
+        Ok(updated == 1)
+    }
+
+    /// Returns the burst of the action for the user, which may be overridden per user.
+    fn burst(
+        &self,
+        uploader: i32,
+        performed_action: LimitedAction,
+        now: NaiveDateTime,
+        conn: &mut PgConnection,
+    ) -> QueryResult<i32> {
+        let burst = publish_rate_overrides::table
+            .find((uploader, performed_action))
+            .filter(
+                publish_rate_overrides::expires_at
+                    .is_null()
+                    .or(publish_rate_overrides::expires_at.gt(now)),
+            )
+            .select(publish_rate_overrides::burst)
+            .first(conn)
+            .optional()?
+            .unwrap_or(self.config_for_action(performed_action).burst);
+
+        Ok(burst)
+    }
+
     fn config_for_action(&self, action: LimitedAction) -> Cow<'_, RateLimiterConfig> {
         // The wrapper returns the default config for the action when not configured.
         match self.config.get(&action) {
@@ -421,6 +538,47 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn check_rate_limit_with_cost_takes_multiple_tokens() -> QueryResult<()> {
+        let conn = &mut pg_connection();
+
+        let rate = SampleRateLimiter {
+            rate: Duration::from_secs(60 * 60),
+            burst: 10,
+            action: LimitedAction::BatchLookup,
+        }
+        .create();
+        let user_id = new_user(conn, "user1")?;
+
+        let action = LimitedAction::BatchLookup;
+        assert!(rate
+            .check_rate_limit_with_cost(user_id, action, 4, conn)
+            .is_ok());
+        assert!(rate
+            .check_rate_limit_with_cost(user_id, action, 4, conn)
+            .is_ok());
+        assert!(rate
+            .check_rate_limit_with_cost(user_id, action, 4, conn)
+            .is_err());
+
+        // The rejected request didn't take any tokens
+        assert!(rate
+            .check_rate_limit_with_cost(user_id, action, 2, conn)
+            .is_ok());
+        assert!(rate.check_rate_limit(user_id, action, conn).is_err());
+
+        // Requests costing more than the burst are always rejected
+        let user_id = new_user(conn, "user2")?;
+        assert!(rate
+            .check_rate_limit_with_cost(user_id, action, 11, conn)
+            .is_err());
+        assert!(rate
+            .check_rate_limit_with_cost(user_id, action, 10, conn)
+            .is_ok());
+
+        Ok(())
+    }
+
     #[test]
     fn a_user_with_no_tokens_gets_a_token_after_exactly_rate() -> QueryResult<()> {
         let conn = &mut pg_connection();
@@ -15,6 +15,7 @@ pub fn build_axum_router(state: AppState) -> Router {
     let mut router = Router::new()
         // Route used by both `cargo search` and the frontend
         .route("/api/v1/crates", get(krate::search::search))
+        .route("/api/v1/crates_batch", post(krate::batch::batch))
         // Routes used by `cargo`
         .route(
             "/api/v1/crates/new",
@@ -94,6 +95,10 @@ pub fn build_axum_router(state: AppState) -> Router {
             "/api/v1/crates/:crate_id/reverse_dependencies",
             get(krate::metadata::reverse_dependencies),
         )
+        .route(
+            "/api/v1/crates/:crate_id/related",
+            get(krate::related::related),
+        )
         .route("/api/v1/keywords", get(keyword::index))
         .route("/api/v1/keywords/:keyword_id", get(keyword::show))
         .route("/api/v1/categories", get(category::index))
@@ -109,6 +114,13 @@ pub fn build_axum_router(state: AppState) -> Router {
         .route("/api/v1/me/updates", get(user::me::updates))
         .route("/api/v1/me/tokens", get(token::list).put(token::new))
         .route("/api/v1/me/tokens/:id", delete(token::revoke))
+        .route("/api/v1/me/tokens/:id/activity", get(token::activity))
+        .route("/api/v1/me/tokens/:id/rotate", post(token::rotate))
+        .route(
+            "/api/v1/me/sessions",
+            get(user::session::list).delete(user::session::revoke_all),
+        )
+        .route("/api/v1/me/sessions/:id", delete(user::session::revoke))
         .route("/api/v1/tokens/current", delete(token::revoke_current))
         .route(
             "/api/v1/me/crate_owner_invitations",
@@ -122,15 +134,37 @@ pub fn build_axum_router(state: AppState) -> Router {
             "/api/v1/me/crate_owner_invitations/accept/:token",
             put(crate_owner_invitation::handle_invite_with_token),
         )
+        .route(
+            "/api/v1/me/emails",
+            get(user::me::list_emails).post(user::me::add_email),
+        )
+        .route(
+            "/api/v1/me/emails/:email_id",
+            delete(user::me::remove_email),
+        )
+        .route(
+            "/api/v1/me/emails/:email_id/primary",
+            put(user::me::set_primary_email),
+        )
+        .route(
+            "/api/v1/me/emails/:email_id/resend",
+            put(user::me::resend_email_confirmation),
+        )
         .route(
             "/api/v1/me/email_notifications",
             put(user::me::update_email_notifications),
         )
+        .route(
+            "/api/v1/me/notification_settings",
+            get(user::me::notification_settings).put(user::me::update_notification_settings),
+        )
         .route("/api/v1/summary", get(krate::metadata::summary))
+        .route("/api/v1/suggest", get(krate::suggest::suggest))
         .route(
             "/api/v1/confirm/:email_token",
             put(user::me::confirm_user_email),
         )
+        .route("/api/v1/unsubscribe/:token", post(user::me::unsubscribe))
         .route(
             "/api/v1/users/:user_id/resend",
             put(user::me::regenerate_token_and_send),
@@ -139,6 +173,7 @@ pub fn build_axum_router(state: AppState) -> Router {
             "/api/v1/site_metadata",
             get(site_metadata::show_deployed_sha),
         )
+        .route("/api/v1/audit/lockfile", post(audit::lockfile))
         // Session management
         .route("/api/private/session/begin", get(user::session::begin))
         .route(
@@ -24,6 +24,38 @@ pub mod sql_types {
     pub use diesel_full_text_search::Tsvector;
 }
 
+diesel::table! {
+    /// The most recent uses of each API token. Older entries are removed when new ones are added, so that the log stays bounded.
+    api_token_activity (id) {
+        /// Unique identifier of the log entry.
+        id -> Int8,
+        /// The API token that was used to authenticate the request.
+        api_token_id -> Int4,
+        /// When the request was authenticated. The entries are written in batches, so this can be slightly older than the row itself.
+        created_at -> Timestamp,
+        /// HTTP method and route of the request, e.g. `PUT /api/v1/crates/new`.
+        endpoint -> Varchar,
+        /// Name of the crate the request was authorized for, if any.
+        crate_name -> Nullable<Varchar>,
+        /// The IP address of the client, if known.
+        ip_address -> Nullable<Inet>,
+        /// The user agent of the client.
+        user_agent -> Varchar,
+    }
+}
+
+diesel::table! {
+    /// The IP addresses each API token was used from, so that the owner can be notified when a token is used from a new IP address.
+    api_token_ip_addresses (api_token_id, ip_address) {
+        /// The API token that was used.
+        api_token_id -> Int4,
+        /// The IP address the token was used from.
+        ip_address -> Inet,
+        /// When the token was used from this IP address for the first time.
+        first_seen_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `api_tokens` table.
     ///
@@ -81,6 +113,10 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         expired_at -> Nullable<Timestamp>,
+        /// NULL or an array of IP address ranges the token can be used from.
+        allowed_ip_ranges -> Nullable<Array<Cidr>>,
+        /// When the last reminder about the upcoming expiry of the token was sent, or NULL if no reminder was sent yet.
+        expiry_notification_at -> Nullable<Timestamp>,
     }
 }
 
@@ -207,6 +243,36 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Daily per-crate download counts, broken down by the client that requested the download.
+    crate_downloads_by_client (crate_id, date, client, cargo_version, ci) {
+        /// The `crate_id` column of the `crate_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_id -> Int4,
+        /// The `date` column of the `crate_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Date`.
+        ///
+        /// (Automatically generated by Diesel.)
+        date -> Date,
+        /// The kind of client that requested the download: `cargo`, `browser` or `other`.
+        client -> Varchar,
+        /// The `major.minor` version of cargo, or an empty string for non-cargo clients and unparsable versions.
+        cargo_version -> Varchar,
+        /// Whether the user agent identified the download as originating from a CI system.
+        ci -> Bool,
+        /// The `downloads` column of the `crate_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        downloads -> Int4,
+    }
+}
+
 diesel::table! {
     /// Representation of the `crate_owner_invitations` table.
     ///
@@ -307,6 +373,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Popularity scores of the crates, recomputed periodically by the `update_crate_scores` background job.
+    crate_scores (crate_id) {
+        /// The `crate_id` column of the `crate_scores` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_id -> Int4,
+        /// Combination of the recent downloads, reverse dependencies, owners and time since the last release of the crate. Higher is more popular.
+        score -> Float8,
+        /// When the score was last recomputed.
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Tsvector;
@@ -498,6 +580,36 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Emails that were queued for delivery by the `send_email` background job, kept for debugging delivery problems.
+    email_outbox (id) {
+        /// Unique identifier of the email, referenced by the background job.
+        id -> Int8,
+        /// When the email was queued.
+        created_at -> Timestamp,
+        /// The email address the email is sent to.
+        recipient -> Varchar,
+        /// The subject of the email.
+        subject -> Varchar,
+        /// The rendered plaintext part of the email, or an empty string once the email was sent or failed permanently.
+        body_text -> Text,
+        /// The rendered HTML part of the email, or an empty string once the email was sent or failed permanently.
+        body_html -> Text,
+        /// Whether the email is pending (0), was sent (1) or failed permanently (2).
+        status -> Int4,
+        /// How often the delivery of the email was attempted.
+        attempts -> Int4,
+        /// The error of the last failed delivery attempt, or NULL if no attempt failed yet.
+        last_error -> Nullable<Text>,
+        /// The `Message-ID` header of the sent email, which our support staff needs to find misdelivered emails.
+        message_id -> Nullable<Varchar>,
+        /// When the email was sent, or NULL if it was not sent yet.
+        sent_at -> Nullable<Timestamp>,
+        /// The signed token of the one-click unsubscribe link in the email, used for the `List-Unsubscribe` header, or NULL if the email has no unsubscribe link.
+        unsubscribe_token -> Nullable<Varchar>,
+    }
+}
+
 diesel::table! {
     /// Representation of the `emails` table.
     ///
@@ -539,6 +651,8 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         token_generated_at -> Nullable<Timestamp>,
+        /// Whether notification emails are sent to this address. Every user with email addresses has exactly one primary address.
+        is_primary -> Bool,
     }
 }
 
@@ -608,6 +722,46 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// The types of notification emails that users opted in to. Only applies to notification types that are disabled by default, like the weekly digest.
+    notification_opt_ins (user_id, notification_type) {
+        /// The user that opted in.
+        user_id -> Int4,
+        /// The type of notification, see the `NotificationType` enum for the possible values.
+        notification_type -> Int4,
+        /// When the user opted in.
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// The types of notification emails that users opted out of. Users receive all notifications that are not listed here.
+    notification_opt_outs (user_id, notification_type) {
+        /// The user that opted out.
+        user_id -> Int4,
+        /// The type of notification, see the `NotificationType` enum for the possible values.
+        notification_type -> Int4,
+        /// When the user opted out.
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// CDN access log files that have already been counted by the `process_cdn_logs` background job.
+    processed_log_files (path) {
+        /// Path of the log file in the storage bucket.
+        path -> Varchar,
+        /// The `processed_at` column of the `processed_log_files` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        processed_at -> Timestamp,
+        /// Number of downloads that were counted from this log file.
+        downloads -> Int4,
+    }
+}
+
 diesel::table! {
     /// Representation of the `publish_limit_buckets` table.
     ///
@@ -692,6 +846,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Crates that are related to each other, recomputed periodically by the `update_related_crates` background job.
+    related_crates (crate_id, kind, related_crate_id) {
+        /// The crate for which the related crate is recommended.
+        crate_id -> Int4,
+        /// The recommended crate.
+        related_crate_id -> Int4,
+        /// 0 for crates that are frequently depended on together with the crate, 1 for crates that are commonly used as alternatives to it.
+        kind -> Int2,
+        /// How strongly the crates are related. Only comparable between rows of the same kind.
+        score -> Float8,
+        /// When the relation was last recomputed.
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `recent_crate_downloads` view.
     ///
@@ -724,6 +894,26 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Login sessions of the users. The session cookie contains the plaintext token of the session, and deleting the row logs the session out.
+    sessions (id) {
+        /// Identifier of the session, used for listing and revoking sessions. Can not be used to authenticate.
+        id -> Int4,
+        /// The user that is logged in with this session.
+        user_id -> Int4,
+        /// SHA256 hash of the token in the session cookie.
+        token -> Bytea,
+        /// When the user logged in. Sessions expire 90 days after this.
+        created_at -> Timestamp,
+        /// When the session was last used to authenticate a request. Only updated every few minutes.
+        last_seen_at -> Timestamp,
+        /// The IP address of the client that logged in, if known.
+        ip_address -> Nullable<Inet>,
+        /// The user agent of the client that logged in.
+        user_agent -> Varchar,
+    }
+}
+
 diesel::table! {
     /// Representation of the `teams` table.
     ///
@@ -768,6 +958,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Crates with the highest week-over-week growth of their downloads, recomputed periodically by the `update_trending_crates` background job.
+    trending_crates (crate_id) {
+        /// The `crate_id` column of the `trending_crates` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_id -> Int4,
+        /// Growth of the downloads in the last week compared to the week before, relative to the downloads of the week before. Higher is trending more.
+        growth -> Float8,
+        /// When the growth was last recomputed.
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `users` table.
     ///
@@ -821,6 +1027,8 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         account_lock_until -> Nullable<Timestamp>,
+        /// When the user logged out of all sessions for the last time, or NULL if they never did. Legacy `user_id` cookies are no longer accepted once this is set.
+        sessions_revoked_at -> Nullable<Timestamp>,
     }
 }
 
@@ -862,6 +1070,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Monthly download counts of the `version_downloads` rows that were archived by the `archive_version_downloads` background job.
+    version_downloads_monthly (version_id, month) {
+        /// The `version_id` column of the `version_downloads_monthly` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// First day of the month.
+        month -> Date,
+        /// Sum of the archived daily downloads of the version in this month.
+        downloads -> Int4,
+    }
+}
+
 diesel::table! {
     /// Representation of the `version_owner_actions` table.
     ///
@@ -1005,6 +1229,14 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         semver_no_prerelease -> Nullable<SemverTriple>,
+        /// Whether the version has a build script. `NULL` for versions that were published before this was recorded.
+        has_build_script -> Nullable<Bool>,
+        /// Whether the library target of the version is a procedural macro. `NULL` for versions that were published before this was recorded.
+        has_proc_macro -> Nullable<Bool>,
+        /// The SPDX licenses mentioned in the `license` expression. `NULL` for versions without a `license`, or whose licenses were not parsed yet.
+        license_ids -> Nullable<Array<Text>>,
+        /// The sets of SPDX licenses that each satisfy the `license` expression on their own, as space separated license names. `NULL` for versions without a `license`, or whose licenses were not parsed yet.
+        license_alternatives -> Nullable<Array<Text>>,
     }
 }
 
@@ -1028,12 +1260,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// The weekly digests that were sent to users, so that every user receives at most one digest per week, even if the job sending them runs more than once.
+    weekly_digests (user_id, week) {
+        /// The user that the digest was sent to.
+        user_id -> Int4,
+        /// The Monday of the week the digest was sent in. The digest covers the seven days before.
+        week -> Date,
+        /// When the digest was queued for delivery.
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(api_token_activity -> api_tokens (api_token_id));
+diesel::joinable!(api_token_ip_addresses -> api_tokens (api_token_id));
 diesel::joinable!(api_tokens -> users (user_id));
 diesel::joinable!(badges -> crates (crate_id));
+diesel::joinable!(crate_downloads_by_client -> crates (crate_id));
 diesel::joinable!(crate_owner_invitations -> crates (crate_id));
 diesel::joinable!(crate_owners -> crates (crate_id));
 diesel::joinable!(crate_owners -> teams (owner_id));
 diesel::joinable!(crate_owners -> users (owner_id));
+diesel::joinable!(crate_scores -> crates (crate_id));
 diesel::joinable!(crates_categories -> categories (category_id));
 diesel::joinable!(crates_categories -> crates (crate_id));
 diesel::joinable!(crates_keywords -> crates (crate_id));
@@ -1043,42 +1291,61 @@ diesel::joinable!(dependencies -> versions (version_id));
 diesel::joinable!(emails -> users (user_id));
 diesel::joinable!(follows -> crates (crate_id));
 diesel::joinable!(follows -> users (user_id));
+diesel::joinable!(notification_opt_ins -> users (user_id));
+diesel::joinable!(notification_opt_outs -> users (user_id));
 diesel::joinable!(publish_limit_buckets -> users (user_id));
 diesel::joinable!(publish_rate_overrides -> users (user_id));
 diesel::joinable!(readme_renderings -> versions (version_id));
 diesel::joinable!(recent_crate_downloads -> crates (crate_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(trending_crates -> crates (crate_id));
 diesel::joinable!(version_downloads -> versions (version_id));
+diesel::joinable!(version_downloads_monthly -> versions (version_id));
 diesel::joinable!(version_owner_actions -> api_tokens (api_token_id));
 diesel::joinable!(version_owner_actions -> users (user_id));
 diesel::joinable!(version_owner_actions -> versions (version_id));
 diesel::joinable!(versions -> crates (crate_id));
 diesel::joinable!(versions -> users (published_by));
 diesel::joinable!(versions_published_by -> versions (version_id));
+diesel::joinable!(weekly_digests -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_token_activity,
+    api_token_ip_addresses,
     api_tokens,
     background_jobs,
     badges,
     categories,
+    crate_downloads_by_client,
     crate_owner_invitations,
     crate_owners,
+    crate_scores,
     crates,
     crates_categories,
     crates_keywords,
     dependencies,
+    email_outbox,
     emails,
     follows,
     keywords,
     metadata,
+    notification_opt_ins,
+    notification_opt_outs,
+    processed_log_files,
     publish_limit_buckets,
     publish_rate_overrides,
     readme_renderings,
     recent_crate_downloads,
+    related_crates,
     reserved_crate_names,
+    sessions,
     teams,
+    trending_crates,
     users,
     version_downloads,
+    version_downloads_monthly,
     version_owner_actions,
     versions,
     versions_published_by,
+    weekly_digests,
 );
@@ -13,6 +13,18 @@ sql_function!(fn floor(x: Double) -> Integer);
 sql_function!(fn greatest<T: SingleValue>(x: T, y: T) -> T);
 sql_function!(fn least<T: SingleValue>(x: T, y: T) -> T);
 sql_function!(fn split_part(string: Text, delimiter: Text, n: Integer) -> Text);
+sql_function!(fn similarity(x: Text, y: Text) -> Float);
+
+/// Escapes the wildcards of `LIKE` patterns, so that the string only matches itself.
+pub fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// Operators of the `pg_trgm` extension
+diesel::infix_operator!(TrigramSimilar, " % ");
+diesel::infix_operator!(TrigramDistance, " <-> ", diesel::sql_types::Float, backend: diesel::pg::Pg);
 
 macro_rules! pg_enum {
     (
@@ -53,3 +65,15 @@ macro_rules! pg_enum {
 }
 
 pub(crate) use pg_enum;
+
+#[cfg(test)]
+mod tests {
+    use super::escape_like;
+
+    #[test]
+    fn like_escaping() {
+        assert_eq!(escape_like("serde"), "serde");
+        assert_eq!(escape_like("a_b%c"), r"a\_b\%c");
+        assert_eq!(escape_like(r"a\b"), r"a\\b");
+    }
+}
@@ -18,6 +18,8 @@ use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
 const PREFIX_CRATES: &str = "crates";
+const PREFIX_CDN_LOGS: &str = "cdn-logs";
+const PREFIX_DOWNLOADS_ARCHIVE: &str = "archive/version-downloads";
 const PREFIX_READMES: &str = "readmes";
 const DEFAULT_REGION: &str = "us-west-1";
 const CONTENT_TYPE_CRATE: &str = "application/gzip";
@@ -256,6 +258,12 @@ impl Storage {
         self.crate_upload_store.put(&path, bytes).await
     }
 
+    #[instrument(skip(self))]
+    pub async fn download_crate_file(&self, name: &str, version: &str) -> Result<Bytes> {
+        let path = crate_file_path(name, version);
+        self.store.get(&path).await?.bytes().await
+    }
+
     #[instrument(skip(self))]
     pub async fn upload_readme(&self, name: &str, version: &str, bytes: Bytes) -> Result<()> {
         let path = readme_path(name, version);
@@ -296,6 +304,42 @@ impl Storage {
         Ok(())
     }
 
+    /// Returns the paths of the CDN access log files in the `directory` of the `cdn-logs/`
+    /// prefix, sorted by path. If `after` is given, only the paths after it are returned.
+    #[instrument(skip(self))]
+    pub async fn list_cdn_log_files(
+        &self,
+        directory: &str,
+        after: Option<&Path>,
+    ) -> Result<Vec<Path>> {
+        let prefix = format!("{PREFIX_CDN_LOGS}/{directory}").into();
+        let objects = match after {
+            Some(after) => self.store.list_with_offset(Some(&prefix), after).await?,
+            None => self.store.list(Some(&prefix)).await?,
+        };
+        let mut paths = objects
+            .map(|meta| meta.map(|m| m.location))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        paths.sort();
+        Ok(paths)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn read_cdn_log_file(&self, path: &Path) -> Result<Bytes> {
+        self.store.get(path).await?.bytes().await
+    }
+
+    /// Uploads a gzip-compressed CSV file of archived `version_downloads` rows and returns the
+    /// path it was stored at.
+    #[instrument(skip(self, bytes))]
+    pub async fn upload_downloads_archive(&self, name: &str, bytes: Bytes) -> Result<Path> {
+        let path = downloads_archive_path(name);
+        self.store.put(&path, bytes).await?;
+        Ok(path)
+    }
+
     /// This should only be used for assertions in the test suite!
     pub fn as_inner(&self) -> &dyn ObjectStore {
         &self.store
@@ -343,6 +387,10 @@ fn readme_path(name: &str, version: &str) -> Path {
     format!("{PREFIX_READMES}/{name}/{name}-{version}.html").into()
 }
 
+fn downloads_archive_path(name: &str) -> Path {
+    format!("{PREFIX_DOWNLOADS_ARCHIVE}/{name}.csv.gz").into()
+}
+
 fn apply_cdn_prefix(cdn_prefix: &Option<String>, path: &Path) -> String {
     match cdn_prefix {
         Some(cdn_prefix) if !cdn_prefix.starts_with("https://") => {
@@ -566,6 +614,68 @@ mod tests {
         assert!(stored_files(&s.store).await.is_empty());
     }
 
+    #[tokio::test]
+    async fn list_cdn_log_files() {
+        let storage = prepare().await;
+
+        let files_to_create = vec![
+            "cdn-logs/fastly/2023-10-02T00:00:00.000-def.log.gz",
+            "cdn-logs/fastly/2023-10-01T00:00:00.000-abc.log.gz",
+            "cdn-logs/cloudfront/E35K556QRQDZXW.2023-10-01-00.a1b2c3d4.gz",
+        ];
+        for path in files_to_create {
+            storage.store.put(&path.into(), Bytes::new()).await.unwrap();
+        }
+
+        let paths = storage.list_cdn_log_files("fastly", None).await.unwrap();
+        let paths = paths.iter().map(Path::to_string).collect::<Vec<_>>();
+        let expected_paths = vec![
+            "cdn-logs/fastly/2023-10-01T00:00:00.000-abc.log.gz",
+            "cdn-logs/fastly/2023-10-02T00:00:00.000-def.log.gz",
+        ];
+        assert_eq!(paths, expected_paths);
+
+        let after = paths[0].as_str().into();
+        let paths = storage
+            .list_cdn_log_files("fastly", Some(&after))
+            .await
+            .unwrap();
+        let paths = paths.iter().map(Path::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["cdn-logs/fastly/2023-10-02T00:00:00.000-def.log.gz"]
+        );
+
+        let paths = storage
+            .list_cdn_log_files("cloudfront", None)
+            .await
+            .unwrap();
+        let paths = paths.iter().map(Path::to_string).collect::<Vec<_>>();
+        let expected_paths = vec!["cdn-logs/cloudfront/E35K556QRQDZXW.2023-10-01-00.a1b2c3d4.gz"];
+        assert_eq!(paths, expected_paths);
+
+        let path = paths[0].as_str().into();
+        let bytes = storage.read_cdn_log_file(&path).await.unwrap();
+        assert_eq!(bytes, Bytes::new());
+    }
+
+    #[tokio::test]
+    async fn upload_downloads_archive() {
+        let s = Storage::from_config(&StorageConfig::in_memory());
+
+        let path = s
+            .upload_downloads_archive("2023-01/2023-10-06", Bytes::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            path.to_string(),
+            "archive/version-downloads/2023-01/2023-10-06.csv.gz"
+        );
+
+        let expected_files = vec!["archive/version-downloads/2023-01/2023-10-06.csv.gz"];
+        assert_eq!(stored_files(&s.store).await, expected_files);
+    }
+
     #[tokio::test]
     async fn upload_db_dump() {
         let s = Storage::from_config(&StorageConfig::in_memory());
@@ -1,8 +1,12 @@
 use crate::util::{MockRequestExt, RequestHelper, Response};
 use crate::TestApp;
 
-use crate::util::encode_session_header;
+use crate::util::{encode_legacy_session_header, encode_session_header};
+use crates_io::models::ApiToken;
+use crates_io::schema::sessions;
+use diesel::prelude::*;
 use http::{header, Method, StatusCode};
+use secrecy::ExposeSecret;
 
 static URL: &str = "/api/v1/me/updates";
 static MUST_LOGIN: &[u8] = br#"{"errors":[{"detail":"must be logged in to perform that action"}]}"#;
@@ -27,19 +31,110 @@ fn token_auth_cannot_find_token() {
     assert_eq!(response.into_json().to_string().as_bytes(), MUST_LOGIN);
 }
 
-// Ensure that an unexpected authentication error is available for logging.  The user would see
-// status 500 instead of 403 as in other authentication tests.  Due to foreign-key constraints in
-// the database, it is not possible to implement this same test for a token.
 #[test]
-fn cookie_auth_cannot_find_user() {
+fn cookie_auth_cannot_find_session() {
     let (app, anon) = TestApp::init().empty();
 
     let session_key = app.as_inner().session_key();
-    let cookie = encode_session_header(session_key, -1);
+    let cookie = encode_session_header(session_key, "cio1tkfake-session");
 
     let mut request = anon.request_builder(Method::GET, URL);
     request.header(header::COOKIE, &cookie);
 
-    let error = anon.run::<()>(request);
-    assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let response: Response<()> = anon.run(request);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.into_json().to_string().as_bytes(), MUST_LOGIN);
+}
+
+#[test]
+fn legacy_cookie_is_migrated_to_session() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+
+    let session_key = app.as_inner().session_key();
+    let cookie = encode_legacy_session_header(session_key, user_id);
+
+    let mut request = anon.request_builder(Method::GET, URL);
+    request.header(header::COOKIE, &cookie);
+
+    let response: Response<()> = anon.run(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+
+    let sessions: Vec<i32> = app.db(|conn| {
+        sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .select(sessions::id)
+            .load(conn)
+            .unwrap()
+    });
+    // One session for the mock cookie user, and one for the migrated cookie
+    assert_eq!(sessions.len(), 2);
+}
+
+#[test]
+fn legacy_cookie_is_rejected_after_logging_out_everywhere() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+
+    let response = user.delete::<()>("/api/v1/me/sessions");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let session_key = app.as_inner().session_key();
+    let cookie = encode_legacy_session_header(session_key, user_id);
+
+    let mut request = anon.request_builder(Method::GET, URL);
+    request.header(header::COOKIE, &cookie);
+
+    let response: Response<()> = anon.run(request);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.into_json().to_string().as_bytes(), MUST_LOGIN);
+
+    let sessions: i64 = app.db(|conn| {
+        sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .unwrap()
+    });
+    assert_eq!(sessions, 0);
+}
+
+#[test]
+fn token_auth_from_allowed_ip_range() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let url = "/api/v1/crates?following=1";
+
+    let new_token = |ranges: &[&str]| {
+        let ranges = ranges.iter().map(|range| range.parse().unwrap()).collect();
+        app.db(|conn| {
+            ApiToken::insert_with_scopes(
+                conn,
+                user.as_model().id,
+                "bar",
+                None,
+                None,
+                None,
+                Some(ranges),
+            )
+            .unwrap()
+        })
+    };
+
+    // Mock requests are sent from 127.0.0.1
+    let token = new_token(&["127.0.0.0/8", "2001:db8::/32"]);
+    let mut request = anon.request_builder(Method::GET, url);
+    request.header(header::AUTHORIZATION, token.plaintext.expose_secret());
+    let response: Response<()> = anon.run(request);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let token = new_token(&["192.0.2.0/24"]);
+    let mut request = anon.request_builder(Method::GET, url);
+    request.header(header::AUTHORIZATION, token.plaintext.expose_secret());
+    let response: Response<()> = anon.run(request);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "this API token can not be used from the IP address 127.0.0.1, which is not in the allowed IP ranges of the token" }] })
+    );
 }
@@ -20,6 +20,8 @@ pub struct VersionBuilder<'a> {
     checksum: String,
     links: Option<String>,
     rust_version: Option<String>,
+    has_build_script: bool,
+    has_proc_macro: bool,
 }
 
 impl<'a> VersionBuilder<'a> {
@@ -45,6 +47,8 @@ impl<'a> VersionBuilder<'a> {
             checksum: String::new(),
             links: None,
             rust_version: None,
+            has_build_script: false,
+            has_proc_macro: false,
         }
     }
 
@@ -89,6 +93,18 @@ impl<'a> VersionBuilder<'a> {
         self
     }
 
+    /// Sets whether the version has a build script.
+    pub fn build_script(mut self, has_build_script: bool) -> Self {
+        self.has_build_script = has_build_script;
+        self
+    }
+
+    /// Sets whether the version is a procedural macro.
+    pub fn proc_macro(mut self, has_proc_macro: bool) -> Self {
+        self.has_proc_macro = has_proc_macro;
+        self
+    }
+
     pub fn build(
         self,
         crate_id: i32,
@@ -109,6 +125,8 @@ impl<'a> VersionBuilder<'a> {
             self.checksum,
             self.links,
             self.rust_version,
+            self.has_build_script,
+            self.has_proc_macro,
         )?
         .save(connection, "someone@example.com")?;
 
@@ -3,8 +3,9 @@ use crate::{RequestHelper, TestApp};
 use crates_io::controllers::github::secret_scanning::{
     GitHubSecretAlertFeedback, GitHubSecretAlertFeedbackLabel,
 };
+use crates_io::models::{ApiToken, NotificationType};
+use crates_io::schema::api_tokens;
 use crates_io::util::token::HashedToken;
-use crates_io::{models::ApiToken, schema::api_tokens};
 use diesel::prelude::*;
 use http::StatusCode;
 
@@ -19,7 +20,7 @@ static GITHUB_PUBLIC_KEY_SIGNATURE: &str = "MEUCIFLZzeK++IhS+y276SRk2Pe5LfDrfvTX
 
 #[test]
 fn github_secret_alert_revokes_token() {
-    let (app, anon, user, token) = TestApp::init().with_token();
+    let (app, anon, user, token) = TestApp::full().with_token();
 
     // Ensure no emails were sent up to this point
     assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
@@ -75,9 +76,46 @@ fn github_secret_alert_revokes_token() {
     });
 
     // Ensure exactly one email was sent
+    app.run_pending_background_jobs();
     assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
 }
 
+#[test]
+fn github_secret_alert_revokes_token_without_email_after_opt_out() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        ApiToken::insert(conn, user.as_model().id, "bar").unwrap();
+        diesel::update(api_tokens::table)
+            .set(api_tokens::token.eq(HashedToken::hash("some_token")))
+            .execute(conn)
+            .unwrap();
+        NotificationType::TokenExposed
+            .set_enabled_for(conn, user.as_model().id, false)
+            .unwrap();
+    });
+
+    let mut request = anon.post_request(URL);
+    *request.body_mut() = GITHUB_ALERT.into();
+    request.header("GITHUB-PUBLIC-KEY-IDENTIFIER", GITHUB_PUBLIC_KEY_IDENTIFIER);
+    request.header("GITHUB-PUBLIC-KEY-SIGNATURE", GITHUB_PUBLIC_KEY_SIGNATURE);
+    let feedback = anon.run::<Vec<GitHubSecretAlertFeedback>>(request).good();
+    assert_eq!(
+        feedback[0].label,
+        GitHubSecretAlertFeedbackLabel::TruePositive
+    );
+
+    // The token is revoked, but the user opted out of the email
+    let revoked: bool = app.db(|conn| {
+        api_tokens::table
+            .select(api_tokens::revoked)
+            .first(conn)
+            .unwrap()
+    });
+    assert!(revoked);
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+}
+
 #[test]
 fn github_secret_alert_for_revoked_token() {
     let (app, anon, user, token) = TestApp::init().with_token();
@@ -6,12 +6,11 @@ use crate::{
     TestApp,
 };
 use crates_io::{
-    models::Crate,
+    models::{Crate, NotificationType},
     views::{
         EncodableCrateOwnerInvitation, EncodableCrateOwnerInvitationV1, EncodableOwner,
         EncodablePublicUser, InvitationResponse,
     },
-    Emails,
 };
 
 use chrono::{Duration, Utc};
@@ -418,7 +417,7 @@ fn owner_change_without_auth() {
 
 #[test]
 fn invite_already_invited_user() {
-    let (app, _, _, owner) = TestApp::init().with_token();
+    let (app, _, _, owner) = TestApp::full().with_token();
     app.db_new_user("invited_user");
     app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn));
 
@@ -437,6 +436,7 @@ fn invite_already_invited_user() {
     );
 
     // Check one email was sent, this will be the ownership invite email
+    app.run_pending_background_jobs();
     assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
 
     // Then invite the user a second time, the message should point out the user is already invited
@@ -451,12 +451,13 @@ fn invite_already_invited_user() {
     );
 
     // Check that no new email is sent after the second invitation
+    app.run_pending_background_jobs();
     assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
 }
 
 #[test]
 fn invite_with_existing_expired_invite() {
-    let (app, _, _, owner) = TestApp::init().with_token();
+    let (app, _, _, owner) = TestApp::full().with_token();
     app.db_new_user("invited_user");
     let krate =
         app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn));
@@ -476,6 +477,7 @@ fn invite_with_existing_expired_invite() {
     );
 
     // Check one email was sent, this will be the ownership invite email
+    app.run_pending_background_jobs();
     assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
 
     // Simulate the previous invite expiring
@@ -493,9 +495,32 @@ fn invite_with_existing_expired_invite() {
     );
 
     // Check that the email for the second invite was sent
+    app.run_pending_background_jobs();
     assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 2);
 }
 
+#[test]
+fn invite_user_without_invitation_emails() {
+    let (app, _, _, owner) = TestApp::full().with_token();
+    let invited_user = app.db_new_user("invited_user");
+    app.db(|conn| {
+        CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn);
+        NotificationType::OwnerInvitation
+            .set_enabled_for(conn, invited_user.as_model().id, false)
+            .unwrap();
+    });
+
+    let response = owner.add_named_owner("crate_name", "invited_user");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The invitation is created, but no email is sent
+    app.run_pending_background_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+
+    let invitations = invited_user.list_invitations();
+    assert_eq!(invitations.crate_owner_invitations.len(), 1);
+}
+
 /// Testing the crate ownership between two crates and one team.
 /// Given two crates, one crate owned by both a team and a user,
 /// one only owned by a user, check that the CrateList returned
@@ -592,6 +617,30 @@ fn api_token_cannot_list_invitations_v1() {
         .assert_forbidden();
 }
 
+#[test]
+fn scoped_api_token_can_list_invitations_v1() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let token = user.db_new_scoped_token(
+        "invitations",
+        None,
+        Some(vec![EndpointScope::ManageInvitations]),
+        None,
+    );
+    let json: InvitationListResponse = token.get("/api/v1/me/crate_owner_invitations").good();
+    assert_eq!(json.crate_owner_invitations.len(), 0);
+
+    let token = user.db_new_scoped_token(
+        "publish",
+        None,
+        Some(vec![EndpointScope::PublishUpdate]),
+        None,
+    );
+    token
+        .get("/api/v1/me/crate_owner_invitations")
+        .assert_forbidden();
+}
+
 #[test]
 fn invitations_list_v1() {
     let (app, _, owner, token) = TestApp::init().with_token();
@@ -715,7 +764,7 @@ fn test_decline_invitation() {
 
 #[test]
 fn test_accept_invitation_by_mail() {
-    let (app, anon, owner, owner_token) = TestApp::init().with_token();
+    let (app, anon, owner, owner_token) = TestApp::full().with_token();
     let owner = owner.as_model();
     let invited_user = app.db_new_user("user_bar");
     let _krate = app.db(|conn| CrateBuilder::new("accept_invitation", owner.id).expect_build(conn));
@@ -724,7 +773,7 @@ fn test_accept_invitation_by_mail() {
     owner_token.add_user_owner("accept_invitation", "user_bar");
 
     // Retrieve the ownership invitation
-    let invite_token = extract_token_from_invite_email(&app.as_inner().emails);
+    let invite_token = extract_token_from_invite_email(&app);
 
     // Accept the invitation anonymously with a token
     anon.accept_ownership_invitation_by_token(&invite_token);
@@ -811,7 +860,7 @@ fn test_decline_expired_invitation() {
 
 #[test]
 fn test_accept_expired_invitation_by_mail() {
-    let (app, anon, owner, owner_token) = TestApp::init().with_token();
+    let (app, anon, owner, owner_token) = TestApp::full().with_token();
     let owner = owner.as_model();
     let _invited_user = app.db_new_user("demo_user");
     let krate = app.db(|conn| CrateBuilder::new("demo_crate", owner.id).expect_build(conn));
@@ -823,7 +872,7 @@ fn test_accept_expired_invitation_by_mail() {
     expire_invitation(&app, krate.id);
 
     // Retrieve the ownership invitation
-    let invite_token = extract_token_from_invite_email(&app.as_inner().emails);
+    let invite_token = extract_token_from_invite_email(&app);
 
     // Try to accept the invitation, and ensure it fails.
     let resp = anon.try_accept_ownership_invitation_by_token::<()>(&invite_token);
@@ -902,8 +951,12 @@ fn highest_gh_id_is_most_recent_account_we_know_of() {
     assert_eq!(json.crate_owner_invitations.len(), 1);
 }
 
-fn extract_token_from_invite_email(emails: &Emails) -> String {
-    let message = emails
+fn extract_token_from_invite_email(app: &TestApp) -> String {
+    app.run_pending_background_jobs();
+
+    let message = app
+        .as_inner()
+        .emails
         .mails_in_memory()
         .unwrap()
         .into_iter()
@@ -1,8 +1,10 @@
 use crate::builders::CrateBuilder;
+use crate::util::{encode_legacy_session_header, MockRequestExt};
 use crate::{RequestHelper, TestApp};
 
+use crates_io::schema::sessions;
 use diesel::prelude::*;
-use http::StatusCode;
+use http::{header, Method, StatusCode};
 
 #[test]
 fn can_hit_read_only_endpoints_in_read_only_mode() {
@@ -58,6 +60,40 @@ fn can_download_crate_in_read_only_mode() {
     })
 }
 
+#[test]
+fn legacy_cookie_is_not_migrated_in_read_only_mode() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+    app.db(set_read_only).unwrap();
+
+    let session_key = app.as_inner().session_key();
+    let cookie = encode_legacy_session_header(session_key, user_id);
+
+    let mut request = anon.request_builder(Method::GET, "/api/v1/me/updates");
+    request.header(header::COOKIE, &cookie);
+
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(header::SET_COOKIE));
+
+    // Restore the transaction so `TestApp::drop` can still access the transaction
+    app.db(|conn| {
+        diesel::sql_query("ROLLBACK TO test_post_readonly")
+            .execute(conn)
+            .unwrap();
+    });
+
+    let sessions: i64 = app.db(|conn| {
+        sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .unwrap()
+    });
+    // Only the session of the mock cookie user
+    assert_eq!(sessions, 1);
+}
+
 fn set_read_only(conn: &mut PgConnection) -> QueryResult<()> {
     diesel::sql_query("SET TRANSACTION READ ONLY").execute(conn)?;
     diesel::sql_query("SAVEPOINT test_post_readonly").execute(conn)?;
@@ -0,0 +1,153 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{RequestHelper, TestApp};
+use crates_io::rate_limiter::LimitedAction;
+use http::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const URL: &str = "/api/v1/audit/lockfile";
+
+const LOCKFILE: &str = r#"
+# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "foo-audit",
+ "bar_audit",
+ "gone",
+]
+
+[[package]]
+name = "foo-audit"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "bar_audit"
+version = "0.1.0"
+source = "sparse+https://index.crates.io/"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "bar_audit"
+version = "0.3.0"
+source = "sparse+https://index.crates.io/"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "gone"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+[[package]]
+name = "private"
+version = "1.0.0"
+source = "registry+https://example.com/index"
+"#;
+
+#[test]
+fn audit_lockfile() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+    let user = cookie.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_audit", user.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("MIT")))
+            .version(VersionBuilder::new("1.1.0").license(Some("MIT")))
+            .version(VersionBuilder::new("1.2.0").yanked(true))
+            .version(VersionBuilder::new("2.0.0").license(Some("Apache-2.0")))
+            .expect_build(conn);
+        CrateBuilder::new("bar_audit", user.id)
+            .version(VersionBuilder::new("0.1.0").yanked(true))
+            .version(VersionBuilder::new("0.2.0"))
+            .expect_build(conn);
+    });
+
+    // The audit requires authentication for the rate limit
+    let response = anon.post::<()>(URL, LOCKFILE);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let json: Value = cookie.post(URL, LOCKFILE).good();
+    assert_eq!(
+        json,
+        json!({ "packages": [
+            {
+                "name": "foo-audit",
+                "version": "1.0.0",
+                "status": "available",
+                "license": "MIT",
+                "newer_compatible_version": "1.1.0",
+                "newer_incompatible_version": "2.0.0",
+            },
+            {
+                "name": "bar_audit",
+                "version": "0.1.0",
+                "status": "yanked",
+                "license": null,
+                "newer_compatible_version": null,
+                "newer_incompatible_version": "0.2.0",
+            },
+            {
+                "name": "bar_audit",
+                "version": "0.3.0",
+                "status": "missing_version",
+                "license": null,
+                "newer_compatible_version": null,
+                "newer_incompatible_version": null,
+            },
+            {
+                "name": "gone",
+                "version": "1.0.0",
+                "status": "missing_crate",
+                "license": null,
+                "newer_compatible_version": null,
+                "newer_incompatible_version": null,
+            },
+        ] })
+    );
+}
+
+#[test]
+fn invalid_lockfile() {
+    let (_, anon) = TestApp::init().empty();
+
+    let response = anon.post::<()>(URL, "[[package]]\nname = ");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = anon.post::<()>(URL, "[[package]]\nname = \"foo\"\n");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid lockfile: missing field `version`" }] })
+    );
+}
+
+#[test]
+fn rate_limit_counts_audited_packages() {
+    let (_, _, user) = TestApp::init()
+        .with_rate_limit(LimitedAction::AuditLockfile, Duration::from_secs(60), 3)
+        .with_user();
+
+    let lockfile = |size: usize| {
+        (0..size)
+            .map(|i| {
+                format!(
+                    "[[package]]\nname = \"crate_{i}\"\nversion = \"1.0.0\"\nsource = \"sparse+https://index.crates.io/\"\n"
+                )
+            })
+            .collect::<String>()
+    };
+
+    // 101 packages take two tokens, and a lockfile without packages still takes the last one
+    user.post::<Value>(URL, lockfile(101)).good();
+    user.post::<Value>(URL, lockfile(0)).good();
+
+    let response = user.post::<()>(URL, lockfile(1));
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
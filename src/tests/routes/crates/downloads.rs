@@ -1,8 +1,12 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
-use crate::util::{MockAnonymousUser, RequestHelper, TestApp};
-use chrono::{Duration, Utc};
+use crate::util::{MockAnonymousUser, MockRequestExt, RequestHelper, TestApp};
+use chrono::{Duration, NaiveDate, Utc};
+use crates_io::models::{CrateVersions, Version};
+use crates_io::schema::version_downloads;
 use crates_io::views::EncodableVersionDownload;
-use http::StatusCode;
+use diesel::prelude::*;
+use http::{header, StatusCode};
+use serde_json::Value;
 
 #[derive(Deserialize)]
 struct Downloads {
@@ -81,3 +85,161 @@ fn download() {
     assert_dl_count(&anon, "FOO_DOWNLOAD/1.0.0", Some(&query), 2);
     assert_dl_count(&anon, "FOO_DOWNLOAD", Some(&query), 2);
 }
+
+#[test]
+fn download_breakdown_by_cargo_version() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_breakdown", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+    });
+
+    let download = |name_and_version: &str, user_agent: &str| {
+        let url = format!("/api/v1/crates/{name_and_version}/download");
+        let mut request = anon.get_request(&url);
+        request.header(header::USER_AGENT, user_agent);
+        let response = anon.run::<()>(request);
+        assert_eq!(response.status(), StatusCode::FOUND);
+    };
+
+    download("foo_breakdown/1.0.0", "cargo 1.72.1 (103a7ff2e 2023-08-15)");
+    download("foo_breakdown/1.1.0", "cargo 1.72.0 (103a7ff2e 2023-08-15)");
+    download(
+        "foo_breakdown/1.1.0",
+        "cargo 1.60.0 (d1fd9fe2c 2022-03-01) github-actions",
+    );
+    download("foo_breakdown/1.0.0", "curl/8.1.2");
+    persist_downloads_count(&app);
+
+    let url = "/api/v1/crates/foo_breakdown/downloads";
+    let json = anon
+        .get_with_query::<Value>(url, "breakdown=cargo_version")
+        .good();
+
+    let today = Utc::now().date_naive().to_string();
+    assert_eq!(
+        json["meta"]["downloads_by_client"],
+        json!([
+            { "date": today, "client": "cargo", "cargo_version": "1.60", "ci": true, "downloads": 1 },
+            { "date": today, "client": "cargo", "cargo_version": "1.72", "ci": false, "downloads": 2 },
+            { "date": today, "client": "other", "cargo_version": null, "ci": false, "downloads": 1 },
+        ])
+    );
+
+    // The breakdown is only included when requested
+    let json = anon.get::<Value>(url).good();
+    assert!(json["meta"].get("downloads_by_client").is_none());
+
+    let response = anon.get_with_query::<()>(url, "breakdown=foo");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn download_history() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_history", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .version(VersionBuilder::new("2.0.0"))
+            .expect_build(conn);
+
+        let versions = krate.all_versions().load::<Version>(conn).unwrap();
+        let version_id = |num: &str| versions.iter().find(|v| v.num == num).unwrap().id;
+        let date = |day| NaiveDate::from_ymd_opt(2023, 10, day).unwrap();
+
+        let rows = [
+            ("1.0.0", date(2), 1),
+            ("1.1.0", date(2), 2),
+            ("1.1.0", date(4), 4),
+            ("2.0.0", date(10), 8),
+            ("2.0.0", date(20), 16),
+        ];
+        for (num, date, downloads) in rows {
+            diesel::insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version_id(num)),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    });
+
+    let url = "/api/v1/crates/foo_history/downloads";
+
+    let json = anon
+        .get_with_query::<Value>(url, "start_date=2023-10-01&end_date=2023-10-15")
+        .good();
+    assert_eq!(
+        json,
+        json!({
+            "downloads": [
+                { "date": "2023-10-02", "series": "1.0.0", "downloads": 1 },
+                { "date": "2023-10-02", "series": "1.1.0", "downloads": 2 },
+                { "date": "2023-10-04", "series": "1.1.0", "downloads": 4 },
+                { "date": "2023-10-10", "series": "2.0.0", "downloads": 8 },
+            ],
+            "meta": {
+                "start_date": "2023-10-01",
+                "end_date": "2023-10-15",
+                "interval": "day",
+                "group_by": "version",
+            },
+        })
+    );
+
+    let query = "start_date=2023-10-01&end_date=2023-10-31&interval=week&group_by=major";
+    let json = anon.get_with_query::<Value>(url, query).good();
+    assert_eq!(
+        json["downloads"],
+        json!([
+            { "date": "2023-10-02", "series": "1", "downloads": 7 },
+            { "date": "2023-10-09", "series": "2", "downloads": 8 },
+            { "date": "2023-10-16", "series": "2", "downloads": 16 },
+        ])
+    );
+
+    let query = "start_date=2023-10-01&end_date=2023-10-31&interval=month&group_by=minor";
+    let mut request = anon.get_request(&format!("{url}?{query}"));
+    request.header(header::ACCEPT, "text/csv");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.into_text(),
+        "date,series,downloads\n2023-10-01,1.0,1\n2023-10-01,1.1,6\n2023-10-01,2.0,24\n"
+    );
+
+    let response = anon.get_with_query::<()>(url, "interval=year");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = anon.get_with_query::<()>(url, "group_by=patch");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = anon.get_with_query::<()>(url, "start_date=yesterday");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let query = "start_date=2023-10-15&end_date=2023-10-01";
+    let response = anon.get_with_query::<()>(url, query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The date range is limited depending on the interval.
+    let query = "start_date=2022-10-01&end_date=2023-10-01&interval=day";
+    anon.get_with_query::<Value>(url, query).good();
+    let query = "start_date=2022-09-30&end_date=2023-10-01&interval=day";
+    let response = anon.get_with_query::<()>(url, query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let query = "start_date=0001-01-01&end_date=2023-10-01&interval=month";
+    let response = anon.get_with_query::<()>(url, query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let query = "start_date=2014-01-01&end_date=2023-10-01&interval=month";
+    anon.get_with_query::<Value>(url, query).good();
+}
@@ -1,5 +1,7 @@
 use crate::builders::CrateBuilder;
 use crate::util::{RequestHelper, TestApp};
+use crates_io::models::token::EndpointScope;
+use serde_json::Value;
 
 #[test]
 fn diesel_not_found_results_in_404() {
@@ -25,3 +27,31 @@ fn disallow_api_token_auth_for_get_crate_following_status() {
         .get(&format!("/api/v1/crates/{a_crate}/following"))
         .assert_forbidden();
 }
+
+#[test]
+fn get_crate_following_status_with_scoped_token() {
+    let (app, _, user) = TestApp::init().with_user();
+    let a_crate = "a_crate";
+
+    app.db(|conn| {
+        CrateBuilder::new(a_crate, user.as_model().id).expect_build(conn);
+    });
+
+    let url = format!("/api/v1/crates/{a_crate}/following");
+    let token = user.db_new_scoped_token(
+        "follows",
+        None,
+        Some(vec![EndpointScope::ManageFollows]),
+        None,
+    );
+    let json: Value = token.get(&url).good();
+    assert_eq!(json, json!({ "following": false }));
+
+    let token = user.db_new_scoped_token(
+        "publish",
+        None,
+        Some(vec![EndpointScope::PublishUpdate]),
+        None,
+    );
+    token.get(&url).assert_forbidden();
+}
@@ -1,10 +1,12 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
 use crate::util::{RequestHelper, TestApp};
-use crate::{new_category, new_user};
+use crate::{add_team_to_crate, new_category, new_team, new_user};
+use chrono::{NaiveDate, NaiveTime};
 use crates_io::models::Category;
 use crates_io::schema::crates;
 use diesel::{dsl::*, prelude::*, update};
 use http::StatusCode;
+use serde_json::Value;
 
 #[test]
 fn index() {
@@ -847,3 +849,434 @@ fn crates_by_user_id_not_including_deleted_owners() {
     let response = anon.search_by_user_id(user.id);
     assert_eq!(response.crates.len(), 0);
 }
+
+#[test]
+fn version_filters_and_facets() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        new_category("Category 1", "cat1", "Category 1 crates")
+            .create_or_update(conn)
+            .unwrap();
+
+        CrateBuilder::new("foo_dual", user.id)
+            .category("cat1")
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MIT OR Apache-2.0"))
+                    .rust_version("1.60")
+                    .build_script(true),
+            )
+            .expect_build(conn);
+
+        // Only the newest version of a crate is considered
+        CrateBuilder::new("foo_gpl", user.id)
+            .category("cat1")
+            .version(VersionBuilder::new("1.0.0").license(Some("MIT")))
+            .version(
+                VersionBuilder::new("2.0.0")
+                    .license(Some("GPL-3.0"))
+                    .rust_version("1.70.1")
+                    .proc_macro(true),
+            )
+            .expect_build(conn);
+
+        // ... unless it is yanked
+        CrateBuilder::new("foo_yanked", user.id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MIT AND Apache-2.0"))
+                    .rust_version("1.70"),
+            )
+            .version(
+                VersionBuilder::new("2.0.0")
+                    .license(Some("GPL-3.0"))
+                    .yanked(true),
+            )
+            .updated_at(
+                NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_time(NaiveTime::MIN),
+            )
+            .expect_build(conn);
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|krate| krate.name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names("license=MIT"), vec!["foo_dual"]);
+    assert_eq!(
+        names("license=MIT%20OR%20Apache-2.0"),
+        vec!["foo_dual", "foo_yanked"]
+    );
+    assert_eq!(names("license=GPL-3.0"), vec!["foo_gpl"]);
+
+    assert_eq!(names("max_rust_version=1.65"), vec!["foo_dual"]);
+    assert_eq!(
+        names("max_rust_version=1.70"),
+        vec!["foo_dual", "foo_yanked"]
+    );
+    assert_eq!(
+        names("max_rust_version=1.70.1"),
+        vec!["foo_dual", "foo_gpl", "foo_yanked"]
+    );
+
+    assert_eq!(
+        names("updated_since=2021-01-01"),
+        vec!["foo_dual", "foo_gpl"]
+    );
+
+    assert_eq!(names("build_script=yes"), vec!["foo_dual"]);
+    assert_eq!(names("build_script=no"), vec!["foo_gpl", "foo_yanked"]);
+    assert_eq!(names("proc_macro=yes"), vec!["foo_gpl"]);
+    assert_eq!(names("proc_macro=no&license=MIT"), vec!["foo_dual"]);
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates", "include_facets=yes&q=foo")
+        .good();
+    assert_eq!(json["meta"]["total"], 3);
+    assert_eq!(
+        json["facets"],
+        json!({
+            "licenses": [
+                { "license": "Apache-2.0", "count": 2 },
+                { "license": "MIT", "count": 2 },
+                { "license": "GPL-3.0", "count": 1 },
+            ],
+            "categories": [
+                { "category": "cat1", "count": 2 },
+            ],
+            "rust_versions": [
+                { "rust_version": "1.60", "count": 1 },
+                { "rust_version": "1.70", "count": 2 },
+            ],
+        })
+    );
+
+    // The facets are only included on request
+    let json: Value = anon.get("/api/v1/crates").good();
+    assert!(json.get("facets").is_none());
+
+    // The facets of the whole registry are not available
+    let response = anon.get_with_query::<()>("/api/v1/crates", "include_facets=yes");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "include_facets requires a search query or at least one filter" }] })
+    );
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates", "include_facets=yes&license=GPL-3.0")
+        .good();
+    assert_eq!(json["meta"]["total"], 1);
+    assert_eq!(
+        json["facets"]["licenses"],
+        json!([{ "license": "GPL-3.0", "count": 1 }])
+    );
+
+    for query in [
+        "license=apache%202.0",
+        "max_rust_version=1.x",
+        "updated_since=yesterday",
+        "build_script=maybe",
+    ] {
+        let response = anon.get_with_query::<()>("/api/v1/crates", query);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // Newly published license expressions are found right away
+    app.db(|conn| {
+        CrateBuilder::new("foo_isc", user.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("(MIT OR Apache-2.0) AND ISC")))
+            .expect_build(conn);
+    });
+    assert_eq!(names("license=ISC"), Vec::<String>::new());
+    assert_eq!(
+        names("license=MIT%20AND%20ISC"),
+        vec!["foo_dual", "foo_isc"]
+    );
+}
+
+#[test]
+fn version_filters_use_highest_version() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        // The backport to the old major version was published last
+        CrateBuilder::new("foo_backport", user.id)
+            .version(
+                VersionBuilder::new("2.0.0")
+                    .license(Some("MIT"))
+                    .rust_version("1.70"),
+            )
+            .version(
+                VersionBuilder::new("1.0.1")
+                    .license(Some("GPL-3.0"))
+                    .rust_version("1.56"),
+            )
+            .expect_build(conn);
+
+        // Prereleases are only considered if there is no stable version
+        CrateBuilder::new("foo_prerelease", user.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("MIT")))
+            .version(VersionBuilder::new("2.0.0-beta.1").license(Some("GPL-3.0")))
+            .expect_build(conn);
+
+        // Components that don't fit into an `int` must not break the query
+        CrateBuilder::new("foo_oversized", user.id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MIT"))
+                    .rust_version("1.99999999999"),
+            )
+            .expect_build(conn);
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|krate| krate.name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        names("license=MIT"),
+        vec!["foo_backport", "foo_oversized", "foo_prerelease"]
+    );
+    assert_eq!(names("license=GPL-3.0"), Vec::<String>::new());
+    assert_eq!(names("max_rust_version=1.60"), Vec::<String>::new());
+    assert_eq!(names("max_rust_version=1.70"), vec!["foo_backport"]);
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates", "include_facets=yes&q=foo")
+        .good();
+    assert_eq!(
+        json["facets"]["licenses"],
+        json!([{ "license": "MIT", "count": 3 }])
+    );
+    assert_eq!(
+        json["facets"]["rust_versions"],
+        json!([
+            { "rust_version": null, "count": 2 },
+            { "rust_version": "1.70", "count": 1 },
+        ])
+    );
+}
+
+#[test]
+fn structured_query() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        new_category(
+            "Network programming",
+            "network-programming",
+            "Network crates",
+        )
+        .create_or_update(conn)
+        .unwrap();
+
+        let other = new_user("other")
+            .create_or_update(None, &app.as_inner().emails, conn)
+            .unwrap();
+        let team = new_team("github:rust-lang:core")
+            .create_or_update(conn)
+            .unwrap();
+
+        CrateBuilder::new("http_async", user.id)
+            .description("An async web framework")
+            .keyword("async")
+            .keyword("web")
+            .category("network-programming")
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MIT"))
+                    .rust_version("1.56"),
+            )
+            .expect_build(conn);
+
+        CrateBuilder::new("http_blocking", other.id)
+            .description("A blocking framework for the web")
+            .keyword("web")
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("Apache-2.0"))
+                    .rust_version("1.60"),
+            )
+            .expect_build(conn);
+
+        let krate = CrateBuilder::new("http_team", other.id)
+            .description("An async blocking web framework")
+            .keyword("async")
+            .expect_build(conn);
+        add_team_to_crate(&team, &krate, &other, conn).unwrap();
+    });
+
+    let names = |q: &str| {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", q)
+            .finish();
+
+        anon.search(&query)
+            .crates
+            .into_iter()
+            .map(|krate| krate.name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names("keyword:async"), vec!["http_async", "http_team"]);
+    assert_eq!(names("keyword:async keyword:web"), vec!["http_async"]);
+    assert_eq!(names("category:network-programming"), vec!["http_async"]);
+    assert_eq!(names("license:MIT"), vec!["http_async"]);
+    assert_eq!(names("owner:foo"), vec!["http_async"]);
+    assert_eq!(names("owner:rust-lang"), vec!["http_team"]);
+    assert_eq!(names("owner:rust_lang"), Vec::<String>::new());
+    assert_eq!(names("msrv:<1.60"), vec!["http_async"]);
+    assert_eq!(names("msrv:1.60"), vec!["http_async", "http_blocking"]);
+    assert_eq!(names(r#""web framework""#), vec!["http_async", "http_team"]);
+    assert_eq!(names("framework !async"), vec!["http_blocking"]);
+    assert_eq!(
+        names(r#"!"async blocking""#),
+        vec!["http_async", "http_blocking"]
+    );
+
+    // A leading `-` does not exclude the word, like before the query syntax was added
+    assert_eq!(names("framework -async"), vec!["http_async", "http_team"]);
+
+    // The free text is combined with the qualifiers
+    assert_eq!(names("http_team owner:other"), vec!["http_team"]);
+    assert_eq!(names("blocking owner:foo"), Vec::<String>::new());
+
+    for (q, error) in [
+        (r#"keyword:"async"#, "unterminated quote in search query"),
+        ("keyword:", "missing value for `keyword:` in search query"),
+        (
+            "msrv:1.60 msrv:1.70",
+            "`msrv:` can only be used once in a search query",
+        ),
+        (
+            "!owner:foo",
+            "`owner:` can not be excluded in a search query",
+        ),
+        (
+            "license:apache",
+            "invalid `license:` expression in search query: apache",
+        ),
+    ] {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", q)
+            .finish();
+        let response = anon.get_with_query::<()>("/api/v1/crates", &query);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.into_json(),
+            json!({ "errors": [{ "detail": error }] })
+        );
+    }
+
+    let response = anon.get_with_query::<()>("/api/v1/crates", "q=license:MIT&license=MIT");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn typo_tolerant_search() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("reqwest", user.id)
+            .description("higher level HTTP client library")
+            .expect_build(conn);
+        CrateBuilder::new("request", user.id).expect_build(conn);
+        CrateBuilder::new("serde", user.id)
+            .description("A serialization framework")
+            .expect_build(conn);
+        CrateBuilder::new("serde_json", user.id).expect_build(conn);
+    });
+
+    let search = |q: &str| -> Value {
+        anon.get_with_query("/api/v1/crates", &format!("q={q}"))
+            .good()
+    };
+    let names = |json: &Value| {
+        json["crates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|krate| krate["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Typos are matched by trigram similarity
+    let json = search("reqwset");
+    assert_eq!(names(&json), vec!["reqwest"]);
+    assert_eq!(json["suggestions"], json!(["reqwest"]));
+
+    // Prefixes still match, and the suggestions don't include exact matches
+    let json = search("serd");
+    assert_eq!(names(&json), vec!["serde", "serde_json"]);
+    assert_eq!(json["suggestions"], json!(["serde", "serde_json"]));
+
+    let json = search("serde");
+    assert_eq!(names(&json), vec!["serde", "serde_json"]);
+    assert_eq!(json["suggestions"], json!(["serde_json"]));
+
+    // No suggestions are included if the full text search finds enough crates
+    app.db(|conn| {
+        for i in 0..3 {
+            CrateBuilder::new(&format!("client_{i}"), user.id)
+                .description("An HTTP client")
+                .expect_build(conn);
+        }
+    });
+
+    let json = search("client");
+    assert_eq!(names(&json).len(), 4);
+    assert_eq!(json["suggestions"], json!([]));
+
+    // The suggestions are only included when searching
+    let json: Value = anon.get("/api/v1/crates").good();
+    assert!(json.get("suggestions").is_none());
+}
+
+#[test]
+fn typo_tolerant_search_only_for_plain_text() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("reqwest", user.id)
+            .description("higher level HTTP client library")
+            .expect_build(conn);
+    });
+
+    let search = |query: &str| -> Value { anon.get_with_query("/api/v1/crates", query).good() };
+
+    let json = search("q=reqwset");
+    assert_eq!(json["crates"].as_array().unwrap().len(), 1);
+
+    // Queries with filters only match the full text search
+    for query in [
+        format!("q=reqwset%20owner:{}", user.gh_login),
+        "q=reqwset&letter=r".into(),
+        "q=reqwset&include_yanked=no".into(),
+    ] {
+        let json = search(&query);
+        assert_eq!(json["crates"], json!([]), "{query}");
+        assert_eq!(json["suggestions"], json!([]), "{query}");
+    }
+
+    // Full text matches are still combined with the filters
+    let json = search(&format!("q=http%20owner:{}", user.gh_login));
+    assert_eq!(json["crates"][0]["name"], "reqwest");
+    assert_eq!(json["suggestions"], json!([]));
+}
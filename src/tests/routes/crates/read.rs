@@ -1,6 +1,8 @@
 use crate::builders::{CrateBuilder, PublishBuilder, VersionBuilder};
 use crate::util::{RequestHelper, TestApp};
 use diesel::prelude::*;
+use http::StatusCode;
+use serde_json::json;
 
 #[test]
 fn show() {
@@ -163,3 +165,29 @@ fn block_bad_documentation_url() {
     let json = anon.show_crate("foo_bad_doc_url");
     assert_eq!(json.krate.documentation, None);
 }
+
+#[test]
+fn show_unknown_crate_suggests_similar_names() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("serde_json", user.id).expect_build(conn);
+        CrateBuilder::new("serde", user.id).expect_build(conn);
+        CrateBuilder::new("tokio", user.id).expect_build(conn);
+    });
+
+    let response = anon.get::<()>("/api/v1/crates/serde-jsn");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{
+            "detail": "crate `serde-jsn` does not exist",
+            "suggestions": ["serde_json"],
+        }] })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/completely_unrelated");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.into_json()["errors"][0]["suggestions"], json!([]));
+}
@@ -1,5 +1,7 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
-use crate::util::{RequestHelper, TestApp};
+use crate::util::{MockRequestExt, RequestHelper, TestApp};
+use http::{header, StatusCode};
+use serde_json::json;
 
 #[test]
 fn download_nonexistent_version_of_existing_crate_404s() {
@@ -62,6 +64,30 @@ fn force_unconditional_redirect() {
         .assert_redirect_ends_with("/crates/bar-download/bar-download-1.0.0.crate");
 }
 
+#[test]
+fn cdn_log_downloads_only_count_clients() {
+    use super::super::downloads;
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.cdn_log_downloads = true;
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_download", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    anon.get::<()>("/api/v1/crates/foo_download/1.0.0/download")
+        .assert_redirect_ends_with("/crates/foo_download/foo_download-1.0.0.crate");
+
+    // The version downloads are counted from the CDN access logs instead.
+    downloads::persist_downloads_count(&app);
+    downloads::assert_dl_count(&anon, "foo_download/1.0.0", None, 0);
+}
+
 #[test]
 fn download_caches_version_id() {
     use super::super::downloads;
@@ -115,3 +141,34 @@ fn download_with_build_metadata() {
     anon.get::<()>("/api/v1/crates/foo/1.0.0+bar/readme")
         .assert_redirect_ends_with("/readmes/foo/foo-1.0.0%2Bbar.html");
 }
+
+#[test]
+fn download_unknown_crate_has_no_suggestions() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_download", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    // The download route does not query similar crate names, even for JSON requests
+    let url = "/api/v1/crates/foo-downlaod/1.0.0/download";
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "Not Found" }] })
+    );
+
+    // ... which are returned by the crate route instead
+    let response = anon.get::<()>("/api/v1/crates/foo-downlaod");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.into_json()["errors"][0]["suggestions"],
+        json!(["foo_download"])
+    );
+}
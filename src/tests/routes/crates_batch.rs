@@ -0,0 +1,125 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{RequestHelper, TestApp};
+use crates_io::rate_limiter::LimitedAction;
+use http::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const URL: &str = "/api/v1/crates_batch";
+
+#[test]
+fn batch_lookup() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_model = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_batch", user_model.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .version(VersionBuilder::new("2.0.0").yanked(true))
+            .recent_downloads(10)
+            .expect_build(conn);
+        CrateBuilder::new("bar_batch", user_model.id)
+            .version(VersionBuilder::new("0.1.0"))
+            .expect_build(conn);
+    });
+
+    let body = json!({ "crates": [
+        { "name": "foo-batch", "version": "2.0.0" },
+        { "name": "missing" },
+        { "name": "bar_batch", "version": "0.2.0" },
+        { "name": "foo_batch" },
+    ] });
+
+    // The lookup requires authentication for the rate limit
+    let response = anon.post::<()>(URL, body.to_string());
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let json: Value = user.post(URL, body.to_string()).good();
+    let results = json["crates"].as_array().unwrap();
+    assert_eq!(results.len(), 4);
+
+    assert_eq!(results[0]["name"], "foo-batch");
+    assert_eq!(results[0]["crate"]["name"], "foo_batch");
+    assert_eq!(results[0]["crate"]["max_version"], "1.1.0");
+    assert_eq!(results[0]["crate"]["recent_downloads"], 10);
+    assert_eq!(results[0]["version"]["num"], "2.0.0");
+    assert_eq!(results[0]["version"]["yanked"], true);
+
+    assert_eq!(
+        results[1],
+        json!({ "name": "missing", "crate": null, "version": null })
+    );
+
+    assert_eq!(results[2]["crate"]["name"], "bar_batch");
+    assert_eq!(results[2]["version"], Value::Null);
+
+    assert_eq!(results[3]["crate"]["name"], "foo_batch");
+    assert_eq!(results[3]["version"], Value::Null);
+}
+
+#[test]
+fn batch_size_is_limited() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let crates = (0..101)
+        .map(|i| json!({ "name": format!("crate_{i}") }))
+        .collect::<Vec<_>>();
+    let body = json!({ "crates": crates });
+
+    let response = user.post::<()>(URL, body.to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "at most 100 crates can be looked up in a single request" }] })
+    );
+
+    let response = user.post::<()>(URL, json!({ "crates": [] }).to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "at least one crate has to be looked up" }] })
+    );
+
+    let response = user.post::<()>(URL, "{}");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn rate_limit_counts_requested_crates() {
+    let (_, _, user) = TestApp::init()
+        .with_rate_limit(LimitedAction::BatchLookup, Duration::from_secs(60), 5)
+        .with_user();
+
+    let batch = |size: usize| {
+        let crates = (0..size)
+            .map(|i| json!({ "name": format!("crate_{i}") }))
+            .collect::<Vec<_>>();
+        json!({ "crates": crates }).to_string()
+    };
+
+    // 40 crates take four tokens, and a single crate takes the last one
+    user.post::<Value>(URL, batch(40)).good();
+    user.post::<Value>(URL, batch(1)).good();
+
+    let response = user.post::<()>(URL, batch(1));
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[test]
+fn crate_named_batch() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("batch", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    // The batch lookup does not shadow the routes of a crate with the same name
+    let json: Value = anon.get("/api/v1/crates/batch").good();
+    assert_eq!(json["crate"]["name"], "batch");
+
+    let json: Value = anon.get("/api/v1/crates/batch/versions").good();
+    assert_eq!(json["versions"][0]["num"], "1.0.0");
+}
@@ -0,0 +1,196 @@
+use crate::util::{MockCookieUser, RequestHelper, TestApp};
+use crate::OkBool;
+use crates_io::schema::emails;
+use diesel::prelude::*;
+use http::StatusCode;
+use serde_json::Value;
+
+static URL: &str = "/api/v1/me/emails";
+
+impl MockCookieUser {
+    fn list_emails(&self) -> Vec<Value> {
+        let json: Value = self.get(URL).good();
+        json["emails"].as_array().unwrap().clone()
+    }
+
+    fn add_email(&self, email: &str) -> Value {
+        let body = json!({ "email": email });
+        let json: Value = self.post(URL, body.to_string()).good();
+        json["email"].clone()
+    }
+}
+
+fn email_token(app: &TestApp, email_id: i32) -> String {
+    app.db(|conn| {
+        emails::table
+            .find(email_id)
+            .select(emails::token)
+            .first(conn)
+            .unwrap()
+    })
+}
+
+#[test]
+fn list_emails() {
+    let (_, anon, user) = TestApp::init().with_user();
+    anon.get::<()>(URL).assert_forbidden();
+
+    let emails = user.list_emails();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["email"], "something@example.com");
+    assert_eq!(emails[0]["verified"], true);
+    assert_eq!(emails[0]["primary"], true);
+}
+
+#[test]
+fn add_and_confirm_email() {
+    let (app, _, user) = TestApp::full().with_user();
+
+    let email = user.add_email(" foo@example.com ");
+    assert_eq!(email["email"], "foo@example.com");
+    assert_eq!(email["verified"], false);
+    assert_eq!(email["verification_email_sent"], true);
+    assert_eq!(email["primary"], false);
+
+    app.run_pending_background_jobs();
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, "foo@example.com");
+
+    let email_id = email["id"].as_i64().unwrap() as i32;
+    let token = email_token(&app, email_id);
+    let url = format!("/api/v1/confirm/{token}");
+    user.put::<OkBool>(&url, &[] as &[u8]).good();
+
+    let emails = user.list_emails();
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[0]["email"], "something@example.com");
+    assert_eq!(emails[0]["primary"], true);
+    assert_eq!(emails[1]["email"], "foo@example.com");
+    assert_eq!(emails[1]["verified"], true);
+    assert_eq!(emails[1]["primary"], false);
+
+    // The primary address is unchanged
+    let json = user.show_me();
+    assert_eq!(json.user.email.unwrap(), "something@example.com");
+    assert!(json.user.email_verified);
+}
+
+#[test]
+fn add_email_validation() {
+    let (_, _, user, token) = TestApp::full().with_token();
+
+    let body = json!({ "email": "foo@example.com" });
+    token.post::<()>(URL, body.to_string()).assert_forbidden();
+
+    let body = json!({ "email": "  " });
+    let response = user.post::<()>(URL, body.to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "empty email rejected" }] })
+    );
+
+    let body = json!({ "email": "SOMETHING@example.com" });
+    let response = user.post::<()>(URL, body.to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "this email address was already added" }] })
+    );
+
+    assert_eq!(user.list_emails().len(), 1);
+}
+
+#[test]
+fn change_primary_email() {
+    let (app, _, user) = TestApp::full().with_user();
+
+    let email = user.add_email("foo@example.com");
+    let email_id = email["id"].as_i64().unwrap() as i32;
+    let url = format!("{URL}/{email_id}/primary");
+
+    let response = user.put::<()>(&url, &[] as &[u8]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "only verified email addresses can become the primary address" }] })
+    );
+
+    let token = email_token(&app, email_id);
+    user.put::<OkBool>(&format!("/api/v1/confirm/{token}"), &[] as &[u8])
+        .good();
+    user.put::<OkBool>(&url, &[] as &[u8]).good();
+
+    let emails = user.list_emails();
+    assert_eq!(emails[0]["email"], "foo@example.com");
+    assert_eq!(emails[0]["primary"], true);
+    assert_eq!(emails[1]["email"], "something@example.com");
+    assert_eq!(emails[1]["primary"], false);
+
+    let json = user.show_me();
+    assert_eq!(json.user.email.unwrap(), "foo@example.com");
+
+    // The old endpoint replaces the new primary address and keeps the other addresses
+    user.update_email("bar@example.com");
+    let emails = user.list_emails();
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[0]["email"], "bar@example.com");
+    assert_eq!(emails[0]["verified"], false);
+    assert_eq!(emails[0]["primary"], true);
+    assert_eq!(emails[1]["email"], "something@example.com");
+
+    // An address that was already added as a secondary address is promoted instead
+    user.update_email("SOMETHING@example.com");
+    let emails = user.list_emails();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["email"], "something@example.com");
+    assert_eq!(emails[0]["verified"], true);
+    assert_eq!(emails[0]["primary"], true);
+}
+
+#[test]
+fn remove_email() {
+    let (app, _, user) = TestApp::full().with_user();
+    let other_user = app.db_new_user("other");
+
+    let primary_id = user.list_emails()[0]["id"].as_i64().unwrap();
+    let email = user.add_email("foo@example.com");
+    let email_id = email["id"].as_i64().unwrap();
+
+    let response = user.delete::<()>(&format!("{URL}/{primary_id}"));
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "the primary email address can't be removed" }] })
+    );
+
+    let response = other_user.delete::<()>(&format!("{URL}/{email_id}"));
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    user.delete::<OkBool>(&format!("{URL}/{email_id}")).good();
+
+    let emails = user.list_emails();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["email"], "something@example.com");
+}
+
+#[test]
+fn resend_email_confirmation() {
+    let (app, _, user) = TestApp::full().with_user();
+
+    let primary_id = user.list_emails()[0]["id"].as_i64().unwrap();
+    let email = user.add_email("foo@example.com");
+    let email_id = email["id"].as_i64().unwrap();
+
+    let response = user.put::<()>(&format!("{URL}/{primary_id}/resend"), &[] as &[u8]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    user.put::<OkBool>(&format!("{URL}/{email_id}/resend"), &[] as &[u8])
+        .good();
+
+    app.run_pending_background_jobs();
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails.len(), 2);
+    assert!(mails.iter().all(|mail| mail.to == "foo@example.com"));
+}
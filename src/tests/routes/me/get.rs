@@ -1,5 +1,6 @@
 use crate::builders::CrateBuilder;
 use crate::util::{RequestHelper, TestApp};
+use crates_io::models::token::EndpointScope;
 use crates_io::views::{EncodablePrivateUser, OwnedCrate};
 
 impl crate::util::MockCookieUser {
@@ -35,6 +36,32 @@ fn me() {
     assert_eq!(updated_json.owned_crates.len(), 1);
 }
 
+#[test]
+fn me_with_token() {
+    let url = "/api/v1/me";
+    let (_, _, user, token) = TestApp::init().with_token();
+
+    // Legacy tokens without endpoint scopes don't have access to account details
+    token.get(url).assert_forbidden();
+
+    let token = user.db_new_scoped_token(
+        "read-account",
+        None,
+        Some(vec![EndpointScope::ReadAccount]),
+        None,
+    );
+    let json: UserShowPrivateResponse = token.get(url).good();
+    assert_eq!(json.user.login, user.as_model().gh_login);
+
+    let token = user.db_new_scoped_token(
+        "publish",
+        None,
+        Some(vec![EndpointScope::PublishUpdate]),
+        None,
+    );
+    token.get(url).assert_forbidden();
+}
+
 #[test]
 fn test_user_owned_crates_doesnt_include_deleted_ownership() {
     let (app, _, user) = TestApp::init().with_user();
@@ -1,4 +1,7 @@
 mod email_notifications;
+mod emails;
 pub mod get;
+mod notification_settings;
+mod sessions;
 pub mod tokens;
 mod updates;
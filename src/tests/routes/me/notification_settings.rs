@@ -0,0 +1,102 @@
+use crate::util::{RequestHelper, TestApp};
+use crates_io::models::token::EndpointScope;
+use http::StatusCode;
+use serde_json::Value;
+
+const URL: &str = "/api/v1/me/notification_settings";
+
+#[test]
+fn notification_settings_logged_out() {
+    let (_, anon) = TestApp::init().empty();
+    anon.get(URL).assert_forbidden();
+    let body = json!({ "notification_settings": { "token_expiry": false } });
+    anon.put::<()>(URL, body.to_string()).assert_forbidden();
+}
+
+#[test]
+fn default_notification_settings() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let json: Value = user.get(URL).good();
+    assert_eq!(
+        json,
+        json!({
+            "notification_settings": {
+                "owner_invitation": true,
+                "token_created": true,
+                "token_expiry": true,
+                "token_exposed": true,
+                "token_new_ip_address": true,
+                "weekly_digest": false,
+            }
+        })
+    );
+}
+
+#[test]
+fn update_notification_settings() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let body =
+        json!({ "notification_settings": { "token_expiry": false, "token_created": false } });
+    let json: Value = user.put(URL, body.to_string()).good();
+    assert_eq!(json["notification_settings"]["token_expiry"], false);
+    assert_eq!(json["notification_settings"]["token_created"], false);
+    assert_eq!(json["notification_settings"]["token_exposed"], true);
+
+    // Settings that are not part of the request are unchanged
+    let body = json!({ "notification_settings": { "token_created": true } });
+    let json: Value = user.put(URL, body.to_string()).good();
+    assert_eq!(json["notification_settings"]["token_expiry"], false);
+    assert_eq!(json["notification_settings"]["token_created"], true);
+
+    let json: Value = user.get(URL).good();
+    assert_eq!(json["notification_settings"]["token_expiry"], false);
+    assert_eq!(json["notification_settings"]["token_created"], true);
+}
+
+#[test]
+fn opt_in_to_weekly_digest() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let body = json!({ "notification_settings": { "weekly_digest": true } });
+    let json: Value = user.put(URL, body.to_string()).good();
+    assert_eq!(json["notification_settings"]["weekly_digest"], true);
+    assert_eq!(json["notification_settings"]["token_expiry"], true);
+
+    let body = json!({ "notification_settings": { "weekly_digest": false } });
+    let json: Value = user.put(URL, body.to_string()).good();
+    assert_eq!(json["notification_settings"]["weekly_digest"], false);
+}
+
+#[test]
+fn update_notification_settings_with_invalid_type() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let body = json!({ "notification_settings": { "newsletter": false } });
+    let response = user.put::<()>(URL, body.to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid json request" }] })
+    );
+}
+
+#[test]
+fn notification_settings_with_api_token() {
+    let (_, _, user, token) = TestApp::init().with_token();
+    token.get(URL).assert_forbidden();
+
+    let token = user.db_new_scoped_token(
+        "read-account",
+        None,
+        Some(vec![EndpointScope::ReadAccount]),
+        None,
+    );
+    let json: Value = token.get(URL).good();
+    assert_eq!(json["notification_settings"]["token_exposed"], true);
+
+    // Tokens can't be used to disable notifications about their own misuse
+    let body = json!({ "notification_settings": { "token_new_ip_address": false } });
+    token.put::<()>(URL, body.to_string()).assert_forbidden();
+}
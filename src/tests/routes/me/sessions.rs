@@ -0,0 +1,124 @@
+use crate::util::{MockCookieUser, RequestHelper, TestApp};
+use crates_io::schema::sessions;
+use diesel::prelude::*;
+use http::StatusCode;
+use serde_json::Value;
+
+static URL: &str = "/api/v1/me/sessions";
+
+#[test]
+fn list_sessions_requires_login() {
+    let (_, anon) = TestApp::init().empty();
+    let response = anon.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn list_sessions_cannot_use_token() {
+    let (_, _, _, token) = TestApp::init().with_token();
+    let response = token.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn list_sessions() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other_session = MockCookieUser::new(&app, user.as_model().clone());
+    app.db_new_user("bar");
+
+    let json = user.get::<Value>(URL).good();
+    let sessions = json["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(
+        sessions
+            .iter()
+            .filter(|session| session["current"] == json!(true))
+            .count(),
+        1
+    );
+
+    let json = other_session.get::<Value>(URL).good();
+    let sessions = json["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions[0]["created_at"].is_string());
+    assert!(sessions[0]["last_seen_at"].is_string());
+    assert_eq!(sessions[0]["ip_address"], json!(null));
+    assert_eq!(sessions[0]["user_agent"], json!(""));
+}
+
+#[test]
+fn list_sessions_excludes_expired_sessions() {
+    let (app, _, user) = TestApp::init().with_user();
+    MockCookieUser::new(&app, user.as_model().clone());
+
+    app.db(|conn| {
+        let expired_id = sessions::table
+            .select(sessions::id)
+            .order(sessions::id.desc())
+            .first::<i32>(conn)
+            .unwrap();
+
+        diesel::update(sessions::table.find(expired_id))
+            .set(sessions::created_at.eq(diesel::dsl::sql("now() - interval '91 days'")))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json = user.get::<Value>(URL).good();
+    assert_eq!(json["sessions"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn revoke_session() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other_session = MockCookieUser::new(&app, user.as_model().clone());
+
+    let json = other_session.get::<Value>(URL).good();
+    let id = json["sessions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|session| session["current"] == json!(true))
+        .map(|session| session["id"].as_i64().unwrap())
+        .unwrap();
+
+    let json = user.delete::<Value>(&format!("{URL}/{id}")).good();
+    assert_eq!(json, json!({ "ok": true }));
+
+    let response = other_session.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let json = user.get::<Value>(URL).good();
+    assert_eq!(json["sessions"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn revoke_session_of_other_user() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other_user = app.db_new_user("bar");
+
+    let json = other_user.get::<Value>(URL).good();
+    let id = json["sessions"][0]["id"].as_i64().unwrap();
+
+    let response = user.delete::<()>(&format!("{URL}/{id}"));
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    other_user.get::<Value>(URL).good();
+}
+
+#[test]
+fn revoke_all_sessions() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other_session = MockCookieUser::new(&app, user.as_model().clone());
+    let other_user = app.db_new_user("bar");
+
+    let json = user.delete::<Value>(URL).good();
+    assert_eq!(json, json!({ "ok": true }));
+
+    let response = user.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = other_session.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    other_user.get::<Value>(URL).good();
+}
@@ -0,0 +1,60 @@
+use crate::builders::PublishBuilder;
+use crate::util::{RequestHelper, TestApp};
+use http::StatusCode;
+use serde_json::Value;
+
+#[test]
+fn token_activity_logged_out() {
+    let (_, anon, _, token) = TestApp::init().with_token();
+    let url = format!("/api/v1/me/tokens/{}/activity", token.as_model().id);
+    anon.get::<()>(&url).assert_forbidden();
+}
+
+#[test]
+fn token_activity_of_other_user() {
+    let (app, _, _, token) = TestApp::init().with_token();
+    let other_user = app.db_new_user("bar");
+
+    let url = format!("/api/v1/me/tokens/{}/activity", token.as_model().id);
+    let response = other_user.get::<()>(&url);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn token_activity() {
+    let (app, _, user, token) = TestApp::full().with_token();
+    let url = format!("/api/v1/me/tokens/{}/activity", token.as_model().id);
+
+    let json: Value = user.get(&url).good();
+    assert_eq!(json, json!({ "activity": [] }));
+
+    token.get::<Value>("/api/v1/crates?following=1").good();
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+
+    // Cookie authenticated requests are not recorded
+    user.get::<Value>("/api/v1/crates?following=1").good();
+
+    // The activity log is only visible after it was persisted
+    let json: Value = user.get(&url).good();
+    assert_eq!(json, json!({ "activity": [] }));
+
+    app.as_inner()
+        .token_activity
+        .persist(app.as_inner())
+        .unwrap();
+
+    let json: Value = user.get(&url).good();
+    let activity = json["activity"].as_array().unwrap();
+    assert_eq!(activity.len(), 2);
+
+    assert_eq!(activity[0]["endpoint"], "PUT /api/v1/crates/new");
+    assert_eq!(activity[0]["crate"], "foo");
+    assert_eq!(activity[0]["ip_address"], "127.0.0.1");
+    assert_eq!(activity[0]["user_agent"], "conduit-test");
+    assert!(activity[0]["created_at"].is_string());
+
+    assert_eq!(activity[1]["endpoint"], "GET /api/v1/crates");
+    assert_eq!(activity[1]["crate"], Value::Null);
+}
@@ -1,9 +1,10 @@
 use crate::util::insta::{self, assert_json_snapshot};
-use crate::util::{RequestHelper, TestApp};
+use crate::util::{MockRequestExt, RequestHelper, TestApp};
 use crates_io::models::token::{CrateScope, EndpointScope};
-use crates_io::models::ApiToken;
+use crates_io::models::{ApiToken, NotificationType};
 use diesel::prelude::*;
-use http::StatusCode;
+use http::{header, Method, StatusCode};
+use secrecy::ExposeSecret;
 use serde_json::Value;
 
 static NEW_BAR: &[u8] = br#"{ "api_token": { "name": "bar" } }"#;
@@ -81,6 +82,51 @@ fn create_token_success() {
     assert_eq!(tokens[0].endpoint_scopes, None);
 }
 
+#[test]
+fn create_token_sends_notification() {
+    let (app, _, user) = TestApp::full().with_user();
+
+    user.put::<Value>("/api/v1/me/tokens", NEW_BAR).good();
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].subject, "A new API token was created");
+    assert!(mails[0].body.contains("\"bar\""));
+}
+
+#[test]
+fn create_token_without_notification_to_unverified_email() {
+    use crates_io::schema::emails;
+
+    let (app, _, user) = TestApp::full().with_user();
+    app.db(|conn| {
+        diesel::update(emails::table.filter(emails::user_id.eq(user.as_model().id)))
+            .set(emails::verified.eq(false))
+            .execute(conn)
+            .unwrap();
+    });
+
+    user.put::<Value>("/api/v1/me/tokens", NEW_BAR).good();
+    app.run_pending_background_jobs();
+
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+}
+
+#[test]
+fn create_token_without_notification_after_opt_out() {
+    let (app, _, user) = TestApp::init().with_user();
+    app.db(|conn| {
+        NotificationType::TokenCreated
+            .set_enabled_for(conn, user.as_model().id, false)
+            .unwrap()
+    });
+
+    user.put::<Value>("/api/v1/me/tokens", NEW_BAR).good();
+
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+}
+
 #[test]
 fn create_token_multiple_have_different_values() {
     let (_, _, user) = TestApp::init().with_user();
@@ -116,6 +162,141 @@ fn cannot_create_token_with_token() {
     );
 }
 
+#[test]
+fn create_token_with_scoped_token() {
+    let (_, _, user) = TestApp::init().with_user();
+    let token = user.db_new_scoped_token(
+        "manage",
+        None,
+        Some(vec![
+            EndpointScope::ManageTokens,
+            EndpointScope::PublishUpdate,
+        ]),
+        None,
+    );
+
+    let json = json!({
+        "api_token": {
+            "name": "bar",
+            "crate_scopes": ["tokio-*"],
+            "endpoint_scopes": ["publish-update"],
+        }
+    });
+
+    let response = token.put::<Value>("/api/v1/me/tokens", serde_json::to_vec(&json).unwrap());
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response.into_json();
+    assert_eq!(json["api_token"]["crate_scopes"], json!(["tokio-*"]));
+    assert_eq!(
+        json["api_token"]["endpoint_scopes"],
+        json!(["publish-update"])
+    );
+}
+
+#[test]
+fn cannot_create_broader_token_with_scoped_token() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let expired_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(7);
+    let token = app.db(|conn| {
+        ApiToken::insert_with_scopes(
+            conn,
+            user.as_model().id,
+            "manage",
+            None,
+            Some(vec![EndpointScope::ManageTokens]),
+            Some(expired_at),
+            Some(vec!["127.0.0.0/8".parse().unwrap()]),
+        )
+        .unwrap()
+    });
+    let token = token.plaintext.expose_secret().to_string();
+
+    let assert_broader = |api_token: Value, permission: &str| {
+        let json = json!({ "api_token": api_token });
+        let mut request = anon.request_builder(Method::PUT, "/api/v1/me/tokens");
+        request.header(header::AUTHORIZATION, &token);
+        *request.body_mut() = serde_json::to_vec(&json).unwrap().into();
+        let response = anon.run::<()>(request);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let detail =
+            format!("an API token can not create tokens with broader {permission} than its own");
+        assert_eq!(
+            response.into_json(),
+            json!({ "errors": [{ "detail": detail }] })
+        );
+    };
+
+    let expires_in_a_day = chrono::Utc::now() + chrono::Duration::days(1);
+    let expires_in_a_month = chrono::Utc::now() + chrono::Duration::days(30);
+
+    assert_broader(
+        json!({
+            "name": "bar",
+            "endpoint_scopes": ["manage-tokens", "publish-new"],
+            "expired_at": expires_in_a_day,
+            "allowed_ip_ranges": ["127.0.0.1"],
+        }),
+        "endpoint scopes",
+    );
+    assert_broader(
+        json!({
+            "name": "bar",
+            "expired_at": expires_in_a_day,
+            "allowed_ip_ranges": ["127.0.0.1"],
+        }),
+        "endpoint scopes",
+    );
+    assert_broader(
+        json!({
+            "name": "bar",
+            "endpoint_scopes": ["manage-tokens"],
+            "expired_at": expires_in_a_month,
+            "allowed_ip_ranges": ["127.0.0.1"],
+        }),
+        "expiration date",
+    );
+    assert_broader(
+        json!({
+            "name": "bar",
+            "endpoint_scopes": ["manage-tokens"],
+            "allowed_ip_ranges": ["127.0.0.1"],
+        }),
+        "expiration date",
+    );
+    assert_broader(
+        json!({
+            "name": "bar",
+            "endpoint_scopes": ["manage-tokens"],
+            "expired_at": expires_in_a_day,
+            "allowed_ip_ranges": ["127.0.0.0/7"],
+        }),
+        "allowed IP ranges",
+    );
+    assert_broader(
+        json!({
+            "name": "bar",
+            "endpoint_scopes": ["manage-tokens"],
+            "expired_at": expires_in_a_day,
+        }),
+        "allowed IP ranges",
+    );
+}
+
+#[test]
+fn cannot_create_token_with_token_without_manage_tokens_scope() {
+    let (_, _, user) = TestApp::init().with_user();
+    let token = user.db_new_scoped_token(
+        "publish",
+        None,
+        Some(vec![EndpointScope::PublishUpdate]),
+        None,
+    );
+
+    let json = json!({ "api_token": { "name": "bar", "endpoint_scopes": ["publish-update"] } });
+    let response = token.put::<()>("/api/v1/me/tokens", serde_json::to_vec(&json).unwrap());
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
 #[test]
 fn create_token_with_scopes() {
     let (app, _, user) = TestApp::init().with_user();
@@ -255,3 +436,62 @@ fn create_token_with_expiry_date() {
         ".api_token.token" => insta::api_token_redaction(),
     });
 }
+
+#[test]
+fn create_token_with_allowed_ip_ranges() {
+    let (_app, _, user) = TestApp::init().with_user();
+
+    let json = json!({
+        "api_token": {
+            "name": "bar",
+            "allowed_ip_ranges": ["192.0.2.1/24", "2001:db8::1"],
+        }
+    });
+
+    let response = user.put::<()>("/api/v1/me/tokens", serde_json::to_vec(&json).unwrap());
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.into_json(), {
+        ".api_token.id" => insta::any_id_redaction(),
+        ".api_token.created_at" => "[datetime]",
+        ".api_token.last_used_at" => "[datetime]",
+        ".api_token.token" => insta::api_token_redaction(),
+    });
+}
+
+#[test]
+fn create_token_with_invalid_allowed_ip_range() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let json = json!({
+        "api_token": {
+            "name": "bar",
+            "allowed_ip_ranges": ["192.0.2.0/24", "192.0.2.0/33"],
+        }
+    });
+
+    let response = user.put::<()>("/api/v1/me/tokens", serde_json::to_vec(&json).unwrap());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid IP range: 192.0.2.0/33" }] })
+    );
+}
+
+#[test]
+fn create_token_with_empty_allowed_ip_ranges() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let json = json!({
+        "api_token": {
+            "name": "bar",
+            "allowed_ip_ranges": [],
+        }
+    });
+
+    let response = user.put::<()>("/api/v1/me/tokens", serde_json::to_vec(&json).unwrap());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "allowed_ip_ranges must not be empty" }] })
+    );
+}
@@ -17,6 +17,29 @@ fn list_with_api_token_is_forbidden() {
     token.get("/api/v1/me/tokens").assert_forbidden();
 }
 
+#[test]
+fn list_with_scoped_api_token() {
+    let (_, _, user) = TestApp::init().with_user();
+    let token = user.db_new_scoped_token(
+        "manage",
+        None,
+        Some(vec![EndpointScope::ManageTokens]),
+        None,
+    );
+    let json: serde_json::Value = token.get("/api/v1/me/tokens").good();
+    let response_tokens = json["api_tokens"].as_array().unwrap();
+    assert_eq!(response_tokens.len(), 1);
+    assert_eq!(response_tokens[0]["name"], "manage");
+
+    let token = user.db_new_scoped_token(
+        "publish",
+        None,
+        Some(vec![EndpointScope::PublishUpdate]),
+        None,
+    );
+    token.get("/api/v1/me/tokens").assert_forbidden();
+}
+
 #[test]
 fn list_empty() {
     let (_, _, user) = TestApp::init().with_user();
@@ -43,7 +66,8 @@ fn list_tokens() {
                     CrateScope::try_from("serde-*").unwrap()
                 ]),
                 Some(vec![EndpointScope::PublishUpdate]),
-                None
+                None,
+                Some(vec!["192.0.2.0/24".parse().unwrap()]),
             )),
             assert_ok!(ApiToken::insert_with_scopes(
                 conn,
@@ -52,6 +76,7 @@ fn list_tokens() {
                 None,
                 None,
                 Some((Utc::now() - Duration::days(1)).naive_utc()),
+                None,
             )),
         ]
     });
@@ -87,6 +112,7 @@ fn list_recently_expired_tokens() {
                 ]),
                 Some(vec![EndpointScope::PublishUpdate]),
                 Some((Utc::now() - Duration::days(31)).naive_utc()),
+                None,
             )),
             assert_ok!(ApiToken::insert_with_scopes(
                 conn,
@@ -95,6 +121,7 @@ fn list_recently_expired_tokens() {
                 None,
                 None,
                 Some((Utc::now() - Duration::days(1)).naive_utc()),
+                None,
             )),
         ]
     });
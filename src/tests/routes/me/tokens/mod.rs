@@ -1,4 +1,6 @@
+pub mod activity;
 pub mod create;
 pub mod delete;
 pub mod delete_current;
 pub mod list;
+pub mod rotate;
@@ -0,0 +1,159 @@
+use crate::util::{MockAnonymousUser, MockRequestExt, RequestHelper, TestApp};
+use chrono::{Duration, NaiveDate};
+use crates_io::models::token::{CrateScope, EndpointScope};
+use http::{header, Method, StatusCode};
+use secrecy::ExposeSecret;
+use serde_json::Value;
+use std::time::Duration as StdDuration;
+
+fn rotate_url(id: i32) -> String {
+    format!("/api/v1/me/tokens/{id}/rotate")
+}
+
+fn token_status(anon: &MockAnonymousUser, token: &str) -> StatusCode {
+    let mut request = anon.request_builder(Method::GET, "/api/v1/crates?following=1");
+    request.header(header::AUTHORIZATION, token);
+    anon.run::<()>(request).status()
+}
+
+#[test]
+fn rotate_token_logged_out() {
+    let (_, anon, _, token) = TestApp::init().with_token();
+    anon.post::<()>(&rotate_url(token.as_model().id), "")
+        .assert_forbidden();
+}
+
+#[test]
+fn rotate_token_with_token() {
+    let (_, _, _, token) = TestApp::init().with_token();
+    let response = token.post::<()>(&rotate_url(token.as_model().id), "");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "cannot use an API token to rotate an API token" }] })
+    );
+}
+
+#[test]
+fn rotate_token_with_scoped_token() {
+    let (_, _, user) = TestApp::init().with_user();
+    let manage = user.db_new_scoped_token(
+        "manage",
+        None,
+        Some(vec![
+            EndpointScope::ManageTokens,
+            EndpointScope::PublishUpdate,
+        ]),
+        None,
+    );
+    let narrower = user.db_new_scoped_token(
+        "narrower",
+        Some(vec![CrateScope::try_from("tokio-util").unwrap()]),
+        Some(vec![EndpointScope::PublishUpdate]),
+        None,
+    );
+    let broader =
+        user.db_new_scoped_token("broader", None, Some(vec![EndpointScope::PublishNew]), None);
+
+    let json: Value = manage.post(&rotate_url(narrower.as_model().id), "").good();
+    assert_eq!(json["api_token"]["name"], "narrower");
+
+    let response = manage.post::<()>(&rotate_url(broader.as_model().id), "");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "an API token can not create tokens with broader endpoint scopes than its own" }] })
+    );
+}
+
+#[test]
+fn rotate_token_of_other_user() {
+    let (app, _, _, token) = TestApp::init().with_token();
+    let other_user = app.db_new_user("bar");
+
+    let response = other_user.post::<()>(&rotate_url(token.as_model().id), "");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn rotate_revoked_token() {
+    let (_, _, user, token) = TestApp::init().with_token();
+    let url = format!("/api/v1/me/tokens/{}", token.as_model().id);
+    user.delete::<Value>(&url).good();
+
+    let response = user.post::<()>(&rotate_url(token.as_model().id), "");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn rotate_token() {
+    let (_, _, user) = TestApp::init().with_user();
+    let expired_at = NaiveDate::from_ymd_opt(2100, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let token = user.db_new_scoped_token(
+        "bar",
+        Some(vec![CrateScope::try_from("foo").unwrap()]),
+        Some(vec![EndpointScope::PublishUpdate]),
+        Some(expired_at),
+    );
+    let old_secret = token.plaintext().expose_secret().clone();
+
+    let json: Value = user.post(&rotate_url(token.as_model().id), "").good();
+    let new_token = &json["api_token"];
+    assert_ne!(new_token["id"], token.as_model().id);
+    assert_eq!(new_token["name"], "bar");
+    assert_eq!(new_token["crate_scopes"], json!(["foo"]));
+    assert_eq!(new_token["endpoint_scopes"], json!(["publish-update"]));
+    assert_eq!(new_token["expired_at"], "2100-01-01T00:00:00+00:00");
+
+    let new_secret = new_token["token"].as_str().unwrap();
+    assert_ne!(new_secret, old_secret);
+
+    // The old token expires at the end of the grace period
+    let json: Value = user.get("/api/v1/me/tokens").good();
+    let tokens = json["api_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 2);
+
+    let old_token = tokens
+        .iter()
+        .find(|t| t["id"] == token.as_model().id)
+        .unwrap();
+    let old_expired_at = old_token["expired_at"].as_str().unwrap();
+    let old_expired_at = chrono::DateTime::parse_from_rfc3339(old_expired_at)
+        .unwrap()
+        .naive_utc();
+    let grace_period_end = chrono::Utc::now().naive_utc() + Duration::hours(1);
+    assert!(old_expired_at <= grace_period_end);
+    assert!(old_expired_at > grace_period_end - Duration::minutes(1));
+}
+
+#[test]
+fn rotate_token_without_grace_period() {
+    let (_, anon, user) = TestApp::init()
+        .with_config(|config| config.token_rotation_grace_period = StdDuration::ZERO)
+        .with_user();
+    let token = user.db_new_token("bar");
+    let old_secret = token.plaintext().expose_secret().clone();
+    assert_eq!(token_status(&anon, &old_secret), StatusCode::OK);
+
+    let json: Value = user.post(&rotate_url(token.as_model().id), "").good();
+    let new_secret = json["api_token"]["token"].as_str().unwrap();
+
+    assert_eq!(token_status(&anon, &old_secret), StatusCode::FORBIDDEN);
+    assert_eq!(token_status(&anon, new_secret), StatusCode::OK);
+}
+
+#[test]
+fn rotate_token_keeps_both_secrets_during_grace_period() {
+    let (_, anon, user) = TestApp::init().with_user();
+    let token = user.db_new_token("bar");
+    let old_secret = token.plaintext().expose_secret().clone();
+
+    let json: Value = user.post(&rotate_url(token.as_model().id), "").good();
+    let new_secret = json["api_token"]["token"].as_str().unwrap();
+
+    assert_eq!(token_status(&anon, &old_secret), StatusCode::OK);
+    assert_eq!(token_status(&anon, new_secret), StatusCode::OK);
+}
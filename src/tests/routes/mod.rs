@@ -11,13 +11,17 @@
 //! - testing output serialization of a route
 //! - testing query parameter combinations of a route
 
+pub mod audit;
 pub mod categories;
 pub mod category_slugs;
 pub mod crates;
+pub mod crates_batch;
 pub mod keywords;
 pub mod me;
 pub mod metrics;
 pub mod session;
+pub mod suggest;
 pub mod summary;
+pub mod unsubscribe;
 pub mod users;
 pub mod versions;
@@ -0,0 +1,17 @@
+use crate::util::{MockCookieUser, RequestHelper, TestApp};
+use http::StatusCode;
+use serde_json::Value;
+
+#[test]
+fn logout_deletes_session() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other_session = MockCookieUser::new(&app, user.as_model().clone());
+
+    let json = user.delete::<Value>("/api/private/session").good();
+    assert_eq!(json, json!(true));
+
+    let response = user.get::<()>("/api/v1/me");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    other_session.get::<Value>("/api/v1/me").good();
+}
@@ -1,2 +1,3 @@
 mod authorize;
 mod begin;
+mod logout;
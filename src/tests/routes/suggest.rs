@@ -0,0 +1,126 @@
+use crate::builders::CrateBuilder;
+use crate::new_category;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::schema::crates;
+use diesel::prelude::*;
+use insta::assert_json_snapshot;
+use serde_json::Value;
+
+#[test]
+fn suggest() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        new_category("Serialization", "serialization", "Serialization crates")
+            .create_or_update(conn)
+            .unwrap();
+
+        CrateBuilder::new("serde", user.id)
+            .recent_downloads(100)
+            .keyword("serde")
+            .keyword("serialization")
+            .category("serialization")
+            .expect_build(conn);
+        CrateBuilder::new("serde_json", user.id)
+            .recent_downloads(1000)
+            .keyword("serde")
+            .expect_build(conn);
+        CrateBuilder::new("serde-yaml", user.id).expect_build(conn);
+        CrateBuilder::new("reqwest", user.id).expect_build(conn);
+    });
+
+    let suggest = |q: &str| -> Value {
+        anon.get_with_query("/api/v1/suggest", &format!("q={q}"))
+            .good()
+    };
+
+    // Crates are ranked by recent downloads, keywords and categories by their number of crates
+    assert_json_snapshot!(suggest("ser"), @r###"
+    {
+      "categories": [
+        {
+          "category": "Serialization",
+          "slug": "serialization"
+        }
+      ],
+      "crates": [
+        {
+          "name": "serde_json",
+          "recent_downloads": 1000
+        },
+        {
+          "name": "serde",
+          "recent_downloads": 100
+        },
+        {
+          "name": "serde-yaml",
+          "recent_downloads": null
+        }
+      ],
+      "keywords": [
+        "serde",
+        "serialization"
+      ]
+    }
+    "###);
+
+    // Hyphens and underscores are interchangeable in crate names, and names starting with the
+    // prefix are ranked before merely similar names
+    let json = suggest("SERDE-J");
+    assert_eq!(json["crates"][0]["name"], "serde_json");
+    assert_eq!(json["crates"][0]["recent_downloads"], 1000);
+    assert_eq!(json["keywords"], json!([]));
+
+    // Prefixes with typos still find similar crates
+    let json = suggest("reqw");
+    assert_eq!(json["crates"][0]["name"], "reqwest");
+    let json = suggest("reqweest");
+    assert_eq!(json["crates"][0]["name"], "reqwest");
+
+    // Underscores are not wildcards
+    let json = suggest("s_r");
+    assert_eq!(json["keywords"], json!([]));
+    assert_eq!(json["categories"], json!([]));
+
+    let json = suggest("");
+    assert_eq!(
+        json,
+        json!({ "crates": [], "keywords": [], "categories": [] })
+    );
+    let json = suggest("%25");
+    assert_eq!(
+        json,
+        json!({ "crates": [], "keywords": [], "categories": [] })
+    );
+
+    // The suggestions are cached per prefix
+    app.db(|conn| {
+        diesel::delete(crates::table.filter(crates::name.eq("reqwest")))
+            .execute(conn)
+            .unwrap();
+    });
+    let json = suggest("reqw");
+    assert_eq!(json["crates"][0]["name"], "reqwest");
+}
+
+#[test]
+fn crate_named_suggest() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("suggest", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    // The suggestions route does not shadow the routes of a crate with the same name
+    let json: Value = anon.get("/api/v1/crates/suggest").good();
+    assert_eq!(json["crate"]["name"], "suggest");
+
+    let json: Value = anon.get("/api/v1/crates/suggest/versions").good();
+    assert_eq!(json["versions"][0]["num"], "1.0.0");
+
+    let json: Value = anon.get("/api/v1/suggest").good();
+    assert_eq!(json["crates"], json!([]));
+}
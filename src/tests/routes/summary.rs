@@ -14,6 +14,7 @@ struct SummaryResponse {
     most_downloaded: Vec<EncodableCrate>,
     most_recently_downloaded: Vec<EncodableCrate>,
     just_updated: Vec<EncodableCrate>,
+    trending: Vec<EncodableCrate>,
     popular_keywords: Vec<EncodableKeyword>,
     popular_categories: Vec<EncodableCategory>,
 }
@@ -106,6 +107,7 @@ fn summary_new_crates() {
     assert_eq!(json.just_updated[1].newest_version, "0.1.2");
 
     assert_eq!(json.new_crates.len(), 5);
+    assert_eq!(json.trending.len(), 0);
 }
 
 #[test]
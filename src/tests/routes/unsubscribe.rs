@@ -0,0 +1,123 @@
+use crate::builders::PublishBuilder;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::email::Subscription;
+use crates_io::models::NotificationType;
+use crates_io::schema::crate_owners;
+use diesel::prelude::*;
+use http::StatusCode;
+use serde_json::Value;
+
+/// Returns the path of the `List-Unsubscribe` header of the last sent email.
+fn last_unsubscribe_path(app: &TestApp) -> String {
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    let url = mails.last().unwrap().list_unsubscribe.clone().unwrap();
+    let (_, token) = url.split_once("/api/v1/unsubscribe/").unwrap();
+    assert!(mails
+        .last()
+        .unwrap()
+        .body
+        .contains(&format!("/unsubscribe/{token}")));
+
+    format!("/api/v1/unsubscribe/{token}")
+}
+
+#[test]
+fn unsubscribe_from_crate() {
+    let (app, anon, user, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+
+    let path = last_unsubscribe_path(&app);
+    let (_, unsubscribe_token) = path.rsplit_once('/').unwrap();
+    let (user_id, subscription) = app
+        .as_inner()
+        .emails
+        .verify_unsubscribe_token(unsubscribe_token)
+        .unwrap();
+    assert_eq!(user_id, user.as_model().id);
+    assert!(matches!(subscription, Subscription::Crate(_)));
+
+    // Mail clients send this body for the one-click unsubscribe of RFC 8058
+    let json: Value = anon.post(&path, "List-Unsubscribe=One-Click").good();
+    assert_eq!(json["ok"], true);
+    assert_eq!(
+        json["msg"],
+        "You will no longer receive notifications about new, yanked and unyanked versions of foo."
+    );
+
+    let email_notifications: bool = app.db(|conn| {
+        crate_owners::table
+            .filter(crate_owners::owner_id.eq(user.as_model().id))
+            .select(crate_owners::email_notifications)
+            .first(conn)
+            .unwrap()
+    });
+    assert!(!email_notifications);
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.1.0"))
+        .good();
+    app.run_pending_background_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+}
+
+#[test]
+fn unsubscribe_from_notification_type() {
+    let (app, anon, user) = TestApp::full().with_user();
+    let user_id = user.as_model().id;
+
+    app.db(|conn| {
+        app.as_inner()
+            .emails
+            .send_token_created_notification(conn, user_id, "foo@example.com", "foo", "bar")
+            .unwrap();
+    });
+
+    let path = last_unsubscribe_path(&app);
+    let json: Value = anon.post(&path, "").good();
+    assert_eq!(json["ok"], true);
+
+    let enabled = app.db(|conn| {
+        NotificationType::TokenCreated
+            .is_enabled_for(conn, user_id)
+            .unwrap()
+    });
+    assert!(!enabled);
+
+    // Using the link again is fine
+    let json: Value = anon.post(&path, "").good();
+    assert_eq!(json["ok"], true);
+}
+
+#[test]
+fn unsubscribe_with_invalid_token() {
+    let (_, anon) = TestApp::init().empty();
+
+    let response = anon.post::<()>("/api/v1/unsubscribe/foo.bar", "");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid or expired unsubscribe link" }] })
+    );
+}
+
+#[test]
+fn confirmation_emails_have_no_unsubscribe_link() {
+    let (app, _) = TestApp::full().empty();
+
+    app.db(|conn| {
+        app.as_inner()
+            .emails
+            .send_user_confirm(conn, "foo@example.com", "foo", "token")
+            .unwrap();
+    });
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails[0].list_unsubscribe, None);
+    assert!(!mails[0].body.contains("unsubscribe"));
+}
@@ -2,7 +2,48 @@ use crate::util::MockRequestExt;
 use crate::{RequestHelper, TestApp};
 use crates_io::{models::ApiToken, util::errors::TOKEN_FORMAT_ERROR, views::EncodableMe};
 use diesel::prelude::*;
-use http::{header, StatusCode};
+use http::{header, Method, StatusCode};
+
+#[test]
+fn using_token_from_new_ip_address_sends_notification() {
+    let (app, _, _, token) = TestApp::full().with_token();
+    let url = "/api/v1/crates?following=1";
+
+    let use_token_from = |ip: &str| {
+        let mut request = token.request_builder(Method::GET, url);
+        request.header("x-real-ip", ip);
+        assert_eq!(token.run::<()>(request).status(), StatusCode::OK);
+    };
+
+    // IP addresses are recorded when the token activity log is persisted
+    let persist_and_run_jobs = || {
+        let inner = app.as_inner();
+        inner.token_activity.persist(inner).unwrap();
+        app.run_pending_background_jobs();
+    };
+
+    // The first IP address a token is used from is expected
+    use_token_from("127.0.0.1");
+    use_token_from("127.0.0.1");
+    persist_and_run_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+
+    use_token_from("192.0.2.1");
+    persist_and_run_jobs();
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(
+        mails[0].subject,
+        "Your API token was used from a new IP address"
+    );
+    assert!(mails[0].body.contains("192.0.2.1"));
+
+    // Known IP addresses don't send further notifications
+    use_token_from("192.0.2.1");
+    use_token_from("127.0.0.1");
+    persist_and_run_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+}
 
 #[test]
 fn using_token_updates_last_used_at() {
@@ -59,8 +59,9 @@ fn github_without_email_does_not_overwrite_email() {
         let u = u
             .create_or_update(None, &app.as_inner().emails, conn)
             .unwrap();
-        MockCookieUser::new(&app, u)
+        u
     });
+    let user_without_github_email = MockCookieUser::new(&app, user_without_github_email);
     let user_without_github_email_model = user_without_github_email.as_model();
 
     let json = user_without_github_email.show_me();
@@ -81,8 +82,10 @@ fn github_without_email_does_not_overwrite_email() {
         let u = u
             .create_or_update(None, &app.as_inner().emails, conn)
             .unwrap();
-        MockCookieUser::new(&app, u)
+        u
     });
+    let again_user_without_github_email =
+        MockCookieUser::new(&app, again_user_without_github_email);
 
     let json = again_user_without_github_email.show_me();
     assert_eq!(json.user.email.unwrap(), "apricot@apricots.apricot");
@@ -116,8 +119,10 @@ fn github_with_email_does_not_overwrite_email() {
         let u = u
             .create_or_update(Some(new_github_email), &app.as_inner().emails, conn)
             .unwrap();
-        MockCookieUser::new(&app, u)
+        u
     });
+    let user_with_different_email_in_github =
+        MockCookieUser::new(&app, user_with_different_email_in_github);
 
     let json = user_with_different_email_in_github.show_me();
     assert_eq!(json.user.email, Some(original_email));
@@ -163,8 +168,9 @@ fn test_confirm_user_email() {
         let u = u
             .create_or_update(Some(email), &app.as_inner().emails, conn)
             .unwrap();
-        MockCookieUser::new(&app, u)
+        u
     });
+    let user = MockCookieUser::new(&app, user);
     let user_model = user.as_model();
 
     let email_token: String = app.db(|conn| {
@@ -209,8 +215,9 @@ fn test_existing_user_email() {
             .set(emails::token_generated_at.eq(None::<NaiveDateTime>))
             .execute(conn)
             .unwrap();
-        MockCookieUser::new(&app, u)
+        u
     });
+    let user = MockCookieUser::new(&app, user);
 
     let json = user.show_me();
     assert_eq!(json.user.email.unwrap(), "potahto@example.com");
@@ -24,7 +24,7 @@ use crate::{
     GoodCrate, OkBool, OwnersResponse, VersionResponse,
 };
 use crates_io::middleware::session;
-use crates_io::models::{ApiToken, CreatedApiToken, User};
+use crates_io::models::{ApiToken, CreatedApiToken, Session, User};
 
 use http::{Method, Request};
 
@@ -57,18 +57,28 @@ pub use test_app::{TestApp, TestDatabase};
 /// include cookie-based authentication.
 ///
 /// ```
-/// let cookie = encode_session_header(session_key, user_id);
+/// let cookie = encode_session_header(session_key, session_token);
 /// request.header(header::COOKIE, &cookie);
 /// ```
 ///
 /// The implementation matches roughly what is happening inside of our
 /// session middleware.
-pub fn encode_session_header(session_key: &cookie::Key, user_id: i32) -> String {
+pub fn encode_session_header(session_key: &cookie::Key, session_token: &str) -> String {
+    encode_session_data(session_key, "session_id", session_token)
+}
+
+/// Like `encode_session_header`, but for cookies from before sessions were stored server-side,
+/// which only contain the `user_id`.
+pub fn encode_legacy_session_header(session_key: &cookie::Key, user_id: i32) -> String {
+    encode_session_data(session_key, "user_id", &user_id.to_string())
+}
+
+fn encode_session_data(session_key: &cookie::Key, key: &str, value: &str) -> String {
     let cookie_name = "cargo_session";
 
     // build session data map
     let mut map = HashMap::new();
-    map.insert("user_id".into(), user_id.to_string());
+    map.insert(key.into(), value.into());
 
     // encode the map into a cookie value string
     let encoded = session::encode(&map);
@@ -136,6 +146,14 @@ pub trait RequestHelper {
         self.run(request)
     }
 
+    /// Issue a POST request
+    #[track_caller]
+    fn post<T>(&self, path: &str, body: impl Into<Bytes>) -> Response<T> {
+        let mut request = self.post_request(path);
+        *request.body_mut() = body.into();
+        self.run(request)
+    }
+
     /// Issue a PUT request
     #[track_caller]
     fn put<T>(&self, path: &str, body: impl Into<Bytes>) -> Response<T> {
@@ -243,12 +261,13 @@ impl RequestHelper for MockAnonymousUser {
 pub struct MockCookieUser {
     app: TestApp,
     user: User,
+    session_token: String,
 }
 
 impl RequestHelper for MockCookieUser {
     fn request_builder(&self, method: Method, path: &str) -> MockRequest {
         let session_key = &self.app.as_inner().session_key();
-        let cookie = encode_session_header(session_key, self.user.id);
+        let cookie = encode_session_header(session_key, &self.session_token);
 
         let mut request = req(method, path);
         request.header(header::COOKIE, &cookie);
@@ -262,10 +281,15 @@ impl RequestHelper for MockCookieUser {
 
 impl MockCookieUser {
     /// Creates an instance from a database `User` instance
+    ///
+    /// This method logs the user in by creating a new session in the database
     pub fn new(app: &TestApp, user: User) -> Self {
+        let session = app.db(|conn| Session::insert(conn, user.id, None, "").unwrap());
+
         Self {
             app: app.clone(),
             user,
+            session_token: session.plaintext.expose_secret().clone(),
         }
     }
 
@@ -299,6 +323,7 @@ impl MockCookieUser {
                 crate_scopes,
                 endpoint_scopes,
                 expired_at,
+                None,
             )
             .unwrap()
         });
@@ -0,0 +1,161 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{RequestHelper, TestApp};
+use chrono::{NaiveDate, Utc};
+use crates_io::background_jobs::Job;
+use crates_io::models::{CrateVersions, Version};
+use crates_io::schema::{version_downloads, version_downloads_monthly};
+use diesel::prelude::*;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::io::Read;
+
+fn date(month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2020, month, day).unwrap()
+}
+
+fn read_archive(app: &TestApp, path: &str) -> String {
+    let store = app.as_inner().storage.as_inner();
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let bytes = rt
+        .block_on(async { store.get(&path.into()).await?.bytes().await })
+        .unwrap();
+
+    let mut content = String::new();
+    GzDecoder::new(&*bytes)
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+#[test]
+fn archive_version_downloads() {
+    let (app, anon, user) = TestApp::full().with_user();
+    let user = user.as_model();
+    let today = Utc::now().date_naive();
+
+    let version_id = app.db(|conn| {
+        let krate = CrateBuilder::new("foo_archive", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+        let version: Version = krate.all_versions().first(conn).unwrap();
+
+        // (date, downloads, counted, processed)
+        let rows = [
+            (date(1, 5), 1, 1, true),
+            (date(1, 20), 2, 2, true),
+            (date(2, 3), 4, 4, true),
+            // Not counted by `update_downloads` yet, so this must not be archived.
+            (date(2, 10), 8, 0, false),
+            (today, 16, 0, false),
+        ];
+        for (date, downloads, counted, processed) in rows {
+            diesel::insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version.id),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(downloads),
+                    version_downloads::counted.eq(counted),
+                    version_downloads::processed.eq(processed),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        version.id
+    });
+
+    app.db(|conn| Job::archive_version_downloads(365).enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let remaining: Vec<(NaiveDate, i32)> = app.db(|conn| {
+        version_downloads::table
+            .select((version_downloads::date, version_downloads::downloads))
+            .order(version_downloads::date)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(remaining, vec![(date(2, 10), 8), (today, 16)]);
+
+    let monthly: Vec<(i32, NaiveDate, i32)> = app.db(|conn| {
+        version_downloads_monthly::table
+            .select((
+                version_downloads_monthly::version_id,
+                version_downloads_monthly::month,
+                version_downloads_monthly::downloads,
+            ))
+            .order(version_downloads_monthly::month)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(
+        monthly,
+        vec![(version_id, date(1, 1), 3), (version_id, date(2, 1), 4)]
+    );
+
+    let path = format!("archive/version-downloads/2020-01/2020-01-05/{today}-{version_id}.csv.gz");
+    assert_eq!(
+        read_archive(&app, &path),
+        format!("version_id,date,downloads\n{version_id},2020-01-05,1\n")
+    );
+    let path = format!("archive/version-downloads/2020-01/2020-01-20/{today}-{version_id}.csv.gz");
+    assert_eq!(
+        read_archive(&app, &path),
+        format!("version_id,date,downloads\n{version_id},2020-01-20,2\n")
+    );
+
+    // The download endpoints include the archived downloads
+    let url = "/api/v1/crates/foo_archive/1.0.0/downloads";
+    let json = anon
+        .get_with_query::<Value>(url, "before_date=2020-02-29")
+        .good();
+    assert_eq!(
+        json["version_downloads"],
+        json!([
+            { "version": version_id, "downloads": 3, "date": "2020-01-01", "interval": "month" },
+            { "version": version_id, "downloads": 4, "date": "2020-02-01", "interval": "month" },
+            { "version": version_id, "downloads": 8, "date": "2020-02-10", "interval": "day" },
+        ])
+    );
+
+    // The archived month the 90 day range starts in is included as a whole
+    let json = anon
+        .get_with_query::<Value>(url, "before_date=2020-04-15")
+        .good();
+    assert_eq!(
+        json["version_downloads"],
+        json!([
+            { "version": version_id, "downloads": 3, "date": "2020-01-01", "interval": "month" },
+            { "version": version_id, "downloads": 4, "date": "2020-02-01", "interval": "month" },
+            { "version": version_id, "downloads": 8, "date": "2020-02-10", "interval": "day" },
+        ])
+    );
+
+    let url = "/api/v1/crates/foo_archive/downloads";
+    let query = "start_date=2020-01-10&end_date=2020-02-29&interval=month";
+    let json = anon.get_with_query::<Value>(url, query).good();
+    assert_eq!(
+        json["downloads"],
+        json!([
+            { "date": "2020-01-01", "series": "1.0.0", "downloads": 3 },
+            { "date": "2020-02-01", "series": "1.0.0", "downloads": 12 },
+        ])
+    );
+
+    // Running the job again does not change anything
+    app.db(|conn| Job::archive_version_downloads(365).enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let count: i64 = app.db(|conn| {
+        version_downloads_monthly::table
+            .select(diesel::dsl::sum(version_downloads_monthly::downloads))
+            .get_result::<Option<i64>>(conn)
+            .unwrap()
+            .unwrap()
+    });
+    assert_eq!(count, 7);
+}
@@ -0,0 +1,72 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{RequestHelper, TestApp};
+use crates_io::background_jobs::Job;
+use crates_io::schema::versions;
+use diesel::prelude::*;
+
+type LicenseColumns = (Option<String>, Option<Vec<String>>, Option<Vec<String>>);
+
+#[test]
+fn backfill_version_licenses() {
+    let (app, anon, user) = TestApp::full().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_dual", user.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("MIT/Apache-2.0")))
+            .expect_build(conn);
+        CrateBuilder::new("foo_custom", user.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("non-standard")))
+            .expect_build(conn);
+        CrateBuilder::new("foo_none", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+
+        // Simulate versions that were published before the licenses were parsed on publish.
+        diesel::update(versions::table)
+            .set((
+                versions::license_ids.eq(None::<Vec<String>>),
+                versions::license_alternatives.eq(None::<Vec<String>>),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|krate| krate.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("license=MIT"), Vec::<String>::new());
+
+    app.db(|conn| Job::backfill_version_licenses().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    assert_eq!(names("license=MIT"), vec!["foo_dual"]);
+
+    let licenses: Vec<LicenseColumns> = app.db(|conn| {
+        versions::table
+            .select((
+                versions::license,
+                versions::license_ids,
+                versions::license_alternatives,
+            ))
+            .order(versions::license)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(
+        licenses,
+        vec![
+            (
+                Some("MIT/Apache-2.0".into()),
+                Some(vec!["Apache-2.0".into(), "MIT".into()]),
+                Some(vec!["Apache-2.0".into(), "MIT".into()]),
+            ),
+            (Some("non-standard".into()), Some(vec![]), Some(vec![])),
+            (None, None, None),
+        ]
+    );
+}
@@ -0,0 +1,72 @@
+use crate::builders::{CrateBuilder, PublishBuilder};
+use crate::util::{RequestHelper, TestApp};
+use crates_io::background_jobs::Job;
+use crates_io::schema::versions;
+use diesel::prelude::*;
+
+#[test]
+fn backfill_version_tarball_info() {
+    let (app, anon, user, token) = TestApp::full().with_token();
+    let user = user.as_model();
+
+    let crate_to_publish = PublishBuilder::new("foo_build", "1.0.0")
+        .add_file("foo_build-1.0.0/build.rs", "fn main() {}");
+    token.publish_crate(crate_to_publish).good();
+
+    let crate_to_publish = PublishBuilder::new("foo_macro", "1.0.0").custom_manifest(
+        "[package]\nname = \"foo_macro\"\nversion = \"1.0.0\"\nlicense = \"MIT\"\ndescription = \"description\"\n\n[lib]\nproc-macro = true\n",
+    );
+    token.publish_crate(crate_to_publish).good();
+
+    token
+        .publish_crate(PublishBuilder::new("foo_plain", "1.0.0"))
+        .good();
+
+    app.db(|conn| {
+        // This version has no crate file in the storage.
+        CrateBuilder::new("foo_missing", user.id).expect_build(conn);
+
+        // Simulate versions that were published before the flags were stored.
+        diesel::update(versions::table)
+            .set((
+                versions::has_build_script.eq(None::<bool>),
+                versions::has_proc_macro.eq(None::<bool>),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|krate| krate.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("build_script=no"), Vec::<String>::new());
+
+    app.db(|conn| Job::backfill_version_tarball_info(0).enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    assert_eq!(names("build_script=yes"), vec!["foo_build"]);
+    assert_eq!(names("build_script=no"), vec!["foo_macro", "foo_plain"]);
+    assert_eq!(names("proc_macro=yes"), vec!["foo_macro"]);
+    assert_eq!(names("proc_macro=no"), vec!["foo_build", "foo_plain"]);
+
+    let flags: Vec<(Option<bool>, Option<bool>)> = app.db(|conn| {
+        versions::table
+            .select((versions::has_build_script, versions::has_proc_macro))
+            .order(versions::id)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(
+        flags,
+        vec![
+            (Some(true), Some(false)),
+            (Some(false), Some(true)),
+            (Some(false), Some(false)),
+            (None, None),
+        ]
+    );
+}
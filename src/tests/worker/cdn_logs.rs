@@ -0,0 +1,261 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::TestApp;
+use crates_io::background_jobs::Job;
+use crates_io::schema::{crates, processed_log_files, version_downloads, versions};
+use diesel::prelude::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::body::Bytes;
+use std::io::Write;
+use std::path::Path;
+
+const FIXTURES_PATH: &str = "src/worker/cdn_logs/fixtures";
+
+fn read_fixture(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(FIXTURES_PATH)
+        .join(name);
+    std::fs::read(path).unwrap()
+}
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn upload_log_file(app: &TestApp, path: &str, content: Vec<u8>) {
+    let store = app.as_inner().storage.as_inner();
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt.block_on(store.put(&path.into(), Bytes::from(content)))
+        .unwrap();
+}
+
+fn downloads(app: &TestApp) -> Vec<(String, String, String, i32)> {
+    app.db(|conn| {
+        version_downloads::table
+            .inner_join(versions::table.inner_join(crates::table))
+            .select((
+                crates::name,
+                versions::num,
+                version_downloads::date,
+                version_downloads::downloads,
+            ))
+            .order((crates::name, versions::num, version_downloads::date))
+            .load::<(String, String, chrono::NaiveDate, i32)>(conn)
+            .unwrap()
+            .into_iter()
+            .map(|(name, num, date, downloads)| (name, num, date.to_string(), downloads))
+            .collect()
+    })
+}
+
+#[test]
+fn process_cdn_logs() {
+    let (app, _, user) = TestApp::full()
+        .with_config(|config| {
+            config.cdn_log_downloads = true;
+        })
+        .with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("serde", user.id)
+            .version(VersionBuilder::new("1.0.188"))
+            .expect_build(conn);
+        CrateBuilder::new("foo", user.id)
+            .version(VersionBuilder::new("1.0.0-beta.1+build"))
+            .expect_build(conn);
+    });
+
+    upload_log_file(
+        &app,
+        "cdn-logs/cloudfront/E35K556QRQDZXW.2023-10-01-00.a1b2c3d4.gz",
+        gzip(&read_fixture("cloudfront.log")),
+    );
+    upload_log_file(
+        &app,
+        "cdn-logs/fastly/2023-10-01T00:00:00.000-abc.log",
+        read_fixture("fastly.log"),
+    );
+
+    app.db(|conn| Job::process_cdn_logs().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    // Downloads of the unknown `tokio` crate are discarded.
+    let expected = vec![
+        (
+            "foo".into(),
+            "1.0.0-beta.1+build".into(),
+            "2023-10-02".into(),
+            2,
+        ),
+        ("serde".into(), "1.0.188".into(), "2023-10-01".into(), 3),
+    ];
+    assert_eq!(downloads(&app), expected);
+
+    let processed: Vec<(String, i32)> = app.db(|conn| {
+        processed_log_files::table
+            .select((processed_log_files::path, processed_log_files::downloads))
+            .order(processed_log_files::path)
+            .load(conn)
+            .unwrap()
+    });
+    let expected_processed = vec![
+        (
+            "cdn-logs/cloudfront/E35K556QRQDZXW.2023-10-01-00.a1b2c3d4.gz".into(),
+            3,
+        ),
+        ("cdn-logs/fastly/2023-10-01T00:00:00.000-abc.log".into(), 2),
+    ];
+    assert_eq!(processed, expected_processed);
+
+    // Log files are only processed once
+    app.db(|conn| Job::process_cdn_logs().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+    assert_eq!(downloads(&app), expected);
+}
+
+#[test]
+fn process_cdn_logs_resets_processed_downloads() {
+    let (app, _, user) = TestApp::full()
+        .with_config(|config| {
+            config.cdn_log_downloads = true;
+        })
+        .with_user();
+    let user = user.as_model();
+
+    let version_id = app.db(|conn| {
+        let krate = CrateBuilder::new("serde", user.id)
+            .version(VersionBuilder::new("1.0.188"))
+            .expect_build(conn);
+
+        let version_id = versions::table
+            .filter(versions::crate_id.eq(krate.id))
+            .select(versions::id)
+            .first::<i32>(conn)
+            .unwrap();
+
+        // The downloads of the day were already added to the crate and version totals.
+        let date = chrono::NaiveDate::from_ymd_opt(2023, 10, 1).unwrap();
+        diesel::insert_into(version_downloads::table)
+            .values((
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(date),
+                version_downloads::downloads.eq(5),
+                version_downloads::processed.eq(true),
+            ))
+            .execute(conn)
+            .unwrap();
+
+        version_id
+    });
+
+    upload_log_file(
+        &app,
+        "cdn-logs/cloudfront/E35K556QRQDZXW.2023-10-01-00.a1b2c3d4.gz",
+        gzip(&read_fixture("cloudfront.log")),
+    );
+
+    app.db(|conn| Job::process_cdn_logs().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let (downloads, processed): (i32, bool) = app.db(|conn| {
+        version_downloads::table
+            .filter(version_downloads::version_id.eq(version_id))
+            .select((version_downloads::downloads, version_downloads::processed))
+            .first(conn)
+            .unwrap()
+    });
+    assert_eq!(downloads, 7);
+    assert!(!processed);
+}
+
+#[test]
+fn process_cdn_logs_requires_cdn_log_downloads() {
+    let (app, _, user) = TestApp::full().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("serde", user.as_model().id)
+            .version(VersionBuilder::new("1.0.188"))
+            .expect_build(conn);
+    });
+
+    upload_log_file(
+        &app,
+        "cdn-logs/cloudfront/E35K556QRQDZXW.2023-10-01-00.a1b2c3d4.gz",
+        gzip(&read_fixture("cloudfront.log")),
+    );
+
+    // The API counts the redirected downloads itself, which must not be counted twice
+    app.db(|conn| Job::process_cdn_logs().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+    assert_eq!(downloads(&app), vec![]);
+}
+
+#[test]
+fn process_cdn_logs_skips_files_before_cursor() {
+    let (app, _, user) = TestApp::full()
+        .with_config(|config| {
+            config.cdn_log_downloads = true;
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0-beta.1+build"))
+            .expect_build(conn);
+
+        // A log file that was processed two days ago moves the cursor past all older files
+        let processed_at = (chrono::Utc::now() - chrono::Duration::days(2)).naive_utc();
+        diesel::insert_into(processed_log_files::table)
+            .values((
+                processed_log_files::path.eq("cdn-logs/fastly/2023-10-02T00:00:00.000-abc.log"),
+                processed_log_files::processed_at.eq(processed_at),
+                processed_log_files::downloads.eq(0),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    upload_log_file(
+        &app,
+        "cdn-logs/fastly/2023-10-01T00:00:00.000-abc.log",
+        read_fixture("fastly.log"),
+    );
+    upload_log_file(
+        &app,
+        "cdn-logs/fastly/2023-10-03T00:00:00.000-abc.log",
+        read_fixture("fastly.log"),
+    );
+
+    app.db(|conn| Job::process_cdn_logs().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let processed: Vec<String> = app.db(|conn| {
+        processed_log_files::table
+            .select(processed_log_files::path)
+            .order(processed_log_files::path)
+            .load(conn)
+            .unwrap()
+    });
+    let expected_processed = vec![
+        "cdn-logs/fastly/2023-10-02T00:00:00.000-abc.log".to_string(),
+        "cdn-logs/fastly/2023-10-03T00:00:00.000-abc.log".to_string(),
+    ];
+    assert_eq!(processed, expected_processed);
+
+    // Only the downloads of the file after the cursor are counted
+    let expected = vec![(
+        "foo".into(),
+        "1.0.0-beta.1+build".into(),
+        "2023-10-02".into(),
+        1,
+    )];
+    assert_eq!(downloads(&app), expected);
+}
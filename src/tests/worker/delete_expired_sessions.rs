@@ -0,0 +1,38 @@
+use crate::util::{MockCookieUser, TestApp};
+use crates_io::background_jobs::Job;
+use crates_io::schema::sessions;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+
+#[test]
+fn delete_expired_sessions() {
+    let (app, _, user) = TestApp::full().with_user();
+    MockCookieUser::new(&app, user.as_model().clone());
+
+    let active_id = app.db(|conn| {
+        let ids: Vec<i32> = sessions::table
+            .select(sessions::id)
+            .order(sessions::id)
+            .load(conn)
+            .unwrap();
+
+        diesel::update(sessions::table.find(ids[1]))
+            .set(sessions::created_at.eq(sql("now() - interval '91 days'")))
+            .execute(conn)
+            .unwrap();
+
+        ids[0]
+    });
+
+    app.db(|conn| Job::delete_expired_sessions().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let remaining: Vec<i32> = app.db(|conn| {
+        sessions::table
+            .select(sessions::id)
+            .order(sessions::id)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(remaining, vec![active_id]);
+}
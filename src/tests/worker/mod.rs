@@ -1 +1,13 @@
+mod archive_version_downloads;
+mod backfill_version_licenses;
+mod backfill_version_tarball_info;
+mod cdn_logs;
+mod delete_expired_sessions;
 mod git;
+mod send_email;
+mod token_expiry_notifications;
+mod update_crate_scores;
+mod update_related_crates;
+mod update_trending_crates;
+mod version_action_notifications;
+mod weekly_digests;
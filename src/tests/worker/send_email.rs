@@ -0,0 +1,83 @@
+use crate::util::TestApp;
+use crates_io::background_jobs::Job;
+use crates_io::models::{OutboxEmail, OutboxEmailStatus};
+use crates_io::schema::email_outbox;
+use diesel::prelude::*;
+
+fn queue_email(app: &TestApp) -> i64 {
+    app.db(|conn| {
+        app.as_inner()
+            .emails
+            .send_token_created_notification(conn, 1, "foo@example.com", "foo", "<bar>")
+            .unwrap();
+
+        email_outbox::table
+            .select(email_outbox::id)
+            .order(email_outbox::id.desc())
+            .first(conn)
+            .unwrap()
+    })
+}
+
+#[test]
+fn queued_emails_are_sent_and_recorded_in_the_outbox() {
+    let (app, _) = TestApp::full().empty();
+
+    let email_id = queue_email(&app);
+    let email = app.db(|conn| OutboxEmail::find(conn, email_id).unwrap());
+    assert_eq!(email.status, OutboxEmailStatus::Pending);
+    assert_eq!(email.attempts, 0);
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, "foo@example.com");
+    assert_eq!(mails[0].subject, "A new API token was created");
+    assert!(mails[0].body.contains("\"<bar>\""));
+    assert!(mails[0].body_html.contains("<strong>&lt;bar&gt;</strong>"));
+
+    let email = app.db(|conn| OutboxEmail::find(conn, email_id).unwrap());
+    assert_eq!(email.status, OutboxEmailStatus::Sent);
+    assert_eq!(email.attempts, 1);
+    assert_eq!(email.last_error, None);
+    assert!(email.message_id.is_some());
+    assert!(email.sent_at.is_some());
+
+    // The contents can contain secrets, so they are not kept after sending the email
+    assert_eq!(email.body_text, "");
+    assert_eq!(email.body_html, "");
+}
+
+#[test]
+fn sent_emails_are_not_sent_again() {
+    let (app, _) = TestApp::full().empty();
+
+    let email_id = queue_email(&app);
+    app.run_pending_background_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+
+    app.db(|conn| Job::send_email(email_id).enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+}
+
+#[test]
+fn invalid_addresses_are_not_queued() {
+    let (app, _) = TestApp::full().empty();
+
+    app.db(|conn| {
+        let result = app.as_inner().emails.send_token_created_notification(
+            conn,
+            1,
+            "String.Format(\"{0}.{1}@live.com\", FirstName, LastName)",
+            "foo",
+            "bar",
+        );
+        assert_err!(result);
+
+        let count: i64 = email_outbox::table.count().get_result(conn).unwrap();
+        assert_eq!(count, 0);
+    });
+}
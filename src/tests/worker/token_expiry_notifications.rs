@@ -0,0 +1,98 @@
+use crate::util::TestApp;
+use chrono::{Duration, NaiveDateTime, Utc};
+use crates_io::background_jobs::Job;
+use crates_io::models::{ApiToken, NotificationType};
+use crates_io::schema::api_tokens;
+use diesel::prelude::*;
+
+fn in_hours(hours: i64) -> NaiveDateTime {
+    (Utc::now() + Duration::hours(hours)).naive_utc()
+}
+
+fn send_notifications(app: &TestApp) -> Vec<String> {
+    let mails_before = app.as_inner().emails.mails_in_memory().unwrap().len();
+
+    app.db(|conn| {
+        Job::send_token_expiry_notifications()
+            .enqueue(conn)
+            .unwrap();
+    });
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    mails[mails_before..]
+        .iter()
+        .map(|mail| {
+            assert_eq!(mail.subject, "Your API token expires soon");
+            mail.body.clone()
+        })
+        .collect()
+}
+
+#[test]
+fn send_token_expiry_notifications() {
+    let (app, _, user) = TestApp::full().with_user();
+    let user_id = user.as_model().id;
+
+    let new_token = |name: &str, expired_at: Option<NaiveDateTime>| {
+        app.db(|conn| {
+            ApiToken::insert_with_scopes(conn, user_id, name, None, None, expired_at, None)
+                .unwrap()
+                .model
+        })
+    };
+
+    let in_three_days = new_token("in-three-days", Some(in_hours(3 * 24)));
+    new_token("in-twelve-hours", Some(in_hours(12)));
+    new_token("in-a-month", Some(in_hours(30 * 24)));
+    new_token("expired", Some(in_hours(-1)));
+    new_token("never", None);
+    let revoked = new_token("revoked", Some(in_hours(12)));
+    app.db(|conn| {
+        diesel::update(api_tokens::table.find(revoked.id))
+            .set(api_tokens::revoked.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let mut mails = send_notifications(&app);
+    mails.sort();
+    assert_eq!(mails.len(), 2);
+    assert!(mails[0].contains("\"in-three-days\""));
+    assert!(mails[1].contains("\"in-twelve-hours\""));
+
+    // Running the job again on the same day doesn't send duplicates
+    assert_eq!(send_notifications(&app).len(), 0);
+
+    // Six days later, the token expiring in three days gets its second reminder
+    app.db(|conn| {
+        diesel::update(api_tokens::table.find(in_three_days.id))
+            .set((
+                api_tokens::expired_at.eq(in_hours(12)),
+                api_tokens::expiry_notification_at.eq(in_hours(-6 * 24)),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let mails = send_notifications(&app);
+    assert_eq!(mails.len(), 1);
+    assert!(mails[0].contains("\"in-three-days\""));
+    assert_eq!(send_notifications(&app).len(), 0);
+}
+
+#[test]
+fn send_token_expiry_notifications_after_opt_out() {
+    let (app, _, user) = TestApp::full().with_user();
+    let user_id = user.as_model().id;
+
+    app.db(|conn| {
+        ApiToken::insert_with_scopes(conn, user_id, "bar", None, None, Some(in_hours(12)), None)
+            .unwrap();
+        NotificationType::TokenExpiry
+            .set_enabled_for(conn, user_id, false)
+            .unwrap();
+    });
+
+    assert_eq!(send_notifications(&app).len(), 0);
+}
@@ -0,0 +1,92 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{RequestHelper, TestApp};
+use chrono::{Duration, Utc};
+use crates_io::background_jobs::Job;
+use crates_io::schema::versions;
+use diesel::prelude::*;
+use serde_json::Value;
+
+#[test]
+fn update_crate_scores() {
+    let (app, anon, user) = TestApp::full().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let core_lib = CrateBuilder::new("core_lib", user.id)
+            .recent_downloads(1000)
+            .expect_build(conn);
+
+        // Lots of downloads, e.g. from CI, but nobody depends on it
+        CrateBuilder::new("ci_heavy", user.id)
+            .recent_downloads(5000)
+            .expect_build(conn);
+
+        // The same downloads, but the last release was two years ago
+        let stale = CrateBuilder::new("stale", user.id)
+            .recent_downloads(5000)
+            .expect_build(conn);
+        diesel::update(versions::table.filter(versions::crate_id.eq(stale.id)))
+            .set(versions::created_at.eq(Utc::now().naive_utc() - Duration::days(730)))
+            .execute(conn)
+            .unwrap();
+
+        for name in ["dependent_a", "dependent_b", "dependent_c"] {
+            CrateBuilder::new(name, user.id)
+                .version(VersionBuilder::new("1.0.0").dependency(&core_lib, None))
+                .expect_build(conn);
+        }
+    });
+
+    let names = |json: &Value| {
+        json["crates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|krate| krate["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Crates without a score are sorted last, by name
+    let json: Value = anon.get_with_query("/api/v1/crates", "sort=score").good();
+    assert_eq!(json["crates"][0]["score"], Value::Null);
+    assert_eq!(
+        names(&json),
+        vec![
+            "ci_heavy",
+            "core_lib",
+            "dependent_a",
+            "dependent_b",
+            "dependent_c",
+            "stale"
+        ]
+    );
+
+    app.db(|conn| Job::update_crate_scores().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let json: Value = anon.get_with_query("/api/v1/crates", "sort=score").good();
+    assert_eq!(
+        names(&json),
+        vec![
+            "core_lib",
+            "ci_heavy",
+            "stale",
+            "dependent_a",
+            "dependent_b",
+            "dependent_c"
+        ]
+    );
+
+    let scores = json["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|krate| krate["score"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+
+    // ln(1 + 1000) + 2 * ln(1 + 3) + 0.5 * ln(1 + 1)
+    let json: Value = anon.get("/api/v1/crates/core_lib").good();
+    let score = json["crate"]["score"].as_f64().unwrap();
+    assert!((score - 10.03).abs() < 0.01, "unexpected score: {score}");
+}
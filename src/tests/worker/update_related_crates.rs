@@ -0,0 +1,89 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::new_category;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::background_jobs::Job;
+use serde_json::Value;
+
+#[test]
+fn update_related_crates() {
+    let (app, anon, user) = TestApp::full().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let serde = CrateBuilder::new("serde", user.id).expect_build(conn);
+        let serde_json = CrateBuilder::new("serde_json", user.id).expect_build(conn);
+        let rand = CrateBuilder::new("rand", user.id).expect_build(conn);
+
+        CrateBuilder::new("app_a", user.id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .dependency(&serde, None)
+                    .dependency(&serde_json, None)
+                    .dependency(&rand, None),
+            )
+            .expect_build(conn);
+        for name in ["app_b", "app_c"] {
+            CrateBuilder::new(name, user.id)
+                .version(
+                    VersionBuilder::new("1.0.0")
+                        .dependency(&serde, None)
+                        .dependency(&serde_json, None),
+                )
+                .expect_build(conn);
+        }
+
+        new_category("Asynchronous", "asynchronous", "Async crates")
+            .create_or_update(conn)
+            .unwrap();
+
+        let tokio = CrateBuilder::new("tokio", user.id)
+            .keyword("async")
+            .keyword("runtime")
+            .category("asynchronous")
+            .expect_build(conn);
+        CrateBuilder::new("async-std", user.id)
+            .keyword("async")
+            .keyword("runtime")
+            .category("asynchronous")
+            .expect_build(conn);
+
+        // Only a single keyword in common
+        CrateBuilder::new("smol", user.id)
+            .keyword("async")
+            .expect_build(conn);
+
+        // Extensions depend on the crate and are not alternatives to it
+        CrateBuilder::new("tokio-util", user.id)
+            .keyword("async")
+            .keyword("runtime")
+            .version(VersionBuilder::new("1.0.0").dependency(&tokio, None))
+            .expect_build(conn);
+    });
+
+    let names = |json: &Value, kind: &str| {
+        json[kind]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|krate| krate["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let json: Value = anon.get("/api/v1/crates/serde/related").good();
+    assert_eq!(json, json!({ "dependencies": [], "alternatives": [] }));
+
+    app.db(|conn| Job::update_related_crates().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let json: Value = anon.get("/api/v1/crates/serde/related").good();
+    assert_eq!(names(&json, "dependencies"), vec!["serde_json"]);
+    assert_eq!(json["dependencies"][0]["score"], 1.0);
+    assert_eq!(names(&json, "alternatives"), Vec::<String>::new());
+
+    let json: Value = anon.get("/api/v1/crates/tokio/related").good();
+    assert_eq!(names(&json, "dependencies"), Vec::<String>::new());
+    assert_eq!(names(&json, "alternatives"), vec!["async-std"]);
+
+    anon.get::<()>("/api/v1/crates/unknown/related")
+        .assert_not_found();
+}
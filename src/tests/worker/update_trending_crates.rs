@@ -0,0 +1,99 @@
+use crate::builders::CrateBuilder;
+use crate::util::{RequestHelper, TestApp};
+use chrono::{Duration, Utc};
+use crates_io::background_jobs::Job;
+use crates_io::models::Crate;
+use crates_io::schema::{version_downloads, versions};
+use diesel::prelude::*;
+use serde_json::Value;
+
+/// Inserts the daily downloads of the last 14 days, starting with the oldest day.
+fn insert_daily_downloads(conn: &mut PgConnection, krate: &Crate, downloads: [i32; 14]) {
+    let version_id: i32 = versions::table
+        .filter(versions::crate_id.eq(krate.id))
+        .select(versions::id)
+        .first(conn)
+        .unwrap();
+
+    let today = Utc::now().date_naive();
+    let rows = downloads
+        .iter()
+        .zip((1..=14).rev())
+        .map(|(&downloads, days_ago)| {
+            (
+                version_downloads::version_id.eq(version_id),
+                version_downloads::downloads.eq(downloads),
+                version_downloads::date.eq(today - Duration::days(days_ago)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(version_downloads::table)
+        .values(rows)
+        .execute(conn)
+        .unwrap();
+}
+
+#[test]
+fn update_trending_crates() {
+    let (app, anon, user) = TestApp::full().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let steady = CrateBuilder::new("steady", user.id).expect_build(conn);
+        insert_daily_downloads(conn, &steady, [1000; 14]);
+
+        let rising = CrateBuilder::new("rising", user.id).expect_build(conn);
+        let mut downloads = [100; 14];
+        downloads[7..].fill(300);
+        insert_daily_downloads(conn, &rising, downloads);
+
+        // A bot downloading the crate on a single day only counts up to five times the median
+        let inflated = CrateBuilder::new("inflated", user.id).expect_build(conn);
+        let mut downloads = [100; 14];
+        downloads[13] = 100_000;
+        insert_daily_downloads(conn, &inflated, downloads);
+
+        // New crates are compared to a baseline, instead of growing infinitely
+        let new = CrateBuilder::new("new", user.id).expect_build(conn);
+        let mut downloads = [0; 14];
+        downloads[7..].fill(20);
+        insert_daily_downloads(conn, &new, downloads);
+
+        // Not enough downloads in the last week
+        let tiny = CrateBuilder::new("tiny", user.id).expect_build(conn);
+        let mut downloads = [0; 14];
+        downloads[7..].fill(10);
+        insert_daily_downloads(conn, &tiny, downloads);
+    });
+
+    let names = |json: &Value| {
+        json["trending"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|krate| krate["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let json: Value = anon.get("/api/v1/summary").good();
+    assert_eq!(names(&json), Vec::<String>::new());
+
+    app.db(|conn| Job::update_trending_crates().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let json: Value = anon.get("/api/v1/summary").good();
+    assert_eq!(names(&json), vec!["rising", "inflated", "new"]);
+
+    // The trending crates are replaced on every run
+    app.db(|conn| {
+        diesel::delete(version_downloads::table)
+            .execute(conn)
+            .unwrap();
+        Job::update_trending_crates().enqueue(conn).unwrap();
+    });
+    app.run_pending_background_jobs();
+
+    let json: Value = anon.get("/api/v1/summary").good();
+    assert_eq!(names(&json), Vec::<String>::new());
+}
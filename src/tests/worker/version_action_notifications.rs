@@ -0,0 +1,88 @@
+use crate::builders::PublishBuilder;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::models::{CrateOwner, OwnerKind};
+use crates_io::schema::{crate_owners, crates, emails};
+use diesel::prelude::*;
+
+#[test]
+fn version_actions_notify_owners() {
+    let (app, _, user, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(mails.len(), 1);
+    assert!(mails[0].body.starts_with("Hello foo!"));
+    assert_eq!(mails[0].subject, "crates.io: foo v1.0.0 was published");
+    assert!(mails[0].body.contains("was published by foo."));
+    assert!(mails[0].body.contains(&format!(
+        "Authenticated with: the API token \"{}\"",
+        token.as_model().name
+    )));
+    assert!(mails[0].body.contains("IP address: 127.0.0.1"));
+
+    // Add two co-owners, one of them without email notifications
+    let subscribed = app.db_new_user("subscribed");
+    let unsubscribed = app.db_new_user("unsubscribed");
+    app.db(|conn| {
+        let crate_id = crates::table
+            .select(crates::id)
+            .filter(crates::name.eq("foo"))
+            .first(conn)
+            .unwrap();
+
+        for (owner, email_notifications) in [(&subscribed, true), (&unsubscribed, false)] {
+            diesel::insert_into(crate_owners::table)
+                .values(&CrateOwner {
+                    crate_id,
+                    owner_id: owner.as_model().id,
+                    created_by: user.as_model().id,
+                    owner_kind: OwnerKind::User as i32,
+                    email_notifications,
+                })
+                .execute(conn)
+                .unwrap();
+        }
+
+        // The primary address of the subscribed co-owner is not verified, but another one is
+        let subscribed_id = subscribed.as_model().id;
+        diesel::update(emails::table.filter(emails::user_id.eq(subscribed_id)))
+            .set(emails::verified.eq(false))
+            .execute(conn)
+            .unwrap();
+        diesel::insert_into(emails::table)
+            .values((
+                emails::user_id.eq(subscribed_id),
+                emails::email.eq("secondary@example.com"),
+                emails::verified.eq(true),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    user.delete::<serde_json::Value>("/api/v1/crates/foo/1.0.0/yank")
+        .good();
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    let mut recipients = mails[1..]
+        .iter()
+        .map(|mail| {
+            assert_eq!(mail.subject, "crates.io: foo v1.0.0 was yanked");
+            assert!(mail
+                .body
+                .contains("Authenticated with: a browser session on the website"));
+            (mail.body.lines().next().unwrap(), mail.to.as_str())
+        })
+        .collect::<Vec<_>>();
+    recipients.sort();
+    assert_eq!(
+        recipients,
+        [
+            ("Hello foo!", "something@example.com"),
+            ("Hello subscribed!", "secondary@example.com"),
+        ]
+    );
+}
@@ -0,0 +1,171 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{MockCookieUser, TestApp};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use crates_io::background_jobs::Job;
+use crates_io::models::{ApiToken, NotificationType};
+use crates_io::schema::{crate_owner_invitations, follows, version_downloads, versions};
+use diesel::prelude::*;
+
+/// Returns the Monday of the current week.
+fn this_week() -> NaiveDate {
+    let today = Utc::now().date_naive();
+    today - Duration::days(today.weekday().num_days_from_monday().into())
+}
+
+fn days_before_this_week(days: i64) -> NaiveDateTime {
+    (this_week() - Duration::days(days))
+        .and_hms_opt(12, 0, 0)
+        .unwrap()
+}
+
+fn send_digests(app: &TestApp) -> Vec<String> {
+    let mails_before = app.as_inner().emails.mails_in_memory().unwrap().len();
+
+    app.db(|conn| Job::send_weekly_digests().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let mails = app.as_inner().emails.mails_in_memory().unwrap();
+    mails[mails_before..]
+        .iter()
+        .map(|mail| {
+            assert_eq!(mail.subject, "crates.io: Your weekly digest");
+            assert!(mail.list_unsubscribe.is_some());
+            mail.body.clone()
+        })
+        .collect()
+}
+
+fn opt_in(app: &TestApp, user: &MockCookieUser) {
+    app.db(|conn| {
+        NotificationType::WeeklyDigest
+            .set_enabled_for(conn, user.as_model().id, true)
+            .unwrap();
+    });
+}
+
+#[test]
+fn weekly_digests_are_opt_in() {
+    let (app, _, user) = TestApp::full().with_user();
+    app.db(|conn| CrateBuilder::new("foo", user.as_model().id).expect_build(conn));
+
+    assert_eq!(send_digests(&app).len(), 0);
+
+    opt_in(&app, &user);
+    assert_eq!(send_digests(&app).len(), 1);
+}
+
+#[test]
+fn weekly_digest_contents() {
+    let (app, _, user) = TestApp::full().with_user();
+    let other = app.db_new_user("other");
+    let user_id = user.as_model().id;
+    let other_id = other.as_model().id;
+    opt_in(&app, &user);
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo", user_id)
+            .version(VersionBuilder::new("1.0.0").created_at(days_before_this_week(30)))
+            .expect_build(conn);
+
+        let version_id: i32 = versions::table
+            .filter(versions::crate_id.eq(krate.id))
+            .select(versions::id)
+            .first(conn)
+            .unwrap();
+        for (days, downloads) in [(1, 10), (7, 5), (8, 3), (15, 100)] {
+            diesel::insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version_id),
+                    version_downloads::date.eq(this_week() - Duration::days(days)),
+                    version_downloads::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        // A new reverse dependency, which the user also follows
+        let dependent = CrateBuilder::new("bar", other_id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .created_at(days_before_this_week(3))
+                    .dependency(&krate, None),
+            )
+            .expect_build(conn);
+        diesel::insert_into(follows::table)
+            .values((
+                follows::user_id.eq(user_id),
+                follows::crate_id.eq(dependent.id),
+            ))
+            .execute(conn)
+            .unwrap();
+
+        // A reverse dependency that already existed before
+        CrateBuilder::new("baz", other_id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .created_at(days_before_this_week(20))
+                    .dependency(&krate, None),
+            )
+            .version(
+                VersionBuilder::new("1.1.0")
+                    .created_at(days_before_this_week(2))
+                    .dependency(&krate, None),
+            )
+            .expect_build(conn);
+
+        let invited_crate = CrateBuilder::new("qux", other_id).expect_build(conn);
+        diesel::insert_into(crate_owner_invitations::table)
+            .values((
+                crate_owner_invitations::invited_user_id.eq(user_id),
+                crate_owner_invitations::invited_by_user_id.eq(other_id),
+                crate_owner_invitations::crate_id.eq(invited_crate.id),
+            ))
+            .execute(conn)
+            .unwrap();
+
+        let expired_at = (Utc::now() + Duration::days(2)).naive_utc();
+        ApiToken::insert_with_scopes(conn, user_id, "ci", None, None, Some(expired_at), None)
+            .unwrap();
+    });
+
+    let mails = send_digests(&app);
+    assert_eq!(mails.len(), 1);
+    let body = &mails[0];
+    assert!(body.starts_with("Hello foo!"));
+    assert!(body.contains("- foo: 15 (3)"));
+    assert!(body.contains("- bar depends on foo"));
+    assert!(!body.contains("baz depends on foo"));
+    assert!(body.contains("- qux"));
+    assert!(body.contains("- \"ci\" expires on "));
+    assert!(body.contains("- bar v1.0.0"));
+}
+
+#[test]
+fn weekly_digests_are_sent_once_per_week() {
+    let (app, _, user) = TestApp::full().with_user();
+    app.db(|conn| CrateBuilder::new("foo", user.as_model().id).expect_build(conn));
+    opt_in(&app, &user);
+
+    assert_eq!(send_digests(&app).len(), 1);
+    assert_eq!(send_digests(&app).len(), 0);
+
+    // Opting in again does not send another digest in the same week
+    app.db(|conn| {
+        let user_id = user.as_model().id;
+        NotificationType::WeeklyDigest
+            .set_enabled_for(conn, user_id, false)
+            .unwrap();
+        NotificationType::WeeklyDigest
+            .set_enabled_for(conn, user_id, true)
+            .unwrap();
+    });
+    assert_eq!(send_digests(&app).len(), 0);
+}
+
+#[test]
+fn empty_weekly_digests_are_not_sent() {
+    let (app, _, user) = TestApp::full().with_user();
+    opt_in(&app, &user);
+
+    assert_eq!(send_digests(&app).len(), 0);
+}
@@ -0,0 +1,333 @@
+use crate::models::token::NewApiTokenActivity;
+use crate::models::{ApiToken, NotificationType, User};
+use crate::schema::{api_token_activity, api_tokens};
+use crate::util::errors::AppResult;
+use crate::App;
+use anyhow::Error;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Integer};
+use parking_lot::Mutex;
+use std::collections::{BTreeSet, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of log entries that are kept in the database for each API token.
+pub const MAX_ENTRIES_PER_TOKEN: i64 = 1000;
+
+/// The number of log entries that are kept in memory until they are persisted. Further entries
+/// are dropped.
+const MAX_PENDING_ENTRIES: usize = 50_000;
+
+/// PostgreSQL supports at most 65535 bind parameters per query, and each entry uses six of them.
+const INSERT_CHUNK_SIZE: usize = 10_000;
+
+/// Every authenticated use of an API token is recorded in the `api_token_activity` table, so
+/// that the owner of a leaked token can find out what it was used for. Like the
+/// `DownloadsCounter`, this struct collects the entries of the current process in memory instead
+/// of executing a write query during each request, and a background thread periodically writes
+/// them to the database in a single batch.
+///
+/// The IP addresses of the entries are recorded in the `api_token_ip_addresses` table in the same
+/// batch, and the owner of a token is notified when it was used from a new IP address. This
+/// happens on the primary database even if the request itself was served from a read-only
+/// replica.
+///
+/// The log is bounded: only the most recent `MAX_ENTRIES_PER_TOKEN` entries of a token are kept
+/// in the database, and older ones are removed whenever new entries of the token are persisted.
+/// The entries that are not persisted yet are lost if the process exits ungracefully, or if more
+/// than `MAX_PENDING_ENTRIES` of them pile up between two batches.
+#[derive(Debug, Default)]
+pub struct TokenActivityLog {
+    /// Entries that are not persisted in the database yet, in the order they were recorded.
+    pending: Mutex<Vec<NewApiTokenActivity>>,
+    /// Number of entries that were lost because they could not be persisted.
+    lost_count: AtomicUsize,
+}
+
+impl TokenActivityLog {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&self, activity: NewApiTokenActivity) {
+        let mut pending = self.pending.lock();
+        if pending.len() >= MAX_PENDING_ENTRIES {
+            self.lost_count.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+
+        pending.push(activity);
+    }
+
+    /// Writes all pending entries to the database and returns how many were written.
+    pub fn persist(&self, app: &App) -> Result<usize, Error> {
+        let conn = &mut app.db_write()?;
+        let (inserted, new_ip_addresses) = self.persist_with_conn(conn)?;
+
+        for (api_token_id, ip) in new_ip_addresses {
+            if let Err(error) = notify_on_new_ip_address(app, conn, api_token_id, ip) {
+                warn!(%api_token_id, ?error, "Failed to send new IP address notification");
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    /// Writes all pending entries to the database, and returns how many were written together
+    /// with the token/IP address pairs that were not seen before.
+    fn persist_with_conn(&self, conn: &mut PgConnection) -> Result<(usize, NewIpAddresses), Error> {
+        let pending = std::mem::take(&mut *self.pending.lock());
+        if pending.is_empty() {
+            return Ok((0, Vec::new()));
+        }
+
+        let result = conn.transaction(|conn| Self::insert_and_prune(conn, &pending));
+        if result.is_err() {
+            self.lost_count.fetch_add(pending.len(), Ordering::SeqCst);
+        }
+
+        result
+    }
+
+    fn insert_and_prune(
+        conn: &mut PgConnection,
+        pending: &[NewApiTokenActivity],
+    ) -> Result<(usize, NewIpAddresses), Error> {
+        let token_ids = pending
+            .iter()
+            .map(|activity| activity.api_token_id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        // Tokens can be deleted together with their user after they were used, which would
+        // otherwise fail the whole batch because of the foreign key.
+        let existing_token_ids: BTreeSet<i32> = api_tokens::table
+            .select(api_tokens::id)
+            .filter(api_tokens::id.eq_any(&token_ids))
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect();
+
+        let to_insert = pending
+            .iter()
+            .filter(|activity| existing_token_ids.contains(&activity.api_token_id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut inserted = 0;
+        for chunk in to_insert.chunks(INSERT_CHUNK_SIZE) {
+            inserted += diesel::insert_into(api_token_activity::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+
+        // Deduplicated in the order of the requests, so that the first address a token was used
+        // from is not reported as a new one.
+        let mut seen = HashSet::new();
+        let ip_addresses = to_insert
+            .iter()
+            .filter_map(|activity| Some((activity.api_token_id, activity.ip_address?.ip())))
+            .filter(|pair| seen.insert(*pair))
+            .collect::<Vec<_>>();
+
+        let new_ip_addresses = ApiToken::record_ip_addresses(conn, &ip_addresses)?;
+
+        let existing_token_ids = existing_token_ids.into_iter().collect::<Vec<_>>();
+        diesel::sql_query(include_str!("token_activity_prune.sql"))
+            .bind::<Array<Integer>, _>(existing_token_ids)
+            .bind::<BigInt, _>(MAX_ENTRIES_PER_TOKEN)
+            .execute(conn)?;
+
+        Ok((inserted, new_ip_addresses))
+    }
+
+    pub(crate) fn pending_count(&self) -> usize {
+        self.pending.lock().len()
+    }
+
+    pub(crate) fn lost_count(&self) -> usize {
+        self.lost_count.load(Ordering::SeqCst)
+    }
+}
+
+/// Token/IP address pairs that were recorded for the first time.
+type NewIpAddresses = Vec<(i32, IpAddr)>;
+
+/// Sends a security notification to the owner of the API token if the token was used from an IP
+/// address that it was not used from before, see `ApiToken::record_ip_addresses()`.
+fn notify_on_new_ip_address(
+    app: &App,
+    conn: &mut PgConnection,
+    api_token_id: i32,
+    ip: IpAddr,
+) -> AppResult<()> {
+    let token: ApiToken = api_tokens::table
+        .find(api_token_id)
+        .select(ApiToken::as_select())
+        .first(conn)?;
+    let user = User::find(conn, token.user_id)?;
+
+    let Some(email) = NotificationType::TokenNewIpAddress.recipient(conn, &user)? else {
+        return Ok(());
+    };
+
+    app.emails.send_token_new_ip_address_notification(
+        conn,
+        user.id,
+        &email,
+        &user.gh_login,
+        &token.name,
+        ip,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::Emails;
+    use crate::models::{ApiToken, NewUser};
+    use crate::test_util::pg_connection;
+    use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+
+    fn activity(api_token_id: i32, created_at: NaiveDateTime) -> NewApiTokenActivity {
+        NewApiTokenActivity {
+            api_token_id,
+            created_at,
+            endpoint: "PUT /api/v1/crates/new".into(),
+            crate_name: Some("foo".into()),
+            ip_address: None,
+            user_agent: "cargo".into(),
+        }
+    }
+
+    fn new_token(conn: &mut PgConnection) -> i32 {
+        let user = NewUser {
+            gh_id: 0,
+            gh_login: "ghost",
+            ..NewUser::default()
+        }
+        .create_or_update(None, &Emails::new_in_memory(), conn)
+        .unwrap();
+
+        ApiToken::insert(conn, user.id, "token").unwrap().model.id
+    }
+
+    fn persisted_count(conn: &mut PgConnection, token_id: i32) -> i64 {
+        api_token_activity::table
+            .filter(api_token_activity::api_token_id.eq(token_id))
+            .count()
+            .get_result(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn record_and_persist() {
+        let log = TokenActivityLog::new();
+        let conn = &mut pg_connection();
+        let token_id = new_token(conn);
+
+        let now = Utc::now().naive_utc();
+        log.record(activity(token_id, now));
+        log.record(activity(token_id, now));
+        assert_eq!(log.pending_count(), 2);
+
+        assert_eq!(log.persist_with_conn(conn).unwrap().0, 2);
+        assert_eq!(log.pending_count(), 0);
+        assert_eq!(persisted_count(conn, token_id), 2);
+
+        // Nothing is written twice
+        assert_eq!(log.persist_with_conn(conn).unwrap().0, 0);
+        assert_eq!(persisted_count(conn, token_id), 2);
+    }
+
+    #[test]
+    fn persist_skips_deleted_tokens() {
+        let log = TokenActivityLog::new();
+        let conn = &mut pg_connection();
+        let token_id = new_token(conn);
+
+        let now = Utc::now().naive_utc();
+        log.record(activity(token_id, now));
+        log.record(activity(-1, now));
+
+        assert_eq!(log.persist_with_conn(conn).unwrap().0, 1);
+        assert_eq!(log.lost_count(), 0);
+    }
+
+    #[test]
+    fn persist_removes_oldest_entries() {
+        let log = TokenActivityLog::new();
+        let conn = &mut pg_connection();
+        let token_id = new_token(conn);
+
+        let start = NaiveDate::from_ymd_opt(2023, 10, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let total = MAX_ENTRIES_PER_TOKEN + 5;
+        for i in 0..total {
+            log.record(activity(token_id, start + Duration::seconds(i)));
+        }
+
+        assert_eq!(log.persist_with_conn(conn).unwrap().0, total as usize);
+        assert_eq!(persisted_count(conn, token_id), MAX_ENTRIES_PER_TOKEN);
+
+        let oldest: NaiveDateTime = api_token_activity::table
+            .select(diesel::dsl::min(api_token_activity::created_at))
+            .filter(api_token_activity::api_token_id.eq(token_id))
+            .get_result::<Option<NaiveDateTime>>(conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(oldest, start + Duration::seconds(5));
+    }
+
+    #[test]
+    fn persist_records_new_ip_addresses() {
+        let log = TokenActivityLog::new();
+        let conn = &mut pg_connection();
+        let token_id = new_token(conn);
+
+        let from_ip = |ip: &str| {
+            let mut activity = activity(token_id, Utc::now().naive_utc());
+            activity.ip_address = Some(ip.parse().unwrap());
+            activity
+        };
+
+        // The first IP address of a token is not new
+        log.record(from_ip("127.0.0.1"));
+        log.record(from_ip("127.0.0.1"));
+        assert_eq!(log.persist_with_conn(conn).unwrap(), (2, vec![]));
+
+        log.record(from_ip("192.0.2.1"));
+        log.record(from_ip("127.0.0.1"));
+        let ip = "192.0.2.1".parse().unwrap();
+        assert_eq!(
+            log.persist_with_conn(conn).unwrap(),
+            (2, vec![(token_id, ip)])
+        );
+    }
+
+    #[test]
+    fn persist_keeps_order_of_ip_addresses() {
+        let log = TokenActivityLog::new();
+        let conn = &mut pg_connection();
+        let token_id = new_token(conn);
+
+        let from_ip = |ip: &str| {
+            let mut activity = activity(token_id, Utc::now().naive_utc());
+            activity.ip_address = Some(ip.parse().unwrap());
+            activity
+        };
+
+        // The token was first used from the address that sorts last
+        log.record(from_ip("192.0.2.1"));
+        log.record(from_ip("127.0.0.1"));
+        log.record(from_ip("192.0.2.1"));
+        let ip = "127.0.0.1".parse().unwrap();
+        assert_eq!(
+            log.persist_with_conn(conn).unwrap(),
+            (3, vec![(token_id, ip)])
+        );
+    }
+}
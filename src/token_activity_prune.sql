@@ -0,0 +1,12 @@
+-- Removes all but the most recent log entries of the given API tokens.
+delete
+from api_token_activity
+where id in (select id
+             from (select id,
+                          row_number() over (
+                              partition by api_token_id
+                              order by created_at desc, id desc
+                              ) as position
+                   from api_token_activity
+                   where api_token_id = any ($1)) as entries
+             where position > $2);
@@ -17,6 +17,7 @@ use axum::response::IntoResponse;
 use std::any::{Any, TypeId};
 use std::error::Error;
 use std::fmt;
+use std::net::IpAddr;
 
 use axum::Extension;
 use chrono::NaiveDateTime;
@@ -63,6 +64,12 @@ pub fn account_locked(reason: &str, until: Option<NaiveDateTime>) -> BoxedAppErr
     })
 }
 
+/// Returns an error with status 403 for API tokens that are used from outside of their allowed
+/// IP ranges
+pub fn ip_address_not_allowed(ip: Option<IpAddr>) -> BoxedAppError {
+    Box::new(json::IpAddressNotAllowed(ip))
+}
+
 pub fn forbidden() -> BoxedAppError {
     Box::new(json::Forbidden)
 }
@@ -71,6 +78,17 @@ pub fn not_found() -> BoxedAppError {
     Box::new(json::NotFound)
 }
 
+/// Returns an error with status 404, which includes the names of similar crates
+///
+/// This is intended for the frontend and other API clients. Routes used by cargo should keep
+/// using `not_found` or `cargo_err`.
+pub fn crate_not_found(name: &str, suggestions: Vec<String>) -> BoxedAppError {
+    Box::new(json::CrateNotFound {
+        name: name.to_string(),
+        suggestions,
+    })
+}
+
 /// Returns an error with status 500 and the provided description as JSON
 pub fn server_error<S: ToString + ?Sized>(error: &S) -> BoxedAppError {
     Box::new(json::ServerError(error.to_string()))
@@ -1,6 +1,7 @@
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use std::fmt;
+use std::net::IpAddr;
 
 use super::{AppError, BoxedAppError, InternalAppErrorStatic};
 
@@ -31,6 +32,27 @@ impl fmt::Display for NotFound {
     }
 }
 
+#[derive(Debug)]
+pub(super) struct CrateNotFound {
+    pub(super) name: String,
+    pub(super) suggestions: Vec<String>,
+}
+
+impl AppError for CrateNotFound {
+    fn response(&self) -> Response {
+        let json = json!({
+            "errors": [{ "detail": self.to_string(), "suggestions": self.suggestions }]
+        });
+        (StatusCode::NOT_FOUND, Json(json)).into_response()
+    }
+}
+
+impl fmt::Display for CrateNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "crate `{}` does not exist", self.name)
+    }
+}
+
 #[derive(Debug)]
 pub(super) struct Forbidden;
 #[derive(Debug)]
@@ -194,6 +216,32 @@ impl fmt::Display for InsecurelyGeneratedTokenRevoked {
     }
 }
 
+#[derive(Debug)]
+pub(super) struct IpAddressNotAllowed(pub(super) Option<IpAddr>);
+
+impl AppError for IpAddressNotAllowed {
+    fn response(&self) -> Response {
+        json_error(&self.to_string(), StatusCode::FORBIDDEN)
+    }
+}
+
+impl fmt::Display for IpAddressNotAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ip) => write!(
+                f,
+                "this API token can not be used from the IP address {ip}, \
+                 which is not in the allowed IP ranges of the token"
+            ),
+            None => write!(
+                f,
+                "this API token can only be used from its allowed IP ranges, \
+                 but the IP address of the request is unknown"
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(super) struct AccountLocked {
     pub(super) reason: String,
@@ -3,9 +3,11 @@ use secrecy::ExposeSecret;
 use url::Url;
 
 use crate::github;
+use crate::models::token::ApiTokenActivity;
 use crate::models::{
-    ApiToken, Category, Crate, CrateOwnerInvitation, CreatedApiToken, Dependency, DependencyKind,
-    Keyword, Owner, ReverseDependency, Team, TopVersions, User, Version, VersionDownload,
+    ApiToken, Category, Crate, CrateDownloadByClient, CrateOwnerInvitation, CreatedApiToken,
+    Dependency, DependencyKind, DownloadsInterval, Email, Keyword, MonthlyVersionDownload, Owner,
+    ReverseDependency, Session, Team, TopVersions, User, Version, VersionDownload,
     VersionOwnerAction,
 };
 use crate::util::rfc3339;
@@ -159,6 +161,9 @@ pub struct EncodableVersionDownload {
     pub version: i32,
     pub downloads: i32,
     pub date: String,
+    /// `day` for the downloads of a single day, or `month` for archived downloads, which are
+    /// only available as a total for the month starting on `date`.
+    pub interval: DownloadsInterval,
 }
 
 impl From<VersionDownload> for EncodableVersionDownload {
@@ -167,6 +172,39 @@ impl From<VersionDownload> for EncodableVersionDownload {
             version: download.version_id,
             downloads: download.downloads,
             date: download.date.to_string(),
+            interval: DownloadsInterval::Day,
+        }
+    }
+}
+
+impl From<MonthlyVersionDownload> for EncodableVersionDownload {
+    fn from(download: MonthlyVersionDownload) -> Self {
+        Self {
+            version: download.version_id,
+            downloads: download.downloads,
+            date: download.month.to_string(),
+            interval: DownloadsInterval::Month,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EncodableCrateDownloadByClient {
+    pub date: String,
+    pub client: String,
+    pub cargo_version: Option<String>,
+    pub ci: bool,
+    pub downloads: i32,
+}
+
+impl From<CrateDownloadByClient> for EncodableCrateDownloadByClient {
+    fn from(download: CrateDownloadByClient) -> Self {
+        Self {
+            date: download.date.to_string(),
+            client: download.client,
+            cargo_version: Some(download.cargo_version).filter(|version| !version.is_empty()),
+            ci: download.ci,
+            downloads: download.downloads,
         }
     }
 }
@@ -212,6 +250,8 @@ pub struct EncodableCrate {
     // NOTE: Used by shields.io, altering `downloads` requires a PR with shields.io
     pub downloads: i64,
     pub recent_downloads: Option<i64>,
+    /// Popularity score, see `worker::update_crate_scores`. `None` if it was not computed yet.
+    pub score: Option<f64>,
     // NOTE: Used by shields.io, altering `max_version` requires a PR with shields.io
     pub max_version: String,
     pub newest_version: String, // Most recently updated version, which may not be max
@@ -235,6 +275,7 @@ impl EncodableCrate {
         badges: Option<Vec<()>>,
         exact_match: bool,
         recent_downloads: Option<i64>,
+        score: Option<f64>,
     ) -> Self {
         let Crate {
             name,
@@ -288,6 +329,7 @@ impl EncodableCrate {
             created_at,
             downloads,
             recent_downloads,
+            score,
             versions,
             keywords: keyword_ids,
             categories: category_ids,
@@ -317,6 +359,7 @@ impl EncodableCrate {
         badges: Option<Vec<()>>,
         exact_match: bool,
         recent_downloads: Option<i64>,
+        score: Option<f64>,
     ) -> Self {
         Self::from(
             krate,
@@ -327,6 +370,7 @@ impl EncodableCrate {
             badges,
             exact_match,
             recent_downloads,
+            score,
         )
     }
 
@@ -485,6 +529,58 @@ impl From<CreatedApiToken> for EncodableApiTokenWithToken {
     }
 }
 
+/// The serialization format for the `Session` model.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableSession {
+    pub id: i32,
+    #[serde(with = "rfc3339")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "rfc3339")]
+    pub last_seen_at: NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: String,
+    /// Whether this is the session of the current request.
+    pub current: bool,
+}
+
+impl EncodableSession {
+    /// Converts this `Session` model into an `EncodableSession` for JSON serialization.
+    pub fn from(session: Session, current_session_id: Option<i32>) -> Self {
+        Self {
+            id: session.id,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            ip_address: session.ip_address.map(|ip| ip.ip().to_string()),
+            user_agent: session.user_agent,
+            current: current_session_id == Some(session.id),
+        }
+    }
+}
+
+/// The serialization format for the `ApiTokenActivity` model.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableApiTokenActivity {
+    #[serde(with = "rfc3339")]
+    pub created_at: NaiveDateTime,
+    pub endpoint: String,
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: String,
+}
+
+impl From<ApiTokenActivity> for EncodableApiTokenActivity {
+    fn from(activity: ApiTokenActivity) -> Self {
+        Self {
+            created_at: activity.created_at,
+            endpoint: activity.endpoint,
+            crate_name: activity.crate_name,
+            ip_address: activity.ip_address.map(|ip| ip.ip().to_string()),
+            user_agent: activity.user_agent,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct OwnedCrate {
     pub id: i32,
@@ -498,6 +594,28 @@ pub struct EncodableMe {
     pub owned_crates: Vec<OwnedCrate>,
 }
 
+/// The serialization format for the `Email` model.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableEmail {
+    pub id: i32,
+    pub email: String,
+    pub verified: bool,
+    pub verification_email_sent: bool,
+    pub primary: bool,
+}
+
+impl From<Email> for EncodableEmail {
+    fn from(email: Email) -> Self {
+        Self {
+            id: email.id,
+            email: email.email,
+            verified: email.verified,
+            verification_email_sent: email.verified || email.token_generated_at.is_some(),
+            primary: email.is_primary,
+        }
+    }
+}
+
 /// The serialization format for the `User` model.
 /// Same as public user, except for addition of
 /// email field
@@ -822,6 +940,7 @@ mod tests {
                 .unwrap(),
             downloads: 0,
             recent_downloads: None,
+            score: None,
             max_version: "".to_string(),
             newest_version: "".to_string(),
             max_stable_version: None,
@@ -0,0 +1,227 @@
+//! Archive old `version_downloads` rows.
+//!
+//! The `version_downloads` table contains one row per version and day, which adds up to a lot of
+//! rows over the years. This background job moves all daily rows of the months that are
+//! completely older than the configured horizon out of the database:
+//!
+//! - the raw rows are uploaded as gzip-compressed CSV files to the
+//!   `archive/version-downloads/` prefix of the storage bucket,
+//! - the downloads are summed up per version and month into the `version_downloads_monthly`
+//!   table, which is used by the download endpoints for dates that are no longer available in
+//!   `version_downloads`,
+//! - and the rows are deleted from `version_downloads`.
+//!
+//! Only rows that were already counted and frozen by the `update_downloads` job are archived, so
+//! the download totals of the versions and crates are not affected.
+//!
+//! The rows are archived day by day in batches ordered by version ID, with one archive file and
+//! one transaction per batch, so that neither the memory usage nor the duration of the
+//! transactions grows with the number of versions.
+
+use crate::background_jobs::Environment;
+use crate::schema::{version_downloads, version_downloads_monthly};
+use crate::swirl::PerformError;
+use anyhow::Context;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use diesel::pg::upsert::excluded;
+use diesel::prelude::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+/// The download endpoints return the daily downloads of the last 90 days, so these must never be
+/// archived.
+const MIN_HORIZON_DAYS: u32 = 90;
+
+/// The maximum number of rows that are archived in one batch.
+const BATCH_SIZE: i64 = 10_000;
+
+#[instrument(skip(conn, env))]
+pub fn perform_archive_version_downloads(
+    conn: &mut PgConnection,
+    env: &Environment,
+    horizon_days: u32,
+) -> Result<(), PerformError> {
+    if horizon_days < MIN_HORIZON_DAYS {
+        let message = format!("The horizon must be at least {MIN_HORIZON_DAYS} days");
+        return Err(message.into());
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to initialize tokio runtime")?;
+
+    let today = Utc::now().date_naive();
+    let cutoff = first_day_of_month(today - Duration::days(horizon_days.into()));
+
+    // Only rows that were counted by the `update_downloads` job and will not be updated anymore
+    // are archived.
+    let days: Vec<NaiveDate> = version_downloads::table
+        .filter(version_downloads::processed.eq(true))
+        .filter(version_downloads::downloads.eq(version_downloads::counted))
+        .filter(version_downloads::date.lt(cutoff))
+        .select(version_downloads::date)
+        .distinct()
+        .order(version_downloads::date)
+        .load(conn)?;
+
+    info!(%cutoff, days = days.len(), "Archiving version downloads");
+
+    for day in days {
+        let mut archived = 0;
+        let mut after_version_id = 0;
+        loop {
+            let batch = conn
+                .transaction(|conn| archive_batch(conn, env, &rt, day, after_version_id, today))?;
+            let Some(batch) = batch else {
+                break;
+            };
+
+            archived += batch.rows;
+            after_version_id = batch.last_version_id;
+        }
+
+        info!(%day, archived, "Archived version downloads");
+    }
+
+    Ok(())
+}
+
+struct Batch {
+    rows: usize,
+    last_version_id: i32,
+}
+
+/// Archives the next batch of rows of the `day` after the `after_version_id`, or returns `None`
+/// if there are no rows left.
+///
+/// The rows of the batch are locked until the transaction ends, and they are only deleted after
+/// the archive was uploaded. If the upload fails, the transaction is rolled back and the rows
+/// will be retried by the next run of this job.
+fn archive_batch(
+    conn: &mut PgConnection,
+    env: &Environment,
+    rt: &tokio::runtime::Runtime,
+    day: NaiveDate,
+    after_version_id: i32,
+    today: NaiveDate,
+) -> Result<Option<Batch>, PerformError> {
+    let rows: Vec<(i32, NaiveDate, i32)> = version_downloads::table
+        .filter(version_downloads::processed.eq(true))
+        .filter(version_downloads::downloads.eq(version_downloads::counted))
+        .filter(version_downloads::date.eq(day))
+        .filter(version_downloads::version_id.gt(after_version_id))
+        .select((
+            version_downloads::version_id,
+            version_downloads::date,
+            version_downloads::downloads,
+        ))
+        .order(version_downloads::version_id)
+        .limit(BATCH_SIZE)
+        .for_update()
+        .load(conn)?;
+
+    let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+        return Ok(None);
+    };
+
+    // The name includes the day of the archival, because rows of an already archived day can
+    // still show up later, e.g. from CDN log files that are processed late.
+    let name = format!("{}/{day}/{today}-{}", day.format("%Y-%m"), first.0);
+    let archive = to_compressed_csv(&rows)?;
+    let path = rt.block_on(env.storage.upload_downloads_archive(&name, archive.into()))?;
+    info!(%path, rows = rows.len(), "Uploaded version downloads archive");
+
+    let version_ids = rows.iter().map(|(version_id, ..)| *version_id);
+    diesel::delete(
+        version_downloads::table
+            .filter(version_downloads::date.eq(day))
+            .filter(version_downloads::version_id.eq_any(version_ids)),
+    )
+    .execute(conn)?;
+
+    let month = first_day_of_month(day);
+    let values = rows
+        .iter()
+        .map(|(version_id, _, downloads)| {
+            (
+                version_downloads_monthly::version_id.eq(version_id),
+                version_downloads_monthly::month.eq(month),
+                version_downloads_monthly::downloads.eq(downloads),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(version_downloads_monthly::table)
+        .values(&values)
+        .on_conflict((
+            version_downloads_monthly::version_id,
+            version_downloads_monthly::month,
+        ))
+        .do_update()
+        .set(
+            version_downloads_monthly::downloads.eq(version_downloads_monthly::downloads
+                + excluded(version_downloads_monthly::downloads)),
+        )
+        .execute(conn)?;
+
+    Ok(Some(Batch {
+        rows: rows.len(),
+        last_version_id: last.0,
+    }))
+}
+
+fn to_compressed_csv(rows: &[(i32, NaiveDate, i32)]) -> anyhow::Result<Vec<u8>> {
+    let mut csv = String::from("version_id,date,downloads\n");
+    for (version_id, date, downloads) in rows {
+        writeln!(csv, "{version_id},{date},{downloads}")?;
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(csv.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+fn first_day_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn compressed_csv() {
+        let date = |day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap();
+        let rows = [(1, date(1), 10), (2, date(31), 5)];
+
+        let compressed = to_compressed_csv(&rows).unwrap();
+        let mut csv = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut csv)
+            .unwrap();
+
+        assert_eq!(
+            csv,
+            "version_id,date,downloads\n1,2023-01-01,10\n2,2023-01-31,5\n"
+        );
+    }
+
+    #[test]
+    fn months() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2023, month, day).unwrap();
+        assert_eq!(first_day_of_month(date(1, 31)), date(1, 1));
+        assert_eq!(
+            first_day_of_month(date(1, 1) + Duration::days(31)),
+            date(2, 1)
+        );
+        assert_eq!(
+            first_day_of_month(date(2, 1) + Duration::days(31)),
+            date(3, 1)
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Parse the `license` of the versions that were published before their SPDX licenses were
+//! stored in the `license_ids` and `license_alternatives` columns, which are used by the search.
+//!
+//! Each run of the job processes a batch of distinct license expressions, and enqueues another run
+//! if there might be more left, so that the updates are split into smaller transactions.
+
+use crate::background_jobs::Job;
+use crate::licenses::spdx_licenses;
+use crate::schema::versions;
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+
+/// The number of distinct license expressions that are processed by a single run of the job.
+const BATCH_SIZE: i64 = 100;
+
+#[instrument(skip_all)]
+pub fn perform_backfill_version_licenses(conn: &mut PgConnection) -> Result<(), PerformError> {
+    let licenses: Vec<String> = versions::table
+        .filter(versions::license.is_not_null())
+        .filter(versions::license_alternatives.is_null())
+        .select(versions::license.assume_not_null())
+        .distinct()
+        .limit(BATCH_SIZE)
+        .load(conn)?;
+
+    let mut updated = 0;
+    for license in &licenses {
+        let (ids, alternatives) = spdx_licenses(license);
+        updated += diesel::update(versions::table)
+            .filter(versions::license.eq(license))
+            .filter(versions::license_alternatives.is_null())
+            .set((
+                versions::license_ids.eq(ids),
+                versions::license_alternatives.eq(alternatives),
+            ))
+            .execute(conn)?;
+    }
+
+    info!(
+        licenses = licenses.len(),
+        updated, "Backfilled version licenses"
+    );
+
+    if licenses.len() as i64 == BATCH_SIZE {
+        Job::backfill_version_licenses().enqueue(conn)?;
+    }
+
+    Ok(())
+}
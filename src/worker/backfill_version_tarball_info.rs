@@ -0,0 +1,102 @@
+//! Read the stored crate files of the versions that were published before their
+//! `has_build_script` and `has_proc_macro` flags were stored, which are used by the search.
+//!
+//! Each run of the job processes a batch of versions ordered by ID, and enqueues another run for
+//! the versions after the batch if there might be more left. Versions whose crate file can't be
+//! read or processed are skipped with a warning and keep their `NULL` flags, so that they don't
+//! block the rest of the backfill.
+
+use crate::background_jobs::{Environment, Job};
+use crate::schema::{crates, versions};
+use crate::swirl::PerformError;
+use crate::util::Maximums;
+use anyhow::Context;
+use crates_io_tarball::process_tarball;
+use diesel::prelude::*;
+
+/// The number of versions that are processed by a single run of the job.
+const BATCH_SIZE: i64 = 100;
+
+#[instrument(skip(conn, env))]
+pub fn perform_backfill_version_tarball_info(
+    conn: &mut PgConnection,
+    env: &Environment,
+    after_version_id: i32,
+) -> Result<(), PerformError> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to initialize tokio runtime")?;
+
+    let pending: Vec<(i32, String, String, Option<i32>)> = versions::table
+        .inner_join(crates::table)
+        .filter(versions::id.gt(after_version_id))
+        .filter(
+            versions::has_build_script
+                .is_null()
+                .or(versions::has_proc_macro.is_null()),
+        )
+        .select((
+            versions::id,
+            crates::name,
+            versions::num,
+            crates::max_upload_size,
+        ))
+        .order(versions::id)
+        .limit(BATCH_SIZE)
+        .load(conn)?;
+
+    let mut updated = 0;
+    for (version_id, name, num, max_upload_size) in &pending {
+        let config = env.config();
+        let maximums = Maximums::new(
+            *max_upload_size,
+            config.max_upload_size,
+            config.max_unpack_size,
+        );
+
+        let pkg_name = format!("{name}-{num}");
+        let tarball_info = rt
+            .block_on(env.storage.download_crate_file(name, num))
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| {
+                Ok(process_tarball(
+                    &pkg_name,
+                    &*bytes,
+                    maximums.max_unpack_size,
+                )?)
+            });
+
+        let tarball_info = match tarball_info {
+            Ok(tarball_info) => tarball_info,
+            Err(error) => {
+                warn!(%name, %num, "Failed to read crate file: {error:#}");
+                continue;
+            }
+        };
+
+        let lib = tarball_info.manifest.lib.as_ref();
+        let has_proc_macro = lib.is_some_and(|lib| lib.proc_macro);
+
+        updated += diesel::update(versions::table)
+            .filter(versions::id.eq(version_id))
+            .set((
+                versions::has_build_script.eq(tarball_info.has_build_script),
+                versions::has_proc_macro.eq(has_proc_macro),
+            ))
+            .execute(conn)?;
+    }
+
+    info!(
+        versions = pending.len(),
+        updated, "Backfilled version tarball info"
+    );
+
+    if pending.len() as i64 == BATCH_SIZE {
+        if let Some((last_version_id, ..)) = pending.last() {
+            Job::backfill_version_tarball_info(*last_version_id).enqueue(conn)?;
+        }
+    }
+
+    Ok(())
+}
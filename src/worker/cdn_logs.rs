@@ -0,0 +1,309 @@
+//! Count crate downloads from the access logs of the CDNs serving the crate files.
+//!
+//! Counting downloads in the `download` API endpoint misses all downloads that go straight to
+//! the CDN, so this background job reads the access log files that CloudFront and Fastly write
+//! to the `cdn-logs/cloudfront/` and `cdn-logs/fastly/` prefixes of the storage bucket, and
+//! adds the downloads to the `version_downloads` table.
+//!
+//! The log lines include the downloads redirected from the `download` API endpoint, so the job
+//! only runs with `CDN_LOG_DOWNLOADS` set, which stops the endpoint from counting version
+//! downloads itself.
+//!
+//! Every log file is only counted once, which is tracked in the `processed_log_files` table.
+//!
+//! To avoid listing all log files ever written, each run only lists the files of a directory
+//! after a cursor, which is the last file that was processed more than `RETRY_WINDOW_HOURS` ago.
+//! This requires the file names in a directory to sort chronologically, which is the case for the
+//! Fastly logs and for the CloudFront logs of a single distribution. Broken log files are
+//! retried by every run until the cursor moves past them.
+
+use crate::background_jobs::Environment;
+use crate::schema::{crates, processed_log_files, version_downloads, versions};
+use crate::swirl::PerformError;
+use anyhow::Context;
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::pg::upsert::excluded;
+use diesel::prelude::*;
+use flate2::read::GzDecoder;
+use object_store::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+
+mod cloudfront;
+mod fastly;
+
+/// How many hours log files that could not be processed are retried, see the module
+/// documentation.
+const RETRY_WINDOW_HOURS: i64 = 24;
+
+/// The maximum number of paths that are checked against `processed_log_files` in one query.
+const PROCESSED_CHUNK_SIZE: usize = 1000;
+
+/// Number of downloads keyed by crate name, version and date.
+type DownloadsMap = BTreeMap<(String, String, NaiveDate), i32>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    CloudFront,
+    Fastly,
+}
+
+impl LogFormat {
+    const ALL: [Self; 2] = [Self::CloudFront, Self::Fastly];
+
+    /// The directory of the `cdn-logs/` prefix that the log files are stored in.
+    fn directory(&self) -> &'static str {
+        match self {
+            Self::CloudFront => "cloudfront",
+            Self::Fastly => "fastly",
+        }
+    }
+
+    fn count_downloads(&self, content: &str) -> anyhow::Result<DownloadsMap> {
+        match self {
+            Self::CloudFront => cloudfront::count_downloads(content),
+            Self::Fastly => fastly::count_downloads(content),
+        }
+    }
+}
+
+#[instrument(skip_all)]
+pub fn perform_process_cdn_logs(
+    conn: &mut PgConnection,
+    env: &Environment,
+) -> Result<(), PerformError> {
+    if !env.config().cdn_log_downloads {
+        warn!("Skipping CDN logs, since the downloads are counted by the API without `CDN_LOG_DOWNLOADS`");
+        return Ok(());
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to initialize tokio runtime")?;
+
+    for format in LogFormat::ALL {
+        let cursor = find_cursor(conn, format)?;
+        let paths = rt.block_on(
+            env.storage
+                .list_cdn_log_files(format.directory(), cursor.as_ref()),
+        )?;
+
+        let mut pending = Vec::new();
+        for chunk in paths.chunks(PROCESSED_CHUNK_SIZE) {
+            let path_strings = chunk.iter().map(Path::to_string).collect::<Vec<_>>();
+            let processed: HashSet<String> = processed_log_files::table
+                .select(processed_log_files::path)
+                .filter(processed_log_files::path.eq_any(&path_strings))
+                .load(conn)?
+                .into_iter()
+                .collect();
+
+            pending.extend(
+                chunk
+                    .iter()
+                    .filter(|path| !processed.contains(&path.to_string())),
+            );
+        }
+
+        info!(
+            directory = format.directory(),
+            cursor = cursor.as_ref().map(Path::to_string),
+            total = paths.len(),
+            pending = pending.len(),
+            "Processing CDN log files"
+        );
+
+        for path in pending {
+            process_log_file(conn, env, &rt, format, path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the last log file in the directory of the `format` that was processed more than
+/// `RETRY_WINDOW_HOURS` ago, or `None` if there is no such file yet.
+fn find_cursor(conn: &mut PgConnection, format: LogFormat) -> QueryResult<Option<Path>> {
+    let pattern = format!("cdn-logs/{}/%", format.directory());
+    let processed_before = (Utc::now() - Duration::hours(RETRY_WINDOW_HOURS)).naive_utc();
+
+    let cursor: Option<String> = processed_log_files::table
+        .filter(processed_log_files::path.like(pattern))
+        .filter(processed_log_files::processed_at.lt(processed_before))
+        .select(diesel::dsl::max(processed_log_files::path))
+        .get_result(conn)?;
+
+    Ok(cursor.map(Path::from))
+}
+
+fn process_log_file(
+    conn: &mut PgConnection,
+    env: &Environment,
+    rt: &tokio::runtime::Runtime,
+    format: LogFormat,
+    path: &Path,
+) -> Result<(), PerformError> {
+    let downloads = rt
+        .block_on(env.storage.read_cdn_log_file(path))
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| decompress(path, &bytes))
+        .and_then(|content| format.count_downloads(&content));
+
+    // A broken log file should not block the processing of all the other files. It is not
+    // marked as processed though, so it will be retried by the next run of this job.
+    let downloads = match downloads {
+        Ok(downloads) => downloads,
+        Err(error) => {
+            warn!(%path, "Failed to read CDN log file: {error:#}");
+            return Ok(());
+        }
+    };
+
+    match save_downloads(conn, path, &downloads) {
+        Ok(counted) => info!(%path, counted, "Counted downloads from CDN log file"),
+        Err(diesel::result::Error::RollbackTransaction) => {
+            info!(%path, "CDN log file was processed concurrently, skipping");
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    Ok(())
+}
+
+fn decompress(path: &Path, bytes: &[u8]) -> anyhow::Result<String> {
+    let mut content = String::new();
+    if path.extension() == Some("gz") {
+        GzDecoder::new(bytes).read_to_string(&mut content)?;
+    } else {
+        content = String::from_utf8(bytes.to_vec())?;
+    }
+    Ok(content)
+}
+
+/// Adds the downloads to the `version_downloads` table and marks the log file as processed, in a
+/// single transaction.
+///
+/// Returns the number of downloads that were counted. Downloads of crates or versions that don't
+/// exist (anymore) are discarded.
+fn save_downloads(
+    conn: &mut PgConnection,
+    path: &Path,
+    downloads: &DownloadsMap,
+) -> QueryResult<i32> {
+    conn.transaction(|conn| {
+        let names = downloads
+            .keys()
+            .map(|(name, _, _)| name.as_str())
+            .collect::<HashSet<_>>();
+
+        let version_ids: HashMap<(String, String), i32> = versions::table
+            .inner_join(crates::table)
+            .select((crates::name, versions::num, versions::id))
+            .filter(crates::name.eq_any(names))
+            .load::<(String, String, i32)>(conn)?
+            .into_iter()
+            .map(|(name, num, id)| ((name, num), id))
+            .collect();
+
+        // Sorting the rows by version ID avoids deadlocks with the `DownloadsCounter`, which
+        // inserts its rows in the same order. See `crate::downloads_counter` for details.
+        let mut counts = BTreeMap::new();
+        for ((name, version, date), count) in downloads {
+            let Some(version_id) = version_ids.get(&(name.clone(), version.clone())) else {
+                continue;
+            };
+            *counts.entry((*version_id, *date)).or_insert(0) += *count;
+        }
+
+        let counted = counts.values().sum();
+
+        let values = counts
+            .into_iter()
+            .map(|((version_id, date), count)| {
+                (
+                    version_downloads::version_id.eq(version_id),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(count),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(version_downloads::table)
+            .values(&values)
+            .on_conflict((version_downloads::version_id, version_downloads::date))
+            .do_update()
+            .set((
+                version_downloads::downloads
+                    .eq(version_downloads::downloads + excluded(version_downloads::downloads)),
+                version_downloads::processed.eq(false),
+            ))
+            .execute(conn)?;
+
+        let inserted = diesel::insert_into(processed_log_files::table)
+            .values((
+                processed_log_files::path.eq(path.to_string()),
+                processed_log_files::downloads.eq(counted),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        // Another worker processed the same file in the meantime, so the downloads we just
+        // added must not be committed.
+        if inserted == 0 {
+            return Err(diesel::result::Error::RollbackTransaction);
+        }
+
+        Ok(counted)
+    })
+}
+
+/// Parses a `crates/{name}/{name}-{version}.crate` path into the crate name and version.
+fn parse_crate_path(path: &str) -> Option<(String, String)> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let (name, file_name) = path.strip_prefix("crates/")?.split_once('/')?;
+
+    let version = file_name
+        .strip_prefix(name)?
+        .strip_prefix('-')?
+        .strip_suffix(".crate")?;
+
+    if name.is_empty() || version.is_empty() {
+        return None;
+    }
+
+    Some((name.to_string(), version.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::assert_none;
+
+    #[test]
+    fn crate_paths() {
+        let parse = |path| parse_crate_path(path).unwrap();
+        let expected = |name: &str, version: &str| (name.to_string(), version.to_string());
+
+        assert_eq!(
+            parse("/crates/serde/serde-1.0.188.crate"),
+            expected("serde", "1.0.188")
+        );
+        assert_eq!(
+            parse("crates/foo-bar/foo-bar-1.0.0-beta.1+build.crate"),
+            expected("foo-bar", "1.0.0-beta.1+build")
+        );
+
+        assert_none!(parse_crate_path("/readmes/serde/serde-1.0.188.html"));
+        assert_none!(parse_crate_path("/crates/serde/tokio-1.0.0.crate"));
+        assert_none!(parse_crate_path("/crates/serde/serde-.crate"));
+        assert_none!(parse_crate_path("/crates/serde/serde-1.0.188.tar.gz"));
+        assert_none!(parse_crate_path("/crates/serde"));
+    }
+
+    #[test]
+    fn log_format_directories() {
+        let directories = LogFormat::ALL.map(|format| format.directory());
+        assert_eq!(directories, ["cloudfront", "fastly"]);
+    }
+}
@@ -0,0 +1,92 @@
+//! Parser for the [standard log file format] of AWS CloudFront.
+//!
+//! [standard log file format]: https://docs.aws.amazon.com/AmazonCloudFront/latest/DeveloperGuide/AccessLogs.html#LogFileFormat
+
+use super::{parse_crate_path, DownloadsMap};
+use anyhow::{anyhow, Context};
+use chrono::NaiveDate;
+
+const FIELD_DATE: &str = "date";
+const FIELD_METHOD: &str = "cs-method";
+const FIELD_PATH: &str = "cs-uri-stem";
+const FIELD_STATUS: &str = "sc-status";
+
+pub(super) fn count_downloads(content: &str) -> anyhow::Result<DownloadsMap> {
+    let mut downloads = DownloadsMap::new();
+    let mut fields: Option<Vec<&str>> = None;
+
+    for line in content.lines() {
+        if let Some(header) = line.strip_prefix("#Fields:") {
+            fields = Some(header.split_whitespace().collect());
+            continue;
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = fields
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing `#Fields` header in CloudFront log file"))?;
+
+        let values = line.split('\t').collect::<Vec<_>>();
+        let field = |name: &str| {
+            fields
+                .iter()
+                .position(|field| *field == name)
+                .and_then(|index| values.get(index).copied())
+                .ok_or_else(|| anyhow!("Missing `{name}` field in CloudFront log line"))
+        };
+
+        if field(FIELD_METHOD)? != "GET" || field(FIELD_STATUS)? != "200" {
+            continue;
+        }
+
+        // CloudFront URL-encodes the already URL-encoded request path, so e.g. a `+` in a
+        // version number ends up as `%252B` in the log file.
+        let path = percent_decode(field(FIELD_PATH)?);
+        let Some((name, version)) = parse_crate_path(&percent_decode(&path)) else {
+            continue;
+        };
+
+        let date = field(FIELD_DATE)?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date in CloudFront log line: {date}"))?;
+
+        *downloads.entry((name, version, date)).or_default() += 1;
+    }
+
+    Ok(downloads)
+}
+
+fn percent_decode(input: &str) -> String {
+    percent_encoding::percent_decode_str(input)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::assert_err;
+
+    #[test]
+    fn count_fixture_downloads() {
+        let content = include_str!("fixtures/cloudfront.log");
+        let downloads = count_downloads(content).unwrap();
+
+        let date = |day| NaiveDate::from_ymd_opt(2023, 10, day).unwrap();
+        let expected = vec![
+            (("foo".into(), "1.0.0-beta.1+build".into(), date(2)), 1),
+            (("serde".into(), "1.0.188".into(), date(1)), 2),
+            (("tokio".into(), "1.32.0".into(), date(1)), 1),
+            (("tokio".into(), "1.32.0".into(), date(2)), 1),
+        ];
+        assert_eq!(downloads.into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn missing_fields_header() {
+        let content = "2023-10-01\t00:00:01\tFRA56-P5\t54081";
+        assert_err!(count_downloads(content));
+    }
+}
@@ -0,0 +1,67 @@
+//! Parser for the Fastly access logs, which are configured to be written as
+//! one JSON object per line.
+
+use super::{parse_crate_path, DownloadsMap};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Deserialize)]
+struct LogLine {
+    date_time: DateTime<Utc>,
+    method: String,
+    url: String,
+    status: u16,
+}
+
+pub(super) fn count_downloads(content: &str) -> anyhow::Result<DownloadsMap> {
+    let mut downloads = DownloadsMap::new();
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line: LogLine = serde_json::from_str(line)
+            .with_context(|| format!("Invalid Fastly log line: {line}"))?;
+
+        if line.method != "GET" || line.status != 200 {
+            continue;
+        }
+
+        let path = percent_encoding::percent_decode_str(&line.url).decode_utf8_lossy();
+        let Some((name, version)) = parse_crate_path(&path) else {
+            continue;
+        };
+
+        let date = line.date_time.date_naive();
+        *downloads.entry((name, version, date)).or_default() += 1;
+    }
+
+    Ok(downloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use claims::assert_err;
+
+    #[test]
+    fn count_fixture_downloads() {
+        let content = include_str!("fixtures/fastly.log");
+        let downloads = count_downloads(content).unwrap();
+
+        let date = |day| NaiveDate::from_ymd_opt(2023, 10, day).unwrap();
+        let expected = vec![
+            (("foo".into(), "1.0.0-beta.1+build".into(), date(2)), 1),
+            (("serde".into(), "1.0.188".into(), date(1)), 1),
+            (("tokio".into(), "1.32.0".into(), date(1)), 1),
+        ];
+        assert_eq!(downloads.into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn invalid_line() {
+        assert_err!(count_downloads("{\"foo\": 42}"));
+    }
+}
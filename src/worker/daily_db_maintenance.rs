@@ -5,9 +5,9 @@ use crate::swirl::PerformError;
 /// Because the `version_downloads` table includes years of historical data, we can accumulate
 /// a *lot* of garbage before an auto-vacuum is run.
 ///
-/// We only need to keep 90 days of entries in `version_downloads`. Once we have a mechanism to
-/// archive daily download counts and drop historical data, we can drop this task and rely on
-/// auto-vacuum again.
+/// Old entries of `version_downloads` are moved out of the table by the
+/// `archive_version_downloads` job, which runs alongside this task. Since that job deletes the
+/// rows of whole months, running a VACUUM afterwards is still worthwhile.
 use diesel::{sql_query, PgConnection, RunQueryDsl};
 
 pub(crate) fn perform_daily_db_maintenance(conn: &mut PgConnection) -> Result<(), PerformError> {
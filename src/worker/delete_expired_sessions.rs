@@ -0,0 +1,15 @@
+//! Delete the login sessions that expired, see `SESSION_MAX_AGE_DAYS`.
+//!
+//! Expired sessions can no longer be used to authenticate and are not listed anymore, so they
+//! would only pile up in the `sessions` table. Revoked sessions are deleted right away.
+
+use crate::models::Session;
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+
+#[instrument(skip_all)]
+pub fn perform_delete_expired_sessions(conn: &mut PgConnection) -> Result<(), PerformError> {
+    let deleted = Session::delete_expired(conn)?;
+    info!(deleted, "Deleted expired sessions");
+    Ok(())
+}
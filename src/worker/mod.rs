@@ -3,18 +3,42 @@
 //! the daily database maintenance, but also operations like rendering READMEs
 //! and uploading them to S3.
 
+mod archive_version_downloads;
+mod backfill_version_licenses;
+mod backfill_version_tarball_info;
+mod cdn_logs;
 pub mod cloudfront;
 mod daily_db_maintenance;
+mod delete_expired_sessions;
 pub mod dump_db;
 pub mod fastly;
 mod git;
 mod readmes;
+mod send_email;
+mod token_expiry_notifications;
+mod update_crate_scores;
 mod update_downloads;
+mod update_related_crates;
+mod update_trending_crates;
+mod version_action_notifications;
+mod weekly_digests;
 
+pub(crate) use archive_version_downloads::perform_archive_version_downloads;
+pub(crate) use backfill_version_licenses::perform_backfill_version_licenses;
+pub(crate) use backfill_version_tarball_info::perform_backfill_version_tarball_info;
+pub(crate) use cdn_logs::perform_process_cdn_logs;
 pub(crate) use daily_db_maintenance::perform_daily_db_maintenance;
+pub(crate) use delete_expired_sessions::perform_delete_expired_sessions;
 pub(crate) use dump_db::perform_dump_db;
 pub(crate) use git::{
     perform_index_squash, perform_normalize_index, sync_to_git_index, sync_to_sparse_index,
 };
 pub(crate) use readmes::perform_render_and_upload_readme;
+pub(crate) use send_email::perform_send_email;
+pub(crate) use token_expiry_notifications::perform_send_token_expiry_notifications;
+pub(crate) use update_crate_scores::perform_update_crate_scores;
 pub(crate) use update_downloads::perform_update_downloads;
+pub(crate) use update_related_crates::perform_update_related_crates;
+pub(crate) use update_trending_crates::perform_update_trending_crates;
+pub(crate) use version_action_notifications::perform_send_version_action_notifications;
+pub(crate) use weekly_digests::perform_send_weekly_digests;
@@ -0,0 +1,53 @@
+//! Send an email from the `email_outbox` table.
+//!
+//! A failing job is rolled back by the background worker, which would also roll back the
+//! bookkeeping in the outbox. Failed delivery attempts are therefore recorded by the job itself,
+//! which then enqueues a new job with the usual exponential backoff, until the email is given up
+//! on after `MAX_ATTEMPTS`.
+//!
+//! The rendered contents of an email can contain secrets like confirmation tokens, so they are
+//! cleared from the outbox once the email was sent or given up on.
+
+use crate::background_jobs::{Environment, Job};
+use crate::models::{OutboxEmail, OutboxEmailStatus};
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+
+/// With the exponential backoff of the background worker, the last attempt happens roughly
+/// four hours after the first one.
+const MAX_ATTEMPTS: i32 = 8;
+
+pub(crate) fn perform_send_email(
+    conn: &mut PgConnection,
+    env: &Environment,
+    email_id: i64,
+) -> Result<(), PerformError> {
+    let Some(email) = OutboxEmail::find(conn, email_id).optional()? else {
+        warn!(email_id, "Email not found in the outbox");
+        return Ok(());
+    };
+
+    if email.status != OutboxEmailStatus::Pending {
+        info!(email_id, status = ?email.status, "Email was already processed");
+        return Ok(());
+    }
+
+    match env.emails().deliver(&email) {
+        Ok(message_id) => email.mark_sent(conn, &message_id)?,
+        Err(error) => {
+            let attempts = email.attempts + 1;
+            warn!(email_id, attempts, %error, "Failed to send email");
+
+            if attempts < MAX_ATTEMPTS {
+                let status = OutboxEmailStatus::Pending;
+                email.mark_attempt_failed(conn, status, &error.to_string())?;
+                Job::send_email(email_id).enqueue_retry(conn, attempts)?;
+            } else {
+                let status = OutboxEmailStatus::Failed;
+                email.mark_attempt_failed(conn, status, &error.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
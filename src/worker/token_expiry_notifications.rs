@@ -0,0 +1,81 @@
+//! Send reminders about API tokens that expire soon, so that they can be replaced before CI
+//! pipelines using them start failing.
+//!
+//! A first reminder is sent when a token expires within `FIRST_REMINDER_DAYS`, and a second one
+//! when it expires within `SECOND_REMINDER_DAYS`. The `expiry_notification_at` column records
+//! when the last reminder was sent, so that running the job more than once a day does not send
+//! any duplicates.
+
+use crate::background_jobs::Environment;
+use crate::models::{ApiToken, NotificationType, User};
+use crate::schema::api_tokens;
+use crate::swirl::PerformError;
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+
+const FIRST_REMINDER_DAYS: i32 = 7;
+const SECOND_REMINDER_DAYS: i32 = 1;
+
+pub(crate) fn perform_send_token_expiry_notifications(
+    conn: &mut PgConnection,
+    env: &Environment,
+) -> Result<(), PerformError> {
+    let tokens: Vec<ApiToken> = api_tokens::table
+        .filter(api_tokens::revoked.eq(false))
+        .filter(api_tokens::expired_at.gt(now))
+        .filter(api_tokens::expired_at.le((now + FIRST_REMINDER_DAYS.days()).nullable()))
+        .filter(
+            api_tokens::expiry_notification_at
+                .is_null()
+                .or(api_tokens::expired_at
+                    .le((now + SECOND_REMINDER_DAYS.days()).nullable())
+                    .and(
+                        api_tokens::expiry_notification_at
+                            .lt(api_tokens::expired_at - SECOND_REMINDER_DAYS.days()),
+                    )),
+        )
+        .select(ApiToken::as_select())
+        .load(conn)?;
+
+    info!("Sending expiry reminders for {} API tokens", tokens.len());
+
+    for token in tokens {
+        if let Err(error) = send_reminder(conn, env, &token) {
+            warn!(token_id = %token.id, ?error, "Failed to send token expiry reminder");
+        }
+    }
+
+    Ok(())
+}
+
+fn send_reminder(
+    conn: &mut PgConnection,
+    env: &Environment,
+    token: &ApiToken,
+) -> Result<(), PerformError> {
+    let Some(expired_at) = token.expired_at else {
+        return Ok(());
+    };
+
+    let user = User::find(conn, token.user_id)?;
+    let Some(email) = NotificationType::TokenExpiry.recipient(conn, &user)? else {
+        return Ok(());
+    };
+
+    env.emails()
+        .send_token_expiry_reminder(
+            conn,
+            user.id,
+            &email,
+            &user.gh_login,
+            &token.name,
+            expired_at,
+        )
+        .map_err(|error| error.to_string())?;
+
+    diesel::update(api_tokens::table.find(token.id))
+        .set(api_tokens::expiry_notification_at.eq(now))
+        .execute(conn)?;
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Recompute the popularity score of all crates.
+//!
+//! Raw download counts are heavily skewed by CI traffic, so the score combines them with signals
+//! that are harder to inflate:
+//!
+//! ```text
+//! score = (DOWNLOADS_WEIGHT * ln(1 + recent downloads)
+//!          + REVERSE_DEPENDENCIES_WEIGHT * ln(1 + reverse dependencies)
+//!          + OWNERS_WEIGHT * ln(1 + owners))
+//!         * (1 + 0.5 ^ (days since the last release / RECENCY_HALF_LIFE_DAYS)) / 2
+//! ```
+//!
+//! The logarithms dampen the differences between the most popular crates, and the recency factor
+//! reduces the score of crates without a release for a long time by up to a half.
+//!
+//! The scores are stored in the `crate_scores` table and are used for `sort=score` in the
+//! search.
+//!
+//! The job is not scheduled by the application itself. It is only enqueued through the
+//! `crates-admin enqueue-job update-crate-scores` command, so it relies on an external scheduler
+//! to run it regularly, e.g. daily like the `daily_db_maintenance` job.
+
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Double;
+
+const DOWNLOADS_WEIGHT: f64 = 1.0;
+const REVERSE_DEPENDENCIES_WEIGHT: f64 = 2.0;
+const OWNERS_WEIGHT: f64 = 0.5;
+const RECENCY_HALF_LIFE_DAYS: f64 = 365.0;
+
+#[instrument(skip_all)]
+pub fn perform_update_crate_scores(conn: &mut PgConnection) -> Result<(), PerformError> {
+    let updated = sql_query(include_str!("update_crate_scores.sql"))
+        .bind::<Double, _>(DOWNLOADS_WEIGHT)
+        .bind::<Double, _>(REVERSE_DEPENDENCIES_WEIGHT)
+        .bind::<Double, _>(OWNERS_WEIGHT)
+        .bind::<Double, _>(RECENCY_HALF_LIFE_DAYS)
+        .execute(conn)?;
+
+    info!(updated, "Updated crate scores");
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+-- Recomputes the popularity scores of all crates, see `update_crate_scores.rs` for the formula.
+WITH newest_versions AS (
+    -- Same definition of reverse dependencies as in `krate_reverse_dependencies.sql`: only the
+    -- max versions of the dependent crates are considered
+    SELECT DISTINCT ON (crate_id)
+        id,
+        crate_id
+    FROM versions
+    WHERE NOT yanked
+    ORDER BY
+        crate_id,
+        semver_no_prerelease DESC NULLS LAST,
+        id DESC
+), reverse_dependencies AS (
+    SELECT
+        dependencies.crate_id,
+        COUNT(DISTINCT newest_versions.crate_id) AS count
+    FROM dependencies
+    INNER JOIN newest_versions
+        ON newest_versions.id = dependencies.version_id
+    GROUP BY dependencies.crate_id
+), owners AS (
+    SELECT
+        crate_id,
+        COUNT(*) AS count
+    FROM crate_owners
+    WHERE NOT deleted
+    GROUP BY crate_id
+), last_releases AS (
+    SELECT
+        crate_id,
+        MAX(created_at) AS created_at
+    FROM versions
+    GROUP BY crate_id
+)
+INSERT INTO crate_scores (crate_id, score, updated_at)
+SELECT
+    crates.id,
+    (
+        $1 * ln(1 + COALESCE(recent_crate_downloads.downloads, 0))
+        + $2 * ln(1 + COALESCE(reverse_dependencies.count, 0))
+        + $3 * ln(1 + COALESCE(owners.count, 0))
+    ) * (1 + power(
+        0.5,
+        EXTRACT(EPOCH FROM NOW() - COALESCE(last_releases.created_at, crates.created_at))
+            / 86400 / $4
+    )) / 2,
+    NOW()
+FROM crates
+LEFT JOIN recent_crate_downloads
+    ON recent_crate_downloads.crate_id = crates.id
+LEFT JOIN reverse_dependencies
+    ON reverse_dependencies.crate_id = crates.id
+LEFT JOIN owners
+    ON owners.crate_id = crates.id
+LEFT JOIN last_releases
+    ON last_releases.crate_id = crates.id
+ON CONFLICT (crate_id) DO UPDATE
+    SET score = excluded.score,
+        updated_at = excluded.updated_at
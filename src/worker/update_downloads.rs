@@ -110,6 +110,8 @@ mod test {
             "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
             None,
             None,
+            false,
+            false,
         )
         .unwrap();
         let version = version.save(conn, "someone@example.com").unwrap();
@@ -0,0 +1,66 @@
+//! Recompute the related crates that are recommended for each crate.
+//!
+//! There are two kinds of related crates:
+//!
+//! - Crates that are frequently depended on together with a crate, e.g. `serde_json` for
+//!   `serde`. The score is the cosine similarity of their dependents:
+//!
+//!   ```text
+//!   score = common dependents / sqrt(dependents of a * dependents of b)
+//!   ```
+//!
+//!   which keeps crates that everything depends on from being related to every crate.
+//!
+//! - Crates that are commonly used as alternatives, e.g. `async-std` for `tokio`. They share at
+//!   least `MIN_SHARED_TAGS` keywords and categories, and the crates depending on them have
+//!   similar keywords and categories. Crates that depend on each other are usually extensions of
+//!   each other instead of alternatives and are excluded. The score is the Jaccard similarity of
+//!   their keywords and categories plus `DEPENDENT_TAGS_WEIGHT` times the Jaccard similarity of
+//!   the keywords and categories of their dependents.
+//!
+//! Only the `MAX_RELATED_CRATES` best crates of each kind are stored in the `related_crates`
+//! table, which is read by the `GET /crates/:crate_id/related` endpoint.
+
+use crate::models::RelatedCrateKind;
+use crate::schema::related_crates;
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double, SmallInt};
+
+const MIN_COMMON_DEPENDENTS: i64 = 2;
+const MIN_SHARED_TAGS: i64 = 2;
+const DEPENDENT_TAGS_WEIGHT: f64 = 0.5;
+
+/// Keywords and categories with more crates are ignored for finding alternatives.
+const MAX_TAG_CRATES: i64 = 500;
+
+/// The number of related crates of each kind that are stored for each crate.
+const MAX_RELATED_CRATES: i64 = 10;
+
+#[instrument(skip_all)]
+pub fn perform_update_related_crates(conn: &mut PgConnection) -> Result<(), PerformError> {
+    let (dependencies, alternatives) = conn.transaction(|conn| {
+        diesel::delete(related_crates::table).execute(conn)?;
+
+        let dependencies = sql_query(include_str!("update_related_crates_dependencies.sql"))
+            .bind::<BigInt, _>(MIN_COMMON_DEPENDENTS)
+            .bind::<SmallInt, _>(RelatedCrateKind::Dependency as i16)
+            .bind::<BigInt, _>(MAX_RELATED_CRATES)
+            .execute(conn)?;
+
+        let alternatives = sql_query(include_str!("update_related_crates_alternatives.sql"))
+            .bind::<BigInt, _>(MAX_TAG_CRATES)
+            .bind::<BigInt, _>(MIN_SHARED_TAGS)
+            .bind::<Double, _>(DEPENDENT_TAGS_WEIGHT)
+            .bind::<SmallInt, _>(RelatedCrateKind::Alternative as i16)
+            .bind::<BigInt, _>(MAX_RELATED_CRATES)
+            .execute(conn)?;
+
+        QueryResult::Ok((dependencies, alternatives))
+    })?;
+
+    info!(dependencies, alternatives, "Updated related crates");
+
+    Ok(())
+}
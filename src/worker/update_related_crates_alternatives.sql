@@ -0,0 +1,122 @@
+-- Recomputes the crates that are commonly used as alternatives to each other, see
+-- `update_related_crates.rs` for the formula.
+WITH tags AS (
+    SELECT crate_id, 'keyword:' || keyword_id AS tag
+    FROM crates_keywords
+    UNION
+    SELECT crate_id, 'category:' || category_id AS tag
+    FROM crates_categories
+), tag_sizes AS (
+    SELECT
+        tag,
+        COUNT(*) AS count
+    FROM tags
+    GROUP BY tag
+), tag_counts AS (
+    SELECT
+        crate_id,
+        COUNT(*) AS count
+    FROM tags
+    GROUP BY crate_id
+), shared_tags AS (
+    -- Very common tags like `cli` are not specific enough to find alternatives, and would
+    -- produce too many pairs
+    SELECT
+        a.crate_id,
+        b.crate_id AS related_crate_id,
+        COUNT(*) AS count
+    FROM tags a
+    INNER JOIN tags b
+        ON b.tag = a.tag
+        AND b.crate_id <> a.crate_id
+    INNER JOIN tag_sizes
+        ON tag_sizes.tag = a.tag
+    WHERE tag_sizes.count <= $1
+    GROUP BY a.crate_id, b.crate_id
+    HAVING COUNT(*) >= $2
+), newest_versions AS (
+    -- Same definition of reverse dependencies as in `krate_reverse_dependencies.sql`: only the
+    -- max versions of the dependent crates are considered
+    SELECT DISTINCT ON (crate_id)
+        id,
+        crate_id
+    FROM versions
+    WHERE NOT yanked
+    ORDER BY
+        crate_id,
+        semver_no_prerelease DESC NULLS LAST,
+        id DESC
+), direct_dependencies AS (
+    SELECT DISTINCT
+        newest_versions.crate_id AS dependent_id,
+        dependencies.crate_id
+    FROM dependencies
+    INNER JOIN newest_versions
+        ON newest_versions.id = dependencies.version_id
+), dependent_tags AS (
+    -- The tags of the crates that depend on a crate describe what it is used for
+    SELECT DISTINCT
+        direct_dependencies.crate_id,
+        tags.tag
+    FROM direct_dependencies
+    INNER JOIN tags
+        ON tags.crate_id = direct_dependencies.dependent_id
+), dependent_tag_counts AS (
+    SELECT
+        crate_id,
+        COUNT(*) AS count
+    FROM dependent_tags
+    GROUP BY crate_id
+), shared_dependent_tags AS (
+    SELECT
+        shared_tags.crate_id,
+        shared_tags.related_crate_id,
+        COUNT(*) AS count
+    FROM shared_tags
+    INNER JOIN dependent_tags a
+        ON a.crate_id = shared_tags.crate_id
+    INNER JOIN dependent_tags b
+        ON b.crate_id = shared_tags.related_crate_id
+        AND b.tag = a.tag
+    GROUP BY shared_tags.crate_id, shared_tags.related_crate_id
+), scored AS (
+    SELECT
+        shared_tags.crate_id,
+        shared_tags.related_crate_id,
+        shared_tags.count::float8 / (a.count + b.count - shared_tags.count)
+            + $3 * COALESCE(
+                shared_dependent_tags.count::float8
+                    / NULLIF(a_dependents.count + b_dependents.count - shared_dependent_tags.count, 0),
+                0
+            ) AS score
+    FROM shared_tags
+    INNER JOIN tag_counts a
+        ON a.crate_id = shared_tags.crate_id
+    INNER JOIN tag_counts b
+        ON b.crate_id = shared_tags.related_crate_id
+    LEFT JOIN shared_dependent_tags
+        ON shared_dependent_tags.crate_id = shared_tags.crate_id
+        AND shared_dependent_tags.related_crate_id = shared_tags.related_crate_id
+    LEFT JOIN dependent_tag_counts a_dependents
+        ON a_dependents.crate_id = shared_tags.crate_id
+    LEFT JOIN dependent_tag_counts b_dependents
+        ON b_dependents.crate_id = shared_tags.related_crate_id
+    -- Crates that depend on each other are extensions, not alternatives
+    WHERE NOT EXISTS (
+        SELECT 1
+        FROM direct_dependencies
+        WHERE (direct_dependencies.dependent_id = shared_tags.crate_id
+                AND direct_dependencies.crate_id = shared_tags.related_crate_id)
+            OR (direct_dependencies.dependent_id = shared_tags.related_crate_id
+                AND direct_dependencies.crate_id = shared_tags.crate_id)
+    )
+), ranked AS (
+    SELECT
+        *,
+        ROW_NUMBER() OVER (PARTITION BY crate_id ORDER BY score DESC, related_crate_id) AS rank
+    FROM scored
+)
+INSERT INTO related_crates (crate_id, related_crate_id, kind, score, updated_at)
+SELECT crate_id, related_crate_id, $4, score, NOW()
+FROM ranked
+WHERE rank <= $5
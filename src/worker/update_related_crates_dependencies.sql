@@ -0,0 +1,61 @@
+-- Recomputes the crates that are frequently depended on together, see `update_related_crates.rs`
+-- for the formula.
+WITH newest_versions AS (
+    -- Same definition of reverse dependencies as in `krate_reverse_dependencies.sql`: only the
+    -- max versions of the dependent crates are considered
+    SELECT DISTINCT ON (crate_id)
+        id,
+        crate_id
+    FROM versions
+    WHERE NOT yanked
+    ORDER BY
+        crate_id,
+        semver_no_prerelease DESC NULLS LAST,
+        id DESC
+), direct_dependencies AS (
+    -- Dev dependencies are ignored, since they are not used together at runtime
+    SELECT DISTINCT
+        newest_versions.crate_id AS dependent_id,
+        dependencies.crate_id
+    FROM dependencies
+    INNER JOIN newest_versions
+        ON newest_versions.id = dependencies.version_id
+    WHERE dependencies.kind <> 2
+        AND dependencies.crate_id <> newest_versions.crate_id
+), dependents AS (
+    SELECT
+        crate_id,
+        COUNT(*) AS count
+    FROM direct_dependencies
+    GROUP BY crate_id
+), pairs AS (
+    SELECT
+        a.crate_id,
+        b.crate_id AS related_crate_id,
+        COUNT(*) AS count
+    FROM direct_dependencies a
+    INNER JOIN direct_dependencies b
+        ON b.dependent_id = a.dependent_id
+        AND b.crate_id <> a.crate_id
+    GROUP BY a.crate_id, b.crate_id
+    HAVING COUNT(*) >= $1
+), scored AS (
+    SELECT
+        pairs.crate_id,
+        pairs.related_crate_id,
+        pairs.count / sqrt(a.count * b.count) AS score
+    FROM pairs
+    INNER JOIN dependents a
+        ON a.crate_id = pairs.crate_id
+    INNER JOIN dependents b
+        ON b.crate_id = pairs.related_crate_id
+), ranked AS (
+    SELECT
+        *,
+        ROW_NUMBER() OVER (PARTITION BY crate_id ORDER BY score DESC, related_crate_id) AS rank
+    FROM scored
+)
+INSERT INTO related_crates (crate_id, related_crate_id, kind, score, updated_at)
+SELECT crate_id, related_crate_id, $2, score, NOW()
+FROM ranked
+WHERE rank <= $3
@@ -0,0 +1,49 @@
+//! Recompute the crates with the highest week-over-week growth of their downloads.
+//!
+//! ```text
+//! growth = (last week - previous week) / (previous week + BASE_DOWNLOADS_OFFSET)
+//! ```
+//!
+//! The offset normalizes the growth by the base size of the crate, so that a crate going from
+//! one to ten downloads doesn't outrank a crate going from 10k to 50k downloads.
+//!
+//! To protect against bots inflating the downloads of a crate, the downloads of each day of the
+//! last week are capped at `MAX_DAILY_SPIKE` times the median daily downloads of the previous
+//! week (or of `MIN_DAILY_BASELINE` for crates with fewer downloads), and crates need at least
+//! `MIN_WEEKLY_DOWNLOADS` capped downloads in the last week.
+//!
+//! The results are stored in the `trending_crates` table, which is read by the summary endpoint
+//! instead of computing the growth on every request.
+
+use crate::schema::trending_crates;
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double};
+
+const MAX_DAILY_SPIKE: f64 = 5.0;
+const MIN_DAILY_BASELINE: f64 = 20.0;
+const BASE_DOWNLOADS_OFFSET: f64 = 1000.0;
+const MIN_WEEKLY_DOWNLOADS: f64 = 100.0;
+
+/// The number of trending crates that are stored.
+const MAX_TRENDING_CRATES: i64 = 100;
+
+#[instrument(skip_all)]
+pub fn perform_update_trending_crates(conn: &mut PgConnection) -> Result<(), PerformError> {
+    let updated = conn.transaction(|conn| {
+        diesel::delete(trending_crates::table).execute(conn)?;
+
+        sql_query(include_str!("update_trending_crates.sql"))
+            .bind::<Double, _>(MAX_DAILY_SPIKE)
+            .bind::<Double, _>(MIN_DAILY_BASELINE)
+            .bind::<Double, _>(BASE_DOWNLOADS_OFFSET)
+            .bind::<Double, _>(MIN_WEEKLY_DOWNLOADS)
+            .bind::<BigInt, _>(MAX_TRENDING_CRATES)
+            .execute(conn)
+    })?;
+
+    info!(updated, "Updated trending crates");
+
+    Ok(())
+}
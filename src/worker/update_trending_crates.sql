@@ -0,0 +1,48 @@
+-- Recomputes the trending crates, see `update_trending_crates.rs` for the formula.
+WITH daily_downloads AS (
+    -- Only complete days are considered, since the downloads of today are still being counted
+    SELECT
+        versions.crate_id,
+        version_downloads.date,
+        SUM(version_downloads.downloads) AS downloads
+    FROM version_downloads
+    INNER JOIN versions
+        ON versions.id = version_downloads.version_id
+    WHERE version_downloads.date >= CURRENT_DATE - 14
+        AND version_downloads.date < CURRENT_DATE
+    GROUP BY versions.crate_id, version_downloads.date
+), baselines AS (
+    SELECT
+        crate_id,
+        percentile_cont(0.5) WITHIN GROUP (ORDER BY downloads) AS median
+    FROM daily_downloads
+    WHERE date < CURRENT_DATE - 7
+    GROUP BY crate_id
+), weekly_downloads AS (
+    -- The downloads of each day of the last week are capped relative to the median of the week
+    -- before, so that a download bot hammering a crate for a few days can't make it trend
+    SELECT
+        daily_downloads.crate_id,
+        SUM(daily_downloads.downloads)
+            FILTER (WHERE daily_downloads.date < CURRENT_DATE - 7) AS previous_week,
+        SUM(LEAST(
+            daily_downloads.downloads,
+            $1 * GREATEST(COALESCE(baselines.median, 0), $2)
+        )) FILTER (WHERE daily_downloads.date >= CURRENT_DATE - 7) AS last_week
+    FROM daily_downloads
+    LEFT JOIN baselines
+        ON baselines.crate_id = daily_downloads.crate_id
+    GROUP BY daily_downloads.crate_id
+), trending AS (
+    SELECT
+        crate_id,
+        (last_week - COALESCE(previous_week, 0)) / (COALESCE(previous_week, 0) + $3) AS growth
+    FROM weekly_downloads
+    WHERE last_week >= $4
+        AND last_week > COALESCE(previous_week, 0)
+    ORDER BY growth DESC, crate_id
+    LIMIT $5
+)
+INSERT INTO trending_crates (crate_id, growth, updated_at)
+SELECT crate_id, growth, NOW()
+FROM trending
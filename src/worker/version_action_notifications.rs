@@ -0,0 +1,99 @@
+//! Notify the owners of a crate when one of its versions is published, yanked or unyanked.
+//!
+//! This is the main way for owners to notice that the account or an API token of a co-owner was
+//! compromised, so the emails name the user, the API token and the IP address of the action.
+
+use crate::background_jobs::Environment;
+use crate::email::VersionActionNotification;
+use crate::models::{OwnerKind, User, VersionAction};
+use crate::schema::{
+    api_tokens, crate_owners, crates, emails, users, version_owner_actions, versions,
+};
+use crate::swirl::PerformError;
+use diesel::prelude::*;
+use std::net::IpAddr;
+
+pub(crate) fn perform_send_version_action_notifications(
+    conn: &mut PgConnection,
+    env: &Environment,
+    version_owner_action_id: i32,
+    ip_address: Option<IpAddr>,
+) -> Result<(), PerformError> {
+    let owner_action = version_owner_actions::table
+        .inner_join(versions::table.inner_join(crates::table))
+        .filter(version_owner_actions::id.eq(version_owner_action_id))
+        .select((
+            version_owner_actions::action,
+            version_owner_actions::user_id,
+            version_owner_actions::api_token_id,
+            versions::num,
+            crates::id,
+            crates::name,
+        ))
+        .first::<(VersionAction, i32, Option<i32>, String, i32, String)>(conn)
+        .optional()?;
+
+    let Some((action, actor_id, api_token_id, version, crate_id, crate_name)) = owner_action else {
+        info!(%version_owner_action_id, "Skipping notifications for deleted version");
+        return Ok(());
+    };
+
+    let actor = User::find(conn, actor_id)?;
+    let token_name = match api_token_id {
+        Some(api_token_id) => Some(
+            api_tokens::table
+                .find(api_token_id)
+                .select(api_tokens::name)
+                .first::<String>(conn)?,
+        ),
+        None => None,
+    };
+
+    // Like `User::verified_email()`, the primary address is preferred, but any other verified
+    // address is used if the primary address is not verified.
+    let recipients: Vec<(i32, String, String)> = crate_owners::table
+        .inner_join(users::table.on(users::id.eq(crate_owners::owner_id)))
+        .inner_join(emails::table.on(emails::user_id.eq(users::id)))
+        .filter(crate_owners::crate_id.eq(crate_id))
+        .filter(crate_owners::owner_kind.eq(OwnerKind::User as i32))
+        .filter(crate_owners::deleted.eq(false))
+        .filter(crate_owners::email_notifications.eq(true))
+        .filter(emails::verified.eq(true))
+        .distinct_on(users::id)
+        .order((users::id, emails::is_primary.desc(), emails::id.asc()))
+        .select((users::id, users::gh_login, emails::email))
+        .load(conn)?;
+
+    let action = match action {
+        VersionAction::Publish => "published",
+        VersionAction::Yank => "yanked",
+        VersionAction::Unyank => "unyanked",
+    };
+
+    let notification = VersionActionNotification {
+        crate_id,
+        crate_name: &crate_name,
+        version: &version,
+        action,
+        actor: &actor.gh_login,
+        token_name: token_name.as_deref(),
+        ip_address,
+    };
+
+    // An invalid email address of one owner must not keep the other owners from being notified.
+    for (user_id, user_name, email) in recipients {
+        let result = env.emails().send_version_action_notification(
+            conn,
+            user_id,
+            &email,
+            &user_name,
+            &notification,
+        );
+
+        if let Err(error) = result {
+            warn!(%crate_name, %version, %user_name, ?error, "Failed to send version action notification");
+        }
+    }
+
+    Ok(())
+}
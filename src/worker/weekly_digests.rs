@@ -0,0 +1,244 @@
+//! Send the weekly digest emails to the users that opted in to them.
+//!
+//! The job can run at any time of a week and sends each user at most one digest per week, which
+//! covers the seven days before the Monday of that week. The `weekly_digests` table records the
+//! digests that were sent. Its row is inserted in the same transaction that queues the email, so
+//! running or retrying the job more than once can neither duplicate nor lose a digest.
+
+use crate::background_jobs::Environment;
+use crate::config;
+use crate::email::{
+    DigestDownloads, DigestReverseDependency, DigestToken, DigestVersion, WeeklyDigest,
+};
+use crate::models::{NotificationType, OwnerKind, User};
+use crate::schema::{
+    api_tokens, crate_owner_invitations, crate_owners, crates, follows, notification_opt_ins,
+    version_downloads, versions, weekly_digests,
+};
+use crate::swirl::PerformError;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Array, Integer, Timestamp};
+use std::collections::BTreeMap;
+
+/// How long before their expiry API tokens are listed in the digest.
+const EXPIRING_TOKENS_DAYS: i32 = 7;
+
+pub(crate) fn perform_send_weekly_digests(
+    conn: &mut PgConnection,
+    env: &Environment,
+) -> Result<(), PerformError> {
+    let today = Utc::now().date_naive();
+    let week = today - Duration::days(today.weekday().num_days_from_monday().into());
+
+    let user_ids: Vec<i32> = notification_opt_ins::table
+        .filter(notification_opt_ins::notification_type.eq(NotificationType::WeeklyDigest))
+        .select(notification_opt_ins::user_id)
+        .load(conn)?;
+
+    info!(%week, "Sending weekly digests to {} users", user_ids.len());
+
+    for user_id in user_ids {
+        // The savepoint undoes the `weekly_digests` row if the email could not be queued.
+        let result = conn.transaction(|conn| send_digest(conn, env, user_id, week));
+
+        if let Err(error) = result {
+            warn!(%user_id, ?error, "Failed to send weekly digest");
+        }
+    }
+
+    Ok(())
+}
+
+fn send_digest(
+    conn: &mut PgConnection,
+    env: &Environment,
+    user_id: i32,
+    week: NaiveDate,
+) -> Result<(), PerformError> {
+    let inserted = diesel::insert_into(weekly_digests::table)
+        .values((
+            weekly_digests::user_id.eq(user_id),
+            weekly_digests::week.eq(week),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    // The digest of this week was already sent. Users without an email address or without
+    // anything to report are also not checked again until next week.
+    if inserted == 0 {
+        return Ok(());
+    }
+
+    let user = User::find(conn, user_id)?;
+    let Some(email) = NotificationType::WeeklyDigest.recipient(conn, &user)? else {
+        return Ok(());
+    };
+
+    let owned_crates: Vec<i32> = crate_owners::table
+        .filter(crate_owners::owner_id.eq(user_id))
+        .filter(crate_owners::owner_kind.eq(OwnerKind::User as i32))
+        .filter(crate_owners::deleted.eq(false))
+        .select(crate_owners::crate_id)
+        .load(conn)?;
+
+    let digest = WeeklyDigest {
+        start: week - Duration::days(7),
+        end: week - Duration::days(1),
+        downloads: downloads(conn, &owned_crates, week)?,
+        new_reverse_dependencies: new_reverse_dependencies(conn, &owned_crates, week)?,
+        pending_invitations: pending_invitations(conn, env.config(), user_id)?,
+        expiring_tokens: expiring_tokens(conn, user_id)?,
+        followed_versions: followed_versions(conn, user_id, week)?,
+    };
+
+    if digest.is_empty() {
+        return Ok(());
+    }
+
+    env.emails()
+        .send_weekly_digest(conn, user.id, &email, &user.gh_login, &digest)
+        .map_err(|error| error.to_string())?;
+
+    Ok(())
+}
+
+/// Compares the downloads of the crates in the week before `week` with the week before that.
+fn downloads(
+    conn: &mut PgConnection,
+    owned_crates: &[i32],
+    week: NaiveDate,
+) -> QueryResult<Vec<DigestDownloads>> {
+    let start = week - Duration::days(7);
+    let previous_start = start - Duration::days(7);
+
+    let crate_names: Vec<(i32, String)> = crates::table
+        .filter(crates::id.eq_any(owned_crates))
+        .select((crates::id, crates::name))
+        .order(crates::name)
+        .load(conn)?;
+
+    let rows: Vec<(i32, NaiveDate, i32)> = version_downloads::table
+        .inner_join(versions::table)
+        .filter(versions::crate_id.eq_any(owned_crates))
+        .filter(version_downloads::date.ge(previous_start))
+        .filter(version_downloads::date.lt(week))
+        .select((
+            versions::crate_id,
+            version_downloads::date,
+            version_downloads::downloads,
+        ))
+        .load(conn)?;
+
+    let mut totals: BTreeMap<i32, (i64, i64)> = BTreeMap::new();
+    for (crate_id, date, downloads) in rows {
+        let (current, previous) = totals.entry(crate_id).or_default();
+        match date >= start {
+            true => *current += i64::from(downloads),
+            false => *previous += i64::from(downloads),
+        }
+    }
+
+    Ok(crate_names
+        .into_iter()
+        .map(|(crate_id, crate_name)| {
+            let (downloads, previous_downloads) =
+                totals.get(&crate_id).copied().unwrap_or_default();
+            DigestDownloads {
+                crate_name,
+                downloads,
+                previous_downloads,
+            }
+        })
+        .collect())
+}
+
+/// Finds the crates that published a version depending on one of the crates in the week before
+/// `week`, without any older version depending on it.
+fn new_reverse_dependencies(
+    conn: &mut PgConnection,
+    owned_crates: &[i32],
+    week: NaiveDate,
+) -> QueryResult<Vec<DigestReverseDependency>> {
+    let (start, end) = week_range(week);
+
+    sql_query(include_str!("weekly_digests_reverse_dependencies.sql"))
+        .bind::<Array<Integer>, _>(owned_crates)
+        .bind::<Timestamp, _>(start)
+        .bind::<Timestamp, _>(end)
+        .load(conn)
+}
+
+fn pending_invitations(
+    conn: &mut PgConnection,
+    config: &config::Server,
+    user_id: i32,
+) -> QueryResult<Vec<String>> {
+    let expiration_days = config.ownership_invitations_expiration_days as i64;
+    let expire_cutoff = Utc::now().naive_utc() - Duration::days(expiration_days);
+
+    crate_owner_invitations::table
+        .inner_join(crates::table)
+        .filter(crate_owner_invitations::invited_user_id.eq(user_id))
+        .filter(crate_owner_invitations::created_at.gt(expire_cutoff))
+        .select(crates::name)
+        .order(crates::name)
+        .load(conn)
+}
+
+fn expiring_tokens(conn: &mut PgConnection, user_id: i32) -> QueryResult<Vec<DigestToken>> {
+    let tokens: Vec<(String, Option<NaiveDateTime>)> = api_tokens::table
+        .filter(api_tokens::user_id.eq(user_id))
+        .filter(api_tokens::revoked.eq(false))
+        .filter(api_tokens::expired_at.gt(now))
+        .filter(api_tokens::expired_at.le((now + EXPIRING_TOKENS_DAYS.days()).nullable()))
+        .select((api_tokens::name, api_tokens::expired_at))
+        .order(api_tokens::expired_at)
+        .load(conn)?;
+
+    Ok(tokens
+        .into_iter()
+        .filter_map(|(name, expired_at)| {
+            let expired_at = expired_at?.format("%Y-%m-%d %H:%M UTC").to_string();
+            Some(DigestToken { name, expired_at })
+        })
+        .collect())
+}
+
+/// Lists the versions of the crates followed by the user that were published in the week before
+/// `week`, like the `/me/updates` endpoint.
+fn followed_versions(
+    conn: &mut PgConnection,
+    user_id: i32,
+    week: NaiveDate,
+) -> QueryResult<Vec<DigestVersion>> {
+    let (start, end) = week_range(week);
+    let followed_crates = follows::table
+        .filter(follows::user_id.eq(user_id))
+        .select(follows::crate_id);
+
+    let versions: Vec<(String, String)> = versions::table
+        .inner_join(crates::table)
+        .filter(crates::id.eq_any(followed_crates))
+        .filter(versions::created_at.ge(start))
+        .filter(versions::created_at.lt(end))
+        .order(versions::created_at.desc())
+        .select((crates::name, versions::num))
+        .load(conn)?;
+
+    Ok(versions
+        .into_iter()
+        .map(|(crate_name, version)| DigestVersion {
+            crate_name,
+            version,
+        })
+        .collect())
+}
+
+/// Returns the start and the exclusive end of the week before `week`.
+fn week_range(week: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    let end = week.and_time(NaiveTime::MIN);
+    (end - Duration::days(7), end)
+}
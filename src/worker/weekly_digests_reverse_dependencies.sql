@@ -0,0 +1,25 @@
+-- Finds the crates that published a version depending on one of the crates `$1` between `$2` and
+-- `$3`, without any version before `$2` depending on it.
+SELECT DISTINCT
+    crates.name AS crate_name,
+    dependents.name AS dependent
+FROM dependencies
+INNER JOIN versions
+    ON versions.id = dependencies.version_id
+INNER JOIN crates
+    ON crates.id = dependencies.crate_id
+INNER JOIN crates AS dependents
+    ON dependents.id = versions.crate_id
+WHERE dependencies.crate_id = ANY($1)
+    AND versions.created_at >= $2
+    AND versions.created_at < $3
+    AND NOT EXISTS (
+        SELECT 1
+        FROM dependencies AS older_dependencies
+        INNER JOIN versions AS older_versions
+            ON older_versions.id = older_dependencies.version_id
+        WHERE older_dependencies.crate_id = dependencies.crate_id
+            AND older_versions.crate_id = versions.crate_id
+            AND older_versions.created_at < $2
+    )
+ORDER BY crates.name, dependents.name